@@ -259,7 +259,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             1 => BarrelShiftOpCode::LSR,
             2 => BarrelShiftOpCode::ASR,
             3 => BarrelShiftOpCode::ROR,
-            _ => unsafe { std::hint::unreachable_unchecked() },
+            _ => unsafe { core::hint::unreachable_unchecked() },
         };
         if SHIFT_BY_REG {
             let rs = offset.bit_range(8..12) as usize;
@@ -1,11 +1,16 @@
-use std::fmt;
+use alloc::format;
+use core::fmt;
 
-use crate::bit::BitIndex;
+use bit::BitIndex;
 
 use super::{ArmDecodeHelper, ArmFormat, ArmInstruction};
 
 use super::{AluOpCode, ArmCond, ArmHalfwordTransferType};
-use crate::arm7tdmi::*;
+use crate::alu::{BarrelShiftOpCode, BarrelShifterValue, ShiftRegisterBy, ShiftedRegister};
+use crate::memory::Addr;
+use crate::psr::RegPSR;
+use crate::reg_string;
+use crate::registers_consts::REG_PC;
 
 impl fmt::Display for ArmCond {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -87,8 +92,8 @@ fn is_lsl0(shift: &ShiftedRegister) -> bool {
 
 impl fmt::Display for ShiftedRegister {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let reg = reg_string(self.reg).to_string();
-        if !is_lsl0(&self) {
+        let reg = reg_string(self.reg);
+        if !is_lsl0(self) {
             write!(f, "{}", reg)
         } else {
             match self.shift_by {
@@ -149,7 +154,7 @@ impl ArmInstruction {
 
         let opcode = self.raw.opcode();
 
-        let rd = self.raw.bit_range(16..20) as usize;
+        let rd = self.raw.bit_range(12..16) as usize;
         let rn = self.raw.bit_range(16..20) as usize;
 
         match opcode {
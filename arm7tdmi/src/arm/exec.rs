@@ -1,7 +1,9 @@
 use bit::BitIndex;
+use log::warn;
 
 use crate::{
     alu::*,
+    exception::Exception,
     memory::{MemoryAccess, MemoryInterface},
     psr::RegPSR,
     registers_consts::{REG_LR, REG_PC},
@@ -14,12 +16,15 @@ use super::ArmDecodeHelper;
 use super::*;
 
 impl<I: MemoryInterface> Arm7tdmiCore<I> {
+    /// Undefined encodings (and the unimplemented coprocessor instructions) take the undefined instruction exception
     pub fn arm_undefined(&mut self, insn: u32) -> CpuAction {
-        panic!(
+        warn!(
             "executing undefined arm instruction {:08x} at @{:08x}",
             insn,
             self.pc_arm()
-        )
+        );
+        self.exception(Exception::UndefinedInstruction, self.pc - 4); // implies pipeline reload
+        CpuAction::PipelineFlushed
     }
 
     /// Branch and Branch with Link (B, BL)
@@ -129,7 +134,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         CpuAction::AdvancePC(Seq)
     }
 
-    fn transfer_spsr_mode(&mut self) {
+    pub(crate) fn transfer_spsr_mode(&mut self) {
         let spsr = self.spsr;
         if self.cpsr.mode() != spsr.mode() {
             self.change_mode(self.cpsr.mode(), spsr.mode());
@@ -159,8 +164,8 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             self.get_reg(rn)
         };
         let mut s_flag = SET_FLAGS;
-        let opcode =
-            AluOpCode::from_u8(OP).unwrap_or_else(|| unsafe { std::hint::unreachable_unchecked() });
+        let opcode = AluOpCode::from_u8(OP)
+            .unwrap_or_else(|| unsafe { core::hint::unreachable_unchecked() });
 
         let mut carry = self.cpsr.C();
         let op2 = if IMM {
@@ -469,31 +474,12 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
         let rlist = insn.register_list();
 
-        if FLAG_S {
-            match self.cpsr.mode() {
-                CpuMode::User | CpuMode::System => {
-                    panic!("LDM/STM with S bit in unprivileged mode")
-                }
-                _ => {}
-            };
-        }
-
-        let user_bank_transfer = if FLAG_S {
-            if LOAD {
-                !rlist.bit(REG_PC)
-            } else {
-                true
-            }
-        } else {
-            false
-        };
-
-        let old_mode = self.cpsr.mode();
-        if user_bank_transfer {
-            self.change_mode(old_mode, CpuMode::User);
-        }
-
-        let psr_transfer = FLAG_S & LOAD & rlist.bit(REG_PC);
+        // With the S bit, a LDM with PC in the list restores CPSR from SPSR,
+        // otherwise the user bank registers are transferred instead of the current mode's.
+        // User and System modes have no SPSR and already use the user bank, so S has no effect there.
+        let privileged = !matches!(self.cpsr.mode(), CpuMode::User | CpuMode::System);
+        let psr_transfer = FLAG_S && privileged && LOAD && rlist.bit(REG_PC);
+        let user_bank_transfer = FLAG_S && privileged && !psr_transfer;
 
         let rlist_count = rlist.count_ones();
 
@@ -523,13 +509,14 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
                         }
                         let val = self.load_32(addr, access);
                         access = Seq;
-                        self.set_reg(r, val);
                         if r == REG_PC {
-                            if psr_transfer {
-                                self.transfer_spsr_mode();
-                            }
-                            self.reload_pipeline32();
+                            // PC is the last register, the pipeline is reloaded once CPSR is final
+                            self.pc = val;
                             result = CpuAction::PipelineFlushed;
+                        } else if user_bank_transfer {
+                            self.set_reg_user(r, val);
+                        } else {
+                            self.set_reg(r, val);
                         }
                         if !full {
                             addr = addr.wrapping_add(4);
@@ -545,6 +532,8 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
                         let val = if r != base_reg {
                             if r == REG_PC {
                                 self.pc_arm() + 12
+                            } else if user_bank_transfer {
+                                self.get_reg_user(r)
                             } else {
                                 self.get_reg(r)
                             }
@@ -596,14 +585,28 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             };
         }
 
-        if user_bank_transfer {
-            self.change_mode(self.cpsr.mode(), old_mode);
-        }
-
+        // the base is written back to the bank of the mode that executed the instruction
         if writeback {
             self.set_reg(base_reg, addr as u32);
         }
 
+        if LOAD && rlist.bit(REG_PC) {
+            if psr_transfer {
+                self.transfer_spsr_mode();
+            }
+            // T bit might have changed
+            match self.cpsr.state() {
+                CpuState::ARM => {
+                    self.pc &= !3;
+                    self.reload_pipeline32();
+                }
+                CpuState::THUMB => {
+                    self.pc &= !1;
+                    self.reload_pipeline16();
+                }
+            };
+        }
+
         result
     }
 
@@ -633,7 +636,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
         self.set_reg(rd, result);
 
-        let m = self.get_required_multipiler_array_cycles(op2);
+        let m = self.get_required_multipiler_array_cycles(op2, true);
         for _ in 0..m {
             self.idle_cycle();
         }
@@ -680,12 +683,14 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         self.set_reg(rd_hi, (result >> 32) as i32 as u32);
         self.set_reg(rd_lo, (result & 0xffffffff) as i32 as u32);
         self.idle_cycle();
-        let m = self.get_required_multipiler_array_cycles(self.get_reg(rs));
+        // the multiplier is read before rd_hi/rd_lo are written, which may overlap rs
+        let m = self.get_required_multipiler_array_cycles(op2, U_FLAG);
         for _ in 0..m {
             self.idle_cycle();
         }
 
         if UPDATE_FLAGS {
+            // N and Z reflect the whole 64bit result, C and V are architecturally meaningless
             self.cpsr.set_N(result.bit(63));
             self.cpsr.set_Z(result == 0);
             self.cpsr.set_C(false);
@@ -702,11 +707,11 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         let rd = insn.bit_range(12..16) as usize;
         if BYTE {
             let t = self.load_8(base_addr, NonSeq);
-            self.store_8(base_addr, self.get_reg(insn.rm()) as u8, Seq);
+            self.store_8(base_addr, self.get_reg(insn.rm()) as u8, NonSeq);
             self.set_reg(rd, t as u32);
         } else {
             let t = self.ldr_word(base_addr, NonSeq);
-            self.store_aligned_32(base_addr, self.get_reg(insn.rm()), Seq);
+            self.store_aligned_32(base_addr, self.get_reg(insn.rm()), NonSeq);
             self.set_reg(rd, t as u32);
         }
         self.idle_cycle();
@@ -721,3 +726,103 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         CpuAction::PipelineFlushed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::tests::make_cpu;
+    use crate::memory::BusIO;
+    use crate::{CpuMode, CpuState};
+
+    #[test]
+    fn test_long_multiply() {
+        let mut cpu = make_cpu(&[
+            0xe0910392, // umulls r0, r1, r2, r3
+            0xe0f10392, // smlals r0, r1, r2, r3
+            0xe0d54392, // smulls r4, r5, r2, r3
+            0xe0d54392, // smulls r4, r5, r2, r3
+        ]);
+
+        cpu.set_reg(2, 0xffff_ffff);
+        cpu.set_reg(3, 2);
+        cpu.step();
+        assert_eq!((cpu.get_reg(1), cpu.get_reg(0)), (1, 0xffff_fffe));
+        assert!(!cpu.cpsr.N());
+        assert!(!cpu.cpsr.Z());
+
+        // 0x1_fffffffe + (-3 * 5)
+        cpu.set_reg(2, -3i32 as u32);
+        cpu.set_reg(3, 5);
+        cpu.step();
+        assert_eq!((cpu.get_reg(1), cpu.get_reg(0)), (1, 0xffff_ffef));
+        assert!(!cpu.cpsr.N());
+
+        // the flags are taken from all 64 bits
+        cpu.set_reg(2, 0x1000_0000);
+        cpu.set_reg(3, -16i32 as u32);
+        cpu.step();
+        assert_eq!((cpu.get_reg(5), cpu.get_reg(4)), (0xffff_ffff, 0));
+        assert!(cpu.cpsr.N());
+        assert!(!cpu.cpsr.Z());
+
+        cpu.set_reg(2, 0);
+        cpu.set_reg(3, 0x7fff_ffff);
+        cpu.step();
+        assert_eq!((cpu.get_reg(5), cpu.get_reg(4)), (0, 0));
+        assert!(!cpu.cpsr.N());
+        assert!(cpu.cpsr.Z());
+    }
+
+    #[test]
+    fn test_ldm_s_bit_restores_cpsr() {
+        let mut cpu = make_cpu(&[
+            0xe321f0d2, // msr cpsr_c, #0xd2
+            0xe361f010, // msr spsr_c, #0x10
+            0xe8fd8001, // ldmfd sp!, {r0, pc}^
+        ]);
+        cpu.set_reg_user(13, 0x2000);
+        cpu.set_reg(0, 0x1234_5678);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.cpsr.mode(), CpuMode::Irq);
+        let sp = 0x3000;
+        cpu.set_reg(13, sp);
+        cpu.bus.write_32(sp, 0xcafe_babe);
+        cpu.bus.write_32(sp + 4, 0x100);
+
+        cpu.step();
+        assert_eq!(cpu.cpsr.mode(), CpuMode::User);
+        assert_eq!(cpu.cpsr.state(), CpuState::ARM);
+        assert_eq!(cpu.pc_arm(), 0x100);
+        assert_eq!(cpu.get_reg(0), 0xcafe_babe);
+        // the writeback goes to the IRQ stack pointer, not the user one
+        assert_eq!(cpu.get_reg(13), 0x2000);
+        assert_eq!(cpu.banks.gpr_banked_r13[2], sp + 8);
+    }
+
+    #[test]
+    fn test_stm_s_bit_user_bank() {
+        let mut cpu = make_cpu(&[
+            0xe321f0d1, // msr cpsr_c, #0xd1
+            0xe8c07f00, // stmia r0, {r8-r14}^
+        ]);
+        for r in 8..15 {
+            cpu.set_reg_user(r, 0x1000 + r as u32);
+        }
+
+        cpu.step();
+        assert_eq!(cpu.cpsr.mode(), CpuMode::Fiq);
+        for r in 8..15 {
+            cpu.set_reg(r, 0xf1f0 + r as u32);
+        }
+        cpu.set_reg(0, 0x2000);
+
+        cpu.step();
+        for r in 8..15 {
+            let addr = 0x2000 + (r as u32 - 8) * 4;
+            assert_eq!(cpu.bus.read_32(addr), 0x1000 + r as u32);
+            assert_eq!(cpu.get_reg(r), 0xf1f0 + r as u32);
+        }
+        assert_eq!(cpu.cpsr.mode(), CpuMode::Fiq);
+    }
+}
@@ -1,4 +1,3 @@
-#[cfg(feature = "debugger")]
 pub mod disass;
 pub mod exec;
 
@@ -9,9 +8,10 @@ use super::memory::Addr;
 use super::InstructionDecoder;
 
 use bit::BitIndex;
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian};
 use num::FromPrimitive;
 
+#[cfg(feature = "std")]
 use std::io;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -21,6 +21,7 @@ pub enum ArmDecodeErrorKind {
     UndefinedConditionCode(u32),
     InvalidShiftType(u32),
     InvalidHSBits(u32),
+    #[cfg(feature = "std")]
     IoError(io::ErrorKind),
 }
 
@@ -81,6 +82,8 @@ pub enum ArmFormat {
     Undefined,
 }
 
+/// Used for disassembly only, the cpu dispatches through `ARM_LUT` (generated by build.rs)
+/// which is indexed by bits 27-20 and 7-4 of the opcode.
 impl From<u32> for ArmFormat {
     fn from(raw: u32) -> ArmFormat {
         use ArmFormat::*;
@@ -150,8 +153,7 @@ impl InstructionDecoder for ArmInstruction {
     }
 
     fn decode_from_bytes(bytes: &[u8], addr: Addr) -> Self {
-        let mut rdr = std::io::Cursor::new(bytes);
-        let raw = rdr.read_u32::<LittleEndian>().unwrap();
+        let raw = LittleEndian::read_u32(bytes);
         Self::decode(raw, addr)
     }
 
@@ -248,7 +250,7 @@ macro_rules! arm_decode_helper_impl {
 
             #[inline(always)]
             fn opcode(&self) -> AluOpCode {
-                use std::hint::unreachable_unchecked;
+                use core::hint::unreachable_unchecked;
 
                 unsafe {
                     if let Some(opc) = AluOpCode::from_u16(self.bit_range(21..25) as u16) {
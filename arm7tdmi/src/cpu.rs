@@ -1,10 +1,14 @@
-use std::fmt;
+use alloc::vec::Vec;
+use core::fmt;
 
 use log::debug;
 use serde::{Deserialize, Serialize};
 use bit::BitIndex;
 use num::FromPrimitive;
-use ansi_term::{Colour, Style};
+#[cfg(feature = "std")]
+use ansi_term::Style;
+#[cfg(feature = "debugger")]
+use ansi_term::Colour;
 
 use rustboyadvance_utils::{Shared, WeakPointer};
 
@@ -19,6 +23,14 @@ use MemoryAccess::*;
 
 use cfg_if::cfg_if;
 
+#[cfg(feature = "trace")]
+use alloc::{boxed::Box, rc::Rc};
+#[cfg(feature = "trace")]
+use core::cell::RefCell;
+
+#[cfg(feature = "trace")]
+use super::trace::{CpuTraceEvent, TraceSink};
+
 #[cfg(feature = "debugger")]
 use super::thumb::ThumbFormat;
 
@@ -122,9 +134,16 @@ pub struct Arm7tdmiCore<I: MemoryInterface> {
     /// Hardware breakpoints for use by gdb
     breakpoints: Vec<Addr>,
 
+    /// Set by an aborted data access, the data abort is taken once the instruction completes
+    pub(super) data_abort: bool,
+
     /// Deprecated in-house debugger state
     #[cfg(feature = "debugger")]
     pub dbg: DebuggerState,
+
+    /// Shared between clones so a cloned core keeps tracing to the same sink
+    #[cfg(feature = "trace")]
+    trace_sink: Option<Rc<RefCell<Box<dyn TraceSink>>>>,
 }
 
 impl<I: MemoryInterface> Arm7tdmiCore<I> {
@@ -141,9 +160,13 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             banks: BankedRegisters::default(),
 
             breakpoints: Vec::new(),
+            data_abort: false,
 
             #[cfg(feature = "debugger")]
             dbg: DebuggerState::default(),
+
+            #[cfg(feature = "trace")]
+            trace_sink: None,
         }
     }
 
@@ -165,10 +188,14 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             next_fetch_access: state.next_fetch_access,
 
             breakpoints: Vec::new(), // TODO include breakpoints in saved state
+            data_abort: false,
 
             // savestate does not keep debugger related information, so just reinitialize to default
             #[cfg(feature = "debugger")]
             dbg: DebuggerState::default(),
+
+            #[cfg(feature = "trace")]
+            trace_sink: None,
         }
     }
 
@@ -199,6 +226,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     }
 
     pub fn add_breakpoint(&mut self, addr: Addr) {
+        if self.breakpoints.contains(&addr) {
+            return;
+        }
         debug!("adding breakpoint {:08x}", addr);
         self.breakpoints.push(addr);
     }
@@ -210,6 +240,10 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         }
     }
 
+    pub fn breakpoints(&self) -> &[Addr] {
+        &self.breakpoints
+    }
+
     pub fn check_breakpoint(&self) -> Option<u32> {
         let next_pc = self.get_next_pc();
         for bp in &self.breakpoints {
@@ -245,19 +279,13 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         self.pc.wrapping_sub(4)
     }
 
-    pub fn get_reg_user(&mut self, r: usize) -> u32 {
-        match r {
-            0..=7 => self.gpr[r],
-            8..=12 => {
-                if self.cpsr.mode() == CpuMode::Fiq {
-                    self.gpr[r]
-                } else {
-                    self.banks.gpr_banked_old_r8_12[r - 8]
-                }
-            }
-            13 => self.banks.gpr_banked_r13[0],
-            14 => self.banks.gpr_banked_r14[0],
-            _ => panic!("invalid register"),
+    /// Gets a register of the user bank, regardless of the current mode
+    pub fn get_reg_user(&self, r: usize) -> u32 {
+        match (r, self.cpsr.mode()) {
+            (8..=12, CpuMode::Fiq) => self.banks.gpr_banked_old_r8_12[r - 8],
+            (13, mode) if mode.bank_index() != 0 => self.banks.gpr_banked_r13[0],
+            (14, mode) if mode.bank_index() != 0 => self.banks.gpr_banked_r14[0],
+            _ => self.get_reg(r),
         }
     }
 
@@ -276,23 +304,13 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         }
     }
 
+    /// Sets a register of the user bank, regardless of the current mode
     pub fn set_reg_user(&mut self, r: usize, val: u32) {
-        match r {
-            0..=7 => self.gpr[r] = val,
-            8..=12 => {
-                if self.cpsr.mode() == CpuMode::Fiq {
-                    self.gpr[r] = val;
-                } else {
-                    self.banks.gpr_banked_old_r8_12[r - 8] = val;
-                }
-            }
-            13 => {
-                self.banks.gpr_banked_r13[0] = val;
-            }
-            14 => {
-                self.banks.gpr_banked_r14[0] = val;
-            }
-            _ => panic!("invalid register"),
+        match (r, self.cpsr.mode()) {
+            (8..=12, CpuMode::Fiq) => self.banks.gpr_banked_old_r8_12[r - 8] = val,
+            (13, mode) if mode.bank_index() != 0 => self.banks.gpr_banked_r13[0] = val,
+            (14, mode) if mode.bank_index() != 0 => self.banks.gpr_banked_r14[0] = val,
+            _ => self.set_reg(r, val),
         }
     }
 
@@ -344,7 +362,10 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         }
     }
 
-    pub(super) fn get_required_multipiler_array_cycles(&self, rs: u32) -> usize {
+    /// The multiplier array terminates early when the top bits of `rs` are all zero,
+    /// or for signed multiplications, all one.
+    pub(super) fn get_required_multipiler_array_cycles(&self, rs: u32, signed: bool) -> usize {
+        let rs = if signed && (rs as i32) < 0 { !rs } else { rs };
         if rs & 0xff == rs {
             1
         } else if rs & 0xffff == rs {
@@ -388,9 +409,29 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         self.dbg.last_executed = Some(d);
     }
 
+    /// Set (or remove) the sink that is called before each executed instruction
+    #[cfg(feature = "trace")]
+    pub fn set_trace_sink(&mut self, sink: Option<Box<dyn TraceSink>>) {
+        self.trace_sink = sink.map(|sink| Rc::new(RefCell::new(sink)));
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace_instruction(&self, pc: Addr, opcode: u32) {
+        if let Some(sink) = &self.trace_sink {
+            sink.borrow_mut().on_instruction(&CpuTraceEvent {
+                pc,
+                opcode,
+                state: self.cpsr.state(),
+                gpr: self.gpr,
+            });
+        }
+    }
+
     fn step_arm_exec(&mut self, insn: u32) -> CpuAction {
         let hash = (((insn >> 16) & 0xff0) | ((insn >> 4) & 0xf)) as usize;
         let arm_info = &Self::ARM_LUT[hash];
+        #[cfg(feature = "trace")]
+        self.trace_instruction(self.pc.wrapping_sub(8), insn);
         #[cfg(feature = "debugger")]
         self.debugger_record_step(DecodedInstruction::Arm(ArmInstruction::new(
             insn,
@@ -402,6 +443,8 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
 
     fn step_thumb_exec(&mut self, insn: u16) -> CpuAction {
         let thumb_info = &Self::THUMB_LUT[(insn >> 6) as usize];
+        #[cfg(feature = "trace")]
+        self.trace_instruction(self.pc.wrapping_sub(4), insn as u32);
         #[cfg(feature = "debugger")]
         self.debugger_record_step(DecodedInstruction::Thumb(ThumbInstruction::new(
             insn,
@@ -414,9 +457,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     /// 2S + 1N
     #[inline(always)]
     pub fn reload_pipeline16(&mut self) {
-        self.pipeline[0] = self.load_16(self.pc, NonSeq) as u32;
+        self.pipeline[0] = self.fetch_16(self.pc, NonSeq) as u32;
         self.advance_thumb();
-        self.pipeline[1] = self.load_16(self.pc, Seq) as u32;
+        self.pipeline[1] = self.fetch_16(self.pc, Seq) as u32;
         self.advance_thumb();
         self.next_fetch_access = Seq;
     }
@@ -424,9 +467,9 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     /// 2S + 1N
     #[inline(always)]
     pub fn reload_pipeline32(&mut self) {
-        self.pipeline[0] = self.load_32(self.pc, NonSeq);
+        self.pipeline[0] = self.fetch_32(self.pc, NonSeq);
         self.advance_arm();
-        self.pipeline[1] = self.load_32(self.pc, Seq);
+        self.pipeline[1] = self.fetch_32(self.pc, Seq);
         self.advance_arm();
         self.next_fetch_access = Seq;
     }
@@ -459,12 +502,17 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             CpuState::ARM => {
                 let pc = self.pc & !3;
 
-                let fetched_now = self.load_32(pc, self.next_fetch_access);
+                let fetched_now = self.fetch_32(pc, self.next_fetch_access);
                 let insn = self.pipeline[0];
                 self.pipeline[0] = self.pipeline[1];
                 self.pipeline[1] = fetched_now;
+                let insn_addr = pc.wrapping_sub(8);
+                if self.bus.is_abort(insn_addr) {
+                    self.exception(Exception::PrefatchAbort, insn_addr.wrapping_add(4));
+                    return;
+                }
                 let cond = ArmCond::from_u8(insn.bit_range(28..32) as u8)
-                    .unwrap_or_else(|| unsafe { std::hint::unreachable_unchecked() });
+                    .unwrap_or_else(|| unsafe { core::hint::unreachable_unchecked() });
                 if cond != ArmCond::AL && !self.check_arm_cond(cond) {
                     self.advance_arm();
                     self.next_fetch_access = MemoryAccess::NonSeq;
@@ -477,14 +525,20 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
                     }
                     CpuAction::PipelineFlushed => {}
                 }
+                self.take_data_abort(insn_addr);
             }
             CpuState::THUMB => {
                 let pc = self.pc & !1;
 
-                let fetched_now = self.load_16(pc, self.next_fetch_access);
+                let fetched_now = self.fetch_16(pc, self.next_fetch_access);
                 let insn = self.pipeline[0];
                 self.pipeline[0] = self.pipeline[1];
                 self.pipeline[1] = fetched_now as u32;
+                let insn_addr = pc.wrapping_sub(4);
+                if self.bus.is_abort(insn_addr) {
+                    self.exception(Exception::PrefatchAbort, insn_addr.wrapping_add(4));
+                    return;
+                }
                 match self.step_thumb_exec(insn as u16) {
                     CpuAction::AdvancePC(access) => {
                         self.advance_thumb();
@@ -492,10 +546,19 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
                     }
                     CpuAction::PipelineFlushed => {}
                 }
+                self.take_data_abort(insn_addr);
             }
         }
     }
 
+    #[inline]
+    fn take_data_abort(&mut self, insn_addr: Addr) {
+        if self.data_abort {
+            self.data_abort = false;
+            self.exception(Exception::DataAbort, insn_addr.wrapping_add(8));
+        }
+    }
+
     /// Get's the address of the next instruction that is going to be executed
     pub fn get_next_pc(&self) -> Addr {
         let insn_size = self.word_size() as u32;
@@ -507,6 +570,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     }
 }
 
+#[cfg(feature = "std")]
 impl<I: MemoryInterface> fmt::Debug for Arm7tdmiCore<I> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "ARM7TDMI Core Status:")?;
@@ -565,3 +629,20 @@ impl<I: MemoryInterface> fmt::Display for Core<I> {
 
 include!(concat!(env!("OUT_DIR"), "/arm_lut.rs"));
 include!(concat!(env!("OUT_DIR"), "/thumb_lut.rs"));
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::SimpleMemory;
+
+    /// A cpu reset into `code`, which is loaded at address 0 of a `SimpleMemory`
+    pub(crate) fn make_cpu(code: &[u32]) -> Arm7tdmiCore<SimpleMemory> {
+        let program: Vec<u8> = code.iter().flat_map(|insn| insn.to_le_bytes()).collect();
+        let mut memory = SimpleMemory::new(0x4000);
+        memory.load_program(&program);
+
+        let mut cpu = Arm7tdmiCore::new(Shared::new(memory));
+        cpu.reset();
+        cpu
+    }
+}
@@ -1,6 +1,7 @@
-use std::fmt;
-use std::fmt::Write;
-use std::marker::PhantomData;
+use alloc::string::String;
+use core::fmt;
+use core::fmt::Write;
+use core::marker::PhantomData;
 
 use super::Addr;
 use super::InstructionDecoder;
@@ -25,7 +26,7 @@ where
             base: base as Addr,
             pos: 0,
             bytes,
-            word_size: std::mem::size_of::<D::IntType>(),
+            word_size: core::mem::size_of::<D::IntType>(),
             instruction_decoder: PhantomData,
         }
     }
@@ -34,7 +35,7 @@ where
 impl<'a, D> Iterator for Disassembler<'a, D>
 where
     D: InstructionDecoder + fmt::Display,
-    <D as InstructionDecoder>::IntType: std::fmt::LowerHex,
+    <D as InstructionDecoder>::IntType: fmt::LowerHex,
 {
     type Item = (Addr, String);
 
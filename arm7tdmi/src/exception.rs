@@ -69,4 +69,20 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     pub fn software_interrupt(&mut self, lr: u32, _cmt: u32) {
         self.exception(Exception::SoftwareInterrupt, lr);
     }
+
+    /// Return from an exception handler to `return_addr`, restoring the CPSR from the SPSR like `movs pc, lr` does
+    pub fn exception_return(&mut self, return_addr: u32) {
+        self.transfer_spsr_mode();
+        self.pc = return_addr;
+        match self.cpsr.state() {
+            CpuState::ARM => {
+                self.pc &= !3;
+                self.reload_pipeline32();
+            }
+            CpuState::THUMB => {
+                self.pc &= !1;
+                self.reload_pipeline16();
+            }
+        }
+    }
 }
@@ -10,6 +10,10 @@ pub mod target;
 pub extern crate gdbstub;
 pub extern crate gdbstub_arch;
 
+/// Target description of the ARM7TDMI, same register layout as `gdbstub_arch::arm::Armv4t`
+/// but with the CPSR flags decoded and the registers sorted into groups.
+pub const TARGET_DESCRIPTION_XML: &str = include_str!("target.xml");
+
 /// Wait for tcp connection on port
 pub fn wait_for_connection(port: u16) -> io::Result<TcpStream> {
     let bind_addr = format!("0.0.0.0:{port}");
@@ -6,13 +6,14 @@ use gdbstub::target::ext::base::singlethread::{
 use gdbstub::target::ext::base::singlethread::{SingleThreadResumeOps, SingleThreadSingleStepOps};
 use gdbstub::target::ext::base::BaseOps;
 use gdbstub::target::ext::breakpoints::BreakpointsOps;
-use gdbstub::target::{self, Target, TargetError, TargetResult};
+use gdbstub::target::{self, Target, TargetResult};
 
-use crate::memory::{DebugRead, MemoryInterface};
+use super::{copy_range_to_buf, TARGET_DESCRIPTION_XML};
+use crate::memory::{DebugRead, DebugWrite, MemoryInterface};
 use crate::registers_consts::*;
 use crate::Arm7tdmiCore;
 
-pub trait MemoryGdbInterface: MemoryInterface + DebugRead {
+pub trait MemoryGdbInterface: MemoryInterface + DebugRead + DebugWrite {
     fn memory_map_xml(&self, offset: u64, length: usize, buf: &mut [u8]) -> usize;
 }
 
@@ -34,6 +35,13 @@ impl<I: MemoryGdbInterface> Target for Arm7tdmiCore<I> {
     fn support_memory_map(&mut self) -> Option<target::ext::memory_map::MemoryMapOps<Self>> {
         Some(self)
     }
+
+    fn support_target_description_xml_override(
+        &mut self,
+    ) -> Option<target::ext::target_description_xml_override::TargetDescriptionXmlOverrideOps<Self>>
+    {
+        Some(self)
+    }
 }
 
 impl<I: MemoryGdbInterface> SingleThreadBase for Arm7tdmiCore<I> {
@@ -66,9 +74,9 @@ impl<I: MemoryGdbInterface> SingleThreadBase for Arm7tdmiCore<I> {
         Ok(())
     }
 
-    fn write_addrs(&mut self, _start_addr: u32, _data: &[u8]) -> TargetResult<(), Self> {
-        // todo!("implement DebugWrite bus extention")
-        Err(TargetError::NonFatal)
+    fn write_addrs(&mut self, start_addr: u32, data: &[u8]) -> TargetResult<(), Self> {
+        self.bus.debug_write_bytes(start_addr, data);
+        Ok(())
     }
 
     // most targets will want to support at resumption as well...
@@ -101,6 +109,24 @@ impl<I: MemoryGdbInterface> SingleThreadSingleStep for Arm7tdmiCore<I> {
     }
 }
 
+impl<I: MemoryGdbInterface>
+    target::ext::target_description_xml_override::TargetDescriptionXmlOverride for Arm7tdmiCore<I>
+{
+    fn target_description_xml(
+        &self,
+        offset: u64,
+        length: usize,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        Ok(copy_range_to_buf(
+            TARGET_DESCRIPTION_XML.as_bytes(),
+            offset,
+            length,
+            buf,
+        ))
+    }
+}
+
 impl<I: MemoryGdbInterface> target::ext::memory_map::MemoryMap for Arm7tdmiCore<I> {
     fn memory_map_xml(
         &self,
@@ -1,10 +1,15 @@
+//! Without the default `std` feature the crate builds as `no_std` + `alloc`, leaving out the gdb stub
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 #[macro_use]
 extern crate serde;
 
 #[macro_use]
 extern crate enum_primitive_derive;
 
-use std::fmt;
+use core::fmt;
 
 use num::Num;
 use serde::{Deserialize, Serialize};
@@ -23,10 +28,14 @@ pub use alu::*;
 use memory::Addr;
 pub mod disass;
 pub mod exception;
+#[cfg(feature = "std")]
 pub mod gdb;
+#[cfg(feature = "std")]
 pub use gdb::{gdbstub, gdbstub_arch};
 pub mod psr;
 mod simple_memory;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub use simple_memory::SimpleMemory;
 
 pub mod registers_consts {
@@ -50,7 +59,6 @@ impl DecodedInstruction {
     }
 }
 
-#[cfg(feature = "debugger")]
 impl fmt::Display for DecodedInstruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1,5 +1,6 @@
 use super::Arm7tdmiCore;
-use std::fmt;
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
 
 pub type Addr = u32;
 
@@ -79,35 +80,49 @@ pub trait MemoryInterface {
     fn store_32(&mut self, addr: u32, value: u32, access: MemoryAccess);
 
     fn idle_cycle(&mut self);
+
+    /// Whether an access to `addr` aborts, like an external memory manager asserting ABORT.
+    /// Aborted instruction fetches raise a prefetch abort once they reach execution,
+    /// aborted data accesses raise a data abort once the instruction completes.
+    #[inline]
+    fn is_abort(&self, _addr: Addr) -> bool {
+        false
+    }
 }
 
 impl<I: MemoryInterface> MemoryInterface for Arm7tdmiCore<I> {
     #[inline]
     fn load_8(&mut self, addr: u32, access: MemoryAccess) -> u8 {
+        self.check_data_abort(addr);
         self.bus.load_8(addr, access)
     }
 
     #[inline]
     fn load_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
+        self.check_data_abort(addr);
         self.bus.load_16(addr & !1, access)
     }
 
     #[inline]
     fn load_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
+        self.check_data_abort(addr);
         self.bus.load_32(addr & !3, access)
     }
 
     #[inline]
     fn store_8(&mut self, addr: u32, value: u8, access: MemoryAccess) {
+        self.check_data_abort(addr);
         self.bus.store_8(addr, value, access);
     }
     #[inline]
     fn store_16(&mut self, addr: u32, value: u16, access: MemoryAccess) {
+        self.check_data_abort(addr);
         self.bus.store_16(addr & !1, value, access);
     }
 
     #[inline]
     fn store_32(&mut self, addr: u32, value: u32, access: MemoryAccess) {
+        self.check_data_abort(addr);
         self.bus.store_32(addr & !3, value, access);
     }
 
@@ -115,10 +130,33 @@ impl<I: MemoryInterface> MemoryInterface for Arm7tdmiCore<I> {
     fn idle_cycle(&mut self) {
         self.bus.idle_cycle();
     }
+
+    #[inline]
+    fn is_abort(&self, addr: Addr) -> bool {
+        self.bus.is_abort(addr)
+    }
 }
 
 /// Implementation of memory access helpers
 impl<I: MemoryInterface> Arm7tdmiCore<I> {
+    /// Instruction fetches bypass the data abort check, they are checked when executed
+    #[inline]
+    pub(super) fn fetch_32(&mut self, addr: Addr, access: MemoryAccess) -> u32 {
+        self.bus.load_32(addr & !3, access)
+    }
+
+    #[inline]
+    pub(super) fn fetch_16(&mut self, addr: Addr, access: MemoryAccess) -> u16 {
+        self.bus.load_16(addr & !1, access)
+    }
+
+    #[inline]
+    fn check_data_abort(&mut self, addr: Addr) {
+        if self.bus.is_abort(addr) {
+            self.data_abort = true;
+        }
+    }
+
     #[inline]
     pub(super) fn store_aligned_32(&mut self, addr: Addr, value: u32, access: MemoryAccess) {
         self.store_32(addr & !0x3, value, access);
@@ -129,37 +167,31 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         self.store_16(addr & !0x1, value, access);
     }
 
-    /// Helper function for "ldr" instruction that handles misaligned addresses
+    /// Helper function for "ldr" instruction that handles misaligned addresses.
+    /// The aligned word is rotated so the addressed byte ends up in the low byte, the flags are left untouched.
     #[inline]
     pub(super) fn ldr_word(&mut self, addr: Addr, access: MemoryAccess) -> u32 {
         if addr & 0x3 != 0 {
             let rotation = (addr & 0x3) << 3;
-            let value = self.load_32(addr & !0x3, access);
-            let mut carry = self.cpsr.C();
-            let v = self.ror(value, rotation, &mut carry, false, false);
-            self.cpsr.set_C(carry);
-            v
+            self.load_32(addr & !0x3, access).rotate_right(rotation)
         } else {
             self.load_32(addr, access)
         }
     }
 
-    /// Helper function for "ldrh" instruction that handles misaligned addresses
+    /// Helper function for "ldrh" instruction that handles misaligned addresses.
+    /// On odd addresses the aligned halfword is rotated right by 8 bits as a 32bit value.
     #[inline]
     pub(super) fn ldr_half(&mut self, addr: Addr, access: MemoryAccess) -> u32 {
         if addr & 0x1 != 0 {
-            let rotation = (addr & 0x1) << 3;
-            let value = self.load_16(addr & !0x1, access);
-            let mut carry = self.cpsr.C();
-            let v = self.ror(value as u32, rotation, &mut carry, false, false);
-            self.cpsr.set_C(carry);
-            v
+            (self.load_16(addr & !0x1, access) as u32).rotate_right(8)
         } else {
             self.load_16(addr, access) as u32
         }
     }
 
-    /// Helper function for "ldrsh" instruction that handles misaligned addresses
+    /// Helper function for "ldrsh" instruction that handles misaligned addresses,
+    /// on odd addresses it loads the addressed byte and sign extends it
     #[inline]
     pub(super) fn ldr_sign_half(&mut self, addr: Addr, access: MemoryAccess) -> u32 {
         if addr & 0x1 != 0 {
@@ -204,7 +236,7 @@ pub trait BusIO {
 
     fn write_8(&mut self, addr: Addr, value: u8);
 
-    fn get_bytes(&mut self, range: std::ops::Range<u32>) -> Vec<u8> {
+    fn get_bytes(&mut self, range: core::ops::Range<u32>) -> Vec<u8> {
         let mut bytes = Vec::new();
         for b in range {
             bytes.push(self.read_8(b));
@@ -225,7 +257,7 @@ pub trait DebugRead: BusIO {
 
     fn debug_read_8(&mut self, addr: Addr) -> u8;
 
-    fn debug_get_bytes(&mut self, range: std::ops::Range<Addr>) -> Vec<u8> {
+    fn debug_get_bytes(&mut self, range: core::ops::Range<Addr>) -> Vec<u8> {
         let mut bytes = Vec::new();
         for b in range {
             bytes.push(self.debug_read_8(b));
@@ -241,6 +273,18 @@ pub trait DebugRead: BusIO {
     }
 }
 
+/// Helper trait for writing memory as if we were an all-powerfull debugger
+pub trait DebugWrite: BusIO {
+    fn debug_write_8(&mut self, addr: Addr, value: u8);
+
+    fn debug_write_bytes(&mut self, start_addr: Addr, bytes: &[u8]) {
+        bytes
+            .iter()
+            .enumerate()
+            .for_each(|(idx, byte)| self.debug_write_8(start_addr + (idx as Addr), *byte));
+    }
+}
+
 /// The caller is assumed to handle out of bound accesses,
 /// For performance reasons, this impl trusts that 'addr' is within the array range.
 impl BusIO for Box<[u8]> {
@@ -263,3 +307,52 @@ impl DebugRead for Box<[u8]> {
         self[addr as usize]
     }
 }
+
+impl DebugWrite for Box<[u8]> {
+    #[inline]
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        self[addr as usize] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BusIO;
+    use crate::cpu::tests::make_cpu;
+
+    #[test]
+    fn test_misaligned_loads() {
+        let mut cpu = make_cpu(&[
+            0xe3a00a02, // mov r0, #0x2000
+            0xe5901001, // ldr r1, [r0, #1]
+            0xe1d020b1, // ldrh r2, [r0, #1]
+            0xe1d030f5, // ldrsh r3, [r0, #5]
+            0xe28f4001, // add r4, pc, #1
+            0xe12fff14, // bx r4
+            0x58452101, // movs r1, #1 ; ldr r5, [r0, r1]
+            0x21055a46, // ldrh r6, [r0, r1] ; movs r1, #5
+            0xe7fe5e47, // ldrsh r7, [r0, r1] ; b .
+        ]);
+        let data = [0x44, 0x33, 0x22, 0x11, 0x66, 0x85, 0x77, 0x88];
+        for (i, byte) in data.iter().enumerate() {
+            cpu.bus.write_8(0x2000 + i as u32, *byte);
+        }
+        cpu.cpsr.set_C(true);
+        for _ in 0..11 {
+            cpu.step();
+        }
+        assert_eq!(cpu.get_next_pc(), 0x22);
+
+        // the aligned word is rotated so the addressed byte is the lowest one
+        assert_eq!(cpu.gpr[1], 0x4411_2233);
+        assert_eq!(cpu.gpr[5], 0x4411_2233);
+        // the aligned halfword is rotated by 8 bits
+        assert_eq!(cpu.gpr[2], 0x4400_0033);
+        assert_eq!(cpu.gpr[6], 0x4400_0033);
+        // the addressed byte is sign extended
+        assert_eq!(cpu.gpr[3], 0xffff_ff85);
+        assert_eq!(cpu.gpr[7], 0xffff_ff85);
+        // loads don't touch the carry flag
+        assert!(cpu.cpsr.C());
+    }
+}
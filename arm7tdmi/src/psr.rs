@@ -1,4 +1,5 @@
 /// The program status register
+#[cfg(feature = "std")]
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
@@ -8,6 +9,7 @@ use num::FromPrimitive;
 
 use super::{CpuMode, CpuState};
 
+#[cfg(feature = "std")]
 use colored::*;
 
 impl From<CpuState> for bool {
@@ -135,6 +137,7 @@ impl RegPSR {
     }
 }
 
+#[cfg(feature = "std")]
 impl fmt::Display for RegPSR {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let disabled_string = |disabled: bool| -> ColoredString {
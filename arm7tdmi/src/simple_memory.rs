@@ -1,5 +1,8 @@
+use alloc::{boxed::Box, vec};
+
+#[cfg(feature = "std")]
 use crate::gdb::{copy_range_to_buf, target::MemoryGdbInterface};
-use crate::memory::{Addr, BusIO, DebugRead, MemoryAccess, MemoryInterface};
+use crate::memory::{Addr, BusIO, DebugRead, DebugWrite, MemoryAccess, MemoryInterface};
 
 /// Simple wrapper around a bytearray for memory access
 /// For use by tests and examples of this crate.
@@ -67,6 +70,15 @@ impl DebugRead for SimpleMemory {
     }
 }
 
+impl DebugWrite for SimpleMemory {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        if let Some(byte) = self.data.get_mut(addr as usize) {
+            *byte = value;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl MemoryGdbInterface for SimpleMemory {
     fn memory_map_xml(&self, offset: u64, length: usize, buf: &mut [u8]) -> usize {
         let memory_map = format!(
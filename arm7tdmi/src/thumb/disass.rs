@@ -1,9 +1,10 @@
-use std::fmt;
+use alloc::{format, string::String};
+use core::fmt;
 
-use crate::bit::BitIndex;
+use bit::BitIndex;
 
 use super::*;
-use crate::arm7tdmi::*;
+use crate::reg_string;
 
 use super::ThumbDecodeHelper;
 
@@ -54,7 +55,7 @@ impl ThumbInstruction {
     fn fmt_thumb_high_reg_op_or_bx(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let op = self.raw.format5_op();
         let dst_reg = if self.raw.flag(consts::flags::FLAG_H1) {
-            self.raw & 0b111 + 8
+            (self.raw & 0b111) + 8
         } else {
             self.raw & 0b111
         };
@@ -82,7 +83,7 @@ impl ThumbInstruction {
             "ldr\t{Rd}, [pc, #{Imm:#x}] ; = #{effective:#x}",
             Rd = reg_string(self.raw.bit_range(8..11)),
             Imm = self.raw.word8(),
-            effective = (self.pc + 4 & !0b10) + (self.raw.word8() as Addr)
+            effective = ((self.pc + 4) & !0b10) + (self.raw.word8() as Addr)
         )
     }
 
@@ -275,7 +276,6 @@ impl ThumbInstruction {
     }
 }
 
-#[cfg(feature = "debugger")]
 impl fmt::Display for ThumbInstruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.fmt {
@@ -6,6 +6,7 @@ use crate::{
 };
 
 use bit::BitIndex;
+use log::warn;
 
 use super::ThumbDecodeHelper;
 use super::*;
@@ -28,7 +29,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             1 => BarrelShiftOpCode::LSR,
             2 => BarrelShiftOpCode::ASR,
             3 => BarrelShiftOpCode::ROR,
-            _ => unsafe { std::hint::unreachable_unchecked() },
+            _ => unsafe { core::hint::unreachable_unchecked() },
         };
         let op2 = self.barrel_shift_op(bsop, self.gpr[rs], shift_amount, &mut carry, true);
         self.gpr[rd] = op2;
@@ -129,7 +130,7 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
             CMN => self.alu_add_flags(dst, src, &mut carry, &mut overflow),
             ORR => dst | src,
             MUL => {
-                let m = self.get_required_multipiler_array_cycles(src);
+                let m = self.get_required_multipiler_array_cycles(src, true);
                 for _ in 0..m {
                     self.idle_cycle();
                 }
@@ -398,42 +399,52 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
     ) -> CpuAction {
         macro_rules! push {
             ($r:expr, $access:ident) => {
-                self.gpr[REG_SP] -= 4;
+                self.gpr[REG_SP] = self.gpr[REG_SP].wrapping_sub(4);
                 let stack_addr = self.gpr[REG_SP] & !3;
                 self.store_32(stack_addr, self.get_reg($r), $access);
                 $access = Seq;
             };
         }
         macro_rules! pop {
-            ($r:expr) => {
-                let val = self.load_32(self.gpr[REG_SP] & !3, Seq);
-                self.set_reg($r, val);
-                self.gpr[REG_SP] += 4;
-            };
             ($r:expr, $access:ident) => {
                 let val = self.load_32(self.gpr[REG_SP] & !3, $access);
                 $access = Seq;
                 self.set_reg($r, val);
-                self.gpr[REG_SP] += 4;
+                self.gpr[REG_SP] = self.gpr[REG_SP].wrapping_add(4);
             };
         }
         let mut result = CpuAction::AdvancePC(NonSeq);
         let rlist = insn.register_list();
         let mut access = MemoryAccess::NonSeq;
-        if POP {
+        if rlist == 0 && !FLAG_R {
+            // Same as LDM/STM: an empty Rlist transfers R15 (ARMv4 only), and SP is adjusted by 40h.
+            let sp = self.gpr[REG_SP];
+            if POP {
+                let val = self.load_32(sp & !3, NonSeq);
+                self.gpr[REG_SP] = sp.wrapping_add(0x40);
+                self.idle_cycle();
+                self.pc = val & !1;
+                result = CpuAction::PipelineFlushed;
+                self.reload_pipeline16();
+            } else {
+                self.gpr[REG_SP] = sp.wrapping_sub(0x40);
+                self.store_32(self.gpr[REG_SP] & !3, self.pc + 2, NonSeq);
+            }
+        } else if POP {
             for r in 0..8 {
                 if rlist.bit(r) {
                     pop!(r, access);
                 }
             }
             if FLAG_R {
-                pop!(REG_PC);
-                self.pc &= !1;
-                result = CpuAction::PipelineFlushed;
-                self.reload_pipeline16();
+                pop!(REG_PC, access);
             }
             // Idle 1 cycle
             self.idle_cycle();
+            if FLAG_R {
+                result = CpuAction::PipelineFlushed;
+                self.reload_pipeline16();
+            }
         } else {
             if FLAG_R {
                 push!(REG_LR, access);
@@ -568,11 +579,79 @@ impl<I: MemoryInterface> Arm7tdmiCore<I> {
         }
     }
 
+    /// Encodings that are undefined on ARMv4T, such as the ARMv5 BLX, take the undefined instruction exception
     pub fn thumb_undefined(&mut self, insn: u16) -> CpuAction {
-        panic!(
-            "executing undefind thumb instruction {:04x} at @{:08x}",
+        warn!(
+            "executing undefined thumb instruction {:04x} at @{:08x}",
             insn,
             self.pc_thumb()
-        )
+        );
+        self.exception(Exception::UndefinedInstruction, self.pc - 2); // implies pipeline reload
+        CpuAction::PipelineFlushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::tests::make_cpu;
+    use crate::memory::BusIO;
+    use crate::registers_consts::{REG_LR, REG_SP};
+    use crate::{CpuMode, CpuState};
+
+    #[test]
+    fn test_push_pop() {
+        let mut cpu = make_cpu(&[
+            0xe28f0001, // add r0, pc, #1
+            0xe12fff10, // bx r0
+            0x2000b501, // push {r0, lr} ; movs r0, #0
+            0xb400bd01, // pop {r0, pc} ; push {}
+            0x0000bc00, // pop {}
+        ]);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.cpsr.state(), CpuState::THUMB);
+
+        let sp = 0x3000;
+        cpu.set_reg(REG_SP, sp);
+        // return to the "push {}"
+        cpu.set_reg(REG_LR, 0x0f);
+        cpu.step();
+        assert_eq!(cpu.get_reg(REG_SP), sp - 8);
+        assert_eq!(cpu.bus.read_32(sp - 8), 0x09);
+        assert_eq!(cpu.bus.read_32(sp - 4), 0x0f);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.get_reg(0), 0x09);
+        assert_eq!(cpu.get_reg(REG_SP), sp);
+        assert_eq!(cpu.get_next_pc(), 0x0e);
+
+        // an empty list pushes PC+6 and moves SP by 40h
+        cpu.step();
+        assert_eq!(cpu.get_reg(REG_SP), sp - 0x40);
+        assert_eq!(cpu.bus.read_32(sp - 0x40), 0x14);
+        // and pops PC
+        cpu.step();
+        assert_eq!(cpu.get_reg(REG_SP), sp);
+        assert_eq!(cpu.get_next_pc(), 0x14);
+    }
+
+    #[test]
+    fn test_undefined_instruction() {
+        let mut cpu = make_cpu(&[
+            0xe28f0001, // add r0, pc, #1
+            0xe12fff10, // bx r0
+            0x0000e800, // ARMv5 BLX suffix, undefined on the ARM7TDMI
+        ]);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.cpsr.state(), CpuState::THUMB);
+        assert_eq!(cpu.pc_thumb(), 0x08);
+
+        cpu.step();
+        assert_eq!(cpu.cpsr.mode(), CpuMode::Undefined);
+        assert_eq!(cpu.cpsr.state(), CpuState::ARM);
+        assert_eq!(cpu.pc_arm(), 0x04);
+        assert_eq!(cpu.get_reg(14), 0x0a);
     }
 }
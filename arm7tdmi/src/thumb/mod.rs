@@ -3,10 +3,9 @@ use super::arm::*;
 use super::memory::Addr;
 use super::InstructionDecoder;
 use bit::BitIndex;
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{ByteOrder, LittleEndian};
 use num::FromPrimitive;
 
-#[cfg(feature = "debugger")]
 pub mod disass;
 pub mod exec;
 
@@ -55,6 +54,8 @@ pub enum ThumbFormat {
     Undefined,
 }
 
+/// Used for disassembly only, the cpu dispatches through `THUMB_LUT` (generated by build.rs)
+/// which is indexed by the top 10 bits of the opcode.
 impl From<u16> for ThumbFormat {
     fn from(raw: u16) -> ThumbFormat {
         use ThumbFormat::*;
@@ -124,8 +125,7 @@ impl InstructionDecoder for ThumbInstruction {
     }
 
     fn decode_from_bytes(bytes: &[u8], addr: Addr) -> Self {
-        let mut rdr = std::io::Cursor::new(bytes);
-        let raw = rdr.read_u16::<LittleEndian>().unwrap();
+        let raw = LittleEndian::read_u16(bytes);
         Self::decode(raw, addr)
     }
 
@@ -0,0 +1,19 @@
+//! Instruction tracing, enabled with the `trace` feature.
+use super::{Addr, CpuState};
+
+/// The CPU state right before an instruction is executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuTraceEvent {
+    /// Address of the instruction
+    pub pc: Addr,
+    /// The raw opcode, THUMB opcodes are zero extended
+    pub opcode: u32,
+    pub state: CpuState,
+    /// r0-r14 of the current mode
+    pub gpr: [u32; 15],
+}
+
+/// Receives a `CpuTraceEvent` for every instruction the CPU executes
+pub trait TraceSink {
+    fn on_instruction(&mut self, event: &CpuTraceEvent);
+}
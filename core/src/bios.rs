@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use super::SysBus;
 use arm7tdmi::{
     memory::{Addr, BusIO, DebugRead},
@@ -6,6 +8,11 @@ use arm7tdmi::{
 
 use rustboyadvance_utils::WeakPointer;
 
+pub mod hle;
+
+/// Callback invoked when a read is blocked by the bios protection, called with the (address, pc)
+pub type BiosProtectionCallback = Rc<dyn Fn(Addr, Addr)>;
+
 /// Struct representing the sytem ROM
 #[derive(Clone)]
 pub struct Bios {
@@ -15,14 +22,23 @@ pub struct Bios {
     last_opcode: u32,
     /// Arm pointer - used only to read the PC register
     arm_core: WeakPointer<Arm7tdmiCore<SysBus>>,
+    /// Number of reads blocked by the read protection
+    protected_reads: usize,
+    protection_callback: Option<BiosProtectionCallback>,
+    /// Software interrupts are handled by `hle` instead of the bios code
+    hle: bool,
 }
 
 impl Bios {
     pub fn new(bios_rom: Box<[u8]>) -> Bios {
+        let hle = hle::is_hle_bios(&bios_rom);
         Bios {
             rom: bios_rom,
             last_opcode: 0xe129f000, // the opcode at [00DCh+8]
             arm_core: WeakPointer::default(),
+            protected_reads: 0,
+            protection_callback: None,
+            hle,
         }
     }
 
@@ -35,6 +51,32 @@ impl Bios {
         self.arm_core.pc < 0x4000
     }
 
+    /// Called when the bios is read from outside of the bios region
+    #[cold]
+    fn on_protected_read(&mut self, addr: Addr) {
+        self.protected_reads += 1;
+        if let Some(callback) = &self.protection_callback {
+            callback(addr, self.arm_core.pc);
+        }
+    }
+
+    pub(crate) fn protected_reads(&self) -> usize {
+        self.protected_reads
+    }
+
+    pub(crate) fn reset_protected_reads(&mut self) {
+        self.protected_reads = 0;
+    }
+
+    pub(crate) fn set_protection_callback(&mut self, callback: Option<BiosProtectionCallback>) {
+        self.protection_callback = callback;
+    }
+
+    #[inline]
+    pub(crate) fn is_hle(&self) -> bool {
+        self.hle
+    }
+
     #[inline]
     pub(crate) fn len(&self) -> usize {
         self.rom.len()
@@ -51,6 +93,7 @@ impl BusIO for Bios {
             self.last_opcode = value;
             value
         } else {
+            self.on_protected_read(addr);
             self.last_opcode
         }
     }
@@ -59,6 +102,7 @@ impl BusIO for Bios {
         if self.read_allowed() {
             self.rom.read_16(addr) as u16
         } else {
+            self.on_protected_read(addr);
             (self.last_opcode >> ((addr & 2) << 3)) as u16
         }
     }
@@ -68,6 +112,7 @@ impl BusIO for Bios {
         if self.read_allowed() {
             self.rom.read_8(addr)
         } else {
+            self.on_protected_read(addr);
             (self.last_opcode >> ((addr & 3) << 3)) as u8
         }
     }
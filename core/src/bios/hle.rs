@@ -0,0 +1,475 @@
+//! High level emulation of the bios software interrupts, for running games without a bios dump.
+//!
+//! The HLE bios image only holds the IRQ vector and handler, the software interrupts are intercepted
+//! when the cpu jumps to the SWI vector and are implemented here.
+
+use std::f32::consts::PI;
+
+use arm7tdmi::memory::{Addr, BusIO};
+use arm7tdmi::{Arm7tdmiCore, CpuMode, CpuState};
+
+use crate::iodev::consts::{REG_IME, REG_SOUNDBIAS};
+use crate::iodev::HaltState;
+use crate::sysbus::SysBus;
+
+const BIOS_SIZE: usize = 0x4000;
+
+pub(crate) const SWI_VECTOR: Addr = 0x08;
+
+/// The bios keeps the interrupt flags for IntrWait here, IRQ handlers acknowledge interrupts by setting them
+const BIOS_IRQ_FLAGS: Addr = 0x0300_7ff8;
+
+/// Build a bios image for use with HLE
+pub fn hle_bios_rom() -> Box<[u8]> {
+    const CODE: &[(Addr, u32)] = &[
+        // SWI vector, only reached if the SWI isn't intercepted
+        (0x08, 0xe1b0_f00e), // movs pc, lr
+        // IRQ vector
+        (0x18, 0xea00_0042), // b 0x128
+        // IRQ handler, calls the user handler at [0x03007FFC]
+        (0x128, 0xe92d_500f), // stmfd sp!, {r0-r3, r12, lr}
+        (0x12c, 0xe3a0_0301), // mov r0, #0x04000000
+        (0x130, 0xe28f_e000), // add lr, pc, #0
+        (0x134, 0xe510_f004), // ldr pc, [r0, #-4]
+        (0x138, 0xe8bd_500f), // ldmfd sp!, {r0-r3, r12, lr}
+        (0x13c, 0xe25e_f004), // subs pc, lr, #4
+    ];
+    let mut rom = vec![0; BIOS_SIZE].into_boxed_slice();
+    for (addr, insn) in CODE {
+        let addr = *addr as usize;
+        rom[addr..addr + 4].copy_from_slice(&insn.to_le_bytes());
+    }
+    rom
+}
+
+pub(crate) fn is_hle_bios(rom: &[u8]) -> bool {
+    *rom == *hle_bios_rom()
+}
+
+/// Handle a software interrupt, called when the cpu is about to execute the SWI vector
+pub(crate) fn handle_swi(cpu: &mut Arm7tdmiCore<SysBus>, bus: &mut SysBus) {
+    debug_assert_eq!(cpu.cpsr.mode(), CpuMode::Supervisor);
+    let return_addr = cpu.gpr[14];
+    let (comment, insn_size) = match cpu.spsr.state() {
+        CpuState::ARM => ((bus.read_32(return_addr.wrapping_sub(4)) >> 16) & 0xff, 4),
+        CpuState::THUMB => ((bus.read_16(return_addr.wrapping_sub(2)) & 0xff) as u32, 2),
+    };
+
+    let r0 = cpu.gpr[0];
+    let r1 = cpu.gpr[1];
+    let r2 = cpu.gpr[2];
+    let r3 = cpu.gpr[3];
+
+    let mut return_addr = return_addr;
+    match comment {
+        0x02 => bus.io.haltcnt = HaltState::Halt,
+        0x03 => bus.io.haltcnt = HaltState::Stop,
+        0x04 | 0x05 => {
+            // VBlankIntrWait is IntrWait with r0 = 1 and r1 = 1
+            let (discard_old_flags, mask) = if comment == 0x05 {
+                cpu.gpr[0] = 1;
+                cpu.gpr[1] = 1;
+                (true, 1)
+            } else {
+                (r0 != 0, r1)
+            };
+            // only the first execution of the SWI discards the flags, not the ones after an interrupt
+            let discard_old_flags = discard_old_flags && !bus.io.hle_intr_wait;
+            if intr_wait(bus, discard_old_flags, mask as u16) {
+                bus.io.hle_intr_wait = false;
+            } else {
+                // wait for an interrupt and then execute this SWI again
+                bus.io.haltcnt = HaltState::Halt;
+                bus.io.hle_intr_wait = true;
+                return_addr = return_addr.wrapping_sub(insn_size);
+            }
+        }
+        0x06 => div(cpu, r0 as i32, r1 as i32),
+        0x07 => div(cpu, r1 as i32, r0 as i32),
+        0x08 => cpu.gpr[0] = sqrt(r0),
+        0x09 => cpu.gpr[0] = arctan(r0 as i32) as i32 as u32,
+        0x0a => cpu.gpr[0] = arctan2(r0 as i32, r1 as i32) as u32,
+        0x0b => cpu_set(bus, r0, r1, r2),
+        0x0c => cpu_fast_set(bus, r0, r1, r2),
+        0x0d => cpu.gpr[0] = 0xbaae_187f,
+        0x0e => bg_affine_set(bus, r0, r1, r2),
+        0x0f => obj_affine_set(bus, r0, r1, r2, r3),
+        0x10 => bit_unpack(bus, r0, r1, r2),
+        0x11 => {
+            let data = lz77_uncomp(bus, r0);
+            write_8bit(bus, r1, &data);
+        }
+        0x12 => {
+            let data = lz77_uncomp(bus, r0);
+            write_16bit(bus, r1, &data);
+        }
+        0x13 => huff_uncomp(bus, r0, r1),
+        0x14 => {
+            let data = rl_uncomp(bus, r0);
+            write_8bit(bus, r1, &data);
+        }
+        0x15 => {
+            let data = rl_uncomp(bus, r0);
+            write_16bit(bus, r1, &data);
+        }
+        0x19 => sound_bias(bus, r0),
+        _ => warn!("HLE: unimplemented SWI 0x{:02x}", comment),
+    }
+
+    cpu.exception_return(return_addr);
+}
+
+/// Returns true if one of the interrupts in `mask` was acknowledged by the IRQ handler
+fn intr_wait(bus: &mut SysBus, discard_old_flags: bool, mask: u16) -> bool {
+    bus.write_16(REG_IME, 1);
+    let flags = bus.read_16(BIOS_IRQ_FLAGS);
+    if discard_old_flags {
+        bus.write_16(BIOS_IRQ_FLAGS, flags & !mask);
+        false
+    } else if flags & mask != 0 {
+        bus.write_16(BIOS_IRQ_FLAGS, flags & !mask);
+        true
+    } else {
+        false
+    }
+}
+
+fn div(cpu: &mut Arm7tdmiCore<SysBus>, num: i32, denom: i32) {
+    let (quot, rem) = if denom == 0 {
+        // the real bios hangs here
+        warn!("HLE: division of {} by zero", num);
+        (if num < 0 { -1 } else { 1 }, num)
+    } else {
+        (num.wrapping_div(denom), num.wrapping_rem(denom))
+    };
+    cpu.gpr[0] = quot as u32;
+    cpu.gpr[1] = rem as u32;
+    cpu.gpr[3] = quot.wrapping_abs() as u32;
+}
+
+fn sqrt(value: u32) -> u32 {
+    let value = value as u64;
+    let mut root = (value as f64).sqrt() as u64;
+    // fix up the floating point rounding
+    while root * root > value {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    root as u32
+}
+
+/// `tan` is in 1.14 fixed point, the result is in the range -PI/2..PI/2 mapped to -0x4000..0x4000
+fn arctan(tan: i32) -> i16 {
+    let a = -(tan.wrapping_mul(tan) >> 14);
+    let mut b = (0xa9i32.wrapping_mul(a) >> 14) + 0x390;
+    for c in [0x91c, 0xfb6, 0x16aa, 0x2081, 0x3651, 0xa2f9] {
+        b = (b.wrapping_mul(a) >> 14) + c;
+    }
+    (tan.wrapping_mul(b) >> 16) as i16
+}
+
+/// Returns the angle of (x, y) in the range 0..2PI mapped to 0..0x10000
+fn arctan2(x: i32, y: i32) -> u16 {
+    let arctan = |i: i32| arctan(i) as i32;
+    let angle = if y == 0 {
+        if x >= 0 {
+            0
+        } else {
+            0x8000
+        }
+    } else if x == 0 {
+        if y >= 0 {
+            0x4000
+        } else {
+            0xc000
+        }
+    } else if y >= 0 {
+        if x >= 0 && x >= y {
+            arctan(y.wrapping_shl(14).wrapping_div(x))
+        } else if x < 0 && x.wrapping_neg() >= y {
+            arctan(y.wrapping_shl(14).wrapping_div(x)) + 0x8000
+        } else {
+            0x4000 - arctan(x.wrapping_shl(14).wrapping_div(y))
+        }
+    } else if x <= 0 && x.wrapping_neg() > y.wrapping_neg() {
+        arctan(y.wrapping_shl(14).wrapping_div(x)) + 0x8000
+    } else if x > 0 && x >= y.wrapping_neg() {
+        arctan(y.wrapping_shl(14).wrapping_div(x)) + 0x10000
+    } else {
+        0xc000 - arctan(x.wrapping_shl(14).wrapping_div(y))
+    };
+    angle as u16
+}
+
+fn cpu_set(bus: &mut SysBus, src: Addr, dst: Addr, control: u32) {
+    let count = control & 0x1f_ffff;
+    let fill = control & (1 << 24) != 0;
+    if control & (1 << 26) != 0 {
+        let (src, dst) = (src & !3, dst & !3);
+        let value = bus.read_32(src);
+        for i in 0..count {
+            let value = if fill {
+                value
+            } else {
+                bus.read_32(src.wrapping_add(i * 4))
+            };
+            bus.write_32(dst.wrapping_add(i * 4), value);
+        }
+    } else {
+        let (src, dst) = (src & !1, dst & !1);
+        let value = bus.read_16(src);
+        for i in 0..count {
+            let value = if fill {
+                value
+            } else {
+                bus.read_16(src.wrapping_add(i * 2))
+            };
+            bus.write_16(dst.wrapping_add(i * 2), value);
+        }
+    }
+}
+
+fn cpu_fast_set(bus: &mut SysBus, src: Addr, dst: Addr, control: u32) {
+    // the count is rounded up to a multiple of 8 words
+    let count = (control & 0x1f_ffff).wrapping_add(7) & !7;
+    let fill = control & (1 << 24) != 0;
+    let (src, dst) = (src & !3, dst & !3);
+    let value = bus.read_32(src);
+    for i in 0..count {
+        let value = if fill {
+            value
+        } else {
+            bus.read_32(src.wrapping_add(i * 4))
+        };
+        bus.write_32(dst.wrapping_add(i * 4), value);
+    }
+}
+
+/// Rotation and scaling parameters, from 8.8 fixed point scale factors and an angle where 0x100 is a full circle
+fn affine_params(sx: i16, sy: i16, angle: u16) -> (f32, f32, f32, f32) {
+    let theta = (angle >> 8) as f32 / 128.0 * PI;
+    let (sin, cos) = theta.sin_cos();
+    let sx = sx as f32 / 256.0;
+    let sy = sy as f32 / 256.0;
+    (cos * sx, -sin * sx, sin * sy, cos * sy)
+}
+
+fn bg_affine_set(bus: &mut SysBus, src: Addr, dst: Addr, count: u32) {
+    for i in 0..count {
+        let src = src.wrapping_add(i.wrapping_mul(20));
+        let dst = dst.wrapping_add(i.wrapping_mul(16));
+        let ox = bus.read_32(src) as i32 as f32 / 256.0;
+        let oy = bus.read_32(src.wrapping_add(4)) as i32 as f32 / 256.0;
+        let cx = bus.read_16(src.wrapping_add(8)) as i16 as f32;
+        let cy = bus.read_16(src.wrapping_add(10)) as i16 as f32;
+        let sx = bus.read_16(src.wrapping_add(12)) as i16;
+        let sy = bus.read_16(src.wrapping_add(14)) as i16;
+        let angle = bus.read_16(src.wrapping_add(16));
+
+        let (pa, pb, pc, pd) = affine_params(sx, sy, angle);
+        let x = ox - (pa * cx + pb * cy);
+        let y = oy - (pc * cx + pd * cy);
+        bus.write_16(dst, (pa * 256.0) as i32 as u16);
+        bus.write_16(dst.wrapping_add(2), (pb * 256.0) as i32 as u16);
+        bus.write_16(dst.wrapping_add(4), (pc * 256.0) as i32 as u16);
+        bus.write_16(dst.wrapping_add(6), (pd * 256.0) as i32 as u16);
+        bus.write_32(dst.wrapping_add(8), (x * 256.0) as i32 as u32);
+        bus.write_32(dst.wrapping_add(12), (y * 256.0) as i32 as u32);
+    }
+}
+
+fn obj_affine_set(bus: &mut SysBus, src: Addr, dst: Addr, count: u32, stride: u32) {
+    for i in 0..count {
+        let src = src.wrapping_add(i.wrapping_mul(8));
+        let dst = dst.wrapping_add(i.wrapping_mul(stride).wrapping_mul(4));
+        let sx = bus.read_16(src) as i16;
+        let sy = bus.read_16(src.wrapping_add(2)) as i16;
+        let angle = bus.read_16(src.wrapping_add(4));
+
+        let (pa, pb, pc, pd) = affine_params(sx, sy, angle);
+        for (j, param) in [pa, pb, pc, pd].iter().enumerate() {
+            let addr = dst.wrapping_add((j as u32).wrapping_mul(stride));
+            bus.write_16(addr, (param * 256.0) as i32 as u16);
+        }
+    }
+}
+
+fn bit_unpack(bus: &mut SysBus, src: Addr, dst: Addr, info: Addr) {
+    let src_len = bus.read_16(info) as u32;
+    let src_width = bus.read_8(info.wrapping_add(2)) as u32;
+    let dst_width = bus.read_8(info.wrapping_add(3)) as u32;
+    let offset = bus.read_32(info.wrapping_add(4));
+    let offset_zero = offset & (1 << 31) != 0;
+    let offset = offset & !(1 << 31);
+    if !matches!(src_width, 1 | 2 | 4 | 8) || !matches!(dst_width, 1 | 2 | 4 | 8 | 16 | 32) {
+        warn!(
+            "HLE: BitUnPack with invalid widths {} -> {}",
+            src_width, dst_width
+        );
+        return;
+    }
+    let src_mask = (1 << src_width) - 1;
+    let dst_mask = if dst_width == 32 {
+        !0
+    } else {
+        (1 << dst_width) - 1
+    };
+
+    let mut dst = dst & !3;
+    let mut out = 0u32;
+    let mut out_bits = 0;
+    for i in 0..src_len {
+        let byte = bus.read_8(src.wrapping_add(i)) as u32;
+        for shift in (0..8).step_by(src_width as usize) {
+            let mut value = (byte >> shift) & src_mask;
+            if value != 0 || offset_zero {
+                value = value.wrapping_add(offset);
+            }
+            out |= (value & dst_mask) << out_bits;
+            out_bits += dst_width;
+            if out_bits == 32 {
+                bus.write_32(dst, out);
+                dst = dst.wrapping_add(4);
+                out = 0;
+                out_bits = 0;
+            }
+        }
+    }
+}
+
+/// Size of the decompressed data from the header of compressed data
+fn uncompressed_size(bus: &mut SysBus, src: Addr) -> usize {
+    (bus.read_32(src) >> 8) as usize
+}
+
+fn lz77_uncomp(bus: &mut SysBus, src: Addr) -> Vec<u8> {
+    let size = uncompressed_size(bus, src);
+    let mut data = Vec::with_capacity(size);
+    let mut src = src.wrapping_add(4);
+    while data.len() < size {
+        let flags = bus.read_8(src);
+        src = src.wrapping_add(1);
+        for block in 0..8 {
+            if data.len() >= size {
+                break;
+            }
+            if flags & (0x80 >> block) != 0 {
+                let b0 = bus.read_8(src) as usize;
+                let b1 = bus.read_8(src.wrapping_add(1)) as usize;
+                src = src.wrapping_add(2);
+                let len = (b0 >> 4) + 3;
+                let disp = ((b0 & 0xf) << 8 | b1) + 1;
+                for _ in 0..len {
+                    let value = data.len().checked_sub(disp).map_or(0, |i| data[i]);
+                    data.push(value);
+                }
+            } else {
+                data.push(bus.read_8(src));
+                src = src.wrapping_add(1);
+            }
+        }
+    }
+    data.truncate(size);
+    data
+}
+
+fn rl_uncomp(bus: &mut SysBus, src: Addr) -> Vec<u8> {
+    let size = uncompressed_size(bus, src);
+    let mut data = Vec::with_capacity(size);
+    let mut src = src.wrapping_add(4);
+    while data.len() < size {
+        let flag = bus.read_8(src);
+        src = src.wrapping_add(1);
+        if flag & 0x80 != 0 {
+            let len = (flag & 0x7f) as usize + 3;
+            let value = bus.read_8(src);
+            src = src.wrapping_add(1);
+            data.extend(std::iter::repeat(value).take(len));
+        } else {
+            let len = (flag & 0x7f) as u32 + 1;
+            for i in 0..len {
+                data.push(bus.read_8(src.wrapping_add(i)));
+            }
+            src = src.wrapping_add(len);
+        }
+    }
+    data.truncate(size);
+    data
+}
+
+fn huff_uncomp(bus: &mut SysBus, src: Addr, dst: Addr) {
+    let header = bus.read_32(src);
+    let bits = header & 0xf;
+    let size = (header >> 8) as usize;
+    if bits != 4 && bits != 8 {
+        warn!("HLE: HuffUnComp with unsupported data size {}", bits);
+        return;
+    }
+    let root = src.wrapping_add(5);
+    let tree_size = (bus.read_8(src.wrapping_add(4)) as u32 + 1) * 2;
+    let mut stream = src.wrapping_add(4).wrapping_add(tree_size);
+
+    let mut dst = dst & !3;
+    let mut written = 0;
+    let mut out = 0u32;
+    let mut out_bits = 0;
+    let mut node_addr = root;
+    let mut node = bus.read_8(node_addr);
+    while written < size {
+        let word = bus.read_32(stream);
+        stream = stream.wrapping_add(4);
+        for bit in (0..32).rev() {
+            let next = (node_addr & !1).wrapping_add(((node & 0x3f) as u32 + 1) * 2);
+            let (child_addr, is_leaf) = if word & (1 << bit) == 0 {
+                (next, node & 0x80 != 0)
+            } else {
+                (next.wrapping_add(1), node & 0x40 != 0)
+            };
+            if !is_leaf {
+                node_addr = child_addr;
+                node = bus.read_8(node_addr);
+                continue;
+            }
+
+            out |= (bus.read_8(child_addr) as u32) << out_bits;
+            out_bits += bits;
+            if out_bits == 32 {
+                bus.write_32(dst, out);
+                dst = dst.wrapping_add(4);
+                written += 4;
+                out = 0;
+                out_bits = 0;
+                if written >= size {
+                    break;
+                }
+            }
+            node_addr = root;
+            node = bus.read_8(node_addr);
+        }
+    }
+}
+
+/// Output of the WRAM variants of the decompression functions
+fn write_8bit(bus: &mut SysBus, dst: Addr, data: &[u8]) {
+    for (i, value) in data.iter().enumerate() {
+        bus.write_8(dst.wrapping_add(i as u32), *value);
+    }
+}
+
+/// Output of the VRAM variants of the decompression functions, VRAM can't be written 8 bits at a time
+fn write_16bit(bus: &mut SysBus, dst: Addr, data: &[u8]) {
+    let dst = dst & !1;
+    for (i, chunk) in data.chunks(2).enumerate() {
+        let value = chunk[0] as u16 | (*chunk.get(1).unwrap_or(&0) as u16) << 8;
+        bus.write_16(dst.wrapping_add(2 * i as u32), value);
+    }
+}
+
+fn sound_bias(bus: &mut SysBus, level: u32) {
+    // The real bios ramps the bias level gradually
+    let bias = bus.read_16(REG_SOUNDBIAS);
+    let level = if level != 0 { 0x200 } else { 0 };
+    bus.write_16(REG_SOUNDBIAS, (bias & !0x3ff) | level);
+}
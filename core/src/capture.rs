@@ -0,0 +1,61 @@
+use std::io;
+use std::path::Path;
+
+use super::gpu::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use super::GameBoyAdvance;
+
+/// Converts a frame buffer of 0x00RRGGBB pixels to packed 8bit RGB
+pub fn frame_to_rgb(frame: &[u32]) -> Vec<u8> {
+    frame
+        .iter()
+        .flat_map(|pixel| {
+            let [b, g, r, _] = pixel.to_le_bytes();
+            [r, g, b]
+        })
+        .collect()
+}
+
+impl GameBoyAdvance {
+    /// Save the current frame as a 240x160 RGB PNG.
+    /// The frame buffer already has the active `ColorCorrection` applied.
+    pub fn save_screenshot(&self, path: &Path) -> io::Result<()> {
+        let rgb = frame_to_rgb(self.get_frame_buffer());
+        image::save_buffer(
+            path,
+            &rgb,
+            DISPLAY_WIDTH as u32,
+            DISPLAY_HEIGHT as u32,
+            image::ColorType::Rgb8,
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::tests::make_mock_gba;
+
+    #[test]
+    fn test_save_screenshot() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.frame();
+
+        let path = std::env::temp_dir().join(format!(
+            "rustboyadvance_test_save_screenshot_{}.png",
+            std::process::id()
+        ));
+        gba.save_screenshot(&path).unwrap();
+        let dimensions = image::image_dimensions(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dimensions, (240, 160));
+    }
+
+    #[test]
+    fn test_frame_to_rgb() {
+        assert_eq!(
+            frame_to_rgb(&[0x00ff_8001, 0]),
+            vec![0xff, 0x80, 0x01, 0, 0, 0]
+        );
+    }
+}
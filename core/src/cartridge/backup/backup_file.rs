@@ -1,26 +1,28 @@
 use std::fmt;
-use std::fs::{File, OpenOptions};
-use std::io::prelude::*;
-use std::io::SeekFrom;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
+use super::storage::{DynSaveStorage, FileStorage};
 use super::BackupMemoryInterface;
-use rustboyadvance_utils::write_bin_file;
 
 #[derive(Debug)]
 pub struct BackupFile {
     size: usize,
-    path: Option<PathBuf>,
-    file: Option<File>,
+    storage: Option<DynSaveStorage>,
     buffer: Vec<u8>,
+    dirty: bool,
 }
 
+fn file_storage(path: Option<PathBuf>) -> Option<DynSaveStorage> {
+    path.map(|path| Box::new(FileStorage::new(path)) as DynSaveStorage)
+}
+
+/// Only the file backed storage survives a clone, other storages are left behind with the original
 impl Clone for BackupFile {
     fn clone(&self) -> Self {
-        BackupFile::new(self.size, self.path.clone())
+        BackupFile::new(self.size, file_storage(self.path().map(Path::to_path_buf)))
     }
 }
 
@@ -31,7 +33,7 @@ impl Serialize for BackupFile {
     {
         let mut state = serializer.serialize_struct("BackupFile", 2)?;
         state.serialize_field("size", &self.size)?;
-        state.serialize_field("path", &self.path)?;
+        state.serialize_field("path", &self.path())?;
         state.end()
     }
 }
@@ -60,7 +62,7 @@ impl<'de> Deserialize<'de> for BackupFile {
                 let path: Option<PathBuf> = seq
                     .next_element()?
                     .ok_or_else(|| de::Error::invalid_length(1, &self))?;
-                Ok(BackupFile::new(size, path))
+                Ok(BackupFile::new(size, file_storage(path)))
             }
         }
 
@@ -70,39 +72,32 @@ impl<'de> Deserialize<'de> for BackupFile {
 }
 
 impl BackupFile {
-    pub fn new(size: usize, path: Option<PathBuf>) -> BackupFile {
-        // TODO handle errors without unwrap
-        let mut file: Option<File> = None;
-        let buffer = if let Some(path) = &path {
-            if !path.is_file() {
-                write_bin_file(path, &vec![0xff; size]).unwrap();
+    pub fn new(size: usize, mut storage: Option<DynSaveStorage>) -> BackupFile {
+        let buffer = if let Some(storage) = &mut storage {
+            if let Some(mut buffer) = storage.load() {
+                buffer.resize(size, 0xff);
+                buffer
+            } else {
+                let buffer = vec![0xff; size];
+                storage.store(&buffer);
+                buffer
             }
-
-            let mut _file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(&path)
-                .unwrap();
-
-            let mut buffer = Vec::new();
-            _file.read_to_end(&mut buffer).unwrap();
-            buffer.resize(size, 0xff);
-
-            file = Some(_file);
-
-            buffer
         } else {
             vec![0xff; size]
         };
 
         BackupFile {
             size,
-            path,
-            file,
+            storage,
             buffer,
+            dirty: false,
         }
     }
 
+    pub fn path(&self) -> Option<&Path> {
+        self.storage.as_ref().and_then(|storage| storage.path())
+    }
+
     pub fn bytes(&self) -> &[u8] {
         &self.buffer
     }
@@ -111,20 +106,45 @@ impl BackupFile {
         &mut self.buffer
     }
 
+    /// Replace the whole buffer, `data` must be exactly as long as the buffer
+    pub fn load_bytes(&mut self, data: &[u8]) {
+        self.buffer.copy_from_slice(data);
+        self.flush();
+    }
+
     pub fn flush(&mut self) {
-        if let Some(file) = &mut self.file {
-            file.seek(SeekFrom::Start(0)).unwrap();
-            file.write_all(&self.buffer).unwrap();
+        if let Some(storage) = &mut self.storage {
+            storage.store(&self.buffer);
         }
+        self.dirty = false;
+    }
+
+    /// Hand the buffer to the storage if it has writes that weren't persisted yet
+    pub fn flush_dirty(&mut self) {
+        if self.dirty {
+            self.flush();
+        }
+    }
+
+    /// Detach the storage, flushing any pending writes to it first
+    pub fn take_storage(&mut self) -> Option<DynSaveStorage> {
+        self.flush_dirty();
+        self.storage.take()
+    }
+
+    /// Attach `storage` and reload the buffer from it
+    pub fn set_storage(&mut self, storage: DynSaveStorage) {
+        *self = BackupFile::new(self.size, Some(storage));
     }
 }
 
 impl BackupMemoryInterface for BackupFile {
     fn write(&mut self, offset: usize, value: u8) {
         self.buffer[offset] = value;
-        if let Some(file) = &mut self.file {
-            file.seek(SeekFrom::Start(offset as u64)).unwrap();
-            file.write_all(&[value]).unwrap();
+        if let Some(storage) = &mut self.storage {
+            if !storage.store_byte(offset, value) {
+                self.dirty = true;
+            }
         }
     }
 
@@ -1,12 +1,10 @@
-use super::{BackupFile, BackupMemoryInterface};
+use super::{BackupFile, BackupMemoryInterface, DynSaveStorage};
 
 use bytesize;
 use num::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
 use std::cell::RefCell;
-use std::fs;
-use std::path::PathBuf;
 
 #[derive(Debug, Clone, Copy)]
 pub enum EepromType {
@@ -284,13 +282,13 @@ pub struct EepromController {
 }
 
 impl EepromController {
-    pub fn new(path: Option<PathBuf>) -> EepromController {
+    pub fn new(mut storage: Option<DynSaveStorage>) -> EepromController {
         let mut detect = true;
         let mut eeprom_type = EepromType::Eeprom512;
-        if let Some(path) = &path {
-            if let Ok(metadata) = fs::metadata(&path) {
-                let human_size = bytesize::ByteSize::b(metadata.len());
-                let assumed_type = match metadata.len() {
+        if let Some(storage) = &mut storage {
+            if let Some(data) = storage.load() {
+                let human_size = bytesize::ByteSize::b(data.len() as u64);
+                let assumed_type = match data.len() {
                     512 => EepromType::Eeprom512,
                     8192 => EepromType::Eeprom8k,
                     _ => panic!("invalid file size ({}) for eeprom save", human_size),
@@ -304,20 +302,52 @@ impl EepromController {
             }
         }
 
-        let mut result = EepromController::new_with_type(path, eeprom_type);
+        let mut result = EepromController::new_with_type(storage, eeprom_type);
         result.detect = detect;
 
         result
     }
 
-    pub fn new_with_type(path: Option<PathBuf>, eeprom_type: EepromType) -> EepromController {
-        let memory = BackupFile::new(eeprom_type.size(), path);
+    pub fn new_with_type(
+        storage: Option<DynSaveStorage>,
+        eeprom_type: EepromType,
+    ) -> EepromController {
+        let memory = BackupFile::new(eeprom_type.size(), storage);
         EepromController {
             chip: RefCell::new(EepromChip::new(eeprom_type, memory)),
             detect: false,
         }
     }
 
+    pub(crate) fn memory_mut(&mut self) -> &mut BackupFile {
+        &mut self.chip.get_mut().memory
+    }
+
+    /// The raw contents of the eeprom
+    pub fn bytes(&self) -> Vec<u8> {
+        self.chip.borrow().memory.bytes().to_vec()
+    }
+
+    /// Replace the contents of the eeprom, if its size wasn't detected yet it is taken from the size of `data`.
+    /// @return false if `data` doesn't fit the eeprom
+    pub fn load_bytes(&mut self, data: &[u8]) -> bool {
+        let eeprom_type = match data.len() {
+            0x0200 => EepromType::Eeprom512,
+            0x2000 => EepromType::Eeprom8k,
+            _ => return false,
+        };
+        let chip = self.chip.get_mut();
+        if self.detect {
+            info!("eeprom type set to {:?} by imported save", eeprom_type);
+            chip.set_type(eeprom_type);
+            self.detect = false;
+        } else if chip.memory.bytes().len() != data.len() {
+            return false;
+        }
+        chip.memory.load_bytes(data);
+        true
+    }
+
     pub fn write_half(&mut self, address: u32, value: u16) {
         assert!(!self.detect);
         self.chip.borrow_mut().clock_data_in(address, value as u8);
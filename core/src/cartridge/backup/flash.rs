@@ -1,10 +1,8 @@
-use super::{BackupFile, BackupMemoryInterface};
+use super::{BackupFile, BackupMemoryInterface, DynSaveStorage};
 
 use num::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-use std::path::PathBuf;
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 enum FlashWriteSequence {
     Initial,
@@ -66,14 +64,14 @@ const SECTOR_SIZE: usize = 0x1000;
 const BANK_SIZE: usize = 0x10000;
 
 impl Flash {
-    pub fn new(flash_path: Option<PathBuf>, flash_size: FlashSize) -> Flash {
+    pub fn new(storage: Option<DynSaveStorage>, flash_size: FlashSize) -> Flash {
         let chip_id = match flash_size {
             FlashSize::Flash64k => MACRONIX_64K_CHIP_ID,
             FlashSize::Flash128k => MACRONIX_128K_CHIP_ID,
         };
 
         let size: usize = flash_size.into();
-        let memory = BackupFile::new(size, flash_path);
+        let memory = BackupFile::new(size, storage);
 
         Flash {
             chip_id,
@@ -85,6 +83,14 @@ impl Flash {
         }
     }
 
+    pub(crate) fn memory(&self) -> &BackupFile {
+        &self.memory
+    }
+
+    pub(crate) fn memory_mut(&mut self) -> &mut BackupFile {
+        &mut self.memory
+    }
+
     fn reset_sequence(&mut self) {
         self.wrseq = FlashWriteSequence::Initial;
     }
@@ -5,6 +5,8 @@ mod backup_file;
 pub use backup_file::BackupFile;
 pub mod eeprom;
 pub mod flash;
+mod storage;
+pub use storage::{DynSaveStorage, FileStorage, SaveStorage};
 
 #[derive(Debug, Primitive, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
 pub enum BackupType {
@@ -16,6 +18,18 @@ pub enum BackupType {
     AutoDetect = 5,
 }
 
+/// Where the loader took the backup type of a cartridge from
+#[derive(SmartDefault, Debug, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+pub enum DetectionSource {
+    /// Found an id string (such as `FLASH1M_V`) left in the ROM by Nintendo's backup library
+    #[default]
+    StringScan,
+    /// The game code is listed in `overrides.yaml`
+    OverrideTable,
+    /// Explicitly requested with `GamepakBuilder::save_type` and friends
+    BuilderForced,
+}
+
 impl FromStr for BackupType {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
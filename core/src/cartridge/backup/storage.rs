@@ -0,0 +1,83 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+/// Where the contents of the backup memory are persisted, e.g a `.sav` file or the browser's local storage.
+pub trait SaveStorage: fmt::Debug {
+    /// The previously stored contents, or None if nothing was stored yet
+    fn load(&mut self) -> Option<Vec<u8>>;
+
+    /// Persist the whole contents of the backup memory
+    fn store(&mut self, data: &[u8]);
+
+    /// Persist a single byte written to the backup memory.
+    /// Storages that can't write in place return false, the backup is then marked dirty and handed to `store` on the next flush.
+    fn store_byte(&mut self, _offset: usize, _value: u8) -> bool {
+        false
+    }
+
+    /// The file backing the storage, savestates keep it so that loading a state reopens the file
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+pub type DynSaveStorage = Box<dyn SaveStorage>;
+
+/// The default storage, writes go straight through to a `.sav` file
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> FileStorage {
+        FileStorage { path, file: None }
+    }
+
+    fn file(&mut self) -> &mut File {
+        // TODO handle errors without unwrap
+        let path = &self.path;
+        self.file.get_or_insert_with(|| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(path)
+                .unwrap()
+        })
+    }
+}
+
+impl SaveStorage for FileStorage {
+    fn load(&mut self) -> Option<Vec<u8>> {
+        if !self.path.is_file() {
+            return None;
+        }
+        let file = self.file();
+        let mut buffer = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut buffer).unwrap();
+        Some(buffer)
+    }
+
+    fn store(&mut self, data: &[u8]) {
+        let file = self.file();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    fn store_byte(&mut self, offset: usize, value: u8) -> bool {
+        let file = self.file();
+        file.seek(SeekFrom::Start(offset as u64)).unwrap();
+        file.write_all(&[value]).unwrap();
+        true
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
@@ -1,15 +1,17 @@
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use memmem::{Searcher, TwoWaySearcher};
-use num::FromPrimitive;
 
 use super::super::overrides;
 use super::super::{GBAError, GBAResult};
 use super::backup::eeprom::*;
 use super::backup::flash::*;
-use super::backup::{BackupFile, BackupType};
+use super::backup::{BackupFile, BackupType, DetectionSource, DynSaveStorage, FileStorage};
 use super::gpio::Gpio;
 use super::header;
+use super::rom_source::{DynRomSource, PagedRom, ROM_PAGE_SIZE};
 use super::BackupMedia;
 use super::Cartridge;
 
@@ -32,6 +34,9 @@ pub struct GamepakBuilder {
     save_type: BackupType,
     gpio_device: GpioDeviceType,
     create_backup_file: bool,
+    initial_save: Option<Vec<u8>>,
+    save_storage: Option<DynSaveStorage>,
+    rom_source: Option<(DynRomSource, usize)>,
 }
 
 impl GamepakBuilder {
@@ -43,6 +48,9 @@ impl GamepakBuilder {
             bytes: None,
             gpio_device: GpioDeviceType::None,
             create_backup_file: true,
+            initial_save: None,
+            save_storage: None,
+            rom_source: None,
         }
     }
 
@@ -56,6 +64,14 @@ impl GamepakBuilder {
         self
     }
 
+    /// Read the ROM from `source` on demand instead of loading all of it to memory,
+    /// keeping at most `cache_pages` pages of `ROM_PAGE_SIZE` bytes in memory.
+    /// Reads through the page cache are slower, so this is only worth it on devices short of memory.
+    pub fn rom_source(mut self, source: DynRomSource, cache_pages: usize) -> Self {
+        self.rom_source = Some((source, cache_pages));
+        self
+    }
+
     pub fn file(mut self, path: &Path) -> Self {
         self.path = Some(path.to_path_buf());
         self
@@ -96,13 +112,31 @@ impl GamepakBuilder {
         self
     }
 
+    /// Fill the backup memory with the contents of a `.sav` file, e.g for frontends without a filesystem.
+    /// The size of `data` must match the backup type.
+    pub fn with_initial_save(mut self, data: &[u8]) -> Self {
+        self.initial_save = Some(data.to_vec());
+        self
+    }
+
+    /// Persist the backup memory through `storage` instead of a save file next to the ROM.
+    /// Storages that can't write in place are only written to by `Cartridge::flush_save`.
+    pub fn save_storage(mut self, storage: DynSaveStorage) -> Self {
+        self.save_storage = Some(storage);
+        self
+    }
+
     pub fn with_rtc(mut self) -> Self {
         self.gpio_device = GpioDeviceType::Rtc;
         self
     }
 
     pub fn build(mut self) -> GBAResult<Cartridge> {
-        let (bytes, symbols) = if let Some(bytes) = self.bytes {
+        let mut paged_rom = None;
+        let (bytes, symbols) = if let Some((source, cache_pages)) = self.rom_source.take() {
+            paged_rom = Some(PagedRom::new(source, cache_pages));
+            Ok((Vec::new(), None))
+        } else if let Some(bytes) = self.bytes {
             match load_from_bytes(bytes.to_vec())? {
                 #[cfg(feature = "elf_support")]
                 LoadRom::Elf { data, symbols } => Ok((data, Some(symbols))),
@@ -120,12 +154,18 @@ impl GamepakBuilder {
             ))
         }?;
 
-        let header = header::parse(&bytes)?;
+        let header = if let Some(rom) = &mut paged_rom {
+            let mut header_bytes = vec![0; rom.size().min(0xc0)];
+            rom.read(0, &mut header_bytes);
+            header::parse(&header_bytes)?
+        } else {
+            header::parse(&bytes)?
+        };
         info!("Loaded ROM: {:?}", header);
 
         if !self.create_backup_file {
             self.save_path = None;
-        } else if self.save_path.is_none() {
+        } else if self.save_path.is_none() && self.save_storage.is_none() {
             if let Some(path) = &self.path {
                 self.save_path = Some(path.with_extension(BACKUP_FILE_EXT));
             } else {
@@ -134,6 +174,7 @@ impl GamepakBuilder {
         }
 
         let mut save_type = self.save_type;
+        let mut detection_source = DetectionSource::BuilderForced;
         let mut gpio_device = self.gpio_device;
 
         if let Some(overrides) = overrides::get_game_overrides(&header.game_code) {
@@ -142,13 +183,15 @@ impl GamepakBuilder {
                 header.game_code, overrides
             );
             if let Some(override_save_type) = overrides.save_type() {
-                if override_save_type != save_type && save_type != BackupType::AutoDetect {
+                if save_type == BackupType::AutoDetect {
+                    save_type = override_save_type;
+                    detection_source = DetectionSource::OverrideTable;
+                } else if override_save_type != save_type {
                     warn!(
                         "Forced save type {:?} takes priority of {:?}",
                         save_type, override_save_type
                     );
                 }
-                save_type = override_save_type;
             }
 
             if overrides.force_rtc() {
@@ -166,7 +209,12 @@ impl GamepakBuilder {
         }
 
         if save_type == BackupType::AutoDetect {
-            if let Some(detected) = detect_backup_type(&bytes) {
+            detection_source = DetectionSource::StringScan;
+            let detected = match &mut paged_rom {
+                Some(rom) => detect_paged_backup_type(rom),
+                None => detect_backup_type(&bytes),
+            };
+            if let Some(detected) = detected {
                 info!("Detected Backup: {:?}", detected);
                 save_type = detected;
             } else {
@@ -174,7 +222,21 @@ impl GamepakBuilder {
             }
         }
 
-        let backup = create_backup(save_type, self.save_path);
+        let storage = self.save_storage.or_else(|| {
+            self.save_path.map(|path| {
+                Box::new(FileStorage::new(path.with_extension(BACKUP_FILE_EXT))) as DynSaveStorage
+            })
+        });
+        let mut backup = create_backup(save_type, storage);
+        if let Some(data) = &self.initial_save {
+            if !backup.load_bytes(data) {
+                return Err(GBAError::CartridgeLoadError(format!(
+                    "{} bytes of save data don't fit the {:?} backup memory",
+                    data.len(),
+                    save_type
+                )));
+            }
+        }
 
         let gpio = match gpio_device {
             GpioDeviceType::None => None,
@@ -185,45 +247,195 @@ impl GamepakBuilder {
             _ => unimplemented!("Gpio device {:?} not implemented", gpio_device),
         };
 
-        let size = bytes.len();
+        let size = paged_rom.as_ref().map_or(bytes.len(), |rom| rom.size());
         Ok(Cartridge {
             header,
             gpio,
             bytes: bytes.into_boxed_slice(),
             size,
+            paged_rom: paged_rom.map(|rom| Rc::new(RefCell::new(rom))),
             backup,
             symbols,
+            detection_source,
         })
     }
 }
 
 const BACKUP_FILE_EXT: &str = "sav";
-fn create_backup(backup_type: BackupType, rom_path: Option<PathBuf>) -> BackupMedia {
-    let backup_path = if let Some(rom_path) = rom_path {
-        Some(rom_path.with_extension(BACKUP_FILE_EXT))
-    } else {
-        None
-    };
+fn create_backup(backup_type: BackupType, storage: Option<DynSaveStorage>) -> BackupMedia {
     match backup_type {
         BackupType::Flash | BackupType::Flash512 => {
-            BackupMedia::Flash(Flash::new(backup_path, FlashSize::Flash64k))
+            BackupMedia::Flash(Flash::new(storage, FlashSize::Flash64k))
         }
-        BackupType::Flash1M => BackupMedia::Flash(Flash::new(backup_path, FlashSize::Flash128k)),
-        BackupType::Sram => BackupMedia::Sram(BackupFile::new(0x8000, backup_path)),
-        BackupType::Eeprom => BackupMedia::Eeprom(EepromController::new(backup_path)),
+        BackupType::Flash1M => BackupMedia::Flash(Flash::new(storage, FlashSize::Flash128k)),
+        BackupType::Sram => BackupMedia::Sram(BackupFile::new(0x8000, storage)),
+        BackupType::Eeprom => BackupMedia::Eeprom(EepromController::new(storage)),
         BackupType::AutoDetect => BackupMedia::Undetected,
     }
 }
 
-fn detect_backup_type(bytes: &[u8]) -> Option<BackupType> {
-    const ID_STRINGS: &[&str] = &["EEPROM", "SRAM", "FLASH_", "FLASH512_", "FLASH1M_"];
+const ID_STRINGS: &[(&str, BackupType)] = &[
+    ("EEPROM_", BackupType::Eeprom),
+    ("SRAM_", BackupType::Sram),
+    ("FLASH_", BackupType::Flash),
+    ("FLASH512_", BackupType::Flash512),
+    ("FLASH1M_", BackupType::Flash1M),
+];
 
-    for i in 0..5 {
-        let search = TwoWaySearcher::new(ID_STRINGS[i].as_bytes());
-        match search.search_in(bytes) {
-            Some(_) => return Some(BackupType::from_u8(i as u8).unwrap()),
-            _ => {}
+pub(super) fn detect_backup_type(bytes: &[u8]) -> Option<BackupType> {
+    for (id_string, backup_type) in ID_STRINGS {
+        let search = TwoWaySearcher::new(id_string.as_bytes());
+        if search.search_in(bytes).is_some() {
+            return Some(*backup_type);
         }
     }
     None
 }
+
+/// Like `detect_backup_type`, but the ROM is scanned a page at a time, bypassing the page cache
+fn detect_paged_backup_type(rom: &mut PagedRom) -> Option<BackupType> {
+    let overlap = ID_STRINGS.iter().map(|(s, _)| s.len()).max().unwrap() - 1;
+    let mut page = vec![0; ROM_PAGE_SIZE];
+    let mut window: Vec<u8> = Vec::with_capacity(overlap + ROM_PAGE_SIZE);
+    for offset in (0..rom.size()).step_by(ROM_PAGE_SIZE) {
+        let page_size = ROM_PAGE_SIZE.min(rom.size() - offset);
+        rom.read_uncached(offset, &mut page[..page_size]);
+        // keep the tail of the previous page, for id strings that cross a page boundary
+        window.drain(..window.len().saturating_sub(overlap));
+        window.extend_from_slice(&page[..page_size]);
+        if let Some(detected) = detect_backup_type(&window) {
+            return Some(detected);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::backup::SaveStorage;
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    fn make_rom(id_string: &str) -> Vec<u8> {
+        let mut rom = vec![0; 0x400];
+        rom[0x200..0x200 + id_string.len()].copy_from_slice(id_string.as_bytes());
+        rom
+    }
+
+    #[test]
+    fn test_detect_flash128k() {
+        let rom = make_rom("FLASH1M_V103");
+        assert_eq!(
+            Cartridge::detect_backup_type(&rom),
+            Some(BackupType::Flash1M)
+        );
+
+        let cartridge = GamepakBuilder::new()
+            .buffer(&rom)
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.backup_type(), BackupType::Flash1M);
+        assert_eq!(cartridge.detection_source(), DetectionSource::StringScan);
+    }
+
+    #[test]
+    fn test_forced_backup_type() {
+        let cartridge = GamepakBuilder::new()
+            .buffer(&make_rom("FLASH1M_V103"))
+            .with_sram()
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.backup_type(), BackupType::Sram);
+        assert_eq!(cartridge.detection_source(), DetectionSource::BuilderForced);
+    }
+
+    #[test]
+    fn test_initial_save() {
+        use arm7tdmi::memory::BusIO;
+
+        let save: Vec<u8> = (0..0x8000).map(|i| (i * 3) as u8).collect();
+        let mut cartridge = GamepakBuilder::new()
+            .buffer(&make_rom("SRAM_V113"))
+            .without_backup_to_file()
+            .with_initial_save(&save)
+            .build()
+            .unwrap();
+        for (i, byte) in save.iter().enumerate() {
+            assert_eq!(cartridge.read_8(0x0e00_0000 + i as u32), *byte);
+        }
+
+        let result = GamepakBuilder::new()
+            .buffer(&make_rom("SRAM_V113"))
+            .without_backup_to_file()
+            .with_initial_save(&save[..0x100])
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct MemoryStorage {
+        data: Rc<RefCell<Option<Vec<u8>>>>,
+        stores: Rc<Cell<usize>>,
+    }
+
+    impl SaveStorage for MemoryStorage {
+        fn load(&mut self) -> Option<Vec<u8>> {
+            self.data.borrow().clone()
+        }
+
+        fn store(&mut self, data: &[u8]) {
+            *self.data.borrow_mut() = Some(data.to_vec());
+            self.stores.set(self.stores.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_save_storage() {
+        use arm7tdmi::memory::BusIO;
+
+        let storage = MemoryStorage::default();
+        let mut save = vec![0xff; 0x8000];
+        save[0] = 0x42;
+        *storage.data.borrow_mut() = Some(save);
+
+        let mut cartridge = GamepakBuilder::new()
+            .buffer(&make_rom("SRAM_V113"))
+            .save_storage(Box::new(storage.clone()))
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.read_8(0x0e00_0000), 0x42);
+
+        cartridge.write_8(0x0e00_0010, 0x99);
+        cartridge.write_8(0x0e00_0011, 0x98);
+        assert_eq!(storage.stores.get(), 0);
+
+        cartridge.flush_save();
+        assert_eq!(storage.stores.get(), 1);
+        let stored = storage.data.borrow().clone().unwrap();
+        assert_eq!(stored.len(), 0x8000);
+        assert_eq!(&stored[0x10..0x12], &[0x99, 0x98]);
+        assert_eq!(stored[0], 0x42);
+
+        // nothing left to flush
+        cartridge.flush_save();
+        assert_eq!(storage.stores.get(), 1);
+    }
+
+    #[test]
+    fn test_undetected_backup_type() {
+        let rom = make_rom("");
+        assert_eq!(Cartridge::detect_backup_type(&rom), None);
+
+        let cartridge = GamepakBuilder::new()
+            .buffer(&rom)
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.backup_type(), BackupType::AutoDetect);
+        assert_eq!(cartridge.detection_source(), DetectionSource::StringScan);
+    }
+}
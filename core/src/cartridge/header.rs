@@ -34,12 +34,96 @@ pub struct CartridgeHeader {
     pub game_title: String,
     pub game_code: String,
     pub maker_code: String,
+    /// Not part of savestates, it is taken from the ROM when a savestate is loaded
+    #[serde(skip)]
+    pub main_unit_code: u8,
     pub software_version: u8,
     pub checksum: u8,
+    /// The compressed logo bitmap at 004h
+    pub logo: Vec<u8>,
+    /// The complement check computed over 0A0h-0BCh
+    pub calculated_checksum: u8,
     // ram_entry_point: Addr,
     // joybus_entry_point: Addr,
 }
 
+/// The Nintendo logo the BIOS checks before booting the cartridge
+pub const NINTENDO_LOGO: [u8; 156] = [
+    0x24, 0xff, 0xae, 0x51, 0x69, 0x9a, 0xa2, 0x21, 0x3d, 0x84, 0x82, 0x0a, 0x84, 0xe4, 0x09, 0xad,
+    0x11, 0x24, 0x8b, 0x98, 0xc0, 0x81, 0x7f, 0x21, 0xa3, 0x52, 0xbe, 0x19, 0x93, 0x09, 0xce, 0x20,
+    0x10, 0x46, 0x4a, 0x4a, 0xf8, 0x27, 0x31, 0xec, 0x58, 0xc7, 0xe8, 0x33, 0x82, 0xe3, 0xce, 0xbf,
+    0x85, 0xf4, 0xdf, 0x94, 0xce, 0x4b, 0x09, 0xc1, 0x94, 0x56, 0x8a, 0xc0, 0x13, 0x72, 0xa7, 0xfc,
+    0x9f, 0x84, 0x4d, 0x73, 0xa3, 0xca, 0x9a, 0x61, 0x58, 0x97, 0xa3, 0x27, 0xfc, 0x03, 0x98, 0x76,
+    0x23, 0x1d, 0xc7, 0x61, 0x03, 0x04, 0xae, 0x56, 0xbf, 0x38, 0x84, 0x00, 0x40, 0xa7, 0x0e, 0xfd,
+    0xff, 0x52, 0xfe, 0x03, 0x6f, 0x95, 0x30, 0xf1, 0x97, 0xfb, 0xc0, 0x85, 0x60, 0xd6, 0x80, 0x25,
+    0xa9, 0x63, 0xbe, 0x03, 0x01, 0x4e, 0x38, 0xe2, 0xf9, 0xa2, 0x34, 0xff, 0xbb, 0x3e, 0x03, 0x44,
+    0x78, 0x00, 0x90, 0xcb, 0x88, 0x11, 0x3a, 0x94, 0x65, 0xc0, 0x7c, 0x63, 0x87, 0xf0, 0x3c, 0xaf,
+    0xd6, 0x25, 0xe4, 0x8b, 0x38, 0x0a, 0xac, 0x72, 0x21, 0xd4, 0xf8, 0x07,
+];
+
+/// The destination region of a game, taken from the last character of the game code
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Japan,
+    Usa,
+    Europe,
+    Germany,
+    France,
+    Italy,
+    Spain,
+    Unknown,
+}
+
+impl From<char> for Region {
+    fn from(c: char) -> Region {
+        match c {
+            'J' => Region::Japan,
+            'E' => Region::Usa,
+            'P' => Region::Europe,
+            'D' => Region::Germany,
+            'F' => Region::France,
+            'I' => Region::Italy,
+            'S' => Region::Spain,
+            _ => Region::Unknown,
+        }
+    }
+}
+
+/// The result of `CartridgeHeader::validate`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeaderValidation {
+    pub logo_valid: bool,
+    pub checksum_valid: bool,
+    pub stored_checksum: u8,
+    pub calculated_checksum: u8,
+}
+
+impl HeaderValidation {
+    /// Would the real BIOS accept this header
+    pub fn is_valid(&self) -> bool {
+        self.logo_valid && self.checksum_valid
+    }
+}
+
+impl CartridgeHeader {
+    /// Check the Nintendo logo and the header complement check
+    pub fn validate(&self) -> HeaderValidation {
+        HeaderValidation {
+            logo_valid: self.logo[..] == NINTENDO_LOGO[..],
+            checksum_valid: self.checksum == self.calculated_checksum,
+            stored_checksum: self.checksum,
+            calculated_checksum: self.calculated_checksum,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.game_code
+            .chars()
+            .nth(3)
+            .map_or(Region::Unknown, Region::from)
+    }
+}
+
 fn calculate_checksum(bytes: &[u8]) -> u8 {
     bytes
         .iter()
@@ -82,9 +166,78 @@ pub fn parse(bytes: &[u8]) -> GBAResult<CartridgeHeader> {
         game_title: String::from(game_title),
         game_code: String::from(game_code),
         maker_code: String::from(maker_code),
+        main_unit_code: bytes[0xb3],
         software_version: bytes[0xbc],
         checksum,
+        logo: bytes[0x04..0xa0].to_vec(),
+        calculated_checksum,
         // ram_entry_point: ram_entry_point,
         // joybus_entry_point: joybus_entry_point,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_header() -> Vec<u8> {
+        let mut bytes = vec![0; 0xc0];
+        bytes[0x04..0xa0].copy_from_slice(&NINTENDO_LOGO);
+        bytes[0xa0..0xac].copy_from_slice(b"RUSTBOYADVNC");
+        bytes[0xac..0xb0].copy_from_slice(b"ARBE");
+        bytes[0xb0..0xb2].copy_from_slice(b"01");
+        bytes[0xb2] = 0x96;
+        bytes[0xbd] = calculate_checksum(&bytes[0xa0..=0xbc]);
+        bytes
+    }
+
+    #[test]
+    fn test_validate() {
+        let header = parse(&make_header()).unwrap();
+        let validation = header.validate();
+        assert!(validation.logo_valid);
+        assert!(validation.checksum_valid);
+        assert_eq!(validation.stored_checksum, validation.calculated_checksum);
+        assert!(validation.is_valid());
+    }
+
+    #[test]
+    fn test_region() {
+        let header = parse(&make_header()).unwrap();
+        assert_eq!(header.game_title, "RUSTBOYADVNC");
+        assert_eq!(header.game_code, "ARBE");
+        assert_eq!(header.maker_code, "01");
+        assert_eq!(header.main_unit_code, 0);
+        assert_eq!(header.region(), Region::Usa);
+
+        for (c, region) in [
+            ('J', Region::Japan),
+            ('E', Region::Usa),
+            ('P', Region::Europe),
+            ('D', Region::Germany),
+            ('F', Region::France),
+            ('I', Region::Italy),
+            ('S', Region::Spain),
+            ('X', Region::Unknown),
+        ]
+        .iter()
+        {
+            let mut bytes = make_header();
+            bytes[0xaf] = *c as u8;
+            assert_eq!(parse(&bytes).unwrap().region(), *region);
+        }
+    }
+
+    #[test]
+    fn test_validate_corrupted() {
+        let mut bytes = make_header();
+        bytes[0x10] ^= 0xff;
+        bytes[0xbc] = 1;
+        let validation = parse(&bytes).unwrap().validate();
+        assert!(!validation.logo_valid);
+        assert!(!validation.checksum_valid);
+        assert_eq!(validation.stored_checksum, bytes[0xbd]);
+        assert_eq!(validation.calculated_checksum, bytes[0xbd].wrapping_sub(1));
+        assert!(!validation.is_valid());
+    }
+}
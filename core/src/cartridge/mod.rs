@@ -1,17 +1,19 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use serde::{Deserialize, Serialize};
 
-use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
 
 pub mod header;
-use header::CartridgeHeader;
+use header::{CartridgeHeader, Region};
 
 mod backup;
 use backup::eeprom::EepromController;
 use backup::flash::Flash;
-pub use backup::BackupType;
 use backup::{BackupFile, BackupMemoryInterface};
+pub use backup::{BackupType, DetectionSource, DynSaveStorage, FileStorage, SaveStorage};
 
 mod gpio;
 mod rtc;
@@ -21,6 +23,10 @@ mod builder;
 mod loader;
 pub use builder::GamepakBuilder;
 
+mod rom_source;
+use rom_source::PagedRom;
+pub use rom_source::{DynRomSource, RomSource, ROM_PAGE_SIZE};
+
 pub const GPIO_PORT_DATA: u32 = 0xC4;
 pub const GPIO_PORT_DIRECTION: u32 = 0xC6;
 pub const GPIO_PORT_CONTROL: u32 = 0xC8;
@@ -33,8 +39,66 @@ pub enum BackupMedia {
     Undetected,
 }
 
+impl BackupMedia {
+    /// The raw contents of the backup memory, in the layout of a `.sav` file
+    pub fn bytes(&self) -> Vec<u8> {
+        match self {
+            BackupMedia::Sram(memory) => memory.bytes().to_vec(),
+            BackupMedia::Flash(flash) => flash.memory().bytes().to_vec(),
+            BackupMedia::Eeprom(eeprom) => eeprom.bytes(),
+            BackupMedia::Undetected => Vec::new(),
+        }
+    }
+
+    /// Replace the contents of the backup memory with the contents of a `.sav` file
+    /// @return false if the size of `data` doesn't match the backup type
+    pub fn load_bytes(&mut self, data: &[u8]) -> bool {
+        match self {
+            BackupMedia::Sram(memory) if memory.bytes().len() == data.len() => {
+                memory.load_bytes(data);
+                true
+            }
+            BackupMedia::Flash(flash) if flash.memory().bytes().len() == data.len() => {
+                flash.memory_mut().load_bytes(data);
+                true
+            }
+            BackupMedia::Eeprom(eeprom) => eeprom.load_bytes(data),
+            _ => false,
+        }
+    }
+
+    fn memory_mut(&mut self) -> Option<&mut BackupFile> {
+        match self {
+            BackupMedia::Sram(memory) => Some(memory),
+            BackupMedia::Flash(flash) => Some(flash.memory_mut()),
+            BackupMedia::Eeprom(eeprom) => Some(eeprom.memory_mut()),
+            BackupMedia::Undetected => None,
+        }
+    }
+
+    /// Persist the writes the save storage didn't take in place
+    pub fn flush(&mut self) {
+        if let Some(memory) = self.memory_mut() {
+            memory.flush_dirty();
+        }
+    }
+}
+
 pub type SymbolTable = HashMap<String, u32>;
 
+/// Metadata of a loaded cartridge, e.g for a game library
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeInfo {
+    pub game_title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    pub main_unit_code: u8,
+    pub software_version: u8,
+    pub rom_size: usize,
+    pub backup_type: BackupType,
+    pub region: Region,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Cartridge {
     pub header: CartridgeHeader,
@@ -42,9 +106,14 @@ pub struct Cartridge {
     bytes: Box<[u8]>,
     #[serde(skip)]
     size: usize,
+    /// Set when the ROM is read from a `RomSource`, `bytes` is then left empty
+    #[serde(skip)]
+    paged_rom: Option<Rc<RefCell<PagedRom>>>,
     gpio: Option<Gpio>,
     symbols: Option<SymbolTable>, // TODO move it somewhere else
     pub(crate) backup: BackupMedia,
+    #[serde(skip)]
+    detection_source: DetectionSource,
 }
 
 impl Cartridge {
@@ -56,31 +125,93 @@ impl Cartridge {
     }
 
     pub fn set_rom_bytes(&mut self, bytes: Box<[u8]>) {
+        if let Some(main_unit_code) = bytes.get(0xb3) {
+            self.header.main_unit_code = *main_unit_code;
+        }
         self.size = bytes.len();
         self.bytes = bytes;
     }
 
+    /// Empty if the ROM is read from a `RomSource`
     pub fn get_rom_bytes(&self) -> &[u8] {
         &self.bytes
     }
 
+    pub fn rom_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo {
+            game_title: self.header.game_title.clone(),
+            game_code: self.header.game_code.clone(),
+            maker_code: self.header.maker_code.clone(),
+            main_unit_code: self.header.main_unit_code,
+            software_version: self.header.software_version,
+            rom_size: self.rom_size(),
+            backup_type: self.backup_type(),
+            region: self.header.region(),
+        }
+    }
+
+    /// The type of the backup memory the cartridge was loaded with
+    pub fn backup_type(&self) -> BackupType {
+        match &self.backup {
+            BackupMedia::Sram(_) => BackupType::Sram,
+            BackupMedia::Flash(flash) if flash.memory().bytes().len() > 0x10000 => {
+                BackupType::Flash1M
+            }
+            BackupMedia::Flash(_) => BackupType::Flash512,
+            BackupMedia::Eeprom(_) => BackupType::Eeprom,
+            BackupMedia::Undetected => BackupType::AutoDetect,
+        }
+    }
+
+    /// Write any pending changes of the backup memory to its save storage
+    pub fn flush_save(&mut self) {
+        self.backup.flush();
+    }
+
+    /// How the loader decided on `backup_type()`
+    pub fn detection_source(&self) -> DetectionSource {
+        self.detection_source
+    }
+
+    /// Scan a ROM for the id strings of Nintendo's backup library
+    pub fn detect_backup_type(bytes: &[u8]) -> Option<BackupType> {
+        builder::detect_backup_type(bytes)
+    }
+
     // 'Clones' the cartridge without the ROM buffer
     pub fn thin_copy(&self) -> Cartridge {
         Cartridge {
             header: self.header.clone(),
             bytes: Default::default(),
             size: 0,
+            paged_rom: None,
             gpio: self.gpio.clone(),
             symbols: self.symbols.clone(),
             backup: self.backup.clone(),
+            detection_source: self.detection_source,
         }
     }
 
     pub fn update_from(&mut self, other: Cartridge) {
+        let main_unit_code = self.header.main_unit_code;
         self.header = other.header;
+        self.header.main_unit_code = main_unit_code;
         self.gpio = other.gpio;
         self.symbols = other.symbols;
+        // Savestates only remember save files, carry over any other storage the backup was built with
+        let storage = self
+            .backup
+            .memory_mut()
+            .filter(|memory| memory.path().is_none())
+            .and_then(|memory| memory.take_storage());
         self.backup = other.backup;
+        if let (Some(storage), Some(memory)) = (storage, self.backup.memory_mut()) {
+            memory.set_storage(storage);
+        }
     }
 
     #[inline]
@@ -96,12 +227,27 @@ impl Cartridge {
             x as u8
         }
     }
+
+    /// Reads of ROM bytes that aren't held in `bytes`, either paged in from the `RomSource` or out of bounds
+    #[cold]
+    fn read_rom_slow(&self, addr: Addr) -> u8 {
+        let offset = (addr & 0x01ff_ffff) as usize;
+        match &self.paged_rom {
+            Some(rom) if offset < self.size => rom.borrow_mut().read_8(offset),
+            _ => self.read_unused(addr),
+        }
+    }
 }
 
 use super::sysbus::consts::*;
 
 pub const EEPROM_BASE_ADDR: u32 = 0x0DFF_FF00;
 
+#[inline]
+fn is_sram_access(addr: u32) -> bool {
+    matches!(addr & 0xff000000, SRAM_LO | SRAM_HI)
+}
+
 fn is_gpio_access(addr: u32) -> bool {
     match addr & 0x1ff_ffff {
         GPIO_PORT_DATA | GPIO_PORT_DIRECTION | GPIO_PORT_CONTROL => true,
@@ -116,19 +262,32 @@ impl BusIO for Cartridge {
             SRAM_LO | SRAM_HI => match &self.backup {
                 BackupMedia::Sram(memory) => memory.read((addr & 0x7FFF) as usize),
                 BackupMedia::Flash(flash) => flash.read(addr),
-                _ => 0,
+                // the data lines are pulled up when there is no SRAM
+                _ => 0xff,
             },
             _ => {
-                if offset >= self.size {
-                    self.read_unused(addr)
-                } else {
+                if offset < self.bytes.len() {
                     unsafe { *self.bytes.get_unchecked(offset as usize) }
+                } else {
+                    self.read_rom_slow(addr)
                 }
             }
         }
     }
 
+    fn read_32(&mut self, addr: Addr) -> u32 {
+        if is_sram_access(addr) {
+            // SRAM has an 8bit data bus, wider reads see the same byte on every lane
+            return self.read_8(addr) as u32 * 0x0101_0101;
+        }
+        self.read_16(addr) as u32 | (self.read_16(addr + 2) as u32) << 16
+    }
+
     fn read_16(&mut self, addr: u32) -> u16 {
+        if is_sram_access(addr) {
+            return self.read_8(addr) as u16 * 0x0101;
+        }
+
         if is_gpio_access(addr) {
             if let Some(gpio) = &self.gpio {
                 if !(gpio.is_readable()) {
@@ -139,7 +298,7 @@ impl BusIO for Cartridge {
         }
 
         if addr & 0xff000000 == GAMEPAK_WS2_HI
-            && (self.bytes.len() <= 16 * 1024 * 1024 || addr >= EEPROM_BASE_ADDR)
+            && (self.size <= 16 * 1024 * 1024 || addr >= EEPROM_BASE_ADDR)
         {
             if let BackupMedia::Eeprom(spi) = &self.backup {
                 return spi.read_half(addr);
@@ -168,7 +327,7 @@ impl BusIO for Cartridge {
         }
 
         if addr & 0xff000000 == GAMEPAK_WS2_HI
-            && (self.bytes.len() <= 16 * 1024 * 1024 || addr >= EEPROM_BASE_ADDR)
+            && (self.size <= 16 * 1024 * 1024 || addr >= EEPROM_BASE_ADDR)
         {
             if let BackupMedia::Eeprom(spi) = &mut self.backup {
                 return spi.write_half(addr, value);
@@ -181,10 +340,29 @@ impl BusIO for Cartridge {
 impl DebugRead for Cartridge {
     fn debug_read_8(&mut self, addr: Addr) -> u8 {
         let offset = (addr & 0x01ff_ffff) as usize;
-        if offset >= self.size {
-            self.read_unused(addr)
-        } else {
+        if offset < self.bytes.len() {
             self.bytes[offset]
+        } else {
+            self.read_rom_slow(addr)
+        }
+    }
+}
+
+impl DebugWrite for Cartridge {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        match addr & 0xff000000 {
+            SRAM_LO | SRAM_HI => {
+                if let BackupMedia::Sram(memory) = &mut self.backup {
+                    memory.write((addr & 0x7FFF) as usize, value);
+                }
+            }
+            _ => {
+                // patch the ROM, paged ROMs can't be patched
+                let offset = (addr & 0x01ff_ffff) as usize;
+                if offset < self.bytes.len() {
+                    self.bytes[offset] = value;
+                }
+            }
         }
     }
 }
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Size of the pages a `RomSource` is read in
+pub const ROM_PAGE_SIZE: usize = 0x4000;
+
+/// Supplies the ROM of a cartridge on demand, so devices with little memory don't have to hold
+/// a whole 32MB ROM. See `GamepakBuilder::rom_source`.
+pub trait RomSource: fmt::Debug {
+    /// Size of the ROM in bytes
+    fn size(&self) -> usize;
+
+    /// Fill `buf` with the ROM contents starting at `offset`.
+    /// `offset` is page aligned and `buf` never reaches past the end of the ROM.
+    fn read_page(&mut self, offset: usize, buf: &mut [u8]);
+}
+
+pub type DynRomSource = Box<dyn RomSource>;
+
+#[derive(Debug)]
+struct Page {
+    index: usize,
+    last_used: u64,
+    data: Box<[u8]>,
+}
+
+/// Keeps the `capacity` most recently used pages of a `RomSource` in memory
+#[derive(Debug)]
+pub(super) struct PagedRom {
+    source: DynRomSource,
+    size: usize,
+    capacity: usize,
+    pages: Vec<Page>,
+    /// page index to its position in `pages`
+    lookup: HashMap<usize, usize>,
+    clock: u64,
+}
+
+impl PagedRom {
+    pub fn new(source: DynRomSource, capacity: usize) -> PagedRom {
+        let size = source.size();
+        PagedRom {
+            source,
+            size,
+            capacity: capacity.max(1),
+            pages: Vec::new(),
+            lookup: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn page(&mut self, index: usize) -> &Page {
+        self.clock += 1;
+        let cached = self.lookup.get(&index).copied();
+        let slot = match cached {
+            Some(slot) => slot,
+            None => {
+                let offset = index * ROM_PAGE_SIZE;
+                let page_size = ROM_PAGE_SIZE.min(self.size - offset);
+                let slot = if self.pages.len() < self.capacity {
+                    self.pages.push(Page {
+                        index,
+                        last_used: 0,
+                        data: vec![0; ROM_PAGE_SIZE].into_boxed_slice(),
+                    });
+                    self.pages.len() - 1
+                } else {
+                    let (slot, evicted) = self
+                        .pages
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, page)| page.last_used)
+                        .map(|(slot, page)| (slot, page.index))
+                        .unwrap();
+                    self.lookup.remove(&evicted);
+                    self.pages[slot].index = index;
+                    slot
+                };
+                self.source
+                    .read_page(offset, &mut self.pages[slot].data[..page_size]);
+                self.lookup.insert(index, slot);
+                slot
+            }
+        };
+        let page = &mut self.pages[slot];
+        page.last_used = self.clock;
+        page
+    }
+
+    /// `offset` must be within the ROM
+    pub fn read_8(&mut self, offset: usize) -> u8 {
+        self.page(offset / ROM_PAGE_SIZE).data[offset % ROM_PAGE_SIZE]
+    }
+
+    /// Read the page at `offset` straight from the source, leaving the cache untouched
+    pub fn read_uncached(&mut self, offset: usize, buf: &mut [u8]) {
+        self.source.read_page(offset, buf);
+    }
+
+    /// Read `buf.len()` bytes starting at `offset` through the page cache
+    pub fn read(&mut self, offset: usize, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.read_8(offset + i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::BackupType;
+    use crate::prelude::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Debug)]
+    struct CountingRom {
+        rom: Vec<u8>,
+        loads: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl RomSource for CountingRom {
+        fn size(&self) -> usize {
+            self.rom.len()
+        }
+
+        fn read_page(&mut self, offset: usize, buf: &mut [u8]) {
+            self.loads.borrow_mut().push(offset / ROM_PAGE_SIZE);
+            buf.copy_from_slice(&self.rom[offset..offset + buf.len()]);
+        }
+    }
+
+    #[test]
+    fn test_paged_rom() {
+        // page 0 jumps to page 2 which loops forever, pages 1 and 3 are never executed
+        let mut rom = vec![0; 4 * ROM_PAGE_SIZE];
+        rom[0..4].copy_from_slice(&0xea00_1ffe_u32.to_le_bytes()); // b 0x08008000
+        rom[0x8000..0x8004].copy_from_slice(&0xe3a0_0001_u32.to_le_bytes()); // mov r0, #1
+        rom[0x8004..0x8008].copy_from_slice(&0xeaff_fffd_u32.to_le_bytes()); // b 0x08008000
+        rom[0xac..0xb0].copy_from_slice(b"PAGE");
+
+        let loads = Rc::new(RefCell::new(Vec::new()));
+        let source = CountingRom {
+            rom,
+            loads: loads.clone(),
+        };
+        let cartridge = GamepakBuilder::new()
+            .rom_source(Box::new(source), 2)
+            .with_sram()
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.header.game_code, "PAGE");
+        assert_eq!(cartridge.rom_size(), 4 * ROM_PAGE_SIZE);
+        assert!(cartridge.get_rom_bytes().is_empty());
+        assert_eq!(*loads.borrow(), vec![0]);
+
+        let bios = vec![0; 0x4000].into_boxed_slice();
+        let mut gba = GameBoyAdvance::new(bios, cartridge, NullAudio::new());
+        gba.skip_bios();
+        gba.cpu.reload_pipeline32();
+        gba.run_for(1000);
+        assert_eq!(gba.cpu.gpr[0], 1);
+        assert_eq!(*loads.borrow(), vec![0, 2]);
+
+        // past the end of the ROM reads don't touch the source
+        assert_eq!(gba.read_memory(0x0801_0000, 2), vec![0x00, 0x80]);
+        assert_eq!(gba.read_memory(0x0800_8000, 1), vec![0x01]);
+        assert_eq!(*loads.borrow(), vec![0, 2]);
+
+        // the least recently used page is evicted
+        gba.read_memory(0x0800_c000, 1);
+        gba.read_memory(0x0800_0000, 1);
+        assert_eq!(*loads.borrow(), vec![0, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_paged_rom_backup_detection() {
+        // the id string straddles two pages
+        let mut rom = vec![0; 2 * ROM_PAGE_SIZE];
+        let id_offset = ROM_PAGE_SIZE - 4;
+        rom[id_offset..id_offset + 12].copy_from_slice(b"FLASH1M_V103");
+
+        let loads = Rc::new(RefCell::new(Vec::new()));
+        let cartridge = GamepakBuilder::new()
+            .rom_source(Box::new(CountingRom { rom, loads }), 1)
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        assert_eq!(cartridge.backup_type(), BackupType::Flash1M);
+    }
+}
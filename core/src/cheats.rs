@@ -0,0 +1,186 @@
+//! A simple cheat engine, every enabled cheat is a list of raw memory writes that are re-applied each frame.
+//!
+//! Cheat list files have one cheat per line:
+//! ```text
+//! # comments start with a '#'
+//! 03007ff0:63 Infinite lives
+//! -02000010:03e7 02000014:0001 Max money, disabled by default
+//! ```
+//! Each code is `ADDRESS:VALUE` in hex, the number of value digits (2, 4 or 8) selects the write width.
+//! Anything after the codes is the name of the cheat, and a leading '-' (or '+') disables (or enables) it.
+use std::fmt;
+
+use arm7tdmi::memory::{Addr, BusIO, MemoryAccessWidth};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheatCode {
+    pub addr: Addr,
+    pub value: u32,
+    pub width: MemoryAccessWidth,
+}
+
+impl CheatCode {
+    fn parse(s: &str) -> Result<CheatCode, String> {
+        let (addr, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected ADDRESS:VALUE, got {:?}", s))?;
+        let width = match value.len() {
+            2 => MemoryAccessWidth::MemoryAccess8,
+            4 => MemoryAccessWidth::MemoryAccess16,
+            8 => MemoryAccessWidth::MemoryAccess32,
+            _ => return Err(format!("value {:?} must have 2, 4 or 8 hex digits", value)),
+        };
+        let addr =
+            Addr::from_str_radix(addr, 16).map_err(|_| format!("invalid address {:?}", addr))?;
+        let value =
+            u32::from_str_radix(value, 16).map_err(|_| format!("invalid value {:?}", value))?;
+        Ok(CheatCode { addr, value, width })
+    }
+
+    fn apply<B: BusIO>(&self, bus: &mut B) {
+        match self.width {
+            MemoryAccessWidth::MemoryAccess8 => bus.write_8(self.addr, self.value as u8),
+            MemoryAccessWidth::MemoryAccess16 => bus.write_16(self.addr, self.value as u16),
+            MemoryAccessWidth::MemoryAccess32 => bus.write_32(self.addr, self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub enabled: bool,
+    pub codes: Vec<CheatCode>,
+}
+
+/// A line of a cheat list that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatParseError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CheatParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn parse_cheat_line(line: &str) -> Result<Cheat, String> {
+    let (enabled, line) = if let Some(rest) = line.strip_prefix('-') {
+        (false, rest)
+    } else {
+        (true, line.strip_prefix('+').unwrap_or(line))
+    };
+
+    let mut codes = Vec::new();
+    let mut rest = line.trim_start();
+    while let Some(token) = rest.split_whitespace().next() {
+        if !token.contains(':') {
+            break;
+        }
+        codes.push(CheatCode::parse(token)?);
+        rest = rest[token.len()..].trim_start();
+    }
+    if codes.is_empty() {
+        return Err("missing cheat codes".to_string());
+    }
+
+    Ok(Cheat {
+        name: rest.trim_end().to_string(),
+        enabled,
+        codes,
+    })
+}
+
+/// Parse a cheat list, lines that fail to parse are reported without discarding the rest of the list
+pub fn parse_cheat_list(text: &str) -> (Vec<Cheat>, Vec<CheatParseError>) {
+    let mut cheats = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_cheat_line(line) {
+            Ok(cheat) => cheats.push(cheat),
+            Err(message) => errors.push(CheatParseError {
+                line: i + 1,
+                message,
+            }),
+        }
+    }
+    (cheats, errors)
+}
+
+#[derive(Debug, Clone)]
+pub struct CheatEngine {
+    cheats: Vec<Cheat>,
+    /// Master switch, when cleared no cheat is applied regardless of its own flag
+    enabled: bool,
+}
+
+impl Default for CheatEngine {
+    fn default() -> CheatEngine {
+        CheatEngine {
+            cheats: Vec::new(),
+            enabled: true,
+        }
+    }
+}
+
+impl CheatEngine {
+    /// @return the index of the new cheat
+    pub fn add(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.cheats.len() - 1
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut [Cheat] {
+        &mut self.cheats
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the cheat at index `id`
+    /// @return false if there is no such cheat
+    pub fn set_cheat_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        if let Some(cheat) = self.cheats.get_mut(id) {
+            cheat.enabled = enabled;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Perform the writes of all enabled cheats
+    /// @return the number of cheats that were applied
+    pub fn apply<B: BusIO>(&self, bus: &mut B) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+        let mut applied = 0;
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            for code in &cheat.codes {
+                code.apply(bus);
+            }
+            applied += 1;
+        }
+        applied
+    }
+}
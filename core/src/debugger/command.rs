@@ -1,28 +1,26 @@
 use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::time;
 
-use crate::arm7tdmi::arm::ArmInstruction;
-use crate::arm7tdmi::thumb::ThumbInstruction;
-use crate::arm7tdmi::CpuState;
-use crate::bus::{Addr, Bus, DebugRead};
-use crate::disass::Disassembler;
-use rustboyadvance_utils::{read_bin_file, write_bin_file};
+use arm7tdmi::arm::ArmInstruction;
+use arm7tdmi::disass::Disassembler;
+use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::thumb::ThumbInstruction;
+use arm7tdmi::CpuState;
+use rustboyadvance_utils::{elf, read_bin_file, write_bin_file};
 
 // use super::palette_view::create_palette_view;
 // use super::tile_view::create_tile_view;
-use super::GameBoyAdvance;
 use super::{parser::Value, Debugger, DebuggerError, DebuggerResult};
-
-use ansi_term::Colour;
+use crate::gpu::{CYCLES_FULL_REFRESH, OAM_SIZE, PALETTE_RAM_SIZE};
+use crate::sysbus::consts::{OAM_ADDR, PALRAM_ADDR, VRAM_ADDR};
+use crate::{GameBoyAdvance, StopReason};
 
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 
-use hexdump;
-
-use goblin;
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum DisassMode {
     ModeArm,
@@ -54,6 +52,26 @@ pub enum InfoCommand {
     Interrupt,
 }
 
+/// Video memory regions that can be dumped to a file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DumpRegion {
+    Vram,
+    Oam,
+    Palette,
+}
+
+impl DumpRegion {
+    fn range(&self) -> Range<Addr> {
+        let (start, size) = match self {
+            // only 96K of the 128K VRAM region exist, the rest mirrors the OBJ tiles
+            DumpRegion::Vram => (VRAM_ADDR, 0x18000),
+            DumpRegion::Oam => (OAM_ADDR, OAM_SIZE as u32),
+            DumpRegion::Palette => (PALRAM_ADDR, PALETTE_RAM_SIZE as u32),
+        };
+        start..start + size
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Command {
     Info(InfoCommand),
@@ -74,6 +92,7 @@ pub enum Command {
     TraceToggle(TraceFlags),
     SaveState(String),
     LoadState(String),
+    Dump(DumpRegion, PathBuf),
     AddSymbolsFile(PathBuf, Option<u32>),
     ListSymbols(Option<String>),
 }
@@ -98,83 +117,112 @@ fn find_nearest_symbol(addr: u32, symbols: &HashMap<String, u32>) -> Option<(Str
     }
 }
 
+/// Format `bytes` 16 per line, with their address and ascii
+fn write_hexdump(out: &mut String, addr: Addr, bytes: &[u8]) -> fmt::Result {
+    for (i, line) in bytes.chunks(16).enumerate() {
+        write!(out, "{:08x}: ", addr + 16 * i as Addr)?;
+        for b in line {
+            write!(out, "{:02x} ", b)?;
+        }
+        for _ in line.len()..16 {
+            write!(out, "   ")?;
+        }
+        let ascii: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+            .collect();
+        writeln!(out, "|{}|", ascii)?;
+    }
+    Ok(())
+}
+
 impl Debugger {
-    pub fn run_command(&mut self, gba: &mut GameBoyAdvance, command: Command) {
+    /// Run a parsed command, the output is appended to `out`
+    pub fn run_command(
+        &mut self,
+        gba: &mut GameBoyAdvance,
+        command: Command,
+        out: &mut String,
+    ) -> DebuggerResult<()> {
         use Command::*;
         #[allow(unreachable_patterns)]
         match command {
             Info(InfoCommand::Cpu) => {
-                let pc = gba.cpu.pc;
+                let pc = gba.cpu.get_next_pc();
                 if let Some((sym, addr)) = find_nearest_symbol(pc, &self.symbols) {
-                    println!("PC at {}+{:#x} ({:08x})", sym, addr - pc, pc);
+                    writeln!(out, "PC at {}+{:#x} ({:08x})", sym, addr - pc, pc)?;
                 } else {
-                    println!("PC at {:08x}", pc);
+                    writeln!(out, "PC at {:08x}", pc)?;
                 }
 
-                println!("{}", gba.cpu);
-                // println!("IME={}", gba.io_devs.intc.interrupt_master_enable);
-                // println!("IE={:#?}", gba.io_devs.intc.interrupt_enable);
-                // println!("IF={:#?}", gba.io_devs.intc.interrupt_flags);
+                writeln!(out, "{:?}", gba.cpu)?;
             }
-            Info(InfoCommand::Gpu) => println!("{}", gba.io_devs.gpu),
+            Info(InfoCommand::Gpu) => writeln!(out, "{}", gba.io_devs.gpu)?,
             Info(InfoCommand::Interrupt) => {
-                println!("IME: {:?}", gba.io_devs.intc.interrupt_master_enable);
-                println!("IE: {:#?}", gba.io_devs.intc.interrupt_enable);
-                println!("IF: {:#?}", gba.io_devs.intc.interrupt_flags.get());
+                let state = gba.interrupt_state();
+                writeln!(out, "IME: {:?}", state.interrupt_master_enable)?;
+                writeln!(out, "IE: {:#?}", state.interrupt_enable)?;
+                writeln!(out, "IF: {:#?}", state.interrupt_flags)?;
+            }
+            Info(InfoCommand::Gpio) => {
+                writeln!(out, "GPIO: {:#?}", gba.sysbus.cartridge.get_gpio())?
             }
-            Info(InfoCommand::Gpio) => println!("GPIO: {:#?}", gba.sysbus.cartridge.get_gpio()),
             Step(count) => {
                 for _ in 0..count {
-                    gba.step_debugger();
-                    while gba.cpu.dbg.last_executed.is_none() {
-                        gba.step_debugger();
-                    }
-                    if let Some(last_executed) = &gba.cpu.dbg.last_executed {
-                        let pc = last_executed.get_pc();
-                        let symbol =
-                            self.symbols
-                                .iter()
-                                .find_map(|(key, &val)| if val == pc { Some(key) } else { None });
+                    let step = match gba.step_instruction() {
+                        Some(step) => step,
+                        None => {
+                            writeln!(out, "the cpu is halted")?;
+                            break;
+                        }
+                    };
+                    let (pc, insn) = (step.addr, step.mnemonic);
 
-                        let text = if let Some(symbol) = symbol {
-                            format!("Executed at {} @0x{:08x}:", symbol, pc)
-                        } else {
-                            format!("Executed at @0x{:08x}:", pc)
-                        };
-                        print!(
-                            "{}\t{}",
-                            Colour::Black.bold().italic().on(Colour::White).paint(text),
-                            last_executed
-                        );
-                        println!(
-                            "{}",
-                            Colour::Purple.dimmed().italic().paint(format!(
-                                "\t\t/// Next instruction at @0x{:08x}",
-                                gba.cpu.get_next_pc()
-                            ))
-                        );
+                    let symbol =
+                        self.symbols
+                            .iter()
+                            .find_map(|(key, &val)| if val == pc { Some(key) } else { None });
+                    if let Some(symbol) = symbol {
+                        write!(out, "Executed at {} @0x{:08x}:", symbol, pc)?;
+                    } else {
+                        write!(out, "Executed at @0x{:08x}:", pc)?;
                     }
+                    writeln!(
+                        out,
+                        "\t{}\t\t/// Next instruction at @0x{:08x}",
+                        insn,
+                        gba.cpu.get_next_pc()
+                    )?;
                 }
-                println!("cycles: {}", gba.scheduler.timestamp());
-                println!("{}\n", gba.cpu);
-            }
-            Continue => 'running: loop {
-                gba.key_poll();
-                if let Some(breakpoint) = gba.step_debugger() {
-                    let mut bp_sym = None;
-                    if let Some(symbols) = gba.sysbus.cartridge.get_symbols() {
-                        for s in symbols.keys() {
-                            if symbols.get(s).unwrap() == &breakpoint {
-                                bp_sym = Some(s.clone());
+                writeln!(out, "cycles: {}", gba.scheduler.timestamp())?;
+                writeln!(out, "{:?}", gba.cpu)?;
+            }
+            Continue => loop {
+                match gba.run_for(CYCLES_FULL_REFRESH) {
+                    StopReason::Breakpoint(breakpoint) => {
+                        let bp_sym = self.symbols.iter().find_map(|(key, &val)| {
+                            if val == breakpoint {
+                                Some(key)
+                            } else {
+                                None
                             }
+                        });
+                        if let Some(sym) = bp_sym {
+                            writeln!(out, "Breakpoint reached! @{}", sym)?;
+                        } else {
+                            writeln!(out, "Breakpoint reached! @{:x}", breakpoint)?;
                         }
+                        break;
                     }
-                    if let Some(sym) = bp_sym {
-                        println!("Breakpoint reached! @{}", sym);
-                    } else {
-                        println!("Breakpoint reached! @{:x}", breakpoint);
+                    StopReason::Watchpoint { addr, old, new } => {
+                        writeln!(
+                            out,
+                            "Watchpoint reached! @{:x} ({:#x} -> {:#x})",
+                            addr, old, new
+                        )?;
+                        break;
                     }
-                    break 'running;
+                    StopReason::CyclesExpired => {}
                 }
             },
             Frame(count) => {
@@ -183,11 +231,11 @@ impl Debugger {
                     gba.frame();
                 }
                 let end = time::Instant::now();
-                println!("that took {:?} seconds", end - start);
+                writeln!(out, "that took {:?} seconds", end - start)?;
             }
             HexDump(addr, nbytes) => {
                 let bytes = gba.sysbus.debug_get_bytes(addr..addr + nbytes);
-                hexdump::hexdump(&bytes);
+                write_hexdump(out, addr, &bytes)?;
             }
             MemWrite(size, addr, val) => match size {
                 MemWriteCommandSize::Byte => gba.sysbus.write_8(addr, val as u8),
@@ -200,88 +248,91 @@ impl Debugger {
                         let bytes = gba.sysbus.debug_get_bytes(addr..addr + 4 * n);
                         let disass = Disassembler::<ArmInstruction>::new(addr, &bytes);
                         for (_, line) in disass.take(n as usize) {
-                            println!("{}", line)
+                            writeln!(out, "{}", line)?;
                         }
                     }
                     DisassMode::ModeThumb => {
                         let bytes = gba.sysbus.debug_get_bytes(addr..addr + 2 * n);
                         let disass = Disassembler::<ThumbInstruction>::new(addr, &bytes);
                         for (_, line) in disass.take(n as usize) {
-                            println!("{}", line)
+                            writeln!(out, "{}", line)?;
                         }
                     }
                 };
             }
             Quit => {
-                print!("Quitting!");
+                writeln!(out, "Quitting!")?;
                 self.stop();
             }
-            AddBreakpoint(addr) => match gba.add_breakpoint(addr) {
-                Some(index) => println!("Added breakpoint [{}] 0x{:08x}", index, addr),
-                None => println!("Breakpint already exists."),
+            AddBreakpoint(addr) => match self.add_breakpoint(gba, addr) {
+                Some(index) => writeln!(out, "Added breakpoint [{}] 0x{:08x}", index, addr)?,
+                None => writeln!(out, "Breakpint already exists.")?,
             },
             DelBreakpoint(addr) => self.delete_breakpoint(gba, addr),
-            ClearBreakpoints => gba.cpu.dbg.breakpoints.clear(),
+            ClearBreakpoints => {
+                for addr in gba.cpu.breakpoints().to_vec() {
+                    gba.remove_breakpoint(addr);
+                }
+            }
             ListBreakpoints => {
-                println!("breakpoint list:");
-                for (i, b) in gba.cpu.dbg.breakpoints.iter().enumerate() {
-                    println!("[{}] 0x{:08x}", i, b)
+                writeln!(out, "breakpoint list:")?;
+                for (i, b) in gba.cpu.breakpoints().iter().enumerate() {
+                    writeln!(out, "[{}] 0x{:08x}", i, b)?;
                 }
             }
             // PaletteView => create_palette_view(&gba.sysbus.palette_ram.mem),
             // TileView(bg) => create_tile_view(bg, &gba),
             Reset => {
-                println!("resetting cpu...");
+                writeln!(out, "resetting cpu...")?;
                 gba.cpu.reset();
-                println!("cpu is restarted!")
+                writeln!(out, "cpu is restarted!")?;
             }
             TraceToggle(flags) => {
                 if flags.contains(TraceFlags::TRACE_OPCODE) {
-                    println!("[*] opcode tracing not implemented")
+                    writeln!(out, "[*] opcode tracing not implemented")?;
                 }
                 if flags.contains(TraceFlags::TRACE_EXCEPTIONS) {
-                    gba.cpu.dbg.trace_exceptions = !gba.cpu.dbg.trace_exceptions;
-                    println!(
-                        "[*] exception tracing {}",
-                        if gba.cpu.dbg.trace_exceptions {
-                            "on"
-                        } else {
-                            "off"
-                        }
-                    )
+                    writeln!(out, "[*] exception tracing not implemented")?;
                 }
                 if flags.contains(TraceFlags::TRACE_DMA) {
                     gba.sysbus.io.dmac.trace = !gba.sysbus.io.dmac.trace;
-                    println!(
+                    writeln!(
+                        out,
                         "[*] dma tracing {}",
                         if gba.sysbus.io.dmac.trace {
                             "on"
                         } else {
                             "off"
                         }
-                    )
+                    )?;
                 }
                 if flags.contains(TraceFlags::TRACE_TIMERS) {
                     gba.sysbus.io.timers.trace = !gba.sysbus.io.timers.trace;
-                    println!(
+                    writeln!(
+                        out,
                         "[*] timer tracing {}",
                         if gba.sysbus.io.timers.trace {
                             "on"
                         } else {
                             "off"
                         }
-                    )
+                    )?;
                 }
             }
             SaveState(save_path) => {
                 let state = gba.save_state().expect("failed to serialize");
-                write_bin_file(&Path::new(&save_path), &state)
-                    .expect("failed to save state to file");
+                write_bin_file(&Path::new(&save_path), &state)?;
             }
             LoadState(load_path) => {
-                let save = read_bin_file(&Path::new(&load_path))
-                    .expect("failed to read save state from file");
-                gba.restore_state(&save).expect("failed to deserialize");
+                let save = read_bin_file(&Path::new(&load_path))?;
+                gba.restore_state(&save).map_err(|e| {
+                    DebuggerError::InvalidArgument(format!("failed to load the state: {}", e))
+                })?;
+            }
+            Dump(region, path) => {
+                let bytes = gba.sysbus.debug_get_bytes(region.range());
+                write_bin_file(&path, &bytes)?;
+                writeln!(out, "wrote {} bytes to {}", bytes.len(), path.display())?;
             }
             ListSymbols(Some(pattern)) => {
                 let matcher = SkimMatcherV2::default();
@@ -290,38 +341,27 @@ impl Debugger {
                     .iter()
                     .filter(|(k, _v)| matcher.fuzzy_match(k, &pattern).is_some())
                 {
-                    println!("{}=0x{:08x}", k, v);
+                    writeln!(out, "{}=0x{:08x}", k, v)?;
                 }
             }
             ListSymbols(None) => {
                 for (k, v) in self.symbols.iter() {
-                    println!("{}=0x{:08x}", k, v);
+                    writeln!(out, "{}=0x{:08x}", k, v)?;
                 }
             }
             AddSymbolsFile(elf_file, offset) => {
                 let offset = offset.unwrap_or(0);
-                if let Ok(elf_buffer) = read_bin_file(&elf_file) {
-                    if let Ok(elf) = goblin::elf::Elf::parse(&elf_buffer) {
-                        let strtab = elf.strtab;
-                        for sym in elf.syms.iter() {
-                            if let Some(Ok(name)) = strtab.get(sym.st_name) {
-                                self.symbols
-                                    .insert(name.to_owned(), offset + (sym.st_value as u32));
-                            } else {
-                                warn!("failed to parse symbol name sym {:?}", sym);
-                            }
-                        }
-                    } else {
-                        println!("[error] Failed to parse elf file!");
-                        return;
-                    }
-                } else {
-                    println!("[error] Can't read elf file!");
-                    return;
-                };
+                let elf_buffer = read_bin_file(&elf_file)?;
+                let symbols = elf::read_symbols(&elf_buffer).map_err(|e| {
+                    DebuggerError::InvalidArgument(format!("failed to parse the elf file: {}", e))
+                })?;
+                for (name, addr) in symbols {
+                    self.symbols.insert(name, offset + addr);
+                }
             }
-            _ => println!("Not Implemented",),
+            _ => writeln!(out, "Not Implemented")?,
         }
+        Ok(())
     }
 
     fn get_disassembler_args(
@@ -495,12 +535,12 @@ impl Debugger {
                     }
                     _ => {
                         return Err(DebuggerError::InvalidCommandFormat(
-                            "mwb [addr] [n]".to_string(),
+                            "mww [addr] [n]".to_string(),
                         ))
                     }
                 };
                 Ok(Command::MemWrite(
-                    MemWriteCommandSize::Half,
+                    MemWriteCommandSize::Word,
                     addr,
                     val as u32,
                 ))
@@ -594,6 +634,21 @@ impl Debugger {
                     }
                 }
             }
+            "dumpvram" | "dumpoam" | "dumppal" => {
+                let region = match command.as_ref() {
+                    "dumpvram" => DumpRegion::Vram,
+                    "dumpoam" => DumpRegion::Oam,
+                    "dumppal" => DumpRegion::Palette,
+                    _ => unreachable!(),
+                };
+                match args.as_slice() {
+                    [Value::Identifier(path)] => Ok(Command::Dump(region, PathBuf::from(path))),
+                    _ => Err(DebuggerError::InvalidCommandFormat(format!(
+                        "usage: {} <file>",
+                        command
+                    ))),
+                }
+            }
             "add-symbols-file" | "load-symbols" | "load-syms" => match args.len() {
                 1 => {
                     if let Value::Identifier(elf_file) = &args[0] {
@@ -7,8 +7,9 @@ use rustyline::Editor;
 
 use colored::*;
 
+use arm7tdmi::memory::{Addr, BusIO};
+
 use super::GameBoyAdvance;
-use super::{Addr, Bus};
 
 mod parser;
 use parser::{parse_expr, DerefType, Expr, Value};
@@ -26,6 +27,7 @@ pub enum DebuggerError {
     InvalidArgument(String),
     InvalidCommandFormat(String),
     IoError(::std::io::Error),
+    FmtError(::std::fmt::Error),
 }
 
 impl From<::std::io::Error> for DebuggerError {
@@ -34,7 +36,13 @@ impl From<::std::io::Error> for DebuggerError {
     }
 }
 
-type DebuggerResult<T> = Result<T, DebuggerError>;
+impl From<::std::fmt::Error> for DebuggerError {
+    fn from(e: ::std::fmt::Error) -> DebuggerError {
+        DebuggerError::FmtError(e)
+    }
+}
+
+pub type DebuggerResult<T> = Result<T, DebuggerError>;
 
 pub struct Debugger {
     running: bool,
@@ -51,19 +59,30 @@ impl Debugger {
         }
     }
 
-    pub fn check_breakpoint(&self, gba: &GameBoyAdvance) -> Option<u32> {
-        let next_pc = gba.cpu.get_next_pc();
-        for bp in &gba.cpu.dbg.breakpoints {
-            if *bp == next_pc {
-                return Some(next_pc);
-            }
+    /// Adds the symbols of the loaded cartridge (e.g from an ELF symbol table) to the symbol map
+    pub fn load_cartridge_symbols(&mut self, gba: &GameBoyAdvance) {
+        if let Some(symbols) = gba.sysbus.cartridge.get_symbols() {
+            self.symbols
+                .extend(symbols.iter().map(|(name, &addr)| (name.clone(), addr)));
         }
+    }
+
+    pub fn check_breakpoint(&self, gba: &GameBoyAdvance) -> Option<u32> {
+        gba.cpu.check_breakpoint()
+    }
 
-        None
+    pub fn add_breakpoint(&mut self, gba: &mut GameBoyAdvance, addr: u32) -> Option<usize> {
+        if !gba.cpu.breakpoints().contains(&addr) {
+            let new_index = gba.cpu.breakpoints().len();
+            gba.add_breakpoint(addr);
+            Some(new_index)
+        } else {
+            None
+        }
     }
 
     pub fn delete_breakpoint(&mut self, gba: &mut GameBoyAdvance, addr: u32) {
-        gba.cpu.dbg.breakpoints.retain(|&a| a != addr);
+        gba.remove_breakpoint(addr);
     }
 
     fn decode_reg(&self, s: &str) -> DebuggerResult<usize> {
@@ -106,13 +125,7 @@ impl Debugger {
         match arg {
             Value::Num(n) => Ok(*n),
             Value::Identifier(ident) => {
-                let symbol = if let Some(symbols) = gba.sysbus.cartridge.get_symbols() {
-                    symbols.get(ident)
-                } else {
-                    None
-                };
-
-                if let Some(address) = symbol {
+                if let Some(address) = self.symbols.get(ident) {
                     Ok(*address)
                 } else {
                     // otherwise, decode as register (TODO special token to separate symbol and register)
@@ -149,31 +162,46 @@ impl Debugger {
         Ok(())
     }
 
-    fn eval_expr(&mut self, gba: &mut GameBoyAdvance, expr: Expr) {
-        match expr {
-            Expr::Command(c, a) => match self.eval_command(gba, c, a) {
-                Ok(cmd) => {
-                    self.previous_command = Some(cmd.clone());
-                    self.run_command(gba, cmd)
-                }
-                Err(DebuggerError::InvalidCommand(c)) => {
-                    println!("{}: {:?}", "invalid command".red(), c)
-                }
-                Err(DebuggerError::InvalidArgument(m)) => {
-                    println!("{}: {}", "invalid argument".red(), m)
-                }
-                Err(DebuggerError::InvalidCommandFormat(m)) => {
-                    println!("help: {}", m.bright_yellow())
-                }
-                Err(e) => println!("{} {:?}", "failed to build command".red(), e),
-            },
-            Expr::Assignment(lvalue, rvalue) => match self.eval_assignment(gba, lvalue, rvalue) {
-                Err(DebuggerError::InvalidArgument(m)) => {
-                    println!("{}: {}", "assignment error".red(), m)
+    /// Parse and run a single debugger command, returning its textual output
+    pub fn execute_command(
+        &mut self,
+        gba: &mut GameBoyAdvance,
+        cmd: &str,
+    ) -> DebuggerResult<String> {
+        let mut out = String::new();
+        match parse_expr(cmd)? {
+            Expr::Command(c, a) => {
+                let cmd = self.eval_command(gba, c, a)?;
+                self.previous_command = Some(cmd.clone());
+                self.run_command(gba, cmd, &mut out)?;
+            }
+            Expr::Assignment(lvalue, rvalue) => self.eval_assignment(gba, lvalue, rvalue)?,
+            Expr::Empty => {
+                // an empty line repeats a single step
+                if let Some(Command::Step(1)) = self.previous_command {
+                    self.run_command(gba, Command::Step(1), &mut out)?;
+                } else {
+                    self.previous_command = None;
                 }
-                _ => (),
-            },
-            Expr::Empty => println!("Got empty expr"),
+            }
+        }
+        Ok(out)
+    }
+
+    fn print_result(result: DebuggerResult<String>) {
+        match result {
+            Ok(out) => print!("{}", out),
+            Err(DebuggerError::InvalidCommand(c)) => {
+                println!("{}: {:?}", "invalid command".red(), c)
+            }
+            Err(DebuggerError::InvalidArgument(m)) => {
+                println!("{}: {}", "invalid argument".red(), m)
+            }
+            Err(DebuggerError::InvalidCommandFormat(m)) => {
+                println!("help: {}", m.bright_yellow())
+            }
+            Err(DebuggerError::ParsingError(msg)) => println!("Parsing error: {}", msg),
+            Err(e) => println!("{} {:?}", "failed to run command".red(), e),
         }
     }
 
@@ -187,6 +215,7 @@ impl Debugger {
         script_file: Option<&str>,
     ) -> DebuggerResult<()> {
         println!("Welcome to rustboyadvance-NG debugger 😎!\n");
+        self.load_cartridge_symbols(gba);
         self.running = true;
         let mut rl = Editor::<()>::new();
         let _ = rl.load_history(".rustboyadvance_history");
@@ -195,29 +224,17 @@ impl Debugger {
             let file = File::open(path)?;
             let reader = BufReader::new(file);
             for line in reader.lines() {
-                let expr = parse_expr(&line.unwrap())?;
-                self.eval_expr(gba, expr);
+                Self::print_result(self.execute_command(gba, &line?));
             }
         }
         while self.running {
             let readline = rl.readline(&format!("({}) ᐅ ", "rustboyadvance-dbg".bold().cyan()));
             match readline {
                 Ok(line) => {
-                    if line.is_empty() {
-                        if let Some(Command::Step(1)) = self.previous_command {
-                            self.run_command(gba, Command::Step(1));
-                        } else {
-                            self.previous_command = None;
-                            continue;
-                        }
-                    }
-                    rl.add_history_entry(line.as_str());
-                    let expr = parse_expr(&line);
-                    match expr {
-                        Ok(expr) => self.eval_expr(gba, expr),
-                        Err(DebuggerError::ParsingError(msg)) => println!("Parsing error: {}", msg),
-                        _ => (),
+                    if !line.is_empty() {
+                        rl.add_history_entry(line.as_str());
                     }
+                    Self::print_result(self.execute_command(gba, &line));
                 }
                 Err(ReadlineError::Interrupted) => {
                     println!("CTRL-C");
@@ -237,3 +254,147 @@ impl Debugger {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::tests::{make_mock_gba, make_mock_gba_with_code};
+
+    fn make_gba() -> GameBoyAdvance {
+        make_mock_gba_with_code(&[0xeafffffe]) // b .
+    }
+
+    #[test]
+    fn test_execute_command() {
+        let mut gba = make_gba();
+        let mut debugger = Debugger::new();
+
+        let out = debugger.execute_command(&mut gba, "r0 = 0x1234").unwrap();
+        assert!(out.is_empty());
+        let out = debugger.execute_command(&mut gba, "info cpu").unwrap();
+        assert!(out.contains("PC at"));
+        assert!(out.contains("0x00001234"));
+        assert!(out.contains("CPSR"));
+
+        let out = debugger.execute_command(&mut gba, "info irq").unwrap();
+        assert!(out.contains("IME"));
+
+        let out = debugger
+            .execute_command(&mut gba, "break 0x08000000")
+            .unwrap();
+        assert!(out.contains("Added breakpoint [0] 0x08000000"));
+        let out = debugger.execute_command(&mut gba, "bl").unwrap();
+        assert!(out.contains("[0] 0x08000000"));
+
+        let out = debugger.execute_command(&mut gba, "step").unwrap();
+        assert!(out.contains("Executed at @0x"));
+        // an empty line repeats the step
+        let out = debugger.execute_command(&mut gba, "").unwrap();
+        assert!(out.contains("Executed at @0x"));
+
+        assert!(matches!(
+            debugger.execute_command(&mut gba, "no-such-command"),
+            Err(DebuggerError::InvalidCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_dump_palette() {
+        use crate::gpu::PALETTE_RAM_SIZE;
+
+        let mut gba = make_gba();
+        let mut debugger = Debugger::new();
+        gba.sysbus.write_16(0x0500_0002, 0x7fff);
+
+        let path = std::env::temp_dir().join(format!(
+            "rustboyadvance_test_dumppal_{}.bin",
+            std::process::id()
+        ));
+        let command = format!("dumppal {}", path.display());
+        let out = debugger.execute_command(&mut gba, &command).unwrap();
+        assert!(out.contains("wrote 1024 bytes"));
+
+        let dump = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dump.len(), PALETTE_RAM_SIZE);
+        assert_eq!(&dump[..4], &[0, 0, 0xff, 0x7f]);
+
+        assert!(matches!(
+            debugger.execute_command(&mut gba, "dumpvram"),
+            Err(DebuggerError::InvalidCommandFormat(_))
+        ));
+    }
+
+    /// Builds a minimal ELF with a single segment loaded at the start of the ROM,
+    /// and a symbol table holding `symbols`
+    fn make_elf(code: &[u8], symbols: &[(&str, u32)]) -> Vec<u8> {
+        const EHDR_SIZE: u32 = 52;
+        const PHDR_SIZE: u32 = 32;
+        const SHDR_SIZE: u32 = 40;
+        const SYM_SIZE: u32 = 16;
+        const LOAD_ADDR: u32 = 0x0800_0000;
+
+        fn push_words(elf: &mut Vec<u8>, words: &[u32]) {
+            for word in words {
+                elf.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYM_SIZE as usize];
+        for (name, value) in symbols {
+            push_words(&mut symtab, &[strtab.len() as u32, *value, 0]);
+            // global function, defined in section 1
+            symtab.extend_from_slice(&[0x12, 0, 1, 0]);
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+        }
+
+        let code_ofs = EHDR_SIZE + PHDR_SIZE;
+        let code_len = code.len() as u32;
+        let symtab_ofs = code_ofs + code_len;
+        let strtab_ofs = symtab_ofs + symtab.len() as u32;
+        let shdr_ofs = strtab_ofs + strtab.len() as u32;
+
+        // ELF32, little endian, ARM executable
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend_from_slice(&[2, 0, 40, 0]);
+        push_words(&mut elf, &[1, LOAD_ADDR, EHDR_SIZE, shdr_ofs, 0]);
+        for half in &[EHDR_SIZE, PHDR_SIZE, 1, SHDR_SIZE, 3, 2] {
+            elf.extend_from_slice(&(*half as u16).to_le_bytes());
+        }
+        // PT_LOAD
+        let phdr = [1, code_ofs, LOAD_ADDR, LOAD_ADDR, code_len, code_len, 5, 4];
+        push_words(&mut elf, &phdr);
+        elf.extend_from_slice(code);
+        elf.extend_from_slice(&symtab);
+        elf.extend_from_slice(&strtab);
+        // section headers: null, .symtab linked to .strtab, .strtab
+        let symtab_len = symtab.len() as u32;
+        let strtab_len = strtab.len() as u32;
+        push_words(&mut elf, &[0; 10]);
+        push_words(
+            &mut elf,
+            &[0, 2, 0, 0, symtab_ofs, symtab_len, 2, 1, 4, SYM_SIZE],
+        );
+        push_words(&mut elf, &[0, 3, 0, 0, strtab_ofs, strtab_len, 0, 0, 1, 0]);
+        elf
+    }
+
+    #[test]
+    fn test_elf_symbols() {
+        // b . ; b .
+        let mut code = 0xeafffffe_u32.to_le_bytes().to_vec();
+        code.extend_from_slice(&0xeafffffe_u32.to_le_bytes());
+        let elf = make_elf(&code, &[("main", 0x0800_0004)]);
+
+        let mut gba = make_mock_gba(&elf);
+
+        let mut debugger = Debugger::new();
+        debugger.load_cartridge_symbols(&gba);
+        assert_eq!(debugger.symbols.get("main"), Some(&0x0800_0004));
+
+        let out = debugger.execute_command(&mut gba, "break main").unwrap();
+        assert!(out.contains("Added breakpoint [0] 0x08000004"));
+    }
+}
@@ -1,3 +1,58 @@
+use crate::gpu::{Gpu, Rgb15};
+
+impl Gpu {
+    /// Returns the BG and OBJ palettes, 256 colors each, without special casing transparent colors
+    pub fn dump_palette(&self) -> ([Rgb15; 256], [Rgb15; 256]) {
+        let color = |index: usize| {
+            let value =
+                u16::from_le_bytes([self.palette_ram[2 * index], self.palette_ram[2 * index + 1]]);
+            Rgb15(value & 0x7fff)
+        };
+        let mut bg_palette = [Rgb15::BLACK; 256];
+        let mut obj_palette = [Rgb15::BLACK; 256];
+        for (i, (bg, obj)) in bg_palette
+            .iter_mut()
+            .zip(obj_palette.iter_mut())
+            .enumerate()
+        {
+            *bg = color(i);
+            *obj = color(256 + i);
+        }
+        (bg_palette, obj_palette)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sched::Scheduler;
+    use arm7tdmi::memory::BusIO;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_dump_palette() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+
+        for i in 0..512 {
+            gpu.write_16(0x0500_0000 + 2 * i, (i * 0x41) as u16);
+        }
+        let (bg_palette, obj_palette) = gpu.dump_palette();
+        for i in 0..256 {
+            assert_eq!(bg_palette[i], Rgb15((i * 0x41) as u16 & 0x7fff));
+            assert_eq!(obj_palette[i], Rgb15(((256 + i) * 0x41) as u16 & 0x7fff));
+        }
+
+        // write the palette back through the bus
+        for i in 0..256 {
+            gpu.write_16(0x0500_0000 + 2 * i as u32, obj_palette[i].0);
+            gpu.write_16(0x0500_0200 + 2 * i as u32, bg_palette[i].0);
+        }
+        assert_eq!(gpu.dump_palette(), (obj_palette, bg_palette));
+    }
+}
+
 // use sdl2::event::Event;
 // use sdl2::pixels::Color;
 // use sdl2::rect::{Point, Rect};
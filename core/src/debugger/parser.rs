@@ -61,7 +61,8 @@ fn parse_boolean<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Val
 
 fn parse_identifier<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Value, E> {
     map(
-        take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+        // file names are passed as identifiers as well
+        take_while1(|c: char| c.is_alphanumeric() || "_-./\\:".contains(c)),
         |s: &str| Value::Identifier(String::from(s)),
     )(i)
 }
@@ -161,53 +162,44 @@ mod tests {
 
     #[test]
     fn test_parse_empty_expr() {
-        assert_eq!(parse_expr("   "), Ok(Expr::Empty));
+        assert_eq!(parse_expr("   ").unwrap(), Expr::Empty);
     }
 
     #[test]
     fn test_parse_command_expr() {
         assert_eq!(
-            parse_expr("command"),
-            Ok(Expr::Command(
-                Value::Identifier("command".to_string()),
-                vec![]
-            ))
+            parse_expr("command").unwrap(),
+            Expr::Command(Value::Identifier("command".to_string()), vec![])
         );
         assert_eq!(
-            parse_expr("command   arg0   0x1337   true  "),
-            Ok(Expr::Command(
+            parse_expr("command   arg0   0x1337   true  ").unwrap(),
+            Expr::Command(
                 Value::Identifier("command".to_string()),
                 vec![
                     Value::Identifier("arg0".to_string()),
                     Value::Num(0x1337),
                     Value::Boolean(true)
                 ]
-            ))
+            )
         );
     }
 
     #[test]
     fn test_parse_assignment_expr() {
         assert_eq!(
-            parse_expr("  pc   = 0x1337 "),
-            Ok(Expr::Assignment(
-                Value::Identifier("pc".to_string()),
-                Value::Num(0x1337)
-            ))
+            parse_expr("  pc   = 0x1337 ").unwrap(),
+            Expr::Assignment(Value::Identifier("pc".to_string()), Value::Num(0x1337))
         );
         assert_eq!(
-            parse_expr("aaa   = false "),
-            Ok(Expr::Assignment(
-                Value::Identifier("aaa".to_string()),
-                Value::Boolean(false)
-            ))
+            parse_expr("aaa   = false ").unwrap(),
+            Expr::Assignment(Value::Identifier("aaa".to_string()), Value::Boolean(false))
         );
         assert_eq!(
-            parse_expr("  pc   = lr "),
-            Ok(Expr::Assignment(
+            parse_expr("  pc   = lr ").unwrap(),
+            Expr::Assignment(
                 Value::Identifier("pc".to_string()),
                 Value::Identifier("lr".to_string())
-            ))
+            )
         );
     }
 
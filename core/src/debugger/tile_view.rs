@@ -1,3 +1,71 @@
+use crate::gpu::{Gpu, PixelFormat, Rgb15};
+
+impl Gpu {
+    /// Decode an 8x8 tile to colors for tile viewers, in row-major order.
+    /// `char_base` is the VRAM offset of the tileset, tiles above 0x10000 use the OBJ palette.
+    /// `palette_bank` only applies to 4bpp tiles, pixels of color 0 are transparent.
+    pub fn dump_tile(
+        &mut self,
+        char_base: u32,
+        tile_index: u32,
+        format: PixelFormat,
+        palette_bank: u32,
+    ) -> [Rgb15; 64] {
+        let (tile_size, palette_bank) = match format {
+            PixelFormat::BPP4 => (0x20, palette_bank),
+            PixelFormat::BPP8 => (0x40, 0),
+        };
+        let tile_addr = char_base + tile_index * tile_size;
+        let palette_offset = if tile_addr >= 0x10000 { 0x200 } else { 0 };
+
+        let mut colors = [Rgb15::TRANSPARENT; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                let index = self.read_pixel_index(tile_addr, x, y, format);
+                colors[(y * 8 + x) as usize] =
+                    self.get_palette_color(index as u32, palette_bank, palette_offset);
+            }
+        }
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sched::Scheduler;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_dump_tile_4bpp() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+
+        // tile #3 of charblock 1, every row counts up the colors 0..8
+        let tile_addr = 0x4000 + 3 * 0x20;
+        for y in 0..8 {
+            gpu.vram[tile_addr + y * 4..][..4].copy_from_slice(&[0x10, 0x32, 0x54, 0x76]);
+        }
+        // palette bank 2
+        for i in 0..16 {
+            let color = Rgb15::from_rgb(i, 0, 31 - i);
+            gpu.palette_ram[0x40 + 2 * i as usize..][..2].copy_from_slice(&color.0.to_le_bytes());
+        }
+
+        let colors = gpu.dump_tile(0x4000, 3, PixelFormat::BPP4, 2);
+        for y in 0..8 {
+            assert_eq!(colors[y * 8], Rgb15::TRANSPARENT);
+            for x in 1..8 {
+                assert_eq!(
+                    colors[y * 8 + x],
+                    Rgb15::from_rgb(x as u16, 0, 31 - x as u16)
+                );
+            }
+        }
+    }
+}
+
 // use std::time::Duration;
 
 // use sdl2::event::Event;
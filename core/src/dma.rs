@@ -57,6 +57,11 @@ impl DmaChannel {
         self.running
     }
 
+    /// The source and destination addresses the next unit is transferred from and to
+    pub fn internal_addresses(&self) -> (u32, u32) {
+        (self.internal.src_addr, self.internal.dst_addr)
+    }
+
     pub fn write_src_low(&mut self, low: u16) {
         let src = self.src;
         self.src = (src & 0xffff0000) | (low as u32);
@@ -83,6 +88,12 @@ impl DmaChannel {
         self.wc = value as u32;
     }
 
+    /// Bits 0-4 are unused and the Game Pak DRQ bit only exists on DMA3, they read back as zero
+    pub fn read_dma_ctrl(&self) -> u16 {
+        let mask = if self.id == 3 { 0xffe0 } else { 0xf7e0 };
+        self.ctrl.0 & mask
+    }
+
     pub fn write_dma_ctrl(&mut self, value: u16, #[cfg(feature = "debugger")] trace: bool) -> bool {
         let ctrl = DmaChannelCtrl(value);
         let timing = ctrl.timing();
@@ -103,13 +114,19 @@ impl DmaChannel {
             }
             self.running = true;
             start_immediately = timing == 0;
-            self.internal.src_addr = self.src;
-            self.internal.dst_addr = self.dst;
-            self.internal.count = self.wc;
             self.fifo_mode = timing == 3
                 && ctrl.repeat()
                 && (self.id == 1 || self.id == 2)
                 && (self.dst == REG_FIFO_A || self.dst == REG_FIFO_B);
+            // the addresses are force aligned to the transfer width
+            let align_mask = if ctrl.is_32bit() || self.fifo_mode {
+                !3
+            } else {
+                !1
+            };
+            self.internal.src_addr = self.src & align_mask;
+            self.internal.dst_addr = self.dst & align_mask;
+            self.internal.count = self.wc;
         }
         if !ctrl.is_enabled() {
             self.running = false;
@@ -119,7 +136,7 @@ impl DmaChannel {
     }
 
     fn transfer(&mut self, sb: &mut SysBus) {
-        let word_size = if self.ctrl.is_32bit() { 4 } else { 2 };
+        let word_size: u32 = if self.ctrl.is_32bit() { 4 } else { 2 };
         let count = match self.internal.count {
             0 => match self.id {
                 3 => 0x1_0000,
@@ -140,42 +157,53 @@ impl DmaChannel {
 
         let fifo_mode = self.fifo_mode;
 
+        // the dma takes 2 internal cycles to start, or 4 if it is from gamepak to gamepak
+        let internal_cycles =
+            if self.internal.src_addr >= 0x0800_0000 && self.internal.dst_addr >= 0x0800_0000 {
+                4
+            } else {
+                2
+            };
+        for _ in 0..internal_cycles {
+            sb.idle_cycle();
+        }
+
         let src_adj = match self.ctrl.src_adj() {
-            /* Increment */ 0 => word_size,
-            /* Decrement */ 1 => 0 - word_size,
+            /* Increment, 3 is prohibited and behaves the same */ 0 | 3 => word_size,
+            /* Decrement */ 1 => word_size.wrapping_neg(),
             /* Fixed */ 2 => 0,
-            _ => panic!("forbidden DMA source address adjustment"),
+            _ => unreachable!(),
         };
         let dst_adj = match self.ctrl.dst_adj() {
             /* Increment[+Reload] */ 0 | 3 => word_size,
-            /* Decrement */ 1 => 0 - word_size,
+            /* Decrement */ 1 => word_size.wrapping_neg(),
             /* Fixed */ 2 => 0,
-            _ => panic!("forbidden DMA dest address adjustment"),
+            _ => unreachable!(),
         };
 
         let mut access = MemoryAccess::NonSeq;
         if fifo_mode {
             for _ in 0..4 {
-                let v = sb.load_32(self.internal.src_addr & !3, access);
-                sb.store_32(self.internal.dst_addr & !3, v, access);
+                let v = sb.load_32(self.internal.src_addr, access);
+                sb.store_32(self.internal.dst_addr, v, access);
                 access = MemoryAccess::Seq;
-                self.internal.src_addr += 4;
+                self.internal.src_addr = self.internal.src_addr.wrapping_add(4);
             }
         } else if word_size == 4 {
             for _ in 0..count {
-                let w = sb.load_32(self.internal.src_addr & !3, access);
-                sb.store_32(self.internal.dst_addr & !3, w, access);
+                let w = sb.load_32(self.internal.src_addr, access);
+                sb.store_32(self.internal.dst_addr, w, access);
                 access = MemoryAccess::Seq;
-                self.internal.src_addr += src_adj;
-                self.internal.dst_addr += dst_adj;
+                self.internal.src_addr = self.internal.src_addr.wrapping_add(src_adj);
+                self.internal.dst_addr = self.internal.dst_addr.wrapping_add(dst_adj);
             }
         } else {
             for _ in 0..count {
-                let hw = sb.load_16(self.internal.src_addr & !1, access);
-                sb.store_16(self.internal.dst_addr & !1, hw, access);
+                let hw = sb.load_16(self.internal.src_addr, access);
+                sb.store_16(self.internal.dst_addr, hw, access);
                 access = MemoryAccess::Seq;
-                self.internal.src_addr += src_adj;
-                self.internal.dst_addr += dst_adj;
+                self.internal.src_addr = self.internal.src_addr.wrapping_add(src_adj);
+                self.internal.dst_addr = self.internal.dst_addr.wrapping_add(dst_adj);
             }
         }
         if self.ctrl.is_triggering_irq() {
@@ -183,8 +211,9 @@ impl DmaChannel {
         }
         if self.ctrl.repeat() {
             /* reload */
+            self.internal.count = self.wc;
             if 3 == self.ctrl.dst_adj() {
-                self.internal.dst_addr = self.dst;
+                self.internal.dst_addr = self.dst & !(word_size - 1);
             }
         } else {
             self.running = false;
@@ -261,13 +290,29 @@ impl DmaController {
     }
 
     pub fn notify_from_gpu(&mut self, timing: u16) {
-        for i in 0..4 {
+        // the special timing of DMA1/2 is the sound FIFO, only DMA3 does video capture
+        let channels = if timing == TIMING_VIDEO_CAPTURE {
+            3..4
+        } else {
+            0..4
+        };
+        for i in channels {
             if self.channels[i].ctrl.is_enabled() && self.channels[i].ctrl.timing() == timing {
                 self.pending_set |= 1 << i;
             }
         }
     }
 
+    /// Video capture stops by itself on line 162
+    pub fn stop_video_capture(&mut self) {
+        let channel = &mut self.channels[3];
+        if channel.ctrl.is_enabled() && channel.ctrl.timing() == TIMING_VIDEO_CAPTURE {
+            channel.running = false;
+            channel.ctrl.set_enabled(false);
+            self.deactivate_channel(3);
+        }
+    }
+
     pub fn notify_sound_fifo(&mut self, fifo_addr: u32) {
         for i in 1..=2 {
             if self.channels[i].ctrl.is_enabled()
@@ -291,9 +336,11 @@ impl DmaController {
 
 pub const TIMING_VBLANK: u16 = 1;
 pub const TIMING_HBLANK: u16 = 2;
+pub const TIMING_VIDEO_CAPTURE: u16 = 3;
 
 pub trait DmaNotifer {
     fn notify(&mut self, timing: u16);
+    fn stop_video_capture(&mut self);
 }
 
 bitfield! {
@@ -1,25 +1,40 @@
 /// Struct containing everything
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
 
-use bincode;
+use bincode::{self, Options};
 use serde::{Deserialize, Serialize};
 
 use crate::gdb_support::{gdb_thread::start_gdb_server_thread, DebuggerRequestHandler};
 
-use super::cartridge::Cartridge;
+use super::bios::{hle, BiosProtectionCallback};
+use super::cartridge::{Cartridge, CartridgeInfo};
+use super::cheats::{self, CheatEngine, CheatParseError};
 use super::dma::DmaController;
 use super::gpu::*;
 use super::interrupt::*;
 use super::iodev::*;
+use super::keypad::InputScript;
+use super::overrides;
+use super::replay::Replay;
 use super::sched::{EventType, Scheduler, SchedulerConnect, SharedScheduler};
-use super::sound::SoundController;
-use super::sysbus::SysBus;
+use super::sound::{ChannelMask, ResamplerKind, SoundController};
+#[cfg(feature = "profiler")]
+use super::sysbus::MemoryProfile;
+use super::sysbus::{SongTableHook, SysBus, WaitstateProfile, WatchKind, SONG_TABLE_ENTRY_SIZE};
 use super::timer::Timers;
 
+use super::gpu::interface::DynVideoInterface;
 use super::sound::interface::DynAudioInterface;
+use super::{GBAError, GBAResult, SaveStateError};
 
-use arm7tdmi::{self, Arm7tdmiCore};
+use arm7tdmi::arm::ArmInstruction;
+use arm7tdmi::memory::{Addr, DebugRead, DebugWrite};
+use arm7tdmi::psr::RegPSR;
+use arm7tdmi::thumb::ThumbInstruction;
+use arm7tdmi::{self, Arm7tdmiCore, CpuState, InstructionDecoder};
 use rustboyadvance_utils::Shared;
 
 pub struct GameBoyAdvance {
@@ -29,7 +44,92 @@ pub struct GameBoyAdvance {
     pub(crate) scheduler: SharedScheduler,
     interrupt_flags: SharedInterruptFlags,
     audio_interface: DynAudioInterface,
+    video_interface: Option<DynVideoInterface>,
     pub(crate) debugger: Option<DebuggerRequestHandler>,
+    cheats: CheatEngine,
+    input_script: InputScript,
+    /// the keys that were set before the input script took over
+    keyinput_before_script: Option<u16>,
+    recording: Option<Replay>,
+    /// cycles emulated by `frame()`, see `set_target_refresh`
+    cycles_per_frame: usize,
+    /// cycles the previous frame ran past its end, the next frame is shortened by as much
+    frame_overshoot: usize,
+    /// cycles emulated so far by a frame that stopped early on a breakpoint, its input was already applied
+    frame_progress: Option<usize>,
+    /// cycles emulated since the emulator was created, up to `counted_timestamp`, for `stats()`
+    total_cycles: u64,
+    /// the scheduler timestamp `total_cycles` was last brought up to date at
+    counted_timestamp: usize,
+    /// frames emulated since the emulator was created, for `stats()`
+    frame_count: u64,
+    /// `frame_hash()` of the last two completed frames, for `frame_changed()`
+    frame_hashes: [u64; 2],
+}
+
+/// The reason `GameBoyAdvance::run_for` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The cpu is about to execute the instruction at a breakpoint
+    Breakpoint(Addr),
+    /// A watched address was accessed, `old` and `new` hold the value before and after the access
+    Watchpoint { addr: Addr, old: u32, new: u32 },
+    /// Ran for the requested amount of cycles
+    CyclesExpired,
+}
+
+/// The reason `GameBoyAdvance::run_to_pc` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    /// The cpu is about to execute the target instruction, after running for `cycles`
+    ReachedPc { cycles: usize },
+    /// The cycle budget ran out before reaching the target
+    CyclesExpired,
+}
+
+/// What happened during `GameBoyAdvance::frame_detailed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameOutcome {
+    /// Cycles actually emulated, less than a full frame if the frame was stopped early or resumed
+    pub cycles: usize,
+    /// Anything but `StopReason::CyclesExpired` means the frame was stopped early
+    pub stop_reason: StopReason,
+    /// Number of cheats written to memory at the end of the frame
+    pub cheats_applied: usize,
+}
+
+impl FrameOutcome {
+    pub fn stopped_early(&self) -> bool {
+        self.stop_reason != StopReason::CyclesExpired
+    }
+}
+
+/// The instruction executed by `GameBoyAdvance::step_instruction`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    pub addr: Addr,
+    pub opcode: u32,
+    pub mnemonic: String,
+    /// The state the instruction was executed in, it may have switched the cpu to the other state
+    pub state: CpuState,
+    /// The instruction wrote the pc, e.g a branch, an exception or a load to the pc.
+    /// A branch to the instruction that follows isn't reported.
+    pub pipeline_flushed: bool,
+    /// Cycles spent on the instruction, including any DMA transfer or halt that delayed it
+    pub cycles: usize,
+}
+
+/// Timing counters for profiling, see `GameBoyAdvance::stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmulatorStats {
+    /// Cycles emulated since the emulator was created, unlike `timestamp` it isn't reset
+    pub total_cycles: u64,
+    /// Frames that ran to completion since the emulator was created
+    pub frame_count: u64,
+    /// The scanline the gpu is on (VCOUNT)
+    pub scanline: usize,
+    /// The current scheduler timestamp, in cycles since the last reset
+    pub timestamp: usize,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +141,131 @@ struct SaveState {
     iwram: Box<[u8]>,
     interrupt_flags: u16,
     cpu_state: arm7tdmi::SavedCpuState,
+    /// Carried over to the next frame, replays started from the savestate depend on it
+    frame_overshoot: usize,
+}
+
+const SAVESTATE_MAGIC: [u8; 4] = *b"RBAS";
+/// Bump whenever `SaveState` changes, and register a `SavestateMigration` from the previous version
+pub const SAVESTATE_VERSION: u32 = 1;
+const SAVESTATE_HEADER_SIZE: usize = 16;
+/// Byte order mark stored in the header, payloads are little-endian whatever the host byte order is
+const SAVESTATE_BYTE_ORDER_MARK: u32 = 0x0102_0304;
+
+/// The bincode configuration of savestate payloads: little-endian, fixed size integers.
+/// Spelled out so the layout can't change under us with the bincode defaults.
+fn savestate_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_little_endian()
+}
+
+/// Upgrades a savestate payload serialized by an older version of the emulator
+pub trait SavestateMigration {
+    /// The payload version this migration accepts, it produces a payload of version `from_version() + 1`
+    fn from_version(&self) -> u32;
+    fn migrate(&self, payload: Vec<u8>) -> Result<Vec<u8>, SaveStateError>;
+}
+
+/// Every migration between savestate versions, none so far
+fn savestate_migrations() -> Vec<Box<dyn SavestateMigration>> {
+    Vec::new()
+}
+
+fn game_code_bytes(game_code: &str) -> [u8; 4] {
+    let mut bytes = [0; 4];
+    for (dst, src) in bytes.iter_mut().zip(game_code.bytes()) {
+        *dst = *src;
+    }
+    bytes
+}
+
+/// Prepend the savestate header: magic, version, the game code of the cartridge and the byte order mark.
+/// Every field is little-endian.
+fn encode_savestate(game_code: &str, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(SAVESTATE_HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&SAVESTATE_MAGIC);
+    bytes.extend_from_slice(&SAVESTATE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&game_code_bytes(game_code));
+    bytes.extend_from_slice(&SAVESTATE_BYTE_ORDER_MARK.to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Validate the savestate header and decode the payload, migrating it to the current version if needed.
+/// If `expected_game_code` is given, the savestate must have been created for that game.
+fn decode_savestate(
+    bytes: &[u8],
+    expected_game_code: Option<[u8; 4]>,
+) -> Result<Box<SaveState>, SaveStateError> {
+    if bytes.len() < SAVESTATE_MAGIC.len() || bytes[0..4] != SAVESTATE_MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    if bytes.len() < SAVESTATE_HEADER_SIZE {
+        return Err(SaveStateError::Deserialize("truncated header".to_string()));
+    }
+    let mut version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    let game_code = [bytes[8], bytes[9], bytes[10], bytes[11]];
+    if let Some(expected) = expected_game_code {
+        if game_code != expected {
+            return Err(SaveStateError::GameCodeMismatch {
+                have: String::from_utf8_lossy(&game_code).into_owned(),
+                want: String::from_utf8_lossy(&expected).into_owned(),
+            });
+        }
+    }
+    if version > SAVESTATE_VERSION {
+        return Err(SaveStateError::VersionMismatch {
+            have: version,
+            want: SAVESTATE_VERSION,
+        });
+    }
+
+    let mark = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+    if mark != SAVESTATE_BYTE_ORDER_MARK {
+        return Err(SaveStateError::ByteOrderMismatch(mark));
+    }
+
+    let mut payload = bytes[SAVESTATE_HEADER_SIZE..].to_vec();
+    let migrations = savestate_migrations();
+    while version < SAVESTATE_VERSION {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from_version() == version)
+            .ok_or(SaveStateError::VersionMismatch {
+                have: version,
+                want: SAVESTATE_VERSION,
+            })?;
+        payload = migration.migrate(payload)?;
+        version += 1;
+    }
+
+    Ok(savestate_options().deserialize_from(&payload[..])?)
+}
+
+/// Top-level parts of the emulator state that are compared by `diff_savestates`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Cpu,
+    Gpu,
+    Apu,
+    Dma,
+    Timers,
+    Interrupts,
+    Scheduler,
+    /// Rest of the io registers (KEYINPUT, WAITCNT, HALTCNT, etc)
+    Io,
+    Ewram,
+    Iwram,
+    Cartridge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsystemDiff {
+    pub subsystem: Subsystem,
+    /// Human readable description of what changed
+    pub summary: String,
 }
 
 #[derive(Debug, PartialEq)]
@@ -69,10 +294,13 @@ impl GameBoyAdvance {
         audio_interface: DynAudioInterface,
     ) -> GameBoyAdvance {
         // Warn the user if the bios is not the real one
-        match check_real_bios(&bios_rom) {
-            true => info!("Verified bios rom"),
-            false => warn!("This is not the real bios rom, some games may not be compatible"),
-        };
+        if hle::is_hle_bios(&bios_rom) {
+            info!("Using the HLE bios");
+        } else if check_real_bios(&bios_rom) {
+            info!("Verified bios rom");
+        } else {
+            warn!("This is not the real bios rom, some games may not be compatible");
+        }
 
         let interrupt_flags = Rc::new(Cell::new(IrqBitmask(0)));
         let mut scheduler = Scheduler::new_shared();
@@ -107,9 +335,21 @@ impl GameBoyAdvance {
             sysbus,
             io_devs,
             audio_interface,
+            video_interface: None,
             scheduler,
             interrupt_flags,
             debugger: None,
+            cheats: CheatEngine::default(),
+            input_script: InputScript::new(),
+            keyinput_before_script: None,
+            recording: None,
+            cycles_per_frame: CYCLES_FULL_REFRESH,
+            total_cycles: 0,
+            counted_timestamp: 0,
+            frame_count: 0,
+            frame_hashes: [0; 2],
+            frame_overshoot: 0,
+            frame_progress: None,
         };
 
         gba.sysbus.init(gba.cpu.weak_ptr());
@@ -117,17 +357,30 @@ impl GameBoyAdvance {
         gba
     }
 
+    /// Create a GameBoyAdvance that doesn't need a bios dump, the bios software interrupts are emulated in the core.
+    /// Since there is no boot sequence, the emulation starts at the cartridge entry point.
+    pub fn new_hle(gamepak: Cartridge, audio_interface: DynAudioInterface) -> GameBoyAdvance {
+        let mut gba = GameBoyAdvance::new(hle::hle_bios_rom(), gamepak, audio_interface);
+        gba.skip_bios();
+        gba
+    }
+
     pub fn from_saved_state(
         savestate: &[u8],
         bios: Box<[u8]>,
         rom: Box<[u8]>,
         audio_interface: DynAudioInterface,
-    ) -> bincode::Result<GameBoyAdvance> {
-        let decoded: Box<SaveState> = bincode::deserialize_from(savestate)?;
+    ) -> GBAResult<GameBoyAdvance> {
+        let expected_game_code = rom
+            .get(0xac..0xb0)
+            .map(|code| [code[0], code[1], code[2], code[3]]);
+        let decoded = decode_savestate(savestate, expected_game_code)?;
 
         let interrupts = Rc::new(Cell::new(IrqBitmask(decoded.interrupt_flags)));
         let scheduler = decoded.scheduler.make_shared();
+        let counted_timestamp = scheduler.timestamp();
         let mut io_devs = Shared::new(decoded.io_devs);
+        io_devs.sound.reset_dc_filter();
         let mut cartridge = decoded.cartridge;
         cartridge.set_rom_bytes(rom);
         io_devs.connect_irq(interrupts.clone());
@@ -153,12 +406,24 @@ impl GameBoyAdvance {
             io_devs,
             interrupt_flags: interrupts,
             audio_interface,
+            video_interface: None,
             scheduler,
             debugger: None,
+            cheats: CheatEngine::default(),
+            input_script: InputScript::new(),
+            keyinput_before_script: None,
+            recording: None,
+            cycles_per_frame: CYCLES_FULL_REFRESH,
+            total_cycles: 0,
+            counted_timestamp,
+            frame_count: 0,
+            frame_hashes: [0; 2],
+            frame_overshoot: decoded.frame_overshoot,
+            frame_progress: None,
         })
     }
 
-    pub fn save_state(&self) -> bincode::Result<Vec<u8>> {
+    pub fn save_state(&self) -> Result<Vec<u8>, SaveStateError> {
         let s = SaveState {
             cpu_state: self.cpu.save_state(),
             io_devs: self.io_devs.clone_inner(),
@@ -167,17 +432,33 @@ impl GameBoyAdvance {
             ewram: Box::from(self.sysbus.get_ewram()),
             interrupt_flags: self.interrupt_flags.get().value(),
             scheduler: self.scheduler.clone_inner(),
+            frame_overshoot: self.frame_overshoot,
         };
 
-        bincode::serialize(&s)
+        let payload = savestate_options().serialize(&s)?;
+        Ok(encode_savestate(
+            &self.sysbus.cartridge.header.game_code,
+            &payload,
+        ))
     }
 
-    pub fn restore_state(&mut self, bytes: &[u8]) -> bincode::Result<()> {
-        let decoded: Box<SaveState> = bincode::deserialize_from(bytes)?;
-
+    /// Restores a savestate created by `save_state`, the savestate must belong to the currently loaded game
+    pub fn restore_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let expected_game_code = game_code_bytes(&self.sysbus.cartridge.header.game_code);
+        let decoded = decode_savestate(bytes, Some(expected_game_code))?;
+        let output_scale = self.io_devs.gpu.output_scale();
+        let color_correction = self.io_devs.gpu.color_correction();
+        let channel_mask = self.io_devs.sound.channel_mask();
+        let dc_filter = self.io_devs.sound.dc_filter_enabled();
+        let resampler = self.io_devs.sound.resampler_kind();
+
+        self.count_cycles();
         self.cpu.restore_state(decoded.cpu_state);
         self.scheduler = Scheduler::make_shared(decoded.scheduler);
+        self.counted_timestamp = self.scheduler.timestamp();
         self.interrupt_flags = Rc::new(Cell::new(IrqBitmask(decoded.interrupt_flags)));
+        self.frame_overshoot = decoded.frame_overshoot;
+        self.frame_progress = None;
         self.io_devs = Shared::new(decoded.io_devs);
         // Restore memory state
         self.cpu.set_memory_interface(self.sysbus.clone());
@@ -189,6 +470,12 @@ impl GameBoyAdvance {
         self.sysbus.set_io_devices(self.io_devs.clone());
         self.sysbus.cartridge.update_from(decoded.cartridge);
         self.sysbus.init(self.cpu.weak_ptr());
+        self.io_devs.gpu.set_output_scale(output_scale);
+        self.io_devs.gpu.set_color_correction(color_correction);
+        self.io_devs.sound.set_channel_mask(channel_mask);
+        self.io_devs.sound.set_dc_filter(dc_filter);
+        self.io_devs.sound.reset_dc_filter();
+        self.io_devs.sound.set_resampler(resampler);
 
         Ok(())
     }
@@ -201,6 +488,10 @@ impl GameBoyAdvance {
         self.sysbus.cartridge.header.game_code.clone()
     }
 
+    pub fn get_cartridge_info(&self) -> CartridgeInfo {
+        self.sysbus.cartridge.info()
+    }
+
     #[inline]
     pub fn get_key_state(&mut self) -> &u16 {
         &self.sysbus.io.keyinput
@@ -211,20 +502,397 @@ impl GameBoyAdvance {
         &mut self.sysbus.io.keyinput
     }
 
+    /// Cycles emulated since `total_cycles` was last brought up to date, whichever way the emulation was run
+    fn uncounted_cycles(&self) -> u64 {
+        (self.scheduler.timestamp() - self.counted_timestamp) as u64
+    }
+
+    /// Bring `total_cycles` up to date, before the scheduler timestamp is reset or restored
+    fn count_cycles(&mut self) {
+        self.total_cycles += self.uncounted_cycles();
+    }
+
+    /// Read the timing counters, e.g for profiling
+    pub fn stats(&self) -> EmulatorStats {
+        EmulatorStats {
+            total_cycles: self.total_cycles + self.uncounted_cycles(),
+            frame_count: self.frame_count,
+            scanline: self.io_devs.gpu.vcount,
+            timestamp: self.scheduler.timestamp(),
+        }
+    }
+
+    /// Read the per-region memory access counters
+    #[cfg(feature = "profiler")]
+    pub fn memory_profile(&self) -> MemoryProfile {
+        self.sysbus.profile.clone()
+    }
+
+    /// Inspect IE, IF and IME along with the CPSR I bit
+    pub fn interrupt_state(&self) -> InterruptState {
+        let intc = &self.io_devs.intc;
+        InterruptState {
+            interrupt_enable: intc.interrupt_enable,
+            interrupt_flags: self.interrupt_flags.get(),
+            interrupt_master_enable: intc.interrupt_master_enable,
+            cpu_irq_enabled: !self.cpu.cpsr.irq_disabled(),
+        }
+    }
+
+    /// Set the IF bit of `interrupt` as if the hardware requested it.
+    /// Whether an IRQ is taken is still up to IE, IME and the CPSR.
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        signal_irq(&self.interrupt_flags, interrupt);
+    }
+
+    /// Address/value pairs of every known io register, read without side effects.
+    /// Use `iodev::io_register_name` to label them.
+    pub fn dump_io_registers(&mut self) -> Vec<(Addr, u16)> {
+        self.io_devs.dump_registers()
+    }
+
+    /// Select the interpolation used to convert audio to the sample rate of the audio interface.
+    /// Cheaper resamplers suit low-power targets, the windowed sinc resampler gives the best quality.
+    pub fn set_resampler(&mut self, kind: ResamplerKind) {
+        self.sysbus.io.sound.set_resampler(kind);
+    }
+
+    pub fn resampler_kind(&self) -> ResamplerKind {
+        self.sysbus.io.sound.resampler_kind()
+    }
+
+    /// Filter the DC offset out of the audio output, like the capacitor on the GBA's audio output does.
+    /// On by default, turning it off gives the raw mixer output.
+    pub fn set_dc_filter(&mut self, enabled: bool) {
+        self.sysbus.io.sound.set_dc_filter(enabled);
+    }
+
+    /// Mute the sound channels missing from `mask`, they keep running but aren't mixed into the output
+    pub fn set_audio_channel_mask(&mut self, mask: ChannelMask) {
+        self.sysbus.io.sound.set_channel_mask(mask);
+    }
+
+    /// Feed `duration_ms` of silence to the audio interface without running the emulation.
+    /// Paused frontends can call this to avoid audio buffer underruns.
+    /// The fraction of a sample that doesn't fit is carried over to the next call.
+    pub fn pump_silence(&mut self, duration_ms: u32) {
+        self.sysbus
+            .io
+            .sound
+            .fill_silence_ms(duration_ms, &mut self.audio_interface);
+    }
+
+    /// Feed `samples` silent stereo samples to the audio interface, like `pump_silence`
+    pub fn pump_silence_samples(&mut self, samples: usize) {
+        self.sysbus
+            .io
+            .sound
+            .fill_silence(samples, &mut self.audio_interface);
+    }
+
+    /// The sample rate of the audio interface
+    pub fn audio_sample_rate(&self) -> i32 {
+        self.audio_interface.get_sample_rate()
+    }
+
+    /// Call `callback` with the song index whenever the game reads a song header from the song table of the
+    /// MP2K (Sappy) sound engine, e.g for ripping its music. The song table address is taken from the game overrides.
+    /// Passing None removes the callback.
+    /// @return false if the song table of the game isn't known
+    pub fn on_sound_engine_access(&mut self, callback: Option<Box<dyn FnMut(usize)>>) -> bool {
+        let song_table = overrides::get_game_overrides(&self.sysbus.cartridge.header.game_code)
+            .and_then(|game| game.mp2k_song_table());
+        match song_table {
+            Some(song_table) => {
+                self.on_sound_engine_access_at(song_table, callback);
+                true
+            }
+            None => {
+                self.sysbus.song_table_hook = None;
+                false
+            }
+        }
+    }
+
+    /// Like `on_sound_engine_access`, for games whose song table isn't in the overrides list
+    pub fn on_sound_engine_access_at(
+        &mut self,
+        song_table: Addr,
+        callback: Option<Box<dyn FnMut(usize)>>,
+    ) {
+        const MAX_SONGS: u32 = 0x400;
+        self.sysbus.song_table_hook = callback.map(|callback| {
+            // the table ends with the first entry that doesn't point into the ROM
+            let num_songs = (0..MAX_SONGS)
+                .take_while(|i| {
+                    let header = self
+                        .sysbus
+                        .debug_read_32(song_table + i * SONG_TABLE_ENTRY_SIZE);
+                    (0x0800_0000..0x0a00_0000).contains(&header)
+                })
+                .count() as u32;
+            SongTableHook {
+                table: song_table,
+                num_songs,
+                callback: Rc::new(RefCell::new(callback)),
+            }
+        });
+    }
+
+    /// Set an interface that is notified of every rendered scanline, or None to remove it
+    pub fn set_video_interface(&mut self, video_interface: Option<DynVideoInterface>) {
+        self.video_interface = video_interface;
+    }
+
+    /// Set a sink that is called before every instruction the CPU executes, or None to stop tracing
+    #[cfg(feature = "trace")]
+    pub fn set_trace_sink(&mut self, sink: Option<Box<dyn arm7tdmi::trace::TraceSink>>) {
+        self.cpu.set_trace_sink(sink);
+    }
+
+    /// Export the battery backed save memory (SRAM, Flash or EEPROM) of the cartridge, as the raw contents of a `.sav` file.
+    /// Returns an empty buffer if the backup type wasn't detected yet.
+    pub fn export_save_ram(&self) -> Vec<u8> {
+        self.sysbus.cartridge.backup.bytes()
+    }
+
+    /// Write the changes to the save memory that weren't persisted yet to the save storage of the cartridge,
+    /// frontends with a storage that can't write in place should call it periodically and on exit.
+    pub fn flush_save(&mut self) {
+        self.sysbus.cartridge.flush_save();
+    }
+
+    /// Overwrite the save memory of the cartridge with the raw contents of a `.sav` file.
+    /// Fails if the size of `data` doesn't match the backup type of the cartridge.
+    pub fn import_save_ram(&mut self, data: &[u8]) -> GBAResult<()> {
+        if self.sysbus.cartridge.backup.load_bytes(data) {
+            Ok(())
+        } else {
+            Err(GBAError::SaveRamError(format!(
+                "{} bytes of save data don't fit the backup memory of {}",
+                data.len(),
+                self.get_game_code()
+            )))
+        }
+    }
+
+    /// Hold `keys` (a KEYINPUT value) for the next `frames` frames, overriding the keys set by the frontend.
+    /// Consecutive calls are queued, see `InputScript`.
+    pub fn set_key_state_for_frames(&mut self, keys: u16, frames: usize) {
+        self.input_script.push(keys, frames);
+    }
+
+    pub fn input_script_mut(&mut self) -> &mut InputScript {
+        &mut self.input_script
+    }
+
+    /// Apply the scripted keys of the coming frame and record them if a recording is in progress.
+    /// Once the script runs out, the keys that were set before it started are restored.
+    fn update_input(&mut self) {
+        match self.input_script.next_frame() {
+            Some(keys) => {
+                let keyinput = &mut self.sysbus.io.keyinput;
+                self.keyinput_before_script.get_or_insert(*keyinput);
+                *keyinput = keys;
+            }
+            None => {
+                if let Some(keys) = self.keyinput_before_script.take() {
+                    self.sysbus.io.keyinput = keys;
+                }
+            }
+        }
+        if let Some(replay) = &mut self.recording {
+            replay.record_frame(self.sysbus.io.keyinput);
+        }
+    }
+
+    /// Start recording the input of every following frame, along with the current state to replay it from.
+    /// A recording that is already in progress is discarded.
+    pub fn start_recording(&mut self) -> Result<(), SaveStateError> {
+        self.recording = Some(Replay::new(self.save_state()?));
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// @return the recorded replay, or None if no recording was started
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
+
+    /// Restore the initial state of `replay` and queue its inputs, the recorded session is then reproduced
+    /// by calling `frame()` for `replay.frames()` frames.
+    /// Any scripted input that was pending is dropped.
+    pub fn play_replay(&mut self, replay: &Replay) -> GBAResult<()> {
+        self.restore_state(replay.initial_state())?;
+        self.input_script = replay.input_script();
+        self.keyinput_before_script = None;
+        Ok(())
+    }
+
     /// Advance the emulation for one frame worth of time
-    pub fn frame(&mut self) {
-        static mut OVERSHOOT: usize = 0;
-        unsafe {
-            OVERSHOOT = CYCLES_FULL_REFRESH.saturating_sub(self.run::<false>(CYCLES_FULL_REFRESH - OVERSHOOT));
+    /// @return the number of cycles emulated, frontends may use it to pace the emulation precisely
+    pub fn frame(&mut self) -> usize {
+        self.run_frame::<false>().cycles
+    }
+
+    /// Run what is left of the current frame, its input is applied first unless an earlier run that
+    /// stopped early already did. The frame is only counted, and the cheats applied, once it completes.
+    fn run_frame<const CHECK_BREAKPOINTS: bool>(&mut self) -> FrameOutcome {
+        let progress = match self.frame_progress.take() {
+            Some(progress) => progress,
+            None => {
+                self.update_input();
+                0
+            }
+        };
+        let budget = self.frame_budget().saturating_sub(progress);
+        let (cycles, stop_reason) = self.run_with_stop_reason::<CHECK_BREAKPOINTS>(budget);
+        let cheats_applied = if stop_reason == StopReason::CyclesExpired {
+            self.carry_overshoot(progress + cycles);
+            let cheats_applied = self.cheats.apply(&mut *self.sysbus);
+            self.end_frame();
+            cheats_applied
+        } else {
+            self.frame_progress = Some(progress + cycles);
+            0
+        };
+        FrameOutcome {
+            cycles,
+            stop_reason,
+            cheats_applied,
         }
     }
 
+    /// Cycles to run for the next frame, minus what the previous frame ran past its end
+    fn frame_budget(&self) -> usize {
+        self.cycles_per_frame.saturating_sub(self.frame_overshoot)
+    }
+
+    /// Runs only stop on an instruction boundary, remember how far this frame went past its end
+    fn carry_overshoot(&mut self, cycles: usize) {
+        self.frame_overshoot =
+            (self.frame_overshoot + cycles).saturating_sub(self.cycles_per_frame);
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_count += 1;
+        self.frame_hashes = [self.frame_hashes[1], self.frame_hash()];
+    }
+
+    /// Cycles emulated by a call to `frame()`, `CYCLES_FULL_REFRESH` unless a different target refresh rate was set
+    pub fn cycles_per_frame(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /// Scale the cycles emulated by `frame()`, so that calling it `refresh_rate` times per second keeps
+    /// the emulation at its native speed. Pacing the frames at any other rate underclocks or overclocks it.
+    pub fn set_target_refresh(&mut self, refresh_rate: f64) {
+        assert!(refresh_rate > 0.0, "invalid refresh rate {}", refresh_rate);
+        let cycles = CYCLES_FULL_REFRESH as f64 * REFRESH_RATE / refresh_rate;
+        self.cycles_per_frame = cycles.round().max(1.0) as usize;
+    }
+
+    /// Like frame(), but stops early if a breakpoint or a watchpoint is hit, and reports what happened.
+    /// A frame that stopped early is resumed by the next call, cheats are only applied when it completes.
+    pub fn frame_detailed(&mut self) -> FrameOutcome {
+        self.run_frame::<true>()
+    }
+
     /// like frame() but stop if a breakpoint is reached
     fn frame_interruptible(&mut self) {
-        static mut OVERSHOOT: usize = 0;
-        unsafe {
-            OVERSHOOT = CYCLES_FULL_REFRESH.saturating_sub(self.run::<true>(CYCLES_FULL_REFRESH - OVERSHOOT));
+        self.run_frame::<true>();
+    }
+
+    /// Disassemble `count` instructions starting at `addr`, the memory is read without side effects.
+    /// @return the address, mnemonic and raw opcode of each instruction
+    pub fn disassemble(
+        &mut self,
+        addr: Addr,
+        count: usize,
+        thumb: bool,
+    ) -> Vec<(Addr, String, u32)> {
+        let mut result = Vec::with_capacity(count);
+        let mut addr = addr;
+        for _ in 0..count {
+            if thumb {
+                let raw = self.sysbus.debug_read_16(addr);
+                let insn = ThumbInstruction::decode(raw, addr);
+                result.push((addr, insn.to_string(), raw as u32));
+                addr = addr.wrapping_add(2);
+            } else {
+                let raw = self.sysbus.debug_read_32(addr);
+                let insn = ArmInstruction::decode(raw, addr);
+                result.push((addr, insn.to_string(), raw));
+                addr = addr.wrapping_add(4);
+            }
+        }
+        result
+    }
+
+    pub fn cheats(&self) -> &CheatEngine {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut CheatEngine {
+        &mut self.cheats
+    }
+
+    /// Stop (or resume) applying all the cheats, without removing them
+    pub fn set_cheats_enabled(&mut self, enabled: bool) {
+        self.cheats.set_enabled(enabled);
+    }
+
+    /// Toggle a single cheat by the index it was added at
+    /// @return false if there is no such cheat
+    pub fn set_cheat_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        self.cheats.set_cheat_enabled(id, enabled)
+    }
+
+    /// Load a cheat list file (see the `cheats` module for the format) into the cheat engine.
+    /// Lines that fail to parse are skipped and returned, the rest of the cheats are still added.
+    pub fn load_cheat_file(&mut self, path: &Path) -> GBAResult<Vec<CheatParseError>> {
+        let text = fs::read_to_string(path)?;
+        let (cheats, errors) = cheats::parse_cheat_list(&text);
+        for error in &errors {
+            warn!("{}: {}", path.display(), error);
+        }
+        info!("loaded {} cheats from {}", cheats.len(), path.display());
+        for cheat in cheats {
+            self.cheats.add(cheat);
+        }
+        Ok(errors)
+    }
+
+    /// Run `n` frames, e.g for fast-forwarding, calling `render` with the frame buffer after each frame.
+    /// With `render_last_only` only the final frame is drawn and handed to `render`, the skipped frames
+    /// are still fully emulated (including audio), only their scanline rendering is skipped.
+    pub fn run_frames<F>(&mut self, n: usize, render_last_only: bool, mut render: F)
+    where
+        F: FnMut(&[u32]),
+    {
+        for i in 0..n {
+            let skip = render_last_only && i + 1 < n;
+            self.sysbus.io.gpu.set_render_enabled(!skip);
+            self.frame();
+            if !skip {
+                render(self.get_frame_buffer());
+            }
+        }
+        self.sysbus.io.gpu.set_render_enabled(true);
+    }
+
+    /// Runs the emulation until the absolute cycle count reaches `target`.
+    /// Since we can only stop on an instruction boundary, the emulation might pass the target by a few cycles.
+    /// @return the cycle count we actually stopped at
+    pub fn run_until_cycle(&mut self, target: u64) -> u64 {
+        let now = self.scheduler.timestamp() as u64;
+        if target > now {
+            self.run::<false>((target - now) as usize);
         }
+        self.scheduler.timestamp() as u64
     }
 
     pub fn start_gdbserver(&mut self, port: u16) {
@@ -272,36 +940,68 @@ impl GameBoyAdvance {
             self.cpu_interrupt();
         }
         self.cpu.step();
+        if self.sysbus.bios.is_hle()
+            && self.cpu.cpsr.state() == CpuState::ARM
+            && self.cpu.get_next_pc() == hle::SWI_VECTOR
+        {
+            hle::handle_swi(&mut self.cpu, &mut self.sysbus);
+        }
     }
 
     #[inline]
     fn get_bus_master(&mut self) -> Option<BusMaster> {
         match (self.io_devs.dmac.is_active(), self.io_devs.haltcnt) {
+            (_, HaltState::Stop) => None,
             (true, _) => Some(BusMaster::Dma),
             (false, HaltState::Running) => Some(BusMaster::Cpu),
             (false, HaltState::Halt) => None,
         }
     }
 
+    /// @return false if the system is in a low-power mode and the scheduler was fast-forwarded,
+    /// in which case the pending events must be handled before stepping again.
     #[inline]
-    pub(crate) fn single_step(&mut self) {
-        // 3 Options:
+    pub(crate) fn single_step(&mut self) -> bool {
+        // 4 Options:
         // 1. DMA is active - thus CPU is blocked
         // 2. DMA inactive and halt state is RUN - CPU can run
         // 3. DMA inactive and halt state is HALT - CPU is blocked
+        // 4. Halt state is STOP - everything but the keypad, gamepak and serial port is paused
         match self.get_bus_master() {
             Some(BusMaster::Dma) => self.dma_step(),
             Some(BusMaster::Cpu) => self.cpu_step(),
             None => {
-                // Halt mode - system is in a low-power mode, only (IE and IF) can release CPU from this state.
-                if self.io_devs.intc.irq_pending() {
-                    self.cpu_interrupt();
+                self.low_power_step();
+                return false;
+            }
+        }
+        true
+    }
+
+    fn low_power_step(&mut self) {
+        match self.io_devs.haltcnt {
+            HaltState::Halt => {
+                // only (IE and IF) can release CPU from this state.
+                if self.io_devs.intc.halt_wakeup_pending() {
+                    self.io_devs.haltcnt = HaltState::Running;
                 } else {
                     // Fast-forward to next pending HW event so we don't waste time idle-looping when we know the only way
                     // To get out of Halt mode is through an interrupt.
                     self.scheduler.fast_forward_to_next();
                 }
             }
+            HaltState::Stop => {
+                if self.io_devs.intc.stop_wakeup_pending() {
+                    self.io_devs.haltcnt = HaltState::Running;
+                } else {
+                    // Nothing that is paused can wake us up, so skip to the end of the run
+                    // and postpone the events of the paused hardware by the same amount of cycles.
+                    let cycles = self.scheduler.skip_to_next(EventType::RunLimitReached);
+                    self.io_devs.timers.postpone(cycles);
+                    self.io_devs.gpu.postpone(cycles);
+                }
+            }
+            HaltState::Running => {}
         }
     }
 
@@ -309,8 +1009,98 @@ impl GameBoyAdvance {
     /// @return number of cycle actually ran
     #[inline]
     pub(super) fn run<const CHECK_BREAKPOINTS: bool>(&mut self, cycles_to_run: usize) -> usize {
+        self.run_with_stop_reason::<CHECK_BREAKPOINTS>(cycles_to_run)
+            .0
+    }
+
+    /// Runs the emulation for `cycles`, or until a breakpoint or a watchpoint is hit.
+    pub fn run_for(&mut self, cycles: usize) -> StopReason {
+        self.run_with_stop_reason::<true>(cycles).1
+    }
+
+    /// Execute exactly one instruction, pending DMA transfers and interrupts are taken care of first.
+    /// @return None if the cpu stays halted for a whole frame, or is stopped and nothing can wake it up
+    pub fn step_instruction(&mut self) -> Option<StepInfo> {
+        let start_time = self.scheduler.timestamp();
+        while self.get_bus_master() != Some(BusMaster::Cpu) {
+            let stopped =
+                self.io_devs.haltcnt == HaltState::Stop && !self.io_devs.intc.stop_wakeup_pending();
+            if stopped || self.scheduler.timestamp() - start_time >= CYCLES_FULL_REFRESH {
+                return None;
+            }
+            self.single_step();
+            let _ = self.handle_events();
+        }
+        if self.io_devs.intc.irq_pending() {
+            self.cpu_interrupt();
+        }
+
+        let addr = self.cpu.get_next_pc();
+        let state = self.cpu.get_cpu_state();
+        let next_addr = addr.wrapping_add(self.cpu.word_size() as u32);
+        let (_, mnemonic, opcode) = self
+            .disassemble(addr, 1, state == CpuState::THUMB)
+            .remove(0);
+        self.cpu_step();
+        let _ = self.handle_events();
+
+        Some(StepInfo {
+            addr,
+            opcode,
+            mnemonic,
+            state,
+            pipeline_flushed: self.cpu.get_cpu_state() != state
+                || self.cpu.get_next_pc() != next_addr,
+            cycles: self.scheduler.timestamp() - start_time,
+        })
+    }
+
+    /// Runs the emulation until the cpu is about to execute the instruction at `target`, or until `max_cycles` run out.
+    /// Other breakpoints and watchpoints don't stop the run.
+    pub fn run_to_pc(&mut self, target: Addr, max_cycles: u64) -> RunResult {
+        if self.cpu.get_next_pc() == target & !1 {
+            return RunResult::ReachedPc { cycles: 0 };
+        }
+        let temporary_breakpoint = !self.cpu.breakpoints().contains(&target);
+        if temporary_breakpoint {
+            self.add_breakpoint(target);
+        }
+
+        let max_cycles = max_cycles as usize;
+        let mut cycles = 0;
+        let result = loop {
+            let (ran, stop_reason) = self.run_with_stop_reason::<true>(max_cycles - cycles);
+            cycles += ran;
+            match stop_reason {
+                StopReason::Breakpoint(bp) if bp == target => {
+                    break RunResult::ReachedPc { cycles }
+                }
+                _ if cycles >= max_cycles => break RunResult::CyclesExpired,
+                _ => {}
+            }
+        };
+
+        if temporary_breakpoint {
+            self.remove_breakpoint(target);
+        }
+        result
+    }
+
+    #[inline]
+    fn run_with_stop_reason<const CHECK_BREAKPOINTS: bool>(
+        &mut self,
+        cycles_to_run: usize,
+    ) -> (usize, StopReason) {
         let start_time = self.scheduler.timestamp();
         let end_time = start_time + cycles_to_run;
+        let mut stop_reason = StopReason::CyclesExpired;
+
+        self.io_devs.check_keypad_irq();
+
+        if CHECK_BREAKPOINTS {
+            // Forget about watchpoints that were hit while we weren't looking
+            self.sysbus.watchpoint_hit = None;
+        }
 
         // Register an event to mark the end of this run
         self.scheduler
@@ -323,7 +1113,10 @@ impl GameBoyAdvance {
             while self.scheduler.timestamp()
                 <= unsafe { self.scheduler.timestamp_of_next_event_unchecked() }
             {
-                self.single_step();
+                if !self.single_step() {
+                    // halted until the next event
+                    break;
+                }
                 if CHECK_BREAKPOINTS {
                     if let Some(bp) = self.cpu.check_breakpoint() {
                         debug!("Arm7tdmi breakpoint hit 0x{:08x}", bp);
@@ -332,6 +1125,18 @@ impl GameBoyAdvance {
                         if let Some(debugger) = &mut self.debugger {
                             debugger.notify_breakpoint(bp);
                         }
+                        stop_reason = StopReason::Breakpoint(bp);
+                        break 'running;
+                    }
+                    if let Some(hit) = self.sysbus.watchpoint_hit.take() {
+                        debug!("watchpoint hit 0x{:08x}", hit.addr);
+                        self.scheduler.cancel_pending(EventType::RunLimitReached);
+                        let _ = self.handle_events();
+                        stop_reason = StopReason::Watchpoint {
+                            addr: hit.addr,
+                            old: hit.old,
+                            new: hit.new,
+                        };
                         break 'running;
                     }
                 }
@@ -342,7 +1147,10 @@ impl GameBoyAdvance {
             }
         }
 
-        self.scheduler.timestamp() - start_time
+        self.io_devs.sound.flush_samples(&mut self.audio_interface);
+
+        let cycles = self.scheduler.timestamp() - start_time;
+        (cycles, stop_reason)
     }
 
     /// Handle all pending scheduler events and return if run limit was reached.
@@ -368,8 +1176,17 @@ impl GameBoyAdvance {
                     let apu = &mut io.sound;
                     Some(timers.handle_overflow_event(channel_id, event_time, apu, dmac))
                 }
-                EventType::Gpu(gpu_event) => Some(io.gpu.on_event(gpu_event, &mut *self.sysbus)),
-                EventType::Apu(event) => Some(io.sound.on_event(event, &mut self.audio_interface)),
+                EventType::Gpu(gpu_event) => Some(io.gpu.on_event(
+                    gpu_event,
+                    event_time,
+                    &mut *self.sysbus,
+                    self.video_interface.as_mut(),
+                )),
+                EventType::Apu(event) => Some(io.sound.on_event(
+                    event,
+                    event_time,
+                    &mut self.audio_interface,
+                )),
             };
             if let Some((new_event, when)) = new_event {
                 // We schedule events added by event handlers relative to the handled event time
@@ -379,87 +1196,354 @@ impl GameBoyAdvance {
         false
     }
 
+    /// Put the machine in the state the bios boot sequence leaves it in when jumping to the cartridge.
     pub fn skip_bios(&mut self) {
+        self.cpu.gpr = [0; 15];
         self.cpu.banks.gpr_banked_r13[0] = 0x0300_7f00; // USR/SYS
         self.cpu.banks.gpr_banked_r13[1] = 0x0300_7f00; // FIQ
         self.cpu.banks.gpr_banked_r13[2] = 0x0300_7fa0; // IRQ
         self.cpu.banks.gpr_banked_r13[3] = 0x0300_7fe0; // SVC
         self.cpu.banks.gpr_banked_r13[4] = 0x0300_7f00; // ABT
         self.cpu.banks.gpr_banked_r13[5] = 0x0300_7f00; // UND
+        self.cpu.banks.gpr_banked_r14 = [0; 6];
+        self.cpu.banks.spsr_bank = [RegPSR::default(); 6];
         self.cpu.gpr[13] = 0x0300_7f00;
+        self.cpu.spsr = RegPSR::default();
         self.cpu.pc = 0x0800_0000;
-        self.cpu.cpsr.set(0x5f);
+        // System mode, ARM state with both interrupts enabled
+        self.cpu.cpsr.set(0x1f);
+
+        // the bios leaves the post boot flag set, the other io registers keep their reset values
+        self.sysbus.io.post_boot_flag = true;
         self.sysbus.io.gpu.skip_bios();
     }
 
-    #[cfg(feature = "debugger")]
-    pub fn add_breakpoint(&mut self, addr: u32) -> Option<usize> {
-        let breakpoints = &mut self.cpu.dbg.breakpoints;
-        if !breakpoints.contains(&addr) {
-            let new_index = breakpoints.len();
-            breakpoints.push(addr);
-            Some(new_index)
-        } else {
-            None
-        }
+    /// Override the gamepak waitstates, e.g `WaitstateProfile::NO_WAIT` for a fast mode.
+    /// `None` restores the hardware-accurate waitstates.
+    pub fn set_speed_profile(&mut self, profile: Option<WaitstateProfile>) {
+        self.sysbus.set_waitstate_override(profile);
     }
 
-    #[cfg(feature = "debugger")]
-    pub fn check_breakpoint(&self) -> Option<u32> {
-        let next_pc = self.cpu.get_next_pc();
-        for bp in &self.cpu.dbg.breakpoints {
-            if (*bp & !1) == next_pc {
-                return Some(*bp);
-            }
-        }
+    /// Copy `len` bytes starting at `addr` through the side-effect-free debug path
+    pub fn read_memory(&mut self, addr: Addr, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.sysbus.debug_get_into_bytes(addr, &mut bytes);
+        bytes
+    }
+
+    /// Copy `data` to `addr` through the debug path, see `DebugWrite`
+    pub fn write_memory(&mut self, addr: Addr, data: &[u8]) {
+        self.sysbus.debug_write_bytes(addr, data);
+    }
+
+    /// Raise prefetch and data aborts on accesses to unused memory instead of reading open bus.
+    /// Off by default as the real hardware never aborts, useful to stop malformed ROMs when fuzzing.
+    pub fn set_abort_on_unmapped(&mut self, enabled: bool) {
+        self.sysbus.set_abort_on_unmapped(enabled);
+    }
+
+    /// Stop `run_for` before the instruction at `addr` is executed
+    pub fn add_breakpoint(&mut self, addr: Addr) {
+        self.cpu.add_breakpoint(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: Addr) {
+        self.cpu.del_breakpoint(addr);
+    }
+
+    /// Stop `run_for` after `addr` is accessed.
+    /// Read watchpoints also trigger on opcode fetches.
+    pub fn add_watchpoint(&mut self, addr: Addr, kind: WatchKind) {
+        self.sysbus.add_watchpoint(addr, kind);
+    }
 
-        None
+    pub fn remove_watchpoint(&mut self, addr: Addr) {
+        self.sysbus.remove_watchpoint(addr);
     }
 
     pub fn get_frame_buffer(&self) -> &[u32] {
         self.sysbus.io.gpu.get_frame_buffer()
     }
 
-    /// Reset the emulator
-    pub fn soft_reset(&mut self) {
-        self.cpu.reset();
+    /// A 64-bit FNV-1a hash of the current frame, meant for comparing output in regression tests.
+    /// The hash is computed over the 15-bit colors of the pixels in little-endian order, so it doesn't
+    /// depend on the frame buffer's pixel format or on the host's byte order.
+    /// It is guaranteed to stay the same across minor versions for the same frame.
+    pub fn frame_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        self.sysbus
+            .io
+            .gpu
+            .get_frame_buffer_rgb15()
+            .iter()
+            .flat_map(|pixel| pixel.0.to_le_bytes())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Whether the last frame emulated by `frame()` differs from the one before it,
+    /// frontends may skip uploading an unchanged frame and present the cached texture instead.
+    pub fn frame_changed(&self) -> bool {
+        self.frame_hashes[0] != self.frame_hashes[1]
+    }
 
-    use crate::prelude::*;
+    /// Have the gpu produce a nearest-neighbor upscaled frame on every VBlank, see `get_scaled_frame_buffer`
+    pub fn set_output_scale(&mut self, scale: usize) {
+        self.sysbus.io.gpu.set_output_scale(scale);
+    }
 
-    fn make_mock_gba(rom: &[u8]) -> GameBoyAdvance {
-        let bios = vec![0; 0x4000].into_boxed_slice();
-        let cartridge = GamepakBuilder::new()
-            .buffer(rom)
-            .with_sram()
-            .without_backup_to_file()
-            .build()
-            .unwrap();
-        let mut gba = GameBoyAdvance::new(bios, cartridge, NullAudio::new());
-        gba.skip_bios();
+    /// Color correct the frame buffer for modern displays, the default is `ColorCorrection::None`
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.sysbus.io.gpu.set_color_correction(correction);
+    }
 
-        gba
+    /// Returns the last frame at the scale requested by `set_output_scale`
+    pub fn get_scaled_frame_buffer(&self) -> &[u32] {
+        self.sysbus.io.gpu.get_scaled_frame_buffer()
     }
 
-    #[test]
-    fn test_arm7tdmi_arm_eggvance() {
-        let mut gba = make_mock_gba(include_bytes!("../../external/gba-suite/arm/arm.gba"));
+    /// Returns how many reads were blocked by the bios protection, i.e reads from the bios while executing outside of it.
+    /// Useful to locate copy-protection routines.
+    pub fn get_bios_protection_hits(&self) -> usize {
+        self.sysbus.bios.protected_reads()
+    }
 
-        for _ in 0..10 {
-            gba.frame();
-        }
+    pub fn reset_bios_protection_hits(&mut self) {
+        self.sysbus.bios.reset_protected_reads();
+    }
 
-        let insn = gba.sysbus.read_32(gba.cpu.pc - 8);
-        assert_eq!(insn, 0xeafffffe); // loop
-        assert_eq!(0, gba.cpu.gpr[12]);
+    /// Register a callback to be called with the (address, pc) on every read that is blocked by the bios protection
+    pub fn set_bios_protection_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(Addr, Addr) + 'static,
+    {
+        let callback: BiosProtectionCallback = Rc::new(callback);
+        self.sysbus.bios.set_protection_callback(Some(callback));
     }
 
-    #[test]
+    pub fn clear_bios_protection_callback(&mut self) {
+        self.sysbus.bios.set_protection_callback(None);
+    }
+
+    /// Reset the emulator
+    pub fn soft_reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    /// Power-cycle the emulator, starting over from the bios reset vector.
+    /// Unlike creating a new GameBoyAdvance, the existing allocations are reused and the loaded cartridge
+    /// (including its backup storage) is kept.
+    pub fn reset_hard(&mut self) {
+        self.count_cycles();
+        self.scheduler.reset();
+        self.counted_timestamp = 0;
+        self.interrupt_flags.set(IrqBitmask(0));
+        self.frame_overshoot = 0;
+        self.frame_progress = None;
+        let sample_rate = self.audio_interface.get_sample_rate() as f32;
+        self.io_devs
+            .reset(self.interrupt_flags.clone(), sample_rate);
+        self.sysbus.reset();
+
+        self.cpu.gpr = [0; 15];
+        self.cpu.banks = Default::default();
+        self.cpu.reset();
+    }
+
+    /// Swap in a different cartridge and power-cycle the emulator, e.g for drag-and-drop ROM loading.
+    /// The audio interface, bios and debugging setup are kept, while the cheats of the previous game are dropped.
+    pub fn load_gamepak(&mut self, gamepak: Cartridge, skip_bios: bool) {
+        info!(
+            "Loading {} ({})",
+            gamepak.header.game_title, gamepak.header.game_code
+        );
+        self.sysbus.cartridge = gamepak;
+        self.sysbus.song_table_hook = None;
+        self.cheats.clear();
+        self.input_script.clear();
+        self.keyinput_before_script = None;
+        self.recording = None;
+        self.reset_hard();
+        if skip_bios {
+            self.skip_bios();
+        }
+    }
+}
+
+/// Compares two savestates and reports which subsystems differ between them.
+/// Useful to pinpoint the source of a divergence between two emulation runs.
+pub fn diff_savestates(a: &[u8], b: &[u8]) -> GBAResult<Vec<SubsystemDiff>> {
+    let a = decode_savestate(a, None)?;
+    let b = decode_savestate(b, None)?;
+
+    let mut diffs = Vec::new();
+    let mut diff = |subsystem: Subsystem, summary: Option<String>| {
+        if let Some(summary) = summary {
+            diffs.push(SubsystemDiff { subsystem, summary });
+        }
+    };
+
+    diff(Subsystem::Cpu, diff_cpu_state(&a.cpu_state, &b.cpu_state)?);
+    diff(
+        Subsystem::Gpu,
+        diff_serialized(&a.io_devs.gpu, &b.io_devs.gpu)?,
+    );
+    diff(
+        Subsystem::Apu,
+        diff_serialized(&a.io_devs.sound, &b.io_devs.sound)?,
+    );
+    diff(
+        Subsystem::Dma,
+        diff_serialized(&a.io_devs.dmac, &b.io_devs.dmac)?,
+    );
+    diff(
+        Subsystem::Timers,
+        diff_serialized(&a.io_devs.timers, &b.io_devs.timers)?,
+    );
+    diff(
+        Subsystem::Interrupts,
+        diff_serialized(
+            &(&a.io_devs.intc, a.interrupt_flags),
+            &(&b.io_devs.intc, b.interrupt_flags),
+        )?,
+    );
+    diff(
+        Subsystem::Scheduler,
+        diff_serialized(&a.scheduler, &b.scheduler)?,
+    );
+    diff(
+        Subsystem::Io,
+        diff_serialized(
+            &(
+                &a.io_devs.keyinput,
+                &a.io_devs.keycnt,
+                &a.io_devs.post_boot_flag,
+                &a.io_devs.waitcnt,
+                &a.io_devs.memcnt,
+                &a.io_devs.haltcnt,
+                &a.io_devs.hle_intr_wait,
+                &a.io_devs.debug,
+            ),
+            &(
+                &b.io_devs.keyinput,
+                &b.io_devs.keycnt,
+                &b.io_devs.post_boot_flag,
+                &b.io_devs.waitcnt,
+                &b.io_devs.memcnt,
+                &b.io_devs.haltcnt,
+                &b.io_devs.hle_intr_wait,
+                &b.io_devs.debug,
+            ),
+        )?,
+    );
+    diff(Subsystem::Ewram, diff_bytes(&a.ewram, &b.ewram));
+    diff(Subsystem::Iwram, diff_bytes(&a.iwram, &b.iwram));
+    diff(
+        Subsystem::Cartridge,
+        diff_serialized(&a.cartridge, &b.cartridge)?,
+    );
+
+    Ok(diffs)
+}
+
+fn diff_bytes(a: &[u8], b: &[u8]) -> Option<String> {
+    if a.len() != b.len() {
+        return Some(format!(
+            "size changed from {} to {} bytes",
+            a.len(),
+            b.len()
+        ));
+    }
+    let first = a.iter().zip(b.iter()).position(|(x, y)| x != y)?;
+    let count = a.iter().zip(b.iter()).filter(|(x, y)| x != y).count();
+    Some(format!(
+        "{} bytes differ, first at offset {:#x}",
+        count, first
+    ))
+}
+
+fn diff_serialized<T: Serialize>(a: &T, b: &T) -> bincode::Result<Option<String>> {
+    Ok(diff_bytes(&bincode::serialize(a)?, &bincode::serialize(b)?))
+}
+
+fn diff_cpu_state(
+    a: &arm7tdmi::SavedCpuState,
+    b: &arm7tdmi::SavedCpuState,
+) -> bincode::Result<Option<String>> {
+    let mut changes = Vec::new();
+    if a.pc != b.pc {
+        changes.push(format!("pc: {:#010x} -> {:#010x}", a.pc, b.pc));
+    }
+    for (i, (x, y)) in a.gpr.iter().zip(b.gpr.iter()).enumerate() {
+        if x != y {
+            changes.push(format!("r{}: {:#010x} -> {:#010x}", i, x, y));
+        }
+    }
+    if a.cpsr.get() != b.cpsr.get() {
+        changes.push(format!(
+            "cpsr: {:#010x} -> {:#010x}",
+            a.cpsr.get(),
+            b.cpsr.get()
+        ));
+    }
+    if !changes.is_empty() {
+        Ok(Some(changes.join(", ")))
+    } else {
+        // the difference might be in the banked registers or the pipeline
+        Ok(diff_serialized(a, b)?.map(|_| "internal state differs".to_string()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use crate::prelude::*;
+
+    pub(crate) fn make_mock_gba(rom: &[u8]) -> GameBoyAdvance {
+        let bios = vec![0; 0x4000].into_boxed_slice();
+        let cartridge = GamepakBuilder::new()
+            .buffer(rom)
+            .with_sram()
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let mut gba = GameBoyAdvance::new(bios, cartridge, NullAudio::new());
+        gba.skip_bios();
+
+        gba
+    }
+
+    /// A mock gba whose ROM starts with `code`, with the pipeline filled to run it
+    pub(crate) fn make_mock_gba_with_code(code: &[u32]) -> GameBoyAdvance {
+        let mut rom = vec![0; 0x200];
+        for (i, insn) in code.iter().enumerate() {
+            rom[4 * i..4 * i + 4].copy_from_slice(&insn.to_le_bytes());
+        }
+        let mut gba = make_mock_gba(&rom);
+        gba.cpu.reload_pipeline32();
+
+        gba
+    }
+
+    #[test]
+    fn test_arm7tdmi_arm_eggvance() {
+        let mut gba = make_mock_gba(include_bytes!("../../external/gba-suite/arm/arm.gba"));
+
+        for _ in 0..10 {
+            gba.frame();
+        }
+
+        let insn = gba.sysbus.read_32(gba.cpu.pc - 8);
+        assert_eq!(insn, 0xeafffffe); // loop
+        assert_eq!(0, gba.cpu.gpr[12]);
+    }
+
+    #[test]
     fn test_arm7tdmi_thumb_eggvance() {
         let mut gba = make_mock_gba(include_bytes!("../../external/gba-suite/thumb/thumb.gba"));
 
@@ -471,4 +1555,1953 @@ mod tests {
         assert_eq!(insn, 0xe7fe); // loop
         assert_eq!(0, gba.cpu.gpr[7]);
     }
+
+    #[test]
+    fn test_bios_protection_hits() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+
+        let blocked_reads = Rc::new(RefCell::new(Vec::new()));
+        let blocked_reads_clone = blocked_reads.clone();
+        gba.set_bios_protection_callback(move |addr, pc| {
+            blocked_reads_clone.borrow_mut().push((addr, pc));
+        });
+
+        // execute `ldr r0, [r1]` from ewram
+        gba.sysbus.write_32(0x0200_0000, 0xe591_0000);
+        gba.cpu.gpr[1] = 0x0000_0100;
+        gba.cpu.pc = 0x0200_0000;
+        gba.cpu.reload_pipeline32();
+        assert_eq!(gba.get_bios_protection_hits(), 0);
+
+        gba.cpu.step();
+
+        assert_eq!(gba.get_bios_protection_hits(), 1);
+        assert_eq!(*blocked_reads.borrow(), vec![(0x0000_0100, 0x0200_0008)]);
+
+        gba.reset_bios_protection_hits();
+        assert_eq!(gba.get_bios_protection_hits(), 0);
+    }
+
+    #[test]
+    fn test_bios_open_bus() {
+        let mut bios = vec![0; 0x4000];
+        bios[0..4].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        let cartridge = GamepakBuilder::new()
+            .buffer(&[0; 0x200])
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let mut gba = GameBoyAdvance::new(bios.into_boxed_slice(), cartridge, NullAudio::new());
+        gba.skip_bios();
+
+        let code: [u32; 3] = [
+            0xe5910000, // ldr r0, [r1]
+            0xe5d12001, // ldrb r2, [r1, #1]
+            0xe1d130b2, // ldrh r3, [r1, #2]
+        ];
+        for (i, insn) in code.iter().enumerate() {
+            gba.sysbus.write_32(0x0200_0000 + 4 * i as u32, *insn);
+        }
+        gba.cpu.gpr[1] = 0;
+        gba.cpu.pc = 0x0200_0000;
+        gba.cpu.reload_pipeline32();
+
+        // reading from outside of the bios gives the last opcode fetched from the bios instead of its contents
+        gba.cpu.step();
+        gba.cpu.step();
+        gba.cpu.step();
+        assert_eq!(gba.cpu.get_reg(0), 0xe129_f000);
+        assert_eq!(gba.cpu.get_reg(2), 0xf0);
+        assert_eq!(gba.cpu.get_reg(3), 0xe129);
+        assert_eq!(gba.sysbus.debug_read_32(0), 0x1234_5678);
+    }
+
+    #[test]
+    fn test_hblank_dma_scroll_effect() {
+        use crate::iodev::consts::*;
+
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+
+        // mode 0 with only BG0 enabled, tile 0 is used for the entire map
+        gba.sysbus.write_16(REG_BG0CNT, 0x0000);
+        gba.sysbus.write_16(REG_DISPCNT, 0x0100);
+        gba.sysbus.write_16(PALRAM_ADDR, 0x0000);
+        gba.sysbus.write_16(PALRAM_ADDR + 2, 0x7fff);
+        // the leftmost column of tile 0 uses color 1, which gives us a vertical line every 8 pixels
+        for row in 0..8 {
+            gba.sysbus.write_32(VRAM_ADDR + row * 4, 0x0000_0001);
+        }
+
+        // increment the BG0 horizontal offset by one each scanline
+        for i in 0..0x200 {
+            gba.sysbus.write_16(EWRAM_ADDR + 2 * i, (i + 1) as u16);
+        }
+        gba.sysbus.write_32(REG_DMA0SAD, EWRAM_ADDR);
+        gba.sysbus.write_32(REG_DMA0DAD, REG_BG0HOFS);
+        gba.sysbus.write_16(REG_DMA0CNT_L, 1);
+        // enable, hblank timing, repeat, 16bit, fixed destination
+        gba.sysbus.write_16(REG_DMA0CNT_H, 0xa240);
+
+        // run for exactly two frames, so the entire frame buffer is from the second frame
+        gba.run::<false>(2 * CYCLES_FULL_REFRESH);
+
+        let frame_buffer = gba.get_frame_buffer();
+        let first_line_pixel = |y: usize| {
+            frame_buffer[y * DISPLAY_WIDTH..(y + 1) * DISPLAY_WIDTH]
+                .iter()
+                .position(|&pixel| pixel == 0xf8f8f8)
+                .unwrap()
+        };
+        for y in 0..(DISPLAY_HEIGHT - 1) {
+            let x = first_line_pixel(y);
+            assert!(x < 8);
+            // the line moves one pixel to the left on the next scanline
+            assert_eq!(first_line_pixel(y + 1), (x + 7) % 8, "scanline {}", y + 1);
+        }
+    }
+
+    #[test]
+    fn test_long_multiply_cycles() {
+        let code: [u32; 2] = [
+            0xe0d54392, // smulls r4, r5, r2, r3
+            0xe0d54392, // smulls r4, r5, r2, r3
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        gba.cpu.set_reg(2, 0x1000_0000);
+        gba.cpu.set_reg(3, -16i32 as u32);
+        let start = gba.scheduler.timestamp();
+        gba.cpu.step();
+        let short_multiply = gba.scheduler.timestamp() - start;
+
+        // a negative multiplier terminates early as well, unlike one with significant top bits
+        gba.cpu.set_reg(2, 0);
+        gba.cpu.set_reg(3, 0x7fff_ffff);
+        let start = gba.scheduler.timestamp();
+        gba.cpu.step();
+        assert_eq!(gba.scheduler.timestamp() - start, short_multiply + 3);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let stats = gba.stats();
+        assert_eq!(stats.frame_count, 0);
+        assert_eq!(stats.total_cycles, 0);
+
+        let cycles = gba.frame();
+        let after = gba.stats();
+        assert_eq!(after.frame_count, 1);
+        assert_eq!(after.timestamp - stats.timestamp, cycles);
+        assert_eq!(after.total_cycles, cycles as u64);
+        // the run ends on the instruction boundary after a full refresh
+        assert!(cycles >= CYCLES_FULL_REFRESH && cycles < CYCLES_FULL_REFRESH + 16);
+
+        gba.frame();
+        assert_eq!(gba.stats().frame_count, 2);
+        gba.run_for(100);
+        assert_eq!(gba.stats().frame_count, 2);
+        assert!(gba.stats().scanline < DISPLAY_HEIGHT + VBLANK_LINES);
+
+        // stepping counts too
+        let total = gba.stats().total_cycles;
+        let step = gba.step_instruction().unwrap();
+        assert_eq!(gba.stats().total_cycles, total + step.cycles as u64);
+        let total = gba.stats().total_cycles;
+        let timestamp = gba.scheduler.timestamp();
+        gba.single_step();
+        let stepped = (gba.scheduler.timestamp() - timestamp) as u64;
+        assert!(stepped > 0);
+        assert_eq!(gba.stats().total_cycles, total + stepped);
+
+        // restoring an earlier state or resetting doesn't take cycles off the total
+        let state = gba.save_state().unwrap();
+        gba.frame();
+        let total = gba.stats().total_cycles;
+        gba.restore_state(&state).unwrap();
+        assert_eq!(gba.stats().total_cycles, total);
+        gba.reset_hard();
+        assert_eq!(gba.stats().total_cycles, total);
+        assert_eq!(gba.stats().timestamp, 0);
+    }
+
+    #[test]
+    fn test_sound_engine_access() {
+        use arm7tdmi::memory::MemoryInterface;
+
+        // 3 songs, followed by the end of the table
+        let table = 0x0800_0100;
+        let mut rom = vec![0; 0x200];
+        for i in 0..3 {
+            let entry = 0x100 + 8 * i;
+            let header = 0x0800_0180 + 8 * i as u32;
+            rom[entry..entry + 4].copy_from_slice(&header.to_le_bytes());
+        }
+        let mut gba = make_mock_gba(&rom);
+        // not in the overrides list
+        assert!(!gba.on_sound_engine_access(Some(Box::new(|_| {}))));
+
+        let songs = Rc::new(RefCell::new(Vec::new()));
+        let songs_ref = songs.clone();
+        gba.on_sound_engine_access_at(
+            table,
+            Some(Box::new(move |song| songs_ref.borrow_mut().push(song))),
+        );
+        gba.sysbus.load_32(table + 16, MemoryAccess::NonSeq);
+        // the music player numbers of the entry, and past the end of the table
+        gba.sysbus.load_16(table + 20, MemoryAccess::NonSeq);
+        gba.sysbus.load_32(table + 24, MemoryAccess::NonSeq);
+        gba.sysbus.load_32(table, MemoryAccess::NonSeq);
+        assert_eq!(*songs.borrow(), vec![2, 0]);
+
+        gba.on_sound_engine_access_at(table, None);
+        gba.sysbus.load_32(table, MemoryAccess::NonSeq);
+        assert_eq!(songs.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_sound_engine_access_overrides() {
+        use arm7tdmi::memory::MemoryInterface;
+
+        // Pokemon Emerald has its song table listed in the overrides
+        let table = 0x086b_49f0;
+        let offset = (table - 0x0800_0000) as usize;
+        let mut rom = vec![0; offset + 0x100];
+        rom[0xac..0xb0].copy_from_slice(b"BPEE");
+        for i in 0..2 {
+            let entry = offset + 8 * i;
+            let header = 0x0800_0200 + 8 * i as u32;
+            rom[entry..entry + 4].copy_from_slice(&header.to_le_bytes());
+        }
+        let mut gba = make_mock_gba(&rom);
+
+        let songs = Rc::new(RefCell::new(Vec::new()));
+        let songs_ref = songs.clone();
+        assert!(gba.on_sound_engine_access(Some(Box::new(move |song| {
+            songs_ref.borrow_mut().push(song)
+        }))));
+        gba.sysbus.load_32(table + 8, MemoryAccess::NonSeq);
+        assert_eq!(*songs.borrow(), vec![1]);
+
+        // the hook belongs to the game, swapping the cartridge removes it
+        let cartridge = GamepakBuilder::new()
+            .buffer(&rom)
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        gba.load_gamepak(cartridge, true);
+        gba.sysbus.load_32(table + 8, MemoryAccess::NonSeq);
+        assert_eq!(songs.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_dma_cycles() {
+        use crate::iodev::consts::*;
+
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+
+        gba.sysbus.write_32(REG_DMA3SAD, IWRAM_ADDR);
+        gba.sysbus.write_32(REG_DMA3DAD, IWRAM_ADDR + 0x1000);
+        gba.sysbus.write_16(REG_DMA3CNT_L, 16);
+        // enable, immediate, 32bit
+        gba.sysbus.write_16(REG_DMA3CNT_H, 0x8400);
+        // the dma starts 3 cycles later
+        gba.scheduler.update(3);
+        gba.handle_events();
+        assert!(gba.io_devs.dmac.is_active());
+
+        // 2 internal cycles, and a single cycle for every IWRAM load and store
+        let start = gba.scheduler.timestamp();
+        gba.dma_step();
+        assert_eq!(gba.scheduler.timestamp() - start, 2 + 16 * 2);
+        assert!(!gba.io_devs.dmac.is_active());
+    }
+
+    #[test]
+    fn test_dma_address_control() {
+        use crate::iodev::consts::*;
+
+        fn run_dma3(gba: &mut GameBoyAdvance, src: u32, dst: u32, ctrl: u16) -> (u32, u32) {
+            gba.sysbus.write_32(REG_DMA3SAD, src);
+            gba.sysbus.write_32(REG_DMA3DAD, dst);
+            gba.sysbus.write_16(REG_DMA3CNT_L, 4);
+            gba.sysbus.write_16(REG_DMA3CNT_H, ctrl);
+            gba.scheduler.update(3);
+            gba.handle_events();
+            gba.dma_step();
+            gba.io_devs.dmac.channels[3].internal_addresses()
+        }
+
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+        for i in 0..0x10 {
+            gba.sysbus.write_32(IWRAM_ADDR + 0xe0 + 4 * i, 0x1000 + i);
+        }
+        let src = IWRAM_ADDR + 0x100;
+        let dst = IWRAM_ADDR + 0x200;
+
+        // 32bit, increment both
+        assert_eq!(run_dma3(&mut gba, src, dst, 0x8400), (src + 16, dst + 16));
+        assert_eq!(gba.sysbus.read_32(dst + 12), 0x100b);
+
+        // 32bit, decrement both
+        assert_eq!(run_dma3(&mut gba, src, dst, 0x84a0), (src - 16, dst - 16));
+        assert_eq!(gba.sysbus.read_32(dst - 12), 0x1005);
+
+        // 32bit, fixed both
+        assert_eq!(run_dma3(&mut gba, src, dst, 0x8540), (src, dst));
+
+        // 32bit, the prohibited source mode increments, and the dest is reloaded on repeat
+        assert_eq!(run_dma3(&mut gba, src, dst, 0x87e0), (src + 16, dst));
+        gba.sysbus.write_16(REG_DMA3CNT_H, 0);
+
+        // unaligned addresses are force aligned to the transfer width
+        assert_eq!(
+            run_dma3(&mut gba, src + 1, dst + 3, 0x8000),
+            (src + 8, dst + 2 + 8)
+        );
+        assert_eq!(
+            run_dma3(&mut gba, src + 2, dst + 3, 0x8400),
+            (src + 16, dst + 16)
+        );
+    }
+
+    #[test]
+    fn test_sound_fifo_dma() {
+        use crate::iodev::consts::*;
+
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+
+        // master enable, FIFO A on both sides driven by timer 0
+        gba.sysbus.write_16(REG_SOUNDCNT_X, 0x0080);
+        gba.sysbus.write_16(REG_SOUNDCNT_H, 0x0300);
+        gba.sysbus.write_32(REG_DMA1SAD, EWRAM_ADDR);
+        gba.sysbus.write_32(REG_DMA1DAD, REG_FIFO_A);
+        // enable, irq, special timing, 32bit, repeat, fixed destination
+        gba.sysbus.write_16(REG_DMA1CNT_H, 0xf640);
+
+        // hblank and vblank don't trigger the FIFO dma
+        gba.run_for(CYCLES_FULL_REFRESH);
+        assert!(!gba.interrupt_state().interrupt_flags.DMA1());
+
+        // overflow every 256 cycles
+        gba.sysbus.write_16(REG_TM0CNT_L, 0xff00);
+        gba.sysbus.write_16(REG_TM0CNT_H, 0x0080);
+        gba.run_for(300);
+        assert!(gba.interrupt_state().interrupt_flags.DMA1());
+
+        // the FIFO is requested again once it drops to 16 bytes
+        gba.sysbus.write_16(REG_IF, 1 << Interrupt::DMA1 as u16);
+        assert!(!gba.interrupt_state().interrupt_flags.DMA1());
+        gba.run_for(300);
+        assert!(gba.interrupt_state().interrupt_flags.DMA1());
+    }
+
+    #[test]
+    fn test_video_capture_dma() {
+        use crate::iodev::consts::*;
+
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+
+        gba.sysbus.write_32(IWRAM_ADDR, 0xdead_beef);
+        gba.sysbus.write_32(REG_DMA3SAD, IWRAM_ADDR);
+        gba.sysbus.write_32(REG_DMA3DAD, IWRAM_ADDR + 0x1000);
+        gba.sysbus.write_16(REG_DMA3CNT_L, 1);
+        // enable, special timing, 32bit, repeat, fixed source
+        gba.sysbus.write_16(REG_DMA3CNT_H, 0xb700);
+
+        gba.run_for(CYCLES_FULL_REFRESH);
+
+        // a single word on each of the lines 2..162
+        let transfers = (0..0x100)
+            .take_while(|i| gba.sysbus.read_32(IWRAM_ADDR + 0x1000 + 4 * i) == 0xdead_beef)
+            .count();
+        assert_eq!(transfers, DISPLAY_HEIGHT);
+        let next = IWRAM_ADDR + 0x1000 + 4 * transfers as u32;
+        assert_eq!(gba.sysbus.read_32(next), 0);
+        // and then it stops by itself
+        assert_eq!(gba.sysbus.read_16(REG_DMA3CNT_H) & 0x8000, 0);
+    }
+
+    #[test]
+    fn test_reset_hard() {
+        let mut rom = vec![0; 0x200];
+        rom[0xa0..0xac].copy_from_slice(b"RESET TEST  ");
+        let mut gba = make_mock_gba(&rom);
+
+        gba.sysbus.write_32(EWRAM_ADDR, 0xdead_beef);
+        gba.sysbus.write_32(IWRAM_ADDR + 0x100, 0xdead_beef);
+        gba.sysbus.write_16(VRAM_ADDR, 0x1234);
+        gba.frame_overshoot = 7;
+        gba.set_output_scale(2);
+        gba.set_color_correction(ColorCorrection::GbaLcd);
+
+        gba.reset_hard();
+
+        assert_eq!(gba.sysbus.read_32(EWRAM_ADDR), 0);
+        assert_eq!(gba.sysbus.read_32(IWRAM_ADDR + 0x100), 0);
+        assert_eq!(gba.sysbus.read_16(VRAM_ADDR), 0);
+        assert_eq!(gba.scheduler.timestamp(), 0);
+        assert_eq!(gba.frame_overshoot, 0);
+        // the render settings aren't part of the emulated hardware
+        assert_eq!(gba.io_devs.gpu.output_scale(), 2);
+        assert_eq!(gba.io_devs.gpu.color_correction(), ColorCorrection::GbaLcd);
+        // cpu is back at the reset vector
+        assert_eq!(gba.cpu.get_next_pc(), 0);
+        // cartridge is still there
+        assert_eq!(gba.get_game_title(), "RESET TEST  ");
+        assert_eq!(
+            gba.sysbus.read_32(0x0800_00a0),
+            u32::from_le_bytes(*b"RESE")
+        );
+    }
+
+    #[test]
+    fn test_diff_savestates() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+
+        let a = gba.save_state().unwrap();
+        assert_eq!(diff_savestates(&a, &a).unwrap(), vec![]);
+
+        gba.cpu.gpr[3] = 0x1234;
+        let b = gba.save_state().unwrap();
+
+        let diffs = diff_savestates(&a, &b).unwrap();
+        assert_eq!(
+            diffs,
+            vec![SubsystemDiff {
+                subsystem: Subsystem::Cpu,
+                summary: "r3: 0x00000000 -> 0x00001234".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_savestate_header() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let state = gba.save_state().unwrap();
+        assert_eq!(&state[0..4], b"RBAS");
+        assert_eq!(&state[4..8], &SAVESTATE_VERSION.to_le_bytes());
+        gba.restore_state(&state).unwrap();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            gba.restore_state(&bad_magic),
+            Err(SaveStateError::BadMagic)
+        ));
+
+        let mut newer_version = state.clone();
+        newer_version[4..8].copy_from_slice(&(SAVESTATE_VERSION + 1).to_le_bytes());
+        match gba.restore_state(&newer_version) {
+            Err(SaveStateError::VersionMismatch { have, want }) => {
+                assert_eq!(have, SAVESTATE_VERSION + 1);
+                assert_eq!(want, SAVESTATE_VERSION);
+            }
+            _ => panic!("expected a version mismatch"),
+        }
+
+        assert!(matches!(
+            gba.restore_state(&state[..2]),
+            Err(SaveStateError::BadMagic)
+        ));
+        for truncated in &[&state[..8], &state[..SAVESTATE_HEADER_SIZE - 1]] {
+            match gba.restore_state(truncated) {
+                Err(SaveStateError::Deserialize(message)) => {
+                    assert_eq!(message, "truncated header")
+                }
+                _ => panic!("expected a truncated header"),
+            }
+        }
+        assert!(matches!(
+            gba.restore_state(&state[..SAVESTATE_HEADER_SIZE + 4]),
+            Err(SaveStateError::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn test_savestate_byte_order() {
+        // payloads are little-endian with fixed size integers on every host
+        assert_eq!(
+            savestate_options()
+                .serialize(&(0x1122_3344_u32, 0x5566_u16, true, Some(0x77_u8)))
+                .unwrap(),
+            vec![0x44, 0x33, 0x22, 0x11, 0x66, 0x55, 0x01, 0x01, 0x77]
+        );
+
+        let mut rom = vec![0; 0x200];
+        rom[0xac..0xb0].copy_from_slice(b"ABCD");
+        let mut gba = make_mock_gba(&rom);
+        gba.cpu.gpr[0] = 0xdead_beef;
+        let state = gba.save_state().unwrap();
+        assert_eq!(&state[8..12], b"ABCD");
+        assert_eq!(&state[12..16], &[0x04, 0x03, 0x02, 0x01]);
+
+        let mut big_endian = state.clone();
+        big_endian[12..16].copy_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+        assert!(matches!(
+            gba.restore_state(&big_endian),
+            Err(SaveStateError::ByteOrderMismatch(0x0403_0201))
+        ));
+    }
+
+    #[test]
+    fn test_restore_state_keeps_resampler() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let state = gba.save_state().unwrap();
+        gba.set_resampler(ResamplerKind::WindowedSinc);
+        gba.restore_state(&state).unwrap();
+        assert_eq!(gba.resampler_kind(), ResamplerKind::WindowedSinc);
+    }
+
+    #[test]
+    fn test_savestate_game_mismatch() {
+        let mut rom = vec![0; 0x200];
+        rom[0xac..0xb0].copy_from_slice(b"AAAA");
+        let mut gba_a = make_mock_gba(&rom);
+        rom[0xac..0xb0].copy_from_slice(b"BBBB");
+        let mut gba_b = make_mock_gba(&rom);
+
+        let state = gba_a.save_state().unwrap();
+        gba_a.cpu.gpr[0] = 0x1234;
+        match gba_b.restore_state(&state) {
+            Err(SaveStateError::GameCodeMismatch { have, want }) => {
+                assert_eq!(have, "AAAA");
+                assert_eq!(want, "BBBB");
+            }
+            _ => panic!("expected a game code mismatch"),
+        }
+        assert!(matches!(
+            GameBoyAdvance::from_saved_state(
+                &state,
+                vec![0; 0x4000].into_boxed_slice(),
+                rom.into_boxed_slice(),
+                NullAudio::new()
+            ),
+            Err(GBAError::SavestateGameMismatch { .. })
+        ));
+
+        gba_a.restore_state(&state).unwrap();
+        assert_eq!(gba_a.cpu.gpr[0], 0);
+    }
+
+    /// Execute `swi comment` from ARM code on a gba with the HLE bios
+    fn call_hle_swi(rom: &mut [u8], comment: u32, regs: &[u32]) -> GameBoyAdvance {
+        rom[0..4].copy_from_slice(&(0xef00_0000 | comment << 16).to_le_bytes());
+        rom[4..8].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes()); // b .
+        let cartridge = GamepakBuilder::new()
+            .buffer(rom)
+            .with_sram()
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let mut gba = GameBoyAdvance::new_hle(cartridge, NullAudio::new());
+        gba.cpu.gpr[..regs.len()].copy_from_slice(regs);
+        for _ in 0..10 {
+            gba.single_step();
+            if gba.cpu.get_next_pc() == 0x0800_0004 {
+                return gba;
+            }
+        }
+        panic!("SWI 0x{:02x} didn't return", comment);
+    }
+
+    #[test]
+    fn test_request_interrupt() {
+        let mut rom = vec![0; 0x200];
+        rom[0..4].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes()); // b .
+
+        // the user IRQ handler
+        rom[0x100..0x104].copy_from_slice(&0xe3a0_4042_u32.to_le_bytes()); // mov r4, #0x42
+        rom[0x104..0x108].copy_from_slice(&0xe12f_ff1e_u32.to_le_bytes()); // bx lr
+        let cartridge = GamepakBuilder::new()
+            .buffer(&rom)
+            .with_sram()
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let mut gba = GameBoyAdvance::new_hle(cartridge, NullAudio::new());
+        gba.sysbus.write_32(0x0300_7ffc, 0x0800_0100);
+        gba.sysbus.write_16(0x0400_0200, 1); // IE = VBlank
+        gba.sysbus.write_16(0x0400_0208, 1); // IME
+
+        let state = gba.interrupt_state();
+        assert!(state.interrupt_enable.LCD_VBlank());
+        assert!(state.interrupt_master_enable);
+        assert!(state.cpu_irq_enabled);
+        assert!(!state.irq_pending());
+
+        gba.run_for(100);
+        assert_eq!(gba.cpu.gpr[4], 0);
+
+        gba.request_interrupt(Interrupt::LCD_VBlank);
+        let state = gba.interrupt_state();
+        assert!(state.interrupt_flags.LCD_VBlank());
+        assert!(state.pending().LCD_VBlank());
+        assert!(state.irq_pending());
+
+        gba.run_for(1000);
+        assert_eq!(gba.cpu.gpr[4], 0x42);
+        // the handler didn't acknowledge the interrupt
+        assert!(gba.interrupt_state().interrupt_flags.LCD_VBlank());
+    }
+
+    #[test]
+    fn test_hle_div() {
+        let gba = call_hle_swi(&mut [0; 0x200], 0x06, &[100, 7]);
+        assert_eq!(gba.cpu.gpr[0], 14);
+        assert_eq!(gba.cpu.gpr[1], 2);
+        assert_eq!(gba.cpu.gpr[3], 14);
+
+        let gba = call_hle_swi(&mut [0; 0x200], 0x06, &[-7i32 as u32, 2]);
+        assert_eq!(gba.cpu.gpr[0], -3i32 as u32);
+        assert_eq!(gba.cpu.gpr[1], -1i32 as u32);
+        assert_eq!(gba.cpu.gpr[3], 3);
+
+        // DivArm swaps the operands
+        let gba = call_hle_swi(&mut [0; 0x200], 0x07, &[7, -100i32 as u32]);
+        assert_eq!(gba.cpu.gpr[0], -14i32 as u32);
+        assert_eq!(gba.cpu.gpr[1], -2i32 as u32);
+        assert_eq!(gba.cpu.gpr[3], 14);
+    }
+
+    #[test]
+    fn test_hle_sqrt() {
+        for (value, root) in [
+            (0, 0),
+            (15, 3),
+            (16, 4),
+            (0x10000, 0x100),
+            (1_000_000, 1000),
+            (0xffff_ffff, 0xffff),
+        ] {
+            let gba = call_hle_swi(&mut [0; 0x200], 0x08, &[value]);
+            assert_eq!(gba.cpu.gpr[0], root, "sqrt({})", value);
+        }
+    }
+
+    #[test]
+    fn test_hle_lz77_uncomp_vram() {
+        // "ABCD" as literals followed by a 12 bytes long back-reference with a displacement of 4
+        let blob = [
+            0x10, 0x10, 0x00, 0x00, 0x08, b'A', b'B', b'C', b'D', 0x90, 0x03,
+        ];
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x100 + blob.len()].copy_from_slice(&blob);
+        let gba = call_hle_swi(&mut rom, 0x12, &[0x0800_0100, 0x0600_0000]);
+        assert_eq!(&gba.io_devs.gpu.vram[0..16], b"ABCDABCDABCDABCD");
+        assert_eq!(gba.io_devs.gpu.vram[16], 0);
+    }
+
+    #[test]
+    fn test_hle_copies_wrap_around() {
+        // guest pointers wrap around the end of the address space instead of overflowing.
+        // CpuSet of words and halfwords
+        let words = 1 << 26 | 2;
+        call_hle_swi(&mut [0; 0x200], 0x0b, &[0x0300_0000, 0xffff_fffc, words]);
+        call_hle_swi(&mut [0; 0x200], 0x0b, &[0xffff_fffe, 0x0300_0000, 2]);
+        // CpuFastSet
+        call_hle_swi(&mut [0; 0x200], 0x0c, &[0xffff_fffc, 0x0300_0000, 8]);
+
+        // BitUnPack of 2 bytes from the last byte of the address space
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x108].copy_from_slice(&[2, 0, 8, 8, 0, 0, 0, 0]);
+        call_hle_swi(&mut rom, 0x10, &[0xffff_ffff, 0x0300_0000, 0x0800_0100]);
+
+        // BgAffineSet and ObjAffineSet writing past the end of the address space, with a huge stride
+        call_hle_swi(&mut [0; 0x200], 0x0e, &[0x0300_0000, 0xffff_fff0, 2]);
+        call_hle_swi(
+            &mut [0; 0x200],
+            0x0f,
+            &[0xffff_fffc, 0x0300_0000, 2, 0x8000_0000],
+        );
+
+        // decompressing to the end of the address space, 8 and 16 bits at a time
+        let blob = [
+            0x10, 0x10, 0x00, 0x00, 0x08, b'A', b'B', b'C', b'D', 0x90, 0x03,
+        ];
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x100 + blob.len()].copy_from_slice(&blob);
+        call_hle_swi(&mut rom, 0x11, &[0x0800_0100, 0xffff_fff8]);
+        call_hle_swi(&mut rom, 0x12, &[0x0800_0100, 0xffff_fff8]);
+    }
+
+    #[test]
+    fn test_load_cheat_file() {
+        let path = std::env::temp_dir().join(format!(
+            "rustboyadvance_test_load_cheat_file_{}.txt",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            "# a comment\n\
+             02000000:12 Byte\n\
+             not a cheat\n\
+             -02000004:deadbeef 02000008:5678 Disabled words\n\
+             03000000:123\n",
+        )
+        .unwrap();
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let errors = gba.load_cheat_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            errors.iter().map(|e| e.line).collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+        let cheats = gba.cheats().cheats();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].name, "Byte");
+        assert!(cheats[0].enabled);
+        assert_eq!(cheats[1].name, "Disabled words");
+        assert!(!cheats[1].enabled);
+        assert_eq!(cheats[1].codes.len(), 2);
+        assert_eq!(cheats[1].codes[0].value, 0xdeadbeef);
+
+        gba.frame();
+        assert_eq!(gba.sysbus.read_8(0x0200_0000), 0x12);
+        assert_eq!(gba.sysbus.read_32(0x0200_0004), 0);
+    }
+
+    #[test]
+    fn test_disable_cheats() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let (cheats, _) = cheats::parse_cheat_list("02000000:12\n02000004:34");
+        let ids: Vec<usize> = cheats
+            .into_iter()
+            .map(|cheat| gba.cheats_mut().add(cheat))
+            .collect();
+
+        gba.set_cheats_enabled(false);
+        gba.frame();
+        assert_eq!(gba.sysbus.read_8(0x0200_0000), 0);
+        assert_eq!(gba.sysbus.read_8(0x0200_0004), 0);
+
+        gba.set_cheats_enabled(true);
+        assert!(gba.set_cheat_enabled(ids[1], false));
+        assert!(!gba.set_cheat_enabled(ids[1] + 1, false));
+        gba.frame();
+        assert_eq!(gba.sysbus.read_8(0x0200_0000), 0x12);
+        assert_eq!(gba.sysbus.read_8(0x0200_0004), 0);
+    }
+
+    #[test]
+    fn test_run_frames() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+
+        let mut renders = 0;
+        gba.run_frames(8, true, |frame| {
+            assert_eq!(frame.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT);
+            renders += 1;
+        });
+        assert_eq!(renders, 1);
+
+        let mut renders = 0;
+        gba.run_frames(8, false, |_| renders += 1);
+        assert_eq!(renders, 8);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.sysbus.write_32(EWRAM_ADDR, 0xe3a0_0001); // mov r0, #1
+        gba.sysbus.write_32(EWRAM_ADDR + 4, 0xeaff_fffe); // b .
+        gba.sysbus.write_16(EWRAM_ADDR + 8, 0x2001); // movs r0, #1
+        gba.sysbus.write_16(EWRAM_ADDR + 10, 0xe7fe); // b .
+
+        let arm = gba.disassemble(EWRAM_ADDR, 2, false);
+        assert_eq!(arm.len(), 2);
+        assert_eq!((arm[0].0, arm[0].2), (EWRAM_ADDR, 0xe3a0_0001));
+        assert!(arm[0].1.starts_with("mov"), "{}", arm[0].1);
+        assert_eq!((arm[1].0, arm[1].2), (EWRAM_ADDR + 4, 0xeaff_fffe));
+        assert!(arm[1].1.starts_with('b'), "{}", arm[1].1);
+        assert!(arm[1].1.ends_with("0x2000004"), "{}", arm[1].1);
+
+        let thumb = gba.disassemble(EWRAM_ADDR + 8, 2, true);
+        assert_eq!((thumb[0].0, thumb[0].2), (EWRAM_ADDR + 8, 0x2001));
+        assert!(thumb[0].1.starts_with("mov"), "{}", thumb[0].1);
+        assert_eq!((thumb[1].0, thumb[1].2), (EWRAM_ADDR + 10, 0xe7fe));
+        assert!(thumb[1].1.starts_with('b'), "{}", thumb[1].1);
+    }
+
+    #[test]
+    fn test_load_gamepak() {
+        struct CountingAudio(Rc<Cell<usize>>);
+        impl AudioInterface for CountingAudio {
+            fn push_sample(&mut self, _sample: &[i16; 2]) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        fn make_rom(game_code: &[u8; 4]) -> Cartridge {
+            let mut rom = vec![0; 0x200];
+            rom[0xac..0xb0].copy_from_slice(game_code);
+            GamepakBuilder::new()
+                .buffer(&rom)
+                .without_backup_to_file()
+                .build()
+                .unwrap()
+        }
+
+        let samples = Rc::new(Cell::new(0));
+        let mut gba =
+            GameBoyAdvance::new_hle(make_rom(b"AAAA"), Box::new(CountingAudio(samples.clone())));
+        assert_eq!(gba.get_game_code(), "AAAA");
+        gba.frame();
+        let samples_before_swap = samples.get();
+        assert!(samples_before_swap > 0);
+
+        gba.load_gamepak(make_rom(b"BBBB"), true);
+        assert_eq!(gba.get_game_code(), "BBBB");
+        assert_eq!(gba.cpu.pc, 0x0800_0000);
+        gba.frame();
+        assert!(samples.get() > samples_before_swap);
+    }
+
+    #[test]
+    fn test_timed_audio_samples() {
+        struct TimedAudio(Rc<RefCell<Vec<(u64, usize)>>>);
+        impl AudioInterface for TimedAudio {
+            fn push_timed_samples(&mut self, timestamp_cycles: u64, batch: &[[i16; 2]]) {
+                self.0.borrow_mut().push((timestamp_cycles, batch.len()));
+            }
+        }
+
+        let cartridge = GamepakBuilder::new()
+            .buffer(&[0; 0x200])
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let batches = Rc::new(RefCell::new(Vec::new()));
+        let mut gba = GameBoyAdvance::new_hle(cartridge, Box::new(TimedAudio(batches.clone())));
+
+        gba.run_for(CYCLES_FULL_REFRESH);
+        let first_frame = batches.replace(Vec::new());
+        gba.run_for(CYCLES_FULL_REFRESH);
+        let second_frame = batches.replace(Vec::new());
+
+        let all: Vec<u64> = first_frame
+            .iter()
+            .chain(second_frame.iter())
+            .map(|(timestamp, _)| *timestamp)
+            .collect();
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // the batches of the next frame are a frame worth of cycles later
+        let frame_cycles = (second_frame[0].0 - first_frame[0].0) as usize;
+        let expected = (CYCLES_FULL_REFRESH - 512)..=(CYCLES_FULL_REFRESH + 512);
+        assert!(expected.contains(&frame_cycles), "{}", frame_cycles);
+
+        // a frame worth of cycles produces about 44100 / 59.73 samples
+        for frame in &[first_frame, second_frame] {
+            let samples: usize = frame.iter().map(|(_, len)| len).sum();
+            assert!((728..=748).contains(&samples), "{}", samples);
+        }
+    }
+
+    #[test]
+    fn test_pump_silence() {
+        struct RecordingAudio(Rc<RefCell<Vec<[i16; 2]>>>);
+        impl AudioInterface for RecordingAudio {
+            fn get_sample_rate(&self) -> i32 {
+                32768
+            }
+
+            fn push_sample(&mut self, sample: &[i16; 2]) {
+                self.0.borrow_mut().push(*sample);
+            }
+        }
+
+        let cartridge = GamepakBuilder::new()
+            .buffer(&[0; 0x200])
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let samples = Rc::new(RefCell::new(Vec::new()));
+        let mut gba = GameBoyAdvance::new_hle(cartridge, Box::new(RecordingAudio(samples.clone())));
+
+        gba.pump_silence(250);
+        assert_eq!(samples.borrow().len(), 8192);
+        assert!(samples.borrow().iter().all(|sample| *sample == [0, 0]));
+
+        gba.pump_silence(0);
+        assert_eq!(samples.borrow().len(), 8192);
+
+        // 32.768 samples per millisecond, the fractions add up
+        for _ in 0..125 {
+            gba.pump_silence(1);
+        }
+        assert_eq!(samples.borrow().len(), 8192 + 4096);
+
+        assert_eq!(gba.audio_sample_rate(), 32768);
+        gba.pump_silence_samples(100);
+        assert_eq!(samples.borrow().len(), 8192 + 4096 + 100);
+    }
+
+    #[test]
+    fn test_input_script() {
+        use crate::keypad::{Keys, KEYINPUT_ALL_RELEASED};
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let a_pressed = KEYINPUT_ALL_RELEASED & !(1 << Keys::ButtonA as u16);
+        gba.set_key_state_for_frames(a_pressed, 3);
+
+        for _ in 0..3 {
+            gba.frame();
+            assert_eq!(*gba.get_key_state(), a_pressed);
+        }
+        gba.frame();
+        assert_eq!(*gba.get_key_state(), KEYINPUT_ALL_RELEASED);
+        assert!(gba.input_script_mut().is_empty());
+    }
+
+    #[test]
+    fn test_debug_read_io_registers() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        signal_irq(&gba.interrupt_flags, Interrupt::LCD_VBlank);
+        gba.sysbus.write_16(0x0400_0102, 0x0080); // start timer 0
+        gba.run_for(1000);
+
+        let timers = bincode::serialize(&gba.io_devs.timers).unwrap();
+        let first = gba.sysbus.debug_read_16(0x0400_0100);
+        let second = gba.sysbus.debug_read_16(0x0400_0100);
+        assert_eq!(first, second);
+        assert_ne!(first, 0);
+        assert_eq!(bincode::serialize(&gba.io_devs.timers).unwrap(), timers);
+
+        let flags = gba.interrupt_flags.get();
+        assert_eq!(gba.sysbus.debug_read_16(0x0400_0202), flags.value());
+        assert_eq!(gba.sysbus.debug_read_16(0x0400_0202), flags.value());
+        assert_eq!(gba.interrupt_flags.get(), flags);
+    }
+
+    #[test]
+    fn test_dump_io_registers() {
+        use crate::iodev::consts::*;
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let registers = gba.dump_io_registers();
+        assert!(registers.contains(&(REG_DISPCNT, 0x0080)));
+        assert!(registers.contains(&(REG_BG0CNT, 0)));
+        assert!(registers.iter().any(|&(addr, _)| addr == REG_DMA0SAD + 2));
+        assert!(registers.iter().any(|&(addr, _)| addr == REG_WAVE_RAM + 14));
+        // the unused halfwords after 16bit registers are left out
+        for unused in &[0x056, 0x086, 0x08a, 0x206, 0x20a, 0x302] {
+            assert!(registers.iter().all(|&(addr, _)| addr != IO_BASE + unused));
+        }
+        assert!(registers
+            .iter()
+            .all(|&(addr, _)| (IO_BASE..IO_BASE + 0x400).contains(&addr)));
+        assert_eq!(io_register_name(REG_BG0CNT), Some("REG_BG0CNT"));
+        assert_eq!(io_register_name(0x0400_00e0), None);
+
+        // dumping doesn't disturb the state
+        assert_eq!(gba.dump_io_registers(), registers);
+    }
+
+    #[test]
+    fn test_debug_write_vram() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.sysbus.debug_write_bytes(0x0600_0001, &[0x12, 0x34]);
+        assert_eq!(gba.sysbus.debug_read_32(0x0600_0000), 0x0034_1200);
+
+        gba.sysbus.debug_write_8(0x0800_0000, 0xaa);
+        assert_eq!(gba.sysbus.read_8(0x0800_0000), 0xaa);
+    }
+
+    #[test]
+    fn test_affine_reference_point_writes() {
+        let mut gba = make_mock_gba_with_code(&[0xeafffffe]); // b .
+
+        // mode 2 with BG2, a 128x128 map where only the top left tile is red
+        gba.sysbus.write_16(0x0400_0000, 0x0402);
+        gba.sysbus.write_16(0x0400_000c, 0x0100);
+        for i in 0..0x20 {
+            gba.sysbus.write_16(0x0600_0040 + 2 * i, 0x0101);
+            gba.sysbus.write_16(0x0600_0080 + 2 * i, 0x0202);
+        }
+        for i in 0..0x80 {
+            gba.sysbus.write_16(0x0600_0800 + 2 * i, 0x0202);
+        }
+        gba.sysbus.write_16(0x0600_0800, 0x0201);
+        let red = Rgb15::from_rgb(31, 0, 0);
+        let blue = Rgb15::from_rgb(0, 0, 31);
+        gba.sysbus.write_16(0x0500_0002, red.0);
+        gba.sysbus.write_16(0x0500_0004, blue.0);
+
+        fn run_until(gba: &mut GameBoyAdvance, vcount: usize, hblank: bool) {
+            while gba.io_devs.gpu.vcount != vcount || gba.io_devs.gpu.dispstat.hblank_flag != hblank
+            {
+                gba.run_for(1);
+            }
+        }
+        let pixel = |gba: &GameBoyAdvance, x: usize, y: usize| gba.get_frame_buffer()[y * 240 + x];
+
+        // written during hdraw, BG2X = 8.0 applies from the next scanline
+        run_until(&mut gba, 2, false);
+        gba.sysbus.write_16(0x0400_0028, 0x0800);
+        run_until(&mut gba, 4, false);
+        assert_eq!(pixel(&gba, 0, 1), red.to_rgb24());
+        assert_eq!(pixel(&gba, 0, 3), blue.to_rgb24());
+
+        // written during hblank, BG2X = 0 and BG2Y = 7.0 is the exact origin of the next scanline
+        run_until(&mut gba, 19, true);
+        gba.sysbus.write_16(0x0400_0028, 0);
+        gba.sysbus.write_16(0x0400_002c, 0x0700);
+        run_until(&mut gba, 21, false);
+        assert_eq!(pixel(&gba, 0, 20), red.to_rgb24());
+        assert_eq!(pixel(&gba, 8, 20), blue.to_rgb24());
+    }
+
+    #[test]
+    fn test_work_ram_mirrors() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.sysbus.write_32(0x0300_7ffc, 0x0800_0100);
+        assert_eq!(gba.sysbus.read_32(0x03ff_fffc), 0x0800_0100);
+        assert_eq!(gba.sysbus.read_16(0x03ff_fffe), 0x0800);
+        assert_eq!(gba.sysbus.read_8(0x03ff_ffff), 0x08);
+
+        gba.sysbus.write_32(0x03ff_fff8, 0xdead_beef);
+        assert_eq!(gba.sysbus.read_32(0x0300_7ff8), 0xdead_beef);
+
+        gba.sysbus.write_32(0x0203_fffc, 0x1234_5678);
+        assert_eq!(gba.sysbus.read_32(0x02ff_fffc), 0x1234_5678);
+        gba.sysbus.write_16(0x02ff_0000, 0xabcd);
+        assert_eq!(gba.sysbus.read_16(0x0203_0000), 0xabcd);
+    }
+
+    #[test]
+    fn test_read_write_memory() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let pattern: Vec<u8> = (0..0x400).map(|i| (i * 7) as u8).collect();
+        for (i, byte) in pattern.iter().enumerate() {
+            gba.sysbus.write_8(EWRAM_ADDR + 0x100 + i as u32, *byte);
+        }
+        assert_eq!(gba.read_memory(EWRAM_ADDR + 0x100, 0x400), pattern);
+
+        // across the last mirror of ewram and into iwram
+        gba.write_memory(0x02ff_fffe, &[1, 2, 3, 4]);
+        assert_eq!(gba.sysbus.read_16(0x0203_fffe), 0x0201);
+        assert_eq!(gba.sysbus.read_16(IWRAM_ADDR), 0x0403);
+        assert_eq!(gba.read_memory(0x0203_fffe, 2), vec![1, 2]);
+
+        // regions without a bulk path are copied byte by byte
+        gba.write_memory(VRAM_ADDR, &pattern);
+        assert_eq!(gba.read_memory(VRAM_ADDR, 0x400), pattern);
+    }
+
+    #[test]
+    fn test_internal_memory_control() {
+        use arm7tdmi::memory::{MemoryAccess, MemoryInterface};
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let cycles = |gba: &mut GameBoyAdvance, addr| {
+            let start = gba.scheduler.timestamp();
+            gba.sysbus.load_16(addr, MemoryAccess::NonSeq);
+            gba.sysbus.load_32(addr, MemoryAccess::NonSeq);
+            gba.scheduler.timestamp() - start
+        };
+
+        assert_eq!(gba.sysbus.read_32(0x0400_0800), 0x0D00_0020);
+        assert_eq!(cycles(&mut gba, 0x0200_0000), 3 + 6);
+
+        // 1 waitstate, also through the mirror of the register
+        gba.sysbus.write_32(0x0401_0800, 0x0E00_0020);
+        assert_eq!(gba.sysbus.read_32(0x0400_0800), 0x0E00_0020);
+        assert_eq!(cycles(&mut gba, 0x0200_0000), 2 + 4);
+
+        // a disabled EWRAM mirrors IWRAM
+        gba.sysbus.write_32(0x0300_0010, 0x1234_5678);
+        gba.sysbus.write_16(0x0400_0800, 0);
+        assert_eq!(gba.sysbus.read_32(0x0200_0010), 0x1234_5678);
+        assert_eq!(cycles(&mut gba, 0x0200_0000), 1 + 1);
+
+        gba.sysbus.write_16(0x0400_0800, 0x20);
+        assert_eq!(gba.sysbus.read_32(0x0200_0010), 0);
+    }
+
+    #[test]
+    fn test_frame_hash() {
+        // Disable the forced blank and idle
+        let code: [u32; 4] = [
+            0xe3a03301, // mov r3, #0x04000000
+            0xe3a04000, // mov r4, #0
+            0xe1c340b0, // strh r4, [r3]
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        gba.frame();
+        gba.frame();
+        let idle_hash = gba.frame_hash();
+        gba.frame();
+        assert_eq!(gba.frame_hash(), idle_hash);
+
+        // The hash doesn't depend on the color correction of the output
+        gba.set_color_correction(ColorCorrection::GbaLcd);
+        gba.frame();
+        assert_eq!(gba.frame_hash(), idle_hash);
+
+        // Change the backdrop color
+        gba.sysbus.write_16(0x0500_0000, 0x001f);
+        gba.frame();
+        assert_ne!(gba.frame_hash(), idle_hash);
+    }
+
+    #[test]
+    fn test_frame_changed() {
+        // Enable BG0 in mode 0 and idle
+        let code: [u32; 4] = [
+            0xe3a03301, // mov r3, #0x04000000
+            0xe3a04c01, // mov r4, #0x100
+            0xe1c340b0, // strh r4, [r3]
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+        // BG0 shows tile 0 everywhere
+        gba.sysbus.write_16(0x0400_0008, 0x0000);
+        gba.sysbus.write_16(0x0500_0002, 0x7fff);
+
+        gba.frame();
+        assert!(gba.frame_changed());
+        gba.frame();
+        gba.frame();
+        gba.frame();
+        assert!(!gba.frame_changed());
+        gba.frame();
+        assert!(!gba.frame_changed());
+
+        // Draw a pixel of tile 0 with color 1
+        gba.sysbus.write_16(0x0600_0000, 0x0001);
+        gba.frame();
+        assert!(gba.frame_changed());
+        gba.frame();
+        assert!(!gba.frame_changed());
+    }
+
+    #[test]
+    fn test_replay() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        use crate::keypad::{Keys, KEYINPUT_ALL_RELEASED};
+
+        fn frame_buffer_hash(gba: &GameBoyAdvance) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            gba.get_frame_buffer().hash(&mut hasher);
+            hasher.finish()
+        }
+
+        // Disable the forced blank, then copy KEYINPUT to the backdrop color forever
+        let code: [u32; 10] = [
+            0xe3a03301, // mov r3, #0x04000000
+            0xe3a04000, // mov r4, #0
+            0xe1c340b0, // strh r4, [r3]
+            0xe59f000c, // ldr r0, =0x04000130
+            0xe59f100c, // ldr r1, =0x05000000
+            0xe1d020b0, // loop: ldrh r2, [r0]
+            0xe1c120b0, // strh r2, [r1]
+            0xeafffffc, // b loop
+            0x04000130, 0x05000000,
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        let key = |key: Keys| KEYINPUT_ALL_RELEASED & !(1 << key as u16);
+        gba.start_recording().unwrap();
+        gba.set_key_state_for_frames(key(Keys::ButtonA), 20);
+        gba.set_key_state_for_frames(key(Keys::Up), 25);
+        gba.set_key_state_for_frames(key(Keys::ButtonL) & key(Keys::ButtonR), 15);
+        for _ in 0..60 {
+            gba.frame();
+        }
+        let replay = gba.stop_recording().unwrap();
+        assert_eq!(replay.frames(), 60);
+        let expected_hash = frame_buffer_hash(&gba);
+
+        // Replay on a fresh instance, after going through serialization
+        let replay = Replay::from_bytes(&replay.to_bytes().unwrap()).unwrap();
+        let mut gba = make_mock_gba_with_code(&code);
+        gba.play_replay(&replay).unwrap();
+        for _ in 0..replay.frames() {
+            gba.frame();
+        }
+        assert_eq!(frame_buffer_hash(&gba), expected_hash);
+        assert_eq!(
+            gba.sysbus.read_16(0x0500_0000),
+            key(Keys::ButtonL) & key(Keys::ButtonR)
+        );
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        // A busy loop counting in r5, whose value at the end of each frame depends on where the frame ended
+        let code: [u32; 3] = [
+            0xe8901ffe, // loop: ldmia r0, {r1-r12}
+            0xe2855001, // add r5, r5, #1
+            0xeafffffc, // b loop
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+        gba.cpu.gpr[0] = 0x0800_0000;
+        gba.frame();
+        while gba.frame_overshoot == 0 {
+            gba.frame();
+        }
+
+        gba.start_recording().unwrap();
+        for _ in 0..10 {
+            gba.frame();
+        }
+        let replay = gba.stop_recording().unwrap();
+        let expected_state = gba.save_state().unwrap();
+
+        let mut replayed = make_mock_gba_with_code(&code);
+        replayed.play_replay(&replay).unwrap();
+        for _ in 0..replay.frames() {
+            replayed.frame();
+        }
+        assert_eq!(replayed.cpu.gpr[5], gba.cpu.gpr[5]);
+        assert_eq!(replayed.save_state().unwrap(), expected_state);
+    }
+
+    #[test]
+    fn test_save_ram_export_import() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        for i in 0..0x100 {
+            gba.sysbus.write_8(0x0E00_0000 + i, (i as u8) ^ 0x5a);
+        }
+        let save = gba.export_save_ram();
+        assert_eq!(save.len(), 0x8000);
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        assert!(gba.import_save_ram(&save[..0x1000]).is_err());
+        gba.import_save_ram(&save).unwrap();
+        for i in 0..0x100 {
+            assert_eq!(gba.sysbus.read_8(0x0E00_0000 + i), (i as u8) ^ 0x5a);
+        }
+        assert_eq!(gba.export_save_ram(), save);
+    }
+
+    #[test]
+    fn test_open_bus_reads() {
+        // mov r0, #1; mov r1, #2; mov r2, #3
+        let mut gba = make_mock_gba_with_code(&[0xe3a00001, 0xe3a01002, 0xe3a02003]);
+        gba.cpu.step();
+
+        // unmapped regions read the last prefetched opcode
+        assert_eq!(gba.cpu.get_prefetched_opcode(), 0xe3a02003);
+        assert_eq!(gba.sysbus.read_32(0x1000_0000), 0xe3a02003);
+        assert_eq!(gba.sysbus.read_16(0x1000_0002), 0xe3a0);
+        assert_eq!(gba.sysbus.read_8(0x1000_0001), 0x20);
+
+        // gamepak reads past the end of the ROM see the address on the data lines
+        assert_eq!(gba.sysbus.read_16(0x0800_0200), 0x0100);
+        assert_eq!(gba.sysbus.read_32(0x0800_1000), 0x0801_0800);
+    }
+
+    #[test]
+    fn test_write_only_io_reads() {
+        // mov r0, #1; mov r1, #2; mov r2, #3
+        let mut gba = make_mock_gba_with_code(&[0xe3a00001, 0xe3a01002, 0xe3a02003]);
+        gba.cpu.step();
+
+        // only the control bits of DMAxCNT_H read back, the word count reads as zero
+        gba.sysbus.write_16(REG_DMA0CNT_L, 0x1234);
+        gba.sysbus.write_16(REG_DMA0CNT_H, 0x3a1f); // DRQ and the unused bits set, DMA disabled
+        assert_eq!(gba.sysbus.read_16(REG_DMA0CNT_L), 0);
+        assert_eq!(gba.sysbus.read_16(REG_DMA0CNT_H), 0x3200);
+        assert_eq!(gba.sysbus.read_32(REG_DMA0CNT_L), 0x3200_0000);
+        gba.sysbus.write_16(REG_DMA3CNT_H, 0x3a1f);
+        assert_eq!(gba.sysbus.read_16(REG_DMA3CNT_H), 0x3a00);
+
+        // the FIFOs and the DMA addresses see the open bus
+        gba.sysbus.write_32(REG_FIFO_A, 0x1122_3344);
+        assert_eq!(gba.sysbus.read_32(REG_FIFO_A), 0xe3a02003);
+        assert_eq!(gba.sysbus.read_16(REG_FIFO_B + 2), 0xe3a0);
+        assert_eq!(gba.sysbus.read_8(REG_FIFO_B + 1), 0x20);
+        gba.sysbus.write_32(REG_DMA0SAD, 0x0200_0000);
+        assert_eq!(gba.sysbus.read_32(REG_DMA0SAD), 0xe3a02003);
+
+        // the debugger still sees the written values
+        gba.sysbus.write_16(REG_BG0HOFS, 0x123);
+        assert_eq!(gba.sysbus.read_16(REG_BG0HOFS), 0x2003);
+        assert_eq!(gba.sysbus.debug_read_16(REG_BG0HOFS), 0x123);
+    }
+
+    #[test]
+    fn test_write_only_io_byte_writes() {
+        // mov r0, #1; mov r1, #2; mov r2, #3
+        let mut gba = make_mock_gba_with_code(&[0xe3a00001, 0xe3a01002, 0xe3a02003]);
+        gba.cpu.step();
+
+        // a byte write keeps the stored value of the other byte, not the open bus the cpu reads
+        gba.sysbus.write_16(REG_WIN0H, 0x10f0);
+        gba.sysbus.write_8(REG_WIN0H, 0x80);
+        assert_eq!(gba.sysbus.debug_read_16(REG_WIN0H), 0x1080);
+        gba.sysbus.write_8(REG_WIN0H + 1, 0x20);
+        assert_eq!(gba.sysbus.debug_read_16(REG_WIN0H), 0x2080);
+
+        gba.sysbus.write_16(REG_MOSAIC, 0x1234);
+        gba.sysbus.write_8(REG_MOSAIC + 1, 0x56);
+        assert_eq!(gba.sysbus.debug_read_16(REG_MOSAIC), 0x5634);
+
+        gba.sysbus.write_32(REG_BG2X_L, 0x0123_4567);
+        gba.sysbus.write_8(REG_BG2X_L, 0x89);
+        assert_eq!(gba.io_devs.gpu.bg_aff[0].x, 0x0123_4589);
+
+        gba.sysbus.write_32(REG_DMA0SAD, 0x0300_1234);
+        gba.sysbus.write_8(REG_DMA0SAD + 1, 0x56);
+        assert_eq!(gba.io_devs.dmac.channels[0].src, 0x0300_5634);
+    }
+
+    #[test]
+    fn test_sram_wide_reads() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.sysbus.write_8(0x0E00_0000, 0x12);
+        gba.sysbus.write_8(0x0E00_0001, 0x5a);
+
+        assert_eq!(gba.sysbus.read_8(0x0E00_0001), 0x5a);
+        assert_eq!(gba.sysbus.read_16(0x0E00_0001), 0x5a5a);
+        assert_eq!(gba.sysbus.read_32(0x0E00_0000), 0x1212_1212);
+        assert_eq!(gba.sysbus.read_32(0x0E00_0001), 0x5a5a_5a5a);
+    }
+
+    #[test]
+    fn test_gamepak_mirrors() {
+        let mut rom = vec![0; 0x200];
+        rom[0x100..0x104].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+        let mut gba = make_mock_gba(&rom);
+
+        // the ROM is mirrored in all three waitstate regions
+        for base in [0x0800_0000, 0x0a00_0000, 0x0c00_0000, 0x0900_0000].iter() {
+            assert_eq!(gba.sysbus.read_32(base + 0x100), 0xdead_beef);
+            // but not within a region, reads past the end of the ROM see the address on the data lines
+            assert_eq!(gba.sysbus.read_16(base + 0x200), 0x0100);
+            assert_eq!(gba.sysbus.read_8(base + 0x203), 0x01);
+            assert_eq!(gba.sysbus.read_32(base + 0x0100_0000), 0x0001_0000);
+        }
+
+        // the 32KB SRAM repeats over the whole SRAM region
+        gba.sysbus.write_8(0x0E00_0010, 0x42);
+        for mirror in [0x0E00_8010, 0x0E01_0010, 0x0E12_3010, 0x0F00_0010].iter() {
+            assert_eq!(gba.sysbus.read_8(*mirror), 0x42);
+        }
+        gba.sysbus.write_8(0x0F00_8020, 0x24);
+        assert_eq!(gba.sysbus.read_8(0x0E00_0020), 0x24);
+    }
+
+    #[test]
+    fn test_halt_skips_to_timer_irq() {
+        use crate::sched::EventType;
+
+        let code: [u32; 10] = [
+            0xe3a00301, // mov r0, #0x04000000
+            0xe2803c02, // add r3, r0, #0x200
+            0xe3a02008, // mov r2, #8
+            0xe1c320b0, // strh r2, [r3] ; IE = Timer 0
+            0xe59f100c, // ldr r1, =0x00c0ff00
+            0xe5801100, // str r1, [r0, #0x100] ; overflow and IRQ in 256 cycles
+            0xe5c00301, // strb r0, [r0, #0x301] ; HALTCNT = 0
+            0xe3a04001, // mov r4, #1
+            0xeafffffe, // b .
+            0x00c0ff00,
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        for _ in 0..7 {
+            assert!(gba.single_step());
+        }
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Halt);
+        assert_eq!(gba.scheduler.peek_next(), Some(EventType::TimerOverflow(0)));
+        let overflow_time = unsafe { gba.scheduler.timestamp_of_next_event_unchecked() };
+
+        // the cpu doesn't run while halted, the time jumps straight to the timer overflow
+        assert!(!gba.single_step());
+        assert_eq!(gba.scheduler.timestamp(), overflow_time);
+        assert_eq!(gba.cpu.gpr[4], 0);
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Halt);
+
+        // the overflow requests an interrupt, which wakes up the cpu even though IME is cleared
+        assert!(!gba.handle_events());
+        assert!(!gba.single_step());
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Running);
+        assert!(gba.single_step());
+        assert_eq!(gba.cpu.gpr[4], 1);
+    }
+
+    #[test]
+    fn test_keypad_irq_wakes_halt() {
+        use crate::keypad::{Keys, KEYINPUT_ALL_RELEASED};
+
+        let code: [u32; 11] = [
+            0xe3a00301, // mov r0, #0x04000000
+            0xe2803c02, // add r3, r0, #0x200
+            0xe3a02a01, // mov r2, #0x1000
+            0xe1c320b0, // strh r2, [r3] ; IE = Keypad
+            0xe3a01903, // mov r1, #0xc000
+            0xe3811003, // orr r1, r1, #3
+            0xe2805c01, // add r5, r0, #0x100
+            0xe1c513b2, // strh r1, [r5, #0x32] ; KEYCNT = A and B, IRQ enabled, AND condition
+            0xe5c00301, // strb r0, [r0, #0x301] ; HALTCNT = 0
+            0xe3a04001, // mov r4, #1
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+        let keypad_irq = |gba: &GameBoyAdvance| gba.interrupt_flags.get().Keypad();
+
+        gba.run_for(1000);
+        assert_eq!(gba.io_devs.keycnt.0, 0xc003);
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Halt);
+        assert!(!keypad_irq(&gba));
+
+        // holding only one of the keys doesn't satisfy the AND condition
+        *gba.get_key_state_mut() = KEYINPUT_ALL_RELEASED & !(1 << Keys::ButtonA as u16);
+        gba.run_for(1000);
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Halt);
+        assert!(!keypad_irq(&gba));
+
+        *gba.get_key_state_mut() &= !(1 << Keys::ButtonB as u16);
+        gba.run_for(1000);
+        assert!(keypad_irq(&gba));
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Running);
+        assert_eq!(gba.cpu.gpr[4], 1);
+
+        // in OR mode any of the selected keys is enough
+        gba.io_devs.intc.clear(0xffff);
+        *gba.get_key_state_mut() = KEYINPUT_ALL_RELEASED & !(1 << Keys::ButtonB as u16);
+        gba.sysbus.write_16(REG_KEYCNT, 0x4003);
+        assert!(keypad_irq(&gba));
+
+        gba.io_devs.intc.clear(0xffff);
+        gba.sysbus.write_16(REG_KEYCNT, 0x0003);
+        gba.run_for(1000);
+        assert!(!keypad_irq(&gba));
+    }
+
+    #[test]
+    fn test_hle_vblank_intr_wait() {
+        let mut rom = vec![0; 0x200];
+        let code: [u32; 12] = [
+            0xef050000, // swi 0x05 ; VBlankIntrWait
+            0xe3a04001, // mov r4, #1
+            0xeafffffe, // b .
+            0x00000000, // padding
+            0xe3a03301, // mov r3, #0x04000000 ; the user IRQ handler
+            0xe2833c02, // add r3, r3, #0x200
+            0xe3a02001, // mov r2, #1
+            0xe1c320b2, // strh r2, [r3, #2] ; acknowledge VBlank in IF
+            0xe59f1004, // ldr r1, =0x03007ff8
+            0xe1c120b0, // strh r2, [r1] ; and in the bios IRQ flags
+            0xe12fff1e, // bx lr
+            0x03007ff8,
+        ];
+        for (i, word) in code.iter().enumerate() {
+            rom[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        let cartridge = GamepakBuilder::new()
+            .buffer(&rom)
+            .without_backup_to_file()
+            .build()
+            .unwrap();
+        let mut gba = GameBoyAdvance::new_hle(cartridge, NullAudio::new());
+        gba.sysbus.write_32(0x0300_7ffc, 0x0800_0010);
+        gba.sysbus.write_16(0x0400_0004, 0x0008); // DISPSTAT VBlank IRQ
+        gba.sysbus.write_16(0x0400_0200, 1); // IE = VBlank
+
+        // the swi halts the cpu, and is executed again once an interrupt arrives
+        assert!(gba.single_step());
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Halt);
+        assert_eq!(gba.cpu.get_next_pc(), 0x0800_0000);
+
+        // no instruction is executed until the VBlank interrupt, the time skips between the events
+        while gba.io_devs.haltcnt == HaltState::Halt {
+            assert!(!gba.single_step());
+            gba.handle_events();
+        }
+        assert_eq!(gba.io_devs.gpu.vcount, DISPLAY_HEIGHT);
+        assert_eq!(gba.scheduler.timestamp(), DISPLAY_HEIGHT * 1232);
+        assert_eq!(gba.cpu.gpr[4], 0);
+
+        // the second execution of the swi sees the flag set by the IRQ handler and returns
+        gba.run_for(1000);
+        assert_eq!(gba.cpu.gpr[4], 1);
+        assert_eq!(gba.io_devs.gpu.vcount, DISPLAY_HEIGHT);
+        assert!(!gba.io_devs.hle_intr_wait);
+        assert_eq!(gba.sysbus.read_16(0x0300_7ff8), 0);
+        assert!(!gba.interrupt_state().interrupt_flags.LCD_VBlank());
+    }
+
+    #[test]
+    fn test_stop_pauses_hardware() {
+        let code: [u32; 4] = [
+            0xe3a00301, // mov r0, #0x04000000
+            0xe3a01080, // mov r1, #0x80
+            0xe5c01301, // strb r1, [r0, #0x301] ; HALTCNT = 0x80
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        for _ in 0..3 {
+            gba.single_step();
+        }
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Stop);
+        let vcount = gba.io_devs.gpu.vcount;
+        let start = gba.scheduler.timestamp();
+        assert_eq!(gba.run_for(CYCLES_FULL_REFRESH), StopReason::CyclesExpired);
+        assert_eq!(gba.scheduler.timestamp(), start + CYCLES_FULL_REFRESH);
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Stop);
+        assert_eq!(gba.io_devs.gpu.vcount, vcount);
+
+        // only a keypad, gamepak or serial interrupt can end the stop mode
+        gba.io_devs.intc.interrupt_enable = IrqBitmask(0x1fff);
+        signal_irq(&gba.interrupt_flags, Interrupt::LCD_VBlank);
+        gba.single_step();
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Stop);
+        signal_irq(&gba.interrupt_flags, Interrupt::Keypad);
+        gba.single_step();
+        assert_eq!(gba.io_devs.haltcnt, HaltState::Running);
+    }
+
+    #[test]
+    fn test_scanline_callback() {
+        struct ScanlineRecorder(Rc<RefCell<Vec<usize>>>);
+        impl VideoInterface for ScanlineRecorder {
+            fn on_scanline(&mut self, vcount: usize, line: &[u32]) {
+                assert_eq!(line.len(), DISPLAY_WIDTH);
+                self.0.borrow_mut().push(vcount);
+            }
+        }
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        gba.set_video_interface(Some(Box::new(ScanlineRecorder(lines.clone()))));
+        for _ in 0..2 {
+            gba.frame();
+            assert_eq!(*lines.borrow(), (0..DISPLAY_HEIGHT).collect::<Vec<_>>());
+            lines.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn test_render_rgb15() {
+        struct FrameRecorder(Rc<RefCell<Vec<Rgb15>>>);
+        impl VideoInterface for FrameRecorder {
+            fn render_rgb15(&mut self, buffer: &[Rgb15]) {
+                *self.0.borrow_mut() = buffer.to_vec();
+            }
+        }
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let frame = Rc::new(RefCell::new(Vec::new()));
+        gba.set_video_interface(Some(Box::new(FrameRecorder(frame.clone()))));
+        // nothing is enabled, the whole screen shows the backdrop color
+        gba.sysbus.write_16(REG_DISPCNT, 0);
+        gba.sysbus.write_16(0x0500_0000, 0x1234);
+        gba.frame();
+
+        let frame = frame.borrow();
+        assert_eq!(frame.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT);
+        assert_eq!(frame[80 * DISPLAY_WIDTH + 120], Rgb15(0x1234));
+        assert_eq!(
+            gba.get_frame_buffer()[80 * DISPLAY_WIDTH + 120],
+            Rgb15(0x1234).to_rgb24()
+        );
+    }
+
+    #[test]
+    fn test_abort_on_unmapped() {
+        use arm7tdmi::CpuMode;
+
+        let code: [u32; 2] = [
+            0xe3a0f201, // mov pc, #0x10000000
+            0xe5910000, // ldr r0, [r1]
+        ];
+
+        // open bus by default
+        let mut gba = make_mock_gba_with_code(&code);
+        gba.cpu.step();
+        gba.cpu.step();
+        assert_eq!(gba.cpu.cpsr.mode(), CpuMode::System);
+
+        let mut gba = make_mock_gba_with_code(&code);
+        gba.set_abort_on_unmapped(true);
+        gba.cpu.step();
+        assert_eq!(gba.cpu.pc_arm(), 0x1000_0000);
+        gba.cpu.step();
+        assert_eq!(gba.cpu.cpsr.mode(), CpuMode::Abort);
+        assert_eq!(gba.cpu.cpsr.state(), CpuState::ARM);
+        assert_eq!(gba.cpu.pc_arm(), 0x0c);
+        assert_eq!(gba.cpu.get_reg(14), 0x1000_0004);
+        assert_eq!(gba.cpu.spsr.mode(), CpuMode::System);
+
+        let mut gba = make_mock_gba_with_code(&code);
+        gba.set_abort_on_unmapped(true);
+        gba.cpu.pc = 0x0800_0004;
+        gba.cpu.reload_pipeline32();
+        gba.cpu.set_reg(1, 0x0100_0000);
+        gba.cpu.step();
+        assert_eq!(gba.cpu.cpsr.mode(), CpuMode::Abort);
+        assert_eq!(gba.cpu.pc_arm(), 0x10);
+        assert_eq!(gba.cpu.get_reg(14), 0x0800_000c);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_trace_sink() {
+        use arm7tdmi::trace::{CpuTraceEvent, TraceSink};
+
+        struct PcRecorder(Rc<RefCell<Vec<(Addr, u32, CpuState)>>>);
+
+        impl TraceSink for PcRecorder {
+            fn on_instruction(&mut self, event: &CpuTraceEvent) {
+                self.0
+                    .borrow_mut()
+                    .push((event.pc, event.opcode, event.state));
+            }
+        }
+
+        // mov r0, #1; mov r1, #2; add r2, r0, r1
+        let mut gba = make_mock_gba_with_code(&[0xe3a00001, 0xe3a01002, 0xe0802001]);
+
+        let trace = Rc::new(RefCell::new(Vec::new()));
+        gba.set_trace_sink(Some(Box::new(PcRecorder(trace.clone()))));
+        for _ in 0..3 {
+            gba.cpu.step();
+        }
+        gba.set_trace_sink(None);
+        gba.cpu.step();
+
+        assert_eq!(
+            *trace.borrow(),
+            vec![
+                (0x0800_0000, 0xe3a00001, CpuState::ARM),
+                (0x0800_0004, 0xe3a01002, CpuState::ARM),
+                (0x0800_0008, 0xe0802001, CpuState::ARM),
+            ]
+        );
+        assert_eq!(gba.cpu.get_reg(2), 3);
+    }
+
+    #[test]
+    fn test_speed_profile() {
+        // a long run of nops in ROM followed by `b .`
+        let mut rom = vec![0; 0x1000];
+        for insn in rom.chunks_exact_mut(4) {
+            insn.copy_from_slice(&0xe1a0_0000_u32.to_le_bytes()); // mov r0, r0
+        }
+        rom[0xffc..].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes());
+
+        fn run_nops(gba: &mut GameBoyAdvance) -> usize {
+            let start = gba.scheduler.timestamp();
+            for _ in 0..100 {
+                gba.single_step();
+            }
+            gba.scheduler.timestamp() - start
+        }
+
+        let mut gba = make_mock_gba(&rom);
+        let accurate_cycles = run_nops(&mut gba);
+
+        gba.set_speed_profile(Some(WaitstateProfile::NO_WAIT));
+        let fast_cycles = run_nops(&mut gba);
+        assert!(
+            fast_cycles < accurate_cycles,
+            "{} >= {}",
+            fast_cycles,
+            accurate_cycles
+        );
+
+        gba.set_speed_profile(None);
+        assert_eq!(run_nops(&mut gba), accurate_cycles);
+    }
+
+    #[test]
+    fn test_run_until_cycle() {
+        let mut gba = make_mock_gba_with_code(&[0xeaff_fffe]); // b .
+
+        // fill the pipeline before measuring how long a single iteration of the loop takes
+        for _ in 0..4 {
+            gba.cpu.step();
+        }
+        let mut sched = gba.scheduler.clone();
+        let insn_cycles = sched.measure_cycles(|| gba.cpu.step()) as u64;
+
+        for target in [1000, 1001, 54321, CYCLES_FULL_REFRESH as u64 + 7] {
+            let stop = gba.run_until_cycle(target);
+            assert_eq!(stop, gba.scheduler.timestamp() as u64);
+            assert!(stop >= target);
+            assert!(
+                stop - target <= insn_cycles,
+                "target {} stop {}",
+                target,
+                stop
+            );
+        }
+
+        // targets in the past are a no-op
+        let now = gba.scheduler.timestamp() as u64;
+        assert_eq!(gba.run_until_cycle(100), now);
+    }
+
+    #[test]
+    fn test_frame_detailed() {
+        let mut gba = make_mock_gba_with_code(&[0, 0, 0, 0, 0xeaff_fffe]); // b . after 4 nops
+        let (cheats, _) = cheats::parse_cheat_list("02000000:12");
+        gba.cheats_mut().add(cheats[0].clone());
+
+        gba.input_script_mut().push(0x3fe, 1);
+        gba.start_recording().unwrap();
+
+        gba.add_breakpoint(0x0800_0010);
+        let stopped = gba.frame_detailed();
+        assert!(stopped.stopped_early());
+        assert_eq!(stopped.stop_reason, StopReason::Breakpoint(0x0800_0010));
+        assert!(stopped.cycles < CYCLES_FULL_REFRESH);
+        assert_eq!(stopped.cheats_applied, 0);
+        assert_eq!(gba.stats().frame_count, 0);
+        assert_eq!(gba.sysbus.debug_read_8(0x0200_0000), 0);
+        gba.remove_breakpoint(0x0800_0010);
+
+        // the next call resumes the same frame, its input isn't consumed again
+        let outcome = gba.frame_detailed();
+        assert!(!outcome.stopped_early());
+        assert_eq!(outcome.cheats_applied, 1);
+        assert_eq!(
+            stopped.cycles + outcome.cycles,
+            CYCLES_FULL_REFRESH + gba.frame_overshoot
+        );
+        assert_eq!(gba.stats().frame_count, 1);
+        assert_eq!(gba.sysbus.debug_read_8(0x0200_0000), 0x12);
+        assert_eq!(gba.stop_recording().unwrap().frames(), 1);
+    }
+
+    #[test]
+    fn test_frame_overshoot() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        let start = gba.scheduler.timestamp();
+        for _ in 0..10 {
+            gba.frame();
+        }
+        // the cycles a frame runs past its end are taken off the next one
+        let elapsed = gba.scheduler.timestamp() - start;
+        assert_eq!(elapsed, 10 * CYCLES_FULL_REFRESH + gba.frame_overshoot);
+        assert!(gba.frame_overshoot < 16);
+    }
+
+    #[test]
+    fn test_target_refresh() {
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        assert!((REFRESH_RATE - 59.7275).abs() < 0.0001);
+        assert_eq!(gba.cycles_per_frame(), CYCLES_FULL_REFRESH);
+
+        let start = gba.scheduler.timestamp();
+        let cycles = gba.frame();
+        assert_eq!(gba.scheduler.timestamp() - start, cycles);
+
+        gba.set_target_refresh(2.0 * REFRESH_RATE);
+        assert_eq!(gba.cycles_per_frame(), CYCLES_FULL_REFRESH / 2);
+        let start = gba.scheduler.timestamp();
+        let cycles = gba.frame();
+        assert_eq!(gba.scheduler.timestamp() - start, cycles);
+        assert!(cycles < CYCLES_FULL_REFRESH);
+
+        gba.set_target_refresh(REFRESH_RATE);
+        assert_eq!(gba.cycles_per_frame(), CYCLES_FULL_REFRESH);
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let code: [u32; 4] = [
+            0xe3a0_0402, // mov r0, #0x02000000
+            0xe3a0_102a, // mov r1, #0x2a
+            0xe580_1000, // str r1, [r0]
+            0xeaff_fffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        gba.add_watchpoint(0x0200_0000, WatchKind::Write);
+        assert_eq!(
+            gba.run_for(CYCLES_FULL_REFRESH),
+            StopReason::Watchpoint {
+                addr: 0x0200_0000,
+                old: 0,
+                new: 0x2a
+            }
+        );
+        gba.remove_watchpoint(0x0200_0000);
+
+        gba.add_breakpoint(0x0800_000c);
+        assert_eq!(
+            gba.run_for(CYCLES_FULL_REFRESH),
+            StopReason::Breakpoint(0x0800_000c)
+        );
+        gba.remove_breakpoint(0x0800_000c);
+
+        assert_eq!(gba.run_for(1000), StopReason::CyclesExpired);
+    }
+
+    #[test]
+    fn test_run_to_pc() {
+        let code: [u32; 6] = [
+            0xe3a0_0000, // mov r0, #0
+            0xea00_0001, // b 0x10
+            0xeaff_fffe, // b .
+            0xeaff_fffe, // b .
+            0xe280_0001, // add r0, r0, #1
+            0xeaff_fffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+        // other breakpoints don't stop the run
+        gba.add_breakpoint(0x0800_0010);
+
+        let start = gba.scheduler.timestamp();
+        match gba.run_to_pc(0x0800_0014, 1000) {
+            RunResult::ReachedPc { cycles } => {
+                assert_eq!(gba.scheduler.timestamp() - start, cycles);
+                assert!(cycles > 0 && cycles < 1000);
+            }
+            result => panic!("didn't reach the target, {:?}", result),
+        }
+        assert_eq!(gba.cpu.get_next_pc(), 0x0800_0014);
+        assert_eq!(gba.cpu.gpr[0], 1);
+        assert_eq!(gba.cpu.breakpoints(), &[0x0800_0010]);
+
+        assert_eq!(
+            gba.run_to_pc(0x0800_0014, 1000),
+            RunResult::ReachedPc { cycles: 0 }
+        );
+
+        let start = gba.scheduler.timestamp();
+        assert_eq!(gba.run_to_pc(0x0800_0008, 1000), RunResult::CyclesExpired);
+        assert!(gba.scheduler.timestamp() - start >= 1000);
+        assert_eq!(gba.cpu.breakpoints(), &[0x0800_0010]);
+    }
+
+    #[test]
+    #[cfg(feature = "profiler")]
+    fn test_memory_profile() {
+        let code: [u32; 9] = [
+            0xe3a00402, // mov r0, #0x02000000
+            0xe3a01406, // mov r1, #0x06000000
+            0xe3a02010, // mov r2, #16
+            0xe4802004, // loop: str r2, [r0], #4
+            0xe5103004, // ldr r3, [r0, #-4]
+            0xe0c120b2, // strh r2, [r1], #2
+            0xe2522001, // subs r2, r2, #1
+            0x1afffffa, // bne loop
+            0xeafffffe, // b .
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        let before = gba.memory_profile();
+        for _ in 0..3 + 16 * 5 {
+            gba.cpu.step();
+        }
+        assert_eq!(gba.cpu.get_reg(2), 0);
+        let profile = gba.memory_profile();
+
+        let reads = |addr| profile.reads_at(addr) - before.reads_at(addr);
+        let writes = |addr| profile.writes_at(addr) - before.writes_at(addr);
+        assert_eq!((reads(EWRAM_ADDR), writes(EWRAM_ADDR)), (16, 16));
+        assert_eq!((reads(VRAM_ADDR), writes(VRAM_ADDR)), (0, 16));
+        assert_eq!((reads(IWRAM_ADDR), writes(IWRAM_ADDR)), (0, 0));
+        // the instruction fetches are attributed to the cartridge
+        assert!(reads(CART_BASE) > 0);
+    }
+
+    #[test]
+    fn test_skip_bios_state() {
+        use crate::iodev::consts::*;
+        use arm7tdmi::registers_consts::{REG_LR, REG_SP};
+
+        let mut gba = make_mock_gba(&[0; 0x200]);
+        gba.cpu.set_reg(0, 0xdead_beef);
+        gba.cpu.set_reg(REG_LR, 0xdead_beef);
+        gba.cpu.banks.gpr_banked_r14[3] = 0xdead_beef;
+        gba.sysbus.io.post_boot_flag = false;
+        gba.skip_bios();
+
+        assert_eq!(gba.cpu.cpsr.get(), 0x1f);
+        assert_eq!(gba.cpu.pc, 0x0800_0000);
+        assert_eq!(gba.cpu.get_reg(REG_SP), 0x0300_7f00);
+        assert_eq!(gba.cpu.banks.gpr_banked_r13[2], 0x0300_7fa0); // IRQ
+        assert_eq!(gba.cpu.banks.gpr_banked_r13[3], 0x0300_7fe0); // SVC
+        assert_eq!(gba.cpu.get_reg(0), 0);
+        assert_eq!(gba.cpu.get_reg(REG_LR), 0);
+        assert_eq!(gba.cpu.banks.gpr_banked_r14[3], 0);
+
+        assert_eq!(gba.sysbus.debug_read_8(REG_POSTFLG), 1);
+        assert_eq!(gba.sysbus.debug_read_16(REG_SOUNDBIAS), 0x200);
+        assert_eq!(gba.sysbus.debug_read_16(REG_BG2PA), 0x100);
+        assert_eq!(gba.sysbus.debug_read_16(REG_BG2PD), 0x100);
+        assert_eq!(gba.sysbus.debug_read_16(REG_KEYINPUT), 0x3ff);
+    }
+
+    #[test]
+    fn test_cartridge_info() {
+        use crate::cartridge::header::Region;
+        use crate::cartridge::BackupType;
+
+        let mut rom = vec![0; 0x200];
+        rom[0xa0..0xac].copy_from_slice(b"RUSTBOYADVNC");
+        rom[0xac..0xb0].copy_from_slice(b"ARBP");
+        rom[0xb0..0xb2].copy_from_slice(b"01");
+        rom[0xb3] = 1;
+        rom[0xbc] = 2;
+        let mut gba = make_mock_gba(&rom);
+
+        let info = gba.get_cartridge_info();
+        assert_eq!(info.game_title, "RUSTBOYADVNC");
+        assert_eq!(info.game_code, "ARBP");
+        assert_eq!(info.maker_code, "01");
+        assert_eq!(info.main_unit_code, 1);
+        assert_eq!(info.software_version, 2);
+        assert_eq!(info.rom_size, 0x200);
+        assert_eq!(info.backup_type, BackupType::Sram);
+        assert_eq!(info.region, Region::Europe);
+
+        // the main unit code isn't saved, it is taken from the ROM again
+        let state = gba.save_state().unwrap();
+        gba.restore_state(&state).unwrap();
+        assert_eq!(gba.get_cartridge_info().main_unit_code, 1);
+        let restored = GameBoyAdvance::from_saved_state(
+            &state,
+            vec![0; 0x4000].into_boxed_slice(),
+            rom.into_boxed_slice(),
+            NullAudio::new(),
+        )
+        .unwrap();
+        assert_eq!(restored.get_cartridge_info().main_unit_code, 1);
+    }
+
+    #[test]
+    fn test_step_instruction() {
+        let code: [u32; 3] = [
+            0xe28f0001, // add r0, pc, #1
+            0xe12fff10, // bx r0
+            0x00002105, // movs r1, #5
+        ];
+        let mut gba = make_mock_gba_with_code(&code);
+
+        let step = gba.step_instruction().unwrap();
+        assert_eq!(step.addr, 0x0800_0000);
+        assert_eq!(step.opcode, 0xe28f0001);
+        assert_eq!(step.state, CpuState::ARM);
+        assert!(!step.pipeline_flushed);
+        assert!(step.cycles > 0);
+
+        let step = gba.step_instruction().unwrap();
+        assert_eq!(step.addr, 0x0800_0004);
+        assert!(step.mnemonic.starts_with("bx"), "{}", step.mnemonic);
+        assert_eq!(step.state, CpuState::ARM);
+        assert!(step.pipeline_flushed);
+        assert_eq!(gba.cpu.get_cpu_state(), CpuState::THUMB);
+
+        let step = gba.step_instruction().unwrap();
+        assert_eq!(step.addr, 0x0800_0008);
+        assert_eq!(step.opcode, 0x2105);
+        assert_eq!(step.state, CpuState::THUMB);
+        assert!(!step.pipeline_flushed);
+        assert_eq!(gba.cpu.get_reg(1), 5);
+        assert_eq!(gba.cpu.get_next_pc(), 0x0800_000a);
+    }
 }
@@ -25,7 +25,6 @@ pub(crate) enum DebuggerRequest {
     ReadRegs(SendSync<ArmCoreRegs>),
     WriteRegs(ArmCoreRegs),
     ReadAddrs(Addr, SendSync<Box<[u8]>>),
-    #[allow(unused)]
     WriteAddrs(Addr, Box<[u8]>),
     AddSwBreakpoint(Addr),
     DelSwBreakpoint(Addr),
@@ -24,14 +24,25 @@ pub(crate) fn start_gdb_server_thread(
     let stop_signal_2 = stop_signal.clone();
     let request_complete_signal_2 = request_complete_signal.clone();
     let memory_map = gba.sysbus.generate_memory_map_xml().unwrap();
+    let symbols = gba
+        .sysbus
+        .cartridge
+        .get_symbols()
+        .clone()
+        .unwrap_or_default();
 
     let conn = wait_for_connection(port)?;
     let thread = std::thread::spawn(move || {
         debug!("starting GDB Server thread");
         let conn: Box<dyn ConnectionExt<Error = std::io::Error>> = Box::new(conn);
 
-        let mut target =
-            DebuggerTarget::new(tx, request_complete_signal_2, stop_signal_2, memory_map);
+        let mut target = DebuggerTarget::new(
+            tx,
+            request_complete_signal_2,
+            stop_signal_2,
+            memory_map,
+            symbols,
+        );
         let gdbserver = GdbStub::new(conn);
         let disconnect_reason = gdbserver
             .run_blocking::<DebuggerEventLoop>(&mut target)
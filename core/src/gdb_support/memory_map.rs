@@ -33,7 +33,7 @@ impl SysBus {
         add_memory(consts::PALRAM_ADDR, self.io.gpu.palette_ram.len())?;
         add_memory(consts::VRAM_ADDR, self.io.gpu.vram.len())?;
         add_memory(consts::OAM_ADDR, self.io.gpu.oam.len())?;
-        add_memory(consts::CART_BASE, self.cartridge.get_rom_bytes().len())?;
+        add_memory(consts::CART_BASE, self.cartridge.rom_size())?;
 
         xml.set_root_element(memory_map);
         let mut writer = Vec::new();
@@ -2,7 +2,7 @@ use std::sync::{Arc, Condvar, Mutex, WaitTimeoutResult};
 use std::time::Duration;
 
 /// Implementing the Target trait for gdbstub
-use arm7tdmi::gdb::{copy_range_to_buf, gdbstub, gdbstub_arch};
+use arm7tdmi::gdb::{copy_range_to_buf, gdbstub, gdbstub_arch, TARGET_DESCRIPTION_XML};
 use crossbeam::channel::Sender;
 use gdbstub::common::Signal;
 use gdbstub::stub::{DisconnectReason, SingleThreadStopReason};
@@ -13,16 +13,18 @@ use gdbstub::target::ext::base::singlethread::{SingleThreadResumeOps, SingleThre
 use gdbstub::target::ext::base::BaseOps;
 use gdbstub::target::ext::breakpoints::BreakpointsOps;
 use gdbstub::target::ext::monitor_cmd::{outputln, ConsoleOutput};
-use gdbstub::target::{self, Target, TargetError, TargetResult};
+use gdbstub::target::{self, Target, TargetResult};
 use gdbstub_arch::arm::reg::ArmCoreRegs;
 
 use super::DebuggerRequest;
+use crate::cartridge::SymbolTable;
 
 pub(crate) struct DebuggerTarget {
     tx: Sender<DebuggerRequest>,
     request_complete_signal: Arc<(Mutex<bool>, Condvar)>,
     pub(crate) stop_signal: Arc<(Mutex<Option<SingleThreadStopReason<u32>>>, Condvar)>,
     pub(crate) memory_map: String,
+    symbols: SymbolTable,
 }
 
 impl DebuggerTarget {
@@ -31,12 +33,14 @@ impl DebuggerTarget {
         request_complete_signal: Arc<(Mutex<bool>, Condvar)>,
         stop_signal: Arc<(Mutex<Option<SingleThreadStopReason<u32>>>, Condvar)>,
         memory_map: String,
+        symbols: SymbolTable,
     ) -> DebuggerTarget {
         DebuggerTarget {
             tx,
             request_complete_signal,
             stop_signal,
             memory_map,
+            symbols,
         }
     }
 
@@ -108,6 +112,13 @@ impl Target for DebuggerTarget {
     fn support_monitor_cmd(&mut self) -> Option<target::ext::monitor_cmd::MonitorCmdOps<'_, Self>> {
         Some(self)
     }
+
+    fn support_target_description_xml_override(
+        &mut self,
+    ) -> Option<target::ext::target_description_xml_override::TargetDescriptionXmlOverrideOps<Self>>
+    {
+        Some(self)
+    }
 }
 
 impl SingleThreadBase for DebuggerTarget {
@@ -130,9 +141,9 @@ impl SingleThreadBase for DebuggerTarget {
         Ok(())
     }
 
-    fn write_addrs(&mut self, _start_addr: u32, _data: &[u8]) -> TargetResult<(), Self> {
-        // todo!("implement DebugWrite bus extention")
-        Err(TargetError::NonFatal)
+    fn write_addrs(&mut self, start_addr: u32, data: &[u8]) -> TargetResult<(), Self> {
+        self.debugger_request(DebuggerRequest::WriteAddrs(start_addr, data.into()));
+        Ok(())
     }
 
     // most targets will want to support at resumption as well...
@@ -181,6 +192,22 @@ impl target::ext::memory_map::MemoryMap for DebuggerTarget {
     }
 }
 
+impl target::ext::target_description_xml_override::TargetDescriptionXmlOverride for DebuggerTarget {
+    fn target_description_xml(
+        &self,
+        offset: u64,
+        length: usize,
+        buf: &mut [u8],
+    ) -> TargetResult<usize, Self> {
+        Ok(copy_range_to_buf(
+            TARGET_DESCRIPTION_XML.as_bytes(),
+            offset,
+            length,
+            buf,
+        ))
+    }
+}
+
 impl target::ext::breakpoints::Breakpoints for DebuggerTarget {
     // there are several kinds of breakpoints - this target uses software breakpoints
     #[inline(always)]
@@ -230,6 +257,13 @@ impl target::ext::monitor_cmd::MonitorCmd for DebuggerTarget {
                 self.debugger_request(DebuggerRequest::Reset);
                 outputln!(out, "sent reset signal");
             }
+            cmd if cmd.starts_with("sym ") => {
+                let name = cmd["sym ".len()..].trim();
+                match self.symbols.get(name) {
+                    Some(addr) => outputln!(out, "{} = {:#010x}", name, addr),
+                    None => outputln!(out, "symbol not found: {}", name),
+                }
+            }
             unk => {
                 outputln!(out, "unknown command: {}", unk);
             }
@@ -0,0 +1,13 @@
+use super::Rgb15;
+
+pub trait VideoInterface {
+    /// Called at the end of every visible scanline, `line` holds the `DISPLAY_WIDTH` pixels rendered for `vcount`
+    #[allow(unused_variables)]
+    fn on_scanline(&mut self, vcount: usize, line: &[u32]) {}
+
+    /// Called on VBlank with the whole frame in the native 15bit colors, for frontends that do their own color processing
+    #[allow(unused_variables)]
+    fn render_rgb15(&mut self, buffer: &[Rgb15]) {}
+}
+
+pub type DynVideoInterface = Box<dyn VideoInterface>;
@@ -1,16 +1,21 @@
+use std::cmp;
+
 use num::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-use arm7tdmi::memory::{Addr, BusIO, DebugRead};
+use arm7tdmi::memory::{Addr, BusIO, DebugRead, DebugWrite};
 use rustboyadvance_utils::index2d;
 
-use super::dma::{DmaNotifer, TIMING_HBLANK, TIMING_VBLANK};
+use super::dma::{DmaNotifer, TIMING_HBLANK, TIMING_VBLANK, TIMING_VIDEO_CAPTURE};
 use super::interrupt::{self, Interrupt, InterruptConnect, SharedInterruptFlags};
 use super::sched::{EventType, FutureEvent, GpuEvent, Scheduler};
 pub use super::sysbus::consts::*;
 
+pub mod interface;
 mod render;
 
+use interface::DynVideoInterface;
+pub use render::obj::ObjStats;
 use render::Point;
 
 mod layer;
@@ -19,7 +24,7 @@ mod rgb15;
 mod sfx;
 mod window;
 
-pub use rgb15::Rgb15;
+pub use rgb15::{ColorCorrection, Rgb15};
 pub use window::*;
 
 pub mod regs;
@@ -47,6 +52,10 @@ pub mod consts {
     pub(super) const CYCLES_VBLANK: usize = 83776;
 
     pub const CYCLES_FULL_REFRESH: usize = 280896;
+    /// The cpu runs at 16.78MHz
+    pub const CPU_CLOCK_HZ: usize = 1 << 24;
+    /// The native refresh rate, about 59.73Hz
+    pub const REFRESH_RATE: f64 = CPU_CLOCK_HZ as f64 / CYCLES_FULL_REFRESH as f64;
 
     pub const TILE_SIZE: u32 = 0x20;
 
@@ -100,6 +109,33 @@ impl Default for ObjBufferEntry {
     }
 }
 
+/// Writes a nearest-neighbor upscaled copy of a native resolution frame into `dst`,
+/// which must hold `DISPLAY_WIDTH * DISPLAY_HEIGHT * scale * scale` pixels.
+pub fn upscale_frame(src: &[u32], scale: usize, dst: &mut [u32]) {
+    let dst_width = DISPLAY_WIDTH * scale;
+    assert_eq!(dst.len(), dst_width * DISPLAY_HEIGHT * scale);
+    for (y, src_line) in src.chunks_exact(DISPLAY_WIDTH).enumerate() {
+        let line_start = y * scale * dst_width;
+        let dst_line = &mut dst[line_start..line_start + dst_width];
+        for (block, pixel) in dst_line.chunks_exact_mut(scale).zip(src_line) {
+            block.fill(*pixel);
+        }
+        // the rest of the lines in this row of blocks are copies of the first one
+        for i in 1..scale {
+            dst.copy_within(
+                line_start..line_start + dst_width,
+                line_start + i * dst_width,
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ScaledOutput {
+    scale: usize,
+    buffer: Box<[u32]>,
+}
+
 #[derive(Serialize, Deserialize, Clone, DebugStub)]
 pub struct Gpu {
     interrupt_flags: SharedInterruptFlags,
@@ -124,13 +160,38 @@ pub struct Gpu {
     pub bldcnt: BlendControl,
     pub bldalpha: BlendAlpha,
     pub bldy: u16,
+    /// Undocumented GREENSWAP register, swaps the green component of every pair of adjacent pixels
+    pub green_swap: bool,
     pub palette_ram: Box<[u8]>,
     pub vram: Box<[u8]>,
     pub oam: Box<[u8]>,
     pub(super) vram_obj_tiles_start: u32,
+    /// timestamp of when the current HDraw period started
+    hdraw_start: usize,
+    /// number of pixels of the current scanline that were already rendered
+    scanline_x: usize,
     pub(super) obj_buffer: Box<[ObjBufferEntry]>,
     pub(super) frame_buffer: Box<[u32]>,
+    /// the frame buffer in the native 15bit colors, see `VideoInterface::render_rgb15`
+    #[serde(skip, default = "alloc_frame_buffer_rgb15")]
+    pub(super) frame_buffer_rgb15: Box<[Rgb15]>,
     pub(super) bg_line: [Box<[Rgb15]>; 4],
+    /// optional upscaled copy of the frame buffer, updated on VBlank
+    #[serde(skip)]
+    scaled_output: Option<ScaledOutput>,
+    #[serde(skip)]
+    obj_stats: ObjStats,
+    /// skip drawing scanlines, for fast-forwarding frames no one will see
+    #[serde(skip)]
+    render_disabled: bool,
+    #[serde(skip)]
+    color_correction: ColorCorrection,
+    /// output colors of all the 15bit colors, when color correction is enabled
+    #[serde(skip)]
+    color_lut: Option<Box<[u32]>>,
+    /// replaces the backdrop color, to see which pixels the layers cover
+    #[serde(skip)]
+    debug_backdrop: Option<Rgb15>,
 }
 
 impl InterruptConnect for Gpu {
@@ -141,6 +202,10 @@ impl InterruptConnect for Gpu {
 
 type FutureGpuEvent = (GpuEvent, usize);
 
+fn alloc_frame_buffer_rgb15() -> Box<[Rgb15]> {
+    vec![Rgb15::BLACK; DISPLAY_WIDTH * DISPLAY_HEIGHT].into_boxed_slice()
+}
+
 impl Gpu {
     pub fn new(sched: &mut Scheduler, interrupt_flags: SharedInterruptFlags) -> Gpu {
         sched.schedule((EventType::Gpu(GpuEvent::HDraw), CYCLES_HDRAW));
@@ -165,6 +230,7 @@ impl Gpu {
             bldcnt: BlendControl::default(),
             bldalpha: BlendAlpha::default(),
             bldy: 0,
+            green_swap: false,
 
             vcount: 0,
             cycles_left_for_current_state: CYCLES_HDRAW,
@@ -173,6 +239,7 @@ impl Gpu {
             oam: vec![0; OAM_SIZE].into_boxed_slice(),
             obj_buffer: vec![Default::default(); DISPLAY_WIDTH * DISPLAY_HEIGHT].into_boxed_slice(),
             frame_buffer: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT].into_boxed_slice(),
+            frame_buffer_rgb15: alloc_frame_buffer_rgb15(),
             bg_line: [
                 alloc_scanline_buffer(),
                 alloc_scanline_buffer(),
@@ -180,7 +247,37 @@ impl Gpu {
                 alloc_scanline_buffer(),
             ],
             vram_obj_tiles_start: VRAM_OBJ_TILES_START_TEXT,
+            hdraw_start: sched.timestamp(),
+            scanline_x: 0,
+            scaled_output: None,
+            obj_stats: ObjStats::default(),
+            render_disabled: false,
+            color_correction: ColorCorrection::None,
+            color_lut: None,
+            debug_backdrop: None,
+        }
+    }
+
+    /// Resets the gpu to its power-on state, the render settings and the upscaled output are kept
+    pub fn reset(&mut self, sched: &mut Scheduler) {
+        let mut gpu = Gpu::new(sched, self.interrupt_flags.clone());
+        gpu.render_disabled = self.render_disabled;
+        gpu.color_correction = self.color_correction;
+        gpu.color_lut = self.color_lut.take();
+        gpu.debug_backdrop = self.debug_backdrop;
+        gpu.scaled_output = self.scaled_output.take();
+        if let Some(output) = &mut gpu.scaled_output {
+            output.buffer.fill(0);
         }
+        *self = gpu;
+    }
+
+    /// The VCount flag follows a change of the VCount setting right away,
+    /// the VCount IRQ is only requested when VCOUNT reaches the setting
+    #[inline]
+    pub fn write_dispstat(&mut self, value: u16) {
+        self.dispstat.write(value);
+        self.dispstat.vcount_flag = self.dispstat.vcount_setting == self.vcount;
     }
 
     #[inline]
@@ -264,65 +361,104 @@ impl Gpu {
         )
     }
 
+    /// Renders the entire current scanline
     pub fn render_scanline(&mut self) {
+        self.scanline_x = 0;
+        self.render_scanline_until(DISPLAY_WIDTH);
+    }
+
+    /// Shift the start of the current scanline, after the scheduler skipped `cycles` during which the gpu was paused
+    pub fn postpone(&mut self, cycles: usize) {
+        self.hdraw_start += cycles;
+    }
+
+    /// Renders the pixels of the current scanline that are produced up until `timestamp`.
+    /// Must be called before writing to registers that may change in the middle of a scanline,
+    /// so the pixels that were already drawn use the old values.
+    pub fn sync_scanline(&mut self, timestamp: usize) {
+        if self.vcount >= DISPLAY_HEIGHT || self.dispstat.hblank_flag {
+            return;
+        }
+        let x = timestamp.saturating_sub(self.hdraw_start) / CYCLES_PIXEL;
+        self.render_scanline_until(x);
+    }
+
+    /// Renders the current scanline from where we last stopped up to (not including) `x`
+    fn render_scanline_until(&mut self, x: usize) {
+        let x_end = cmp::min(x, DISPLAY_WIDTH);
+        let x_start = self.scanline_x;
+        if x_start >= x_end {
+            return;
+        }
+        self.scanline_x = x_end;
+
+        if self.render_disabled {
+            return;
+        }
+
         if self.dispcnt.force_blank {
+            let white = self.output_color(Rgb15::WHITE);
             for x in self.frame_buffer[self.vcount * DISPLAY_WIDTH..]
                 .iter_mut()
-                .take(DISPLAY_WIDTH)
+                .take(x_end)
+                .skip(x_start)
             {
-                *x = 0xf8f8f8;
+                // the screen is white while blanked
+                *x = white;
             }
+            self.frame_buffer_rgb15[self.vcount * DISPLAY_WIDTH..][x_start..x_end]
+                .fill(Rgb15::WHITE);
             return;
         }
 
-        if self.dispcnt.enable_obj {
+        // objects are rendered only once per scanline
+        if x_start == 0 && self.dispcnt.enable_obj {
             self.render_objs();
         }
         match self.dispcnt.mode {
             0 => {
                 for bg in 0..=3 {
                     if self.dispcnt.enable_bg[bg] {
-                        self.render_reg_bg(bg);
+                        self.render_reg_bg(bg, x_start, x_end);
                     }
                 }
-                self.finalize_scanline(0, 3);
+                self.finalize_scanline(0, 3, x_start, x_end);
             }
             1 => {
                 if self.dispcnt.enable_bg[2] {
-                    self.render_aff_bg(2);
+                    self.render_aff_bg(2, x_start, x_end);
                 }
                 if self.dispcnt.enable_bg[1] {
-                    self.render_reg_bg(1);
+                    self.render_reg_bg(1, x_start, x_end);
                 }
                 if self.dispcnt.enable_bg[0] {
-                    self.render_reg_bg(0);
+                    self.render_reg_bg(0, x_start, x_end);
                 }
-                self.finalize_scanline(0, 2);
+                self.finalize_scanline(0, 2, x_start, x_end);
             }
             2 => {
                 if self.dispcnt.enable_bg[3] {
-                    self.render_aff_bg(3);
+                    self.render_aff_bg(3, x_start, x_end);
                 }
                 if self.dispcnt.enable_bg[2] {
-                    self.render_aff_bg(2);
+                    self.render_aff_bg(2, x_start, x_end);
                 }
-                self.finalize_scanline(2, 3);
+                self.finalize_scanline(2, 3, x_start, x_end);
             }
             3 => {
-                self.render_mode3(2);
-                self.finalize_scanline(2, 2);
+                self.render_mode3(2, x_start, x_end);
+                self.finalize_scanline(2, 2, x_start, x_end);
             }
             4 => {
-                self.render_mode4(2);
-                self.finalize_scanline(2, 2);
+                self.render_mode4(2, x_start, x_end);
+                self.finalize_scanline(2, 2, x_start, x_end);
             }
             5 => {
-                self.render_mode5(2);
-                self.finalize_scanline(2, 2);
+                self.render_mode5(2, x_start, x_end);
+                self.finalize_scanline(2, 2, x_start, x_end);
             }
             _ => panic!("{:?} not supported", self.dispcnt.mode),
         }
-        // self.mosaic_sfx();
     }
 
     /// Clears the gpu obj buffer
@@ -336,6 +472,90 @@ impl Gpu {
         &self.frame_buffer
     }
 
+    /// The frame buffer in the native 15bit colors, before color correction
+    pub fn get_frame_buffer_rgb15(&self) -> &[Rgb15] {
+        &self.frame_buffer_rgb15
+    }
+
+    /// When disabled the gpu keeps its timing, but the frame buffer is not updated
+    pub fn set_render_enabled(&mut self, enabled: bool) {
+        self.render_disabled = !enabled;
+    }
+
+    /// Applies `correction` to the frame buffer colors, starting from the next rendered pixel
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.color_correction = correction;
+        self.color_lut = match correction {
+            ColorCorrection::None => None,
+            _ => Some(
+                (0..0x8000)
+                    .map(|c| Rgb15(c).to_rgb24_corrected(correction))
+                    .collect(),
+            ),
+        };
+    }
+
+    pub fn color_correction(&self) -> ColorCorrection {
+        self.color_correction
+    }
+
+    /// Draw the pixels no layer covers in `color` instead of palette entry 0, or None to draw them normally.
+    /// The palette RAM itself isn't changed.
+    pub fn set_debug_backdrop(&mut self, color: Option<Rgb15>) {
+        self.debug_backdrop = color;
+    }
+
+    #[inline]
+    pub(super) fn backdrop_color(&mut self) -> Rgb15 {
+        match self.debug_backdrop {
+            Some(color) => color,
+            None => Rgb15(self.palette_ram.read_16(0)),
+        }
+    }
+
+    /// Converts a final pixel to the frame buffer format
+    #[inline]
+    pub(super) fn output_color(&self, color: Rgb15) -> u32 {
+        match &self.color_lut {
+            Some(lut) => lut[(color.0 & 0x7fff) as usize],
+            None => color.to_rgb24(),
+        }
+    }
+
+    /// Returns OBJ rendering statistics of the most recently rendered scanline
+    pub fn last_scanline_obj_stats(&self) -> ObjStats {
+        self.obj_stats
+    }
+
+    /// Request an additional frame buffer upscaled by an integer `scale`, rendered on every VBlank.
+    /// A scale of 1 keeps only the native resolution frame buffer.
+    pub fn set_output_scale(&mut self, scale: usize) {
+        assert!(scale > 0);
+        if scale == self.output_scale() {
+            return;
+        }
+        self.scaled_output = if scale == 1 {
+            None
+        } else {
+            let mut buffer =
+                vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * scale * scale].into_boxed_slice();
+            upscale_frame(&self.frame_buffer, scale, &mut buffer);
+            Some(ScaledOutput { scale, buffer })
+        };
+    }
+
+    pub fn output_scale(&self) -> usize {
+        self.scaled_output.as_ref().map_or(1, |output| output.scale)
+    }
+
+    /// Returns the frame buffer at the requested output scale
+    pub fn get_scaled_frame_buffer(&self) -> &[u32] {
+        match &self.scaled_output {
+            Some(output) => &output.buffer,
+            None => &self.frame_buffer,
+        }
+    }
+
     #[inline]
     fn update_vcount(&mut self, value: usize) {
         self.vcount = value;
@@ -347,29 +567,56 @@ impl Gpu {
         }
     }
 
+    /// Starts a new visible scanline, the pixels are rendered during HDraw as registers are written to
+    #[inline]
+    fn begin_scanline(&mut self, hdraw_start: usize) {
+        self.hdraw_start = hdraw_start;
+        self.scanline_x = 0;
+    }
+
     #[inline]
     fn handle_hdraw_end<D: DmaNotifer>(&mut self, dma_notifier: &mut D) -> FutureGpuEvent {
+        // render what's left of the scanline
+        self.render_scanline_until(DISPLAY_WIDTH);
+        // advance the BG2/3 reference points to the next scanline,
+        // a reference point written during hblank is the origin of the next scanline as is
+        for i in 0..2 {
+            self.bg_aff[i].internal_x += self.bg_aff[i].pb as i16 as i32;
+            self.bg_aff[i].internal_y += self.bg_aff[i].pd as i16 as i32;
+        }
         self.dispstat.hblank_flag = true;
         if self.dispstat.hblank_irq_enable {
             interrupt::signal_irq(&self.interrupt_flags, Interrupt::LCD_HBlank);
         };
         dma_notifier.notify(TIMING_HBLANK);
+        self.notify_video_capture(dma_notifier);
 
         // Next event
         (GpuEvent::HBlank, CYCLES_HBLANK)
     }
 
-    fn handle_hblank_end<D: DmaNotifer>(&mut self, dma_notifier: &mut D) -> FutureGpuEvent {
+    /// Video capture DMA transfers one line on each hblank of lines 2 to 161
+    fn notify_video_capture<D: DmaNotifer>(&self, dma_notifier: &mut D) {
+        if self.vcount >= 2 && self.vcount < DISPLAY_HEIGHT + 2 {
+            dma_notifier.notify(TIMING_VIDEO_CAPTURE);
+        }
+    }
+
+    fn handle_hblank_end<D: DmaNotifer>(
+        &mut self,
+        event_time: usize,
+        dma_notifier: &mut D,
+        mut video: Option<&mut DynVideoInterface>,
+    ) -> FutureGpuEvent {
+        if let Some(video) = video.as_deref_mut() {
+            let line = &self.frame_buffer[self.vcount * DISPLAY_WIDTH..][..DISPLAY_WIDTH];
+            video.on_scanline(self.vcount, line);
+        }
         self.update_vcount(self.vcount + 1);
 
         if self.vcount < DISPLAY_HEIGHT {
             self.dispstat.hblank_flag = false;
-            self.render_scanline();
-            // update BG2/3 reference points on the end of a scanline
-            for i in 0..2 {
-                self.bg_aff[i].internal_x += self.bg_aff[i].pb as i16 as i32;
-                self.bg_aff[i].internal_y += self.bg_aff[i].pd as i16 as i32;
-            }
+            self.begin_scanline(event_time);
 
             (GpuEvent::HDraw, CYCLES_HDRAW)
         } else {
@@ -389,59 +636,84 @@ impl Gpu {
 
             self.obj_buffer_reset();
 
+            if let (Some(output), false) = (&mut self.scaled_output, self.render_disabled) {
+                upscale_frame(&self.frame_buffer, output.scale, &mut output.buffer);
+            }
+            if let (Some(video), false) = (video, self.render_disabled) {
+                video.render_rgb15(&self.frame_buffer_rgb15);
+            }
+
             (GpuEvent::VBlankHDraw, CYCLES_HDRAW)
         }
     }
 
-    fn handle_vblank_hdraw_end(&mut self) -> FutureGpuEvent {
+    fn handle_vblank_hdraw_end<D: DmaNotifer>(&mut self, dma_notifier: &mut D) -> FutureGpuEvent {
         self.dispstat.hblank_flag = true;
         if self.dispstat.hblank_irq_enable {
             interrupt::signal_irq(&self.interrupt_flags, Interrupt::LCD_HBlank);
         };
+        self.notify_video_capture(dma_notifier);
         (GpuEvent::VBlankHBlank, CYCLES_HBLANK)
     }
 
-    fn handle_vblank_hblank_end(&mut self) -> FutureGpuEvent {
+    fn handle_vblank_hblank_end<D: DmaNotifer>(
+        &mut self,
+        event_time: usize,
+        dma_notifier: &mut D,
+    ) -> FutureGpuEvent {
         if self.vcount < DISPLAY_HEIGHT + VBLANK_LINES - 1 {
             self.update_vcount(self.vcount + 1);
+            if self.vcount == DISPLAY_HEIGHT + 2 {
+                dma_notifier.stop_video_capture();
+            }
             self.dispstat.hblank_flag = false;
             (GpuEvent::VBlankHDraw, CYCLES_HDRAW)
         } else {
             self.update_vcount(0);
             self.dispstat.vblank_flag = false;
             self.dispstat.hblank_flag = false;
-            self.render_scanline();
+            self.begin_scanline(event_time);
             (GpuEvent::HDraw, CYCLES_HDRAW)
         }
     }
 
-    pub fn on_event<D>(&mut self, event: GpuEvent, dma_notifier: &mut D) -> FutureEvent
+    pub fn on_event<D>(
+        &mut self,
+        event: GpuEvent,
+        event_time: usize,
+        dma_notifier: &mut D,
+        video: Option<&mut DynVideoInterface>,
+    ) -> FutureEvent
     where
         D: DmaNotifer,
     {
         let (event, when) = match event {
             GpuEvent::HDraw => self.handle_hdraw_end(dma_notifier),
-            GpuEvent::HBlank => self.handle_hblank_end(dma_notifier),
-            GpuEvent::VBlankHDraw => self.handle_vblank_hdraw_end(),
-            GpuEvent::VBlankHBlank => self.handle_vblank_hblank_end(),
+            GpuEvent::HBlank => self.handle_hblank_end(event_time, dma_notifier, video),
+            GpuEvent::VBlankHDraw => self.handle_vblank_hdraw_end(dma_notifier),
+            GpuEvent::VBlankHBlank => self.handle_vblank_hblank_end(event_time, dma_notifier),
         };
         (EventType::Gpu(event), when)
     }
 }
 
+/// VRAM is 96K mirrored in 128K steps, where the last 32K mirror the OBJ tiles at 0x10000..0x18000
+#[inline]
+fn vram_offset(addr: Addr) -> u32 {
+    let ofs = addr & ((VIDEO_RAM_SIZE as u32) - 1);
+    if ofs >= 0x18000 {
+        ofs - 0x8000
+    } else {
+        ofs
+    }
+}
+
 impl BusIO for Gpu {
     fn read_8(&mut self, addr: Addr) -> u8 {
         let page = (addr >> 24) as usize;
         match page {
             PAGE_PALRAM => self.palette_ram.read_8(addr & 0x3ff),
-            PAGE_VRAM => {
-                // complicated
-                let mut ofs = addr & ((VIDEO_RAM_SIZE as u32) - 1);
-                if ofs > 0x18000 {
-                    ofs -= 0x8000;
-                }
-                self.vram.read_8(ofs)
-            }
+            PAGE_VRAM => self.vram.read_8(vram_offset(addr)),
             PAGE_OAM => self.oam.read_8(addr & 0x3ff),
             _ => unreachable!(),
         }
@@ -451,13 +723,7 @@ impl BusIO for Gpu {
         let page = (addr >> 24) as usize;
         match page {
             PAGE_PALRAM => self.palette_ram.write_16(addr & 0x3fe, value),
-            PAGE_VRAM => {
-                let mut ofs = addr & ((VIDEO_RAM_SIZE as u32) - 1);
-                if ofs > 0x18000 {
-                    ofs -= 0x8000;
-                }
-                self.vram.write_16(ofs, value)
-            }
+            PAGE_VRAM => self.vram.write_16(vram_offset(addr), value),
             PAGE_OAM => self.oam.write_16(addr & 0x3fe, value),
             _ => unreachable!(),
         }
@@ -472,10 +738,9 @@ impl BusIO for Gpu {
         match page {
             PAGE_PALRAM => self.palette_ram.write_16(addr & 0x3fe, expand_value(value)),
             PAGE_VRAM => {
-                let mut ofs = addr & ((VIDEO_RAM_SIZE as u32) - 1);
-                if ofs > 0x18000 {
-                    ofs -= 0x8000;
-                }
+                // 8bit stores to BG memory (tiles, maps or bitmap frames) write the byte to both halves
+                // of the halfword, while 8bit stores to OBJ tiles are ignored.
+                let ofs = vram_offset(addr);
                 if ofs < self.vram_obj_tiles_start {
                     self.vram.write_16(ofs & !1, expand_value(value));
                 }
@@ -491,13 +756,26 @@ impl DebugRead for Gpu {
         let page = (addr >> 24) as usize;
         match page {
             PAGE_PALRAM => self.palette_ram.read_8(addr & 0x3ff),
-            PAGE_VRAM => self.vram.read_8(addr & ((VIDEO_RAM_SIZE as u32) - 1)),
+            PAGE_VRAM => self.vram.read_8(vram_offset(addr)),
             PAGE_OAM => self.oam.read_8(addr & 0x3ff),
             _ => unreachable!(),
         }
     }
 }
 
+impl DebugWrite for Gpu {
+    /// Unlike `write_8`, byte writes to VRAM and PALRAM are not widened to halfwords
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        let page = (addr >> 24) as usize;
+        match page {
+            PAGE_PALRAM => self.palette_ram.write_8(addr & 0x3ff, value),
+            PAGE_VRAM => self.vram.write_8(vram_offset(addr), value),
+            PAGE_OAM => self.oam.write_8(addr & 0x3ff, value),
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[cfg(feature = "debugger")]
 impl fmt::Display for Gpu {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -521,6 +799,7 @@ mod tests {
     struct NopDmaNotifer;
     impl DmaNotifer for NopDmaNotifer {
         fn notify(&mut self, _timing: u16) {}
+        fn stop_video_capture(&mut self) {}
     }
 
     #[test]
@@ -538,7 +817,9 @@ mod tests {
                 let (event, event_time) = sched.pop_pending_event().unwrap();
                 assert_eq!(event_time, sched.timestamp());
                 let next_event = match event {
-                    EventType::Gpu(event) => gpu.on_event(event, &mut dma_notifier),
+                    EventType::Gpu(event) => {
+                        gpu.on_event(event, event_time, &mut dma_notifier, None)
+                    }
                     _ => panic!("Found unexpected event in queue!"),
                 };
                 sched.schedule(next_event);
@@ -595,4 +876,803 @@ mod tests {
         assert_eq!(gpu.dispstat.vcount_flag, true);
         assert_eq!(gpu.dispstat.hblank_flag, false);
     }
+
+    #[test]
+    fn test_vcount_match_timing() {
+        let mut sched = Scheduler::new();
+        let interrupt_flags = Rc::new(Cell::new(Default::default()));
+        let mut gpu = Gpu::new(&mut sched, interrupt_flags.clone());
+        let mut dma_notifier = NopDmaNotifer;
+        // vcount irq enabled, vcount setting at line 5
+        gpu.write_dispstat(0x0520);
+        assert!(!gpu.dispstat.vcount_flag);
+
+        let mut check_match = |setting: usize| {
+            gpu.write_dispstat(0x0020 | (setting as u16) << 8);
+            while gpu.vcount != setting {
+                let cycles = sched.get_cycles_to_next_event();
+                sched.update(cycles);
+                let (event, event_time) = sched.pop_pending_event().unwrap();
+                let next_event = match event {
+                    EventType::Gpu(event) => {
+                        gpu.on_event(event, event_time, &mut dma_notifier, None)
+                    }
+                    _ => panic!("Found unexpected event in queue!"),
+                };
+                sched.schedule(next_event);
+                let matched = interrupt_flags.get().LCD_VCounterMatch();
+                // the irq is requested at the start of the matching scanline
+                assert_eq!(matched, gpu.vcount == setting);
+                assert_eq!(gpu.dispstat.vcount_flag, gpu.vcount == setting);
+            }
+            assert_eq!(sched.timestamp() % CYCLES_SCANLINE, 0);
+            assert_eq!(
+                sched.timestamp() % CYCLES_FULL_REFRESH,
+                setting * CYCLES_SCANLINE
+            );
+            interrupt_flags.set(Default::default());
+        };
+
+        check_match(5);
+        // during vblank
+        check_match(200);
+        check_match(227);
+        // across the wrap to line 0
+        check_match(0);
+
+        // the flag follows a write of the setting immediately
+        gpu.write_dispstat(0x0120);
+        assert!(!gpu.dispstat.vcount_flag);
+        gpu.write_dispstat(0x0020);
+        assert!(gpu.dispstat.vcount_flag);
+        assert!(!interrupt_flags.get().LCD_VCounterMatch());
+    }
+
+    #[test]
+    fn test_affine_obj_double_size() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        // 1D obj mapping, obj enabled
+        gpu.write_dispcnt(0x1040);
+
+        // hide all objs
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+
+        for index in 1..=5u32 {
+            gpu.palette_ram
+                .write_16(0x200 + 2 * index, (index as u16) * 0x0421);
+        }
+
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        let mut set_pixel = |x: usize, y: usize, index: u8| {
+            // 16x16 4bpp obj, 2 tiles per row
+            let tile = (y / 8) * 2 + (x / 8);
+            let ofs = obj_tiles + tile * 0x20 + (y % 8) * 4 + (x % 8) / 2;
+            let byte = &mut gpu.vram[ofs];
+            if x & 1 != 0 {
+                *byte = (*byte & 0x0f) | (index << 4);
+            } else {
+                *byte = (*byte & 0xf0) | index;
+            }
+        };
+        for y in 0..16 {
+            for x in 0..16 {
+                set_pixel(x, y, 1);
+            }
+        }
+        set_pixel(0, 0, 2);
+        set_pixel(15, 0, 3);
+        set_pixel(0, 15, 4);
+        set_pixel(15, 15, 5);
+
+        // obj 0: 16x16 double-size affine obj at (50, 20), using matrix 0
+        gpu.oam.write_16(0, 0x0300 | 20);
+        gpu.oam.write_16(2, 0x4000 | 50);
+        gpu.oam.write_16(4, 0);
+        // rotate by 90 degrees
+        gpu.oam.write_16(0x06, 0);
+        gpu.oam.write_16(0x0e, 0x100);
+        gpu.oam.write_16(0x16, (-0x100i16) as u16);
+        gpu.oam.write_16(0x1e, 0);
+
+        // obj 1: 64x64 double-size affine obj at (0, 150) that wraps around to the top of the screen,
+        // using matrix 1 to stretch it vertically
+        for b in gpu.vram[obj_tiles + 0x80..obj_tiles + 0x880].iter_mut() {
+            *b = 0x11;
+        }
+        gpu.oam.write_16(8, 0x0300 | 150);
+        gpu.oam.write_16(10, 0xc000 | (1 << 9));
+        gpu.oam.write_16(12, 4);
+        gpu.oam.write_16(0x26, 0x100);
+        gpu.oam.write_16(0x2e, 0);
+        gpu.oam.write_16(0x36, 0);
+        gpu.oam.write_16(0x3e, 0x80);
+
+        for line in 0..DISPLAY_HEIGHT {
+            gpu.vcount = line;
+            gpu.render_objs();
+        }
+
+        let color_at = |x: usize, y: usize| gpu.obj_buffer_get(x, y).color;
+
+        // the corners of the texture are rotated within the doubled bounding box,
+        // which spans (50, 20) to (82, 52)
+        assert_eq!(color_at(74, 28), Rgb15(2 * 0x0421));
+        assert_eq!(color_at(74, 43), Rgb15(3 * 0x0421));
+        assert_eq!(color_at(59, 28), Rgb15(4 * 0x0421));
+        assert_eq!(color_at(59, 43), Rgb15(5 * 0x0421));
+        assert_eq!(color_at(66, 36), Rgb15(0x0421));
+        // outside of the texture
+        assert_eq!(color_at(50, 20), Rgb15::TRANSPARENT);
+        assert_eq!(color_at(75, 28), Rgb15::TRANSPARENT);
+        assert_eq!(color_at(58, 43), Rgb15::TRANSPARENT);
+
+        // the bounding box of obj 1 spans lines 150 to 277, i.e lines 0..=21 after wrapping
+        assert_eq!(color_at(32, 0), Rgb15(0x0421));
+        assert_eq!(color_at(31, 0), Rgb15::TRANSPARENT);
+        assert_eq!(color_at(95, 21), Rgb15(0x0421));
+        assert_eq!(color_at(96, 21), Rgb15::TRANSPARENT);
+        assert_eq!(color_at(95, 22), Rgb15::TRANSPARENT);
+    }
+
+    #[test]
+    fn test_obj_tile_mapping() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+
+        // hide all objs
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        for index in 1..=8u32 {
+            gpu.palette_ram
+                .write_16(0x200 + 2 * index, (index as u16) * 0x0421);
+        }
+
+        // fill each 0x20 byte tile slot with a solid color
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        let mut fill_slot = |slot: usize, value: u8| {
+            let ofs = obj_tiles + slot * 0x20;
+            for b in gpu.vram[ofs..ofs + 0x20].iter_mut() {
+                *b = value;
+            }
+        };
+        // 4bpp obj, tile 0
+        fill_slot(0, 0x11);
+        fill_slot(4, 0x22);
+        fill_slot(32, 0x33);
+        // 8bpp obj, tile 64
+        fill_slot(64, 4);
+        fill_slot(65, 4);
+        fill_slot(72, 5);
+        fill_slot(73, 5);
+        fill_slot(96, 6);
+        fill_slot(97, 6);
+
+        // obj 0: 32x32 4bpp obj at (0, 0)
+        gpu.oam.write_16(0, 0);
+        gpu.oam.write_16(2, 0x8000);
+        gpu.oam.write_16(4, 0);
+        // obj 1: 32x32 8bpp obj at (64, 0)
+        gpu.oam.write_16(8, 0x2000);
+        gpu.oam.write_16(10, 0x8000 | 64);
+        gpu.oam.write_16(12, 64);
+
+        let render = |gpu: &mut Gpu, dispcnt: u16| {
+            gpu.write_dispcnt(dispcnt);
+            gpu.obj_buffer_reset();
+            for line in 0..32 {
+                gpu.vcount = line;
+                gpu.render_objs();
+            }
+        };
+
+        // 1D mapping, the second tile row follows the first one
+        render(&mut gpu, 0x1040);
+        assert_eq!(gpu.obj_buffer_get(0, 0).color, Rgb15(0x0421));
+        assert_eq!(gpu.obj_buffer_get(0, 8).color, Rgb15(2 * 0x0421));
+        assert_eq!(gpu.obj_buffer_get(64, 0).color, Rgb15(4 * 0x0421));
+        assert_eq!(gpu.obj_buffer_get(64, 8).color, Rgb15(5 * 0x0421));
+
+        // 2D mapping, the second tile row is 32 slots further
+        render(&mut gpu, 0x1000);
+        assert_eq!(gpu.obj_buffer_get(0, 0).color, Rgb15(0x0421));
+        assert_eq!(gpu.obj_buffer_get(0, 8).color, Rgb15(3 * 0x0421));
+        assert_eq!(gpu.obj_buffer_get(64, 0).color, Rgb15(4 * 0x0421));
+        assert_eq!(gpu.obj_buffer_get(64, 8).color, Rgb15(6 * 0x0421));
+
+        // the lower bit of the tile number is ignored for 8bpp objs in 2D mapping
+        gpu.oam.write_16(12, 65);
+        render(&mut gpu, 0x1000);
+        assert_eq!(gpu.obj_buffer_get(64, 0).color, Rgb15(4 * 0x0421));
+        assert_eq!(gpu.obj_buffer_get(64, 8).color, Rgb15(6 * 0x0421));
+    }
+
+    #[test]
+    fn test_vram_8bit_writes() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+
+        // mode 4, both bitmap frames accept 8bit writes
+        gpu.write_dispcnt(0x0404);
+        gpu.write_8(VRAM_ADDR + 0x1235, 0x42);
+        assert_eq!(&gpu.vram[0x1234..0x1236], &[0x42, 0x42]);
+        gpu.write_8(VRAM_ADDR + 0x13ffe, 0x17);
+        assert_eq!(&gpu.vram[0x13ffe..0x14000], &[0x17, 0x17]);
+
+        // OBJ tiles ignore 8bit writes, including through the upper 32K mirror
+        gpu.write_8(VRAM_ADDR + 0x14000, 0x42);
+        gpu.write_8(VRAM_ADDR + 0x18000, 0x42);
+        gpu.write_8(VRAM_ADDR + 0x1c000, 0x42);
+        assert_eq!(&gpu.vram[0x14000..0x14002], &[0, 0]);
+        assert_eq!(&gpu.vram[0x10000..0x10002], &[0, 0]);
+
+        // in text modes the OBJ tiles start earlier
+        gpu.write_dispcnt(0x0100);
+        gpu.write_8(VRAM_ADDR + 0xfffe, 0x11);
+        gpu.write_8(VRAM_ADDR + 0x10000, 0x22);
+        assert_eq!(&gpu.vram[0xfffe..0x10002], &[0x11, 0x11, 0, 0]);
+
+        // 16bit writes to the upper 32K are mirrored to the OBJ tiles
+        gpu.write_16(VRAM_ADDR + 0x18000, 0xbeef);
+        assert_eq!(gpu.read_16(VRAM_ADDR + 0x10000), 0xbeef);
+        assert_eq!(gpu.read_8(VRAM_ADDR + 0x18001), 0xbe);
+
+        gpu.write_8(PALRAM_ADDR + 3, 0x1f);
+        assert_eq!(gpu.read_16(PALRAM_ADDR + 2), 0x1f1f);
+    }
+
+    #[test]
+    fn test_scaled_output() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        assert_eq!(
+            gpu.get_scaled_frame_buffer().len(),
+            DISPLAY_WIDTH * DISPLAY_HEIGHT
+        );
+
+        gpu.set_output_scale(2);
+        for (i, pixel) in gpu.frame_buffer.iter_mut().enumerate() {
+            *pixel = i as u32;
+        }
+        // entering VBlank renders the scaled frame
+        gpu.vcount = DISPLAY_HEIGHT - 1;
+        gpu.handle_hblank_end(0, &mut NopDmaNotifer);
+
+        let scaled = gpu.get_scaled_frame_buffer();
+        assert_eq!(scaled.len(), 4 * DISPLAY_WIDTH * DISPLAY_HEIGHT);
+        for y in 0..2 * DISPLAY_HEIGHT {
+            for x in 0..2 * DISPLAY_WIDTH {
+                assert_eq!(
+                    scaled[y * 2 * DISPLAY_WIDTH + x],
+                    ((y / 2) * DISPLAY_WIDTH + x / 2) as u32
+                );
+            }
+        }
+
+        gpu.set_output_scale(1);
+        assert_eq!(gpu.get_scaled_frame_buffer(), gpu.get_frame_buffer());
+    }
+
+    #[test]
+    fn test_green_swap() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+
+        // forced blank
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert!(gpu.frame_buffer[..DISPLAY_WIDTH]
+            .iter()
+            .all(|pixel| *pixel == 0xf8f8f8));
+
+        // mode 3, a gradient of red and green pixels
+        gpu.write_dispcnt(0x0403);
+        let color = |x: u16| Rgb15::from_rgb(x, 31 - x, 0);
+        for x in 0..DISPLAY_WIDTH as u16 {
+            gpu.vram.write_16(2 * x as u32, color(x % 32).0);
+        }
+        let pixel = |x: u16, green_x: u16| {
+            color(x % 32).to_rgb24() & !0xff00 | color(green_x % 32).to_rgb24() & 0xff00
+        };
+
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        for x in 0..DISPLAY_WIDTH {
+            assert_eq!(gpu.frame_buffer[x], pixel(x as u16, x as u16));
+        }
+
+        // render the scanline in two parts that split a pixel pair
+        gpu.green_swap = true;
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(3);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        for x in 0..DISPLAY_WIDTH as u16 {
+            assert_eq!(gpu.frame_buffer[x as usize], pixel(x, x ^ 1));
+        }
+    }
+
+    #[test]
+    fn test_partial_scanline_render() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+
+        // every pixel of BG2 uses palette entry 1, in mode 4 and in mode 2 with a 128x128 map of tile 1
+        for b in gpu.vram[..DISPLAY_WIDTH].iter_mut() {
+            *b = 1;
+        }
+        for b in gpu.vram[0x40..0x80].iter_mut() {
+            *b = 1;
+        }
+        let red = Rgb15::from_rgb(31, 0, 0);
+        let blue = Rgb15::from_rgb(0, 0, 31);
+
+        for dispcnt in [0x0404, 0x0402].iter() {
+            gpu.write_dispcnt(*dispcnt);
+            gpu.bgcnt[2].write(0x2000);
+            gpu.palette_ram.write_16(2, red.0);
+            gpu.begin_scanline(0);
+            gpu.render_scanline_until(100);
+            gpu.palette_ram.write_16(2, blue.0);
+            gpu.render_scanline_until(DISPLAY_WIDTH);
+
+            // each sync only renders the pixels it reached
+            for x in 0..DISPLAY_WIDTH {
+                let expected = if x < 100 { red } else { blue };
+                assert_eq!(gpu.bg_line[2][x], expected, "x = {}", x);
+                assert_eq!(gpu.frame_buffer[x], expected.to_rgb24(), "x = {}", x);
+            }
+        }
+    }
+
+    #[test]
+    fn test_obj_window() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0, BG0, BG1 and OBJ enabled, 1D obj mapping, WIN1 and the OBJ window enabled
+        gpu.write_dispcnt(0xd340);
+
+        // BG0 is red on top of the blue BG1, both share an opaque tile 0
+        let red = Rgb15::from_rgb(31, 0, 0);
+        let blue = Rgb15::from_rgb(0, 0, 31);
+        gpu.bgcnt[0].write(0x0800);
+        gpu.bgcnt[1].write(0x0901);
+        for b in gpu.vram[..0x20].iter_mut() {
+            *b = 0x11;
+        }
+        for entry in 0..0x400 {
+            gpu.vram.write_16(0x4800 + 2 * entry, 0x1000);
+        }
+        gpu.palette_ram.write_16(2, red.0);
+        gpu.palette_ram.write_16(0x22, blue.0);
+
+        // a 16x16 OBJ window sprite at (0, 0)
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        for b in gpu.vram[obj_tiles..obj_tiles + 0x80].iter_mut() {
+            *b = 0x11;
+        }
+        gpu.palette_ram.write_16(0x202, Rgb15::from_rgb(0, 31, 0).0);
+        gpu.oam.write_16(0, 0x0800);
+        gpu.oam.write_16(2, 0x4000);
+        gpu.oam.write_16(4, 0);
+
+        // alpha blend BG0 over BG1 outside of the windows, only BG1 is visible inside the OBJ window,
+        // and WIN1 which covers x=8..24 takes priority over the OBJ window and shows only BG0
+        gpu.bldcnt.write(0x0241);
+        gpu.bldalpha.write(0x0808);
+        gpu.winout_flags = WindowFlags::from(0x23);
+        gpu.winobj_flags = WindowFlags::from(0x02);
+        gpu.win1 = Window {
+            left: 8,
+            right: 24,
+            top: 0,
+            bottom: DISPLAY_HEIGHT as u8,
+            flags: WindowFlags::from(0x01),
+        };
+
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        let blended = Rgb15::from_rgb(15, 0, 15);
+        for x in 0..DISPLAY_WIDTH {
+            let expected = match x {
+                0..=7 => blue,
+                8..=23 => red,
+                _ => blended,
+            };
+            assert_eq!(gpu.frame_buffer[x], expected.to_rgb24(), "x = {}", x);
+        }
+
+        // without the OBJ window, the sprite area falls back to WINOUT
+        gpu.write_dispcnt(0x5340);
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert_eq!(gpu.frame_buffer[0], blended.to_rgb24());
+        assert_eq!(gpu.frame_buffer[8], red.to_rgb24());
+    }
+
+    #[test]
+    fn test_equal_priority_layers() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0, BG1 and BG2 enabled
+        gpu.write_dispcnt(0x0600);
+
+        // both at priority 1 and fully opaque, BG1 is red and BG2 is blue
+        let red = Rgb15::from_rgb(31, 0, 0);
+        let blue = Rgb15::from_rgb(0, 0, 31);
+        let green = Rgb15::from_rgb(0, 31, 0);
+        gpu.bgcnt[1].write(0x0801);
+        gpu.bgcnt[2].write(0x0901);
+        for b in gpu.vram[..0x20].iter_mut() {
+            *b = 0x11;
+        }
+        for entry in 0..0x400 {
+            gpu.vram.write_16(0x4800 + 2 * entry, 0x1000);
+        }
+        gpu.palette_ram.write_16(2, red.0);
+        gpu.palette_ram.write_16(0x22, blue.0);
+
+        let render = |gpu: &mut Gpu| {
+            gpu.begin_scanline(0);
+            gpu.render_scanline_until(DISPLAY_WIDTH);
+            gpu.frame_buffer[..DISPLAY_WIDTH].to_vec()
+        };
+
+        // the lower background number wins the tie
+        assert!(render(&mut gpu).iter().all(|p| *p == red.to_rgb24()));
+
+        // a lower priority value still beats the background number
+        gpu.bgcnt[2].write(0x0900);
+        assert!(render(&mut gpu).iter().all(|p| *p == blue.to_rgb24()));
+        gpu.bgcnt[2].write(0x0901);
+
+        // a 16x16 sprite at (0, 0), also at priority 1, is drawn over both backgrounds
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        for b in gpu.vram[obj_tiles..obj_tiles + 0x80].iter_mut() {
+            *b = 0x11;
+        }
+        gpu.palette_ram.write_16(0x202, green.0);
+        gpu.oam.write_16(0, 0x0000);
+        gpu.oam.write_16(2, 0x4000);
+        gpu.oam.write_16(4, 0x0400);
+        gpu.write_dispcnt(0x1640);
+        let line = render(&mut gpu);
+        for (x, pixel) in line.iter().enumerate() {
+            let expected = if x < 16 { green } else { red };
+            assert_eq!(*pixel, expected.to_rgb24(), "x = {}", x);
+        }
+    }
+
+    #[test]
+    fn test_affine_bg_wraparound() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 2, BG2 enabled
+        gpu.write_dispcnt(0x0402);
+
+        // 128x128 affine BG2 using screen block 1, the first tile column of the texture is blue, the rest is red
+        let red = Rgb15::from_rgb(31, 0, 0);
+        let blue = Rgb15::from_rgb(0, 0, 31);
+        let green = Rgb15::from_rgb(0, 31, 0);
+        for b in gpu.vram[0x40..0x80].iter_mut() {
+            *b = 1;
+        }
+        for b in gpu.vram[0x80..0xc0].iter_mut() {
+            *b = 2;
+        }
+        for y in 0..16 {
+            for x in 0..16 {
+                gpu.vram[0x800 + y * 16 + x] = if x == 0 { 2 } else { 1 };
+            }
+        }
+        gpu.palette_ram.write_16(0, green.0);
+        gpu.palette_ram.write_16(2, red.0);
+        gpu.palette_ram.write_16(4, blue.0);
+
+        let render = |gpu: &mut Gpu| {
+            // scrolled halfway past the right edge of the texture
+            gpu.bg_aff[0].internal_x = 64 << 8;
+            gpu.bg_aff[0].internal_y = 0;
+            gpu.begin_scanline(0);
+            gpu.render_scanline_until(DISPLAY_WIDTH);
+            gpu.frame_buffer[..DISPLAY_WIDTH].to_vec()
+        };
+
+        // with the wraparound bit set the texture repeats
+        gpu.bgcnt[2].write(0x2100);
+        let line = render(&mut gpu);
+        for (x, pixel) in line.iter().enumerate() {
+            let expected = if (x + 64) % 128 < 8 { blue } else { red };
+            assert_eq!(*pixel, expected.to_rgb24(), "x = {}", x);
+        }
+
+        // otherwise, everything past the edge is transparent and shows the backdrop
+        gpu.bgcnt[2].write(0x0100);
+        let line = render(&mut gpu);
+        for (x, pixel) in line.iter().enumerate() {
+            let expected = if x < 64 { red } else { green };
+            assert_eq!(*pixel, expected.to_rgb24(), "x = {}", x);
+        }
+    }
+
+    #[test]
+    fn test_semi_transparent_obj_blending() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0, BG0 and OBJ enabled, 1D obj mapping
+        gpu.write_dispcnt(0x1140);
+
+        // an opaque blue BG0
+        let blue = Rgb15::from_rgb(0, 0, 31);
+        gpu.bgcnt[0].write(0x0800);
+        for b in gpu.vram[..0x20].iter_mut() {
+            *b = 0x11;
+        }
+        gpu.palette_ram.write_16(2, blue.0);
+
+        // a semi-transparent red 8x8 sprite at (0, 0)
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        for b in gpu.vram[obj_tiles..obj_tiles + 0x20].iter_mut() {
+            *b = 0x11;
+        }
+        gpu.palette_ram.write_16(0x202, Rgb15::from_rgb(31, 0, 0).0);
+        gpu.oam.write_16(0, 0x0400);
+        gpu.oam.write_16(2, 0);
+        gpu.oam.write_16(4, 0);
+
+        // no blend mode and OBJ isn't a 1st target, only BG0 is a 2nd target
+        gpu.bldcnt.write(0x0100);
+        gpu.bldalpha.write(0x0808);
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        for x in 0..DISPLAY_WIDTH {
+            let expected = match x {
+                0..=7 => Rgb15::from_rgb(15, 0, 15),
+                _ => blue,
+            };
+            assert_eq!(gpu.frame_buffer[x], expected.to_rgb24(), "x = {}", x);
+        }
+
+        // coefficients above 16 act as 16
+        gpu.palette_ram.write_16(0x202, Rgb15::from_rgb(10, 0, 0).0);
+        gpu.bldalpha.write(0x0014);
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert_eq!(gpu.frame_buffer[0], Rgb15::from_rgb(10, 0, 0).to_rgb24());
+
+        // without a 2nd target below it, the sprite is drawn as is
+        gpu.bldcnt.write(0x0000);
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert_eq!(gpu.frame_buffer[0], Rgb15::from_rgb(10, 0, 0).to_rgb24());
+    }
+
+    #[test]
+    fn test_brightness_effects() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0 with no layers enabled, only the backdrop is visible
+        gpu.write_dispcnt(0x0000);
+
+        let render_backdrop = |gpu: &mut Gpu, color: Rgb15, bldcnt: u16, bldy: u16| {
+            gpu.palette_ram.write_16(0, color.0);
+            gpu.bldcnt.write(bldcnt);
+            gpu.bldy = bldy;
+            gpu.begin_scanline(0);
+            gpu.render_scanline_until(DISPLAY_WIDTH);
+            gpu.frame_buffer[0]
+        };
+
+        // full brightness increase on a mid-gray backdrop
+        let gray = Rgb15::from_rgb(16, 16, 16);
+        assert_eq!(
+            render_backdrop(&mut gpu, gray, 0x00a0, 16),
+            Rgb15::WHITE.to_rgb24()
+        );
+        assert_eq!(
+            render_backdrop(&mut gpu, gray, 0x00a0, 8),
+            Rgb15::from_rgb(23, 23, 23).to_rgb24()
+        );
+        // full brightness decrease
+        assert_eq!(
+            render_backdrop(&mut gpu, gray, 0x00e0, 16),
+            Rgb15::BLACK.to_rgb24()
+        );
+        // the decrease is rounded down, so dark channels are left untouched by small coefficients
+        assert_eq!(
+            render_backdrop(&mut gpu, Rgb15::from_rgb(1, 15, 31), 0x00e0, 1),
+            Rgb15::from_rgb(1, 15, 30).to_rgb24()
+        );
+        // the backdrop isn't a 1st target
+        assert_eq!(render_backdrop(&mut gpu, gray, 0x0080, 16), gray.to_rgb24());
+    }
+
+    #[test]
+    fn test_color_correction() {
+        let gray = Rgb15::from_rgb(16, 16, 16);
+        assert_eq!(
+            gray.to_rgb24_corrected(ColorCorrection::None),
+            gray.to_rgb24()
+        );
+        // the GBA LCD darkens the mid-tones and gives white a slight tint
+        assert_eq!(gray.to_rgb24_corrected(ColorCorrection::GbaLcd), 0x4c4849);
+        assert_eq!(
+            Rgb15::WHITE.to_rgb24_corrected(ColorCorrection::GbaLcd),
+            0xfceef2
+        );
+        assert_eq!(Rgb15::BLACK.to_rgb24_corrected(ColorCorrection::GbaLcd), 0);
+        assert_eq!(gray.to_rgb24_corrected(ColorCorrection::Nds), 0x848484);
+        assert_eq!(
+            Rgb15::WHITE.to_rgb24_corrected(ColorCorrection::Nds),
+            0xffffff
+        );
+
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0 with no layers enabled, only the backdrop is visible
+        gpu.write_dispcnt(0x0000);
+        gpu.palette_ram.write_16(0, gray.0);
+
+        let mut render_backdrop = |correction| {
+            gpu.set_color_correction(correction);
+            gpu.begin_scanline(0);
+            gpu.render_scanline_until(DISPLAY_WIDTH);
+            gpu.frame_buffer[0]
+        };
+        assert_eq!(render_backdrop(ColorCorrection::GbaLcd), 0x4c4849);
+        assert_eq!(render_backdrop(ColorCorrection::None), gray.to_rgb24());
+    }
+
+    #[test]
+    fn test_debug_backdrop() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        gpu.skip_bios();
+        // mode 0 with no layers enabled, only the backdrop is visible
+        gpu.write_dispcnt(0x0000);
+        gpu.palette_ram.write_16(0, Rgb15::BLACK.0);
+
+        let magenta = Rgb15::from_rgb(31, 0, 31);
+        gpu.set_debug_backdrop(Some(magenta));
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert!(gpu.frame_buffer[..DISPLAY_WIDTH]
+            .iter()
+            .all(|&pixel| pixel == magenta.to_rgb24()));
+        assert_eq!(gpu.palette_ram.read_16(0), Rgb15::BLACK.0);
+
+        gpu.set_debug_backdrop(None);
+        gpu.begin_scanline(0);
+        gpu.render_scanline_until(DISPLAY_WIDTH);
+        assert!(gpu.frame_buffer[..DISPLAY_WIDTH]
+            .iter()
+            .all(|&pixel| pixel == Rgb15::BLACK.to_rgb24()));
+    }
+
+    #[test]
+    fn test_obj_mosaic() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        // 1D obj mapping, obj enabled
+        gpu.write_dispcnt(0x1040);
+        // 4x2 obj mosaic
+        gpu.mosaic = RegMosaic(0x1300);
+
+        // hide all objs
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        // a 8x8 8bpp gradient obj, every pixel has a different color
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        for index in 1..=64 {
+            gpu.vram[obj_tiles + index - 1] = index as u8;
+            gpu.palette_ram
+                .write_16(0x200 + 2 * index as u32, index as u16);
+        }
+        let gradient_color = |x: usize, y: usize| Rgb15((1 + x + 8 * y) as u16);
+
+        // mosaic, 8bpp obj at (0, 0)
+        gpu.oam.write_16(0, 0x3000);
+        gpu.oam.write_16(2, 0);
+        gpu.oam.write_16(4, 0);
+        for y in 0..8 {
+            gpu.vcount = y;
+            gpu.render_objs();
+            for x in 0..8 {
+                assert_eq!(
+                    gpu.obj_buffer_get(x, y).color,
+                    gradient_color(x - x % 4, y - y % 2),
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+
+        // without the mosaic flag, the obj is drawn as is
+        gpu.obj_buffer_reset();
+        gpu.oam.write_16(0, 0x2000);
+        for y in 0..8 {
+            gpu.vcount = y;
+            gpu.render_objs();
+            for x in 0..8 {
+                assert_eq!(gpu.obj_buffer_get(x, y).color, gradient_color(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_last_scanline_obj_stats() {
+        let mut sched = Scheduler::new();
+        let mut gpu = Gpu::new(&mut sched, Rc::new(Cell::new(Default::default())));
+        // 1D obj mapping, obj enabled
+        gpu.write_dispcnt(0x1040);
+        gpu.palette_ram.write_16(0x202, 0x7fff);
+
+        // hide all objs
+        for obj in 0..128 {
+            gpu.oam.write_16(obj * 8, 0x0200);
+        }
+        // tile 0 is opaque, tile 1 is transparent
+        let obj_tiles = VRAM_OBJ_TILES_START_TEXT as usize;
+        for b in gpu.vram[obj_tiles..obj_tiles + 0x20].iter_mut() {
+            *b = 0x11;
+        }
+
+        // three visible 8x8 objs at line 10
+        for (obj, x) in [(0, 0), (1, 20), (2, 40)] {
+            gpu.oam.write_16(obj * 8, 10);
+            gpu.oam.write_16(obj * 8 + 2, x);
+            gpu.oam.write_16(obj * 8 + 4, 0);
+        }
+        // a transparent 16x16 obj that doesn't contribute any pixels, but still takes rendering cycles
+        gpu.oam.write_16(3 * 8, 4);
+        gpu.oam.write_16(3 * 8 + 2, 0x4000 | 100);
+        gpu.oam.write_16(3 * 8 + 4, 1);
+        // an obj on a different line
+        gpu.oam.write_16(4 * 8, 100);
+        gpu.oam.write_16(4 * 8 + 2, 60);
+        gpu.oam.write_16(4 * 8 + 4, 0);
+
+        gpu.vcount = 10;
+        gpu.render_objs();
+        assert_eq!(
+            gpu.last_scanline_obj_stats(),
+            ObjStats {
+                objs_rendered: 3,
+                obj_cycles: 3 * 8 + 16,
+                over_cycle_limit: false,
+            }
+        );
+
+        // 20 64x64 objs are more than the hardware can render on a single line
+        for obj in 0..20 {
+            gpu.oam.write_16(obj * 8, 10);
+            gpu.oam.write_16(obj * 8 + 2, 0xc000);
+            gpu.oam.write_16(obj * 8 + 4, 0);
+        }
+        gpu.render_objs();
+        let stats = gpu.last_scanline_obj_stats();
+        assert_eq!(stats.obj_cycles, 20 * 64);
+        assert!(stats.over_cycle_limit);
+    }
 }
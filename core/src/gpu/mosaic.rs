@@ -1,35 +1,42 @@
-use super::*;
 use regs::RegMosaic;
 
+use super::*;
+
 impl RegMosaic {
-    fn is_enabled_for_bg(&self) -> bool {
-        (self.bg_hsize() != 0) || (self.bg_vsize() != 0)
+    /// Width and height of the BG mosaic blocks
+    pub fn bg_block_size(&self) -> (i32, i32) {
+        (self.bg_hsize() as i32 + 1, self.bg_vsize() as i32 + 1)
+    }
+
+    /// Width and height of the OBJ mosaic blocks
+    pub fn obj_block_size(&self) -> (i32, i32) {
+        (self.obj_hsize() as i32 + 1, self.obj_vsize() as i32 + 1)
     }
 }
 
-impl Gpu {
-    fn mosaic_bg(&mut self) {
-        // let hsize = (self.mosaic.bg_hsize() + 1) as usize;
-        // let vsize = (self.mosaic.bg_vsize() + 1) as usize;
+/// Returns the first screen coordinate of the mosaic block `coord` belongs to.
+/// Mosaic blocks are aligned to the screen, every pixel of a block shows the color of its first pixel.
+#[inline]
+pub(super) fn mosaic_start(coord: i32, block_size: i32) -> i32 {
+    coord - coord.rem_euclid(block_size)
+}
 
-        // for bg in 0..4 {
-        //     if self.dispcnt.enable_bg[bg] && self.backgrounds[bg].mosaic {
-        //         let y = self.vcount as usize;
-        //         if y % vsize == 0 {
-        //             self.backgrounds[bg].mosaic_first_row = self.backgrounds[bg].line.clone();
-        //         }
-        //         for x in 0..DISPLAY_WIDTH {
-        //             let color = self.backgrounds[bg].mosaic_first_row[(x / hsize) * hsize];
-        //             self.backgrounds[bg].line[x] = color;
-        //         }
-        //     }
-        // }
+impl Gpu {
+    /// Mosaic block size of a BG, 1x1 if mosaic is disabled for it
+    pub(super) fn bg_mosaic_size(&self, bg: usize) -> (i32, i32) {
+        if self.bgcnt[bg].mosaic {
+            self.mosaic.bg_block_size()
+        } else {
+            (1, 1)
+        }
     }
 
-    pub fn mosaic_sfx(&mut self) {
-        if self.mosaic.is_enabled_for_bg() {
-            self.mosaic_bg();
+    /// Mosaic block size of an OBJ, 1x1 if the OBJ doesn't have mosaic enabled
+    pub(super) fn obj_mosaic_size(&self, mosaic_enabled: bool) -> (i32, i32) {
+        if mosaic_enabled {
+            self.mosaic.obj_block_size()
+        } else {
+            (1, 1)
         }
-        // TODO obj mosaic
     }
 }
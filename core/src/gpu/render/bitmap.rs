@@ -10,7 +10,7 @@ use crate::prelude::BusIO;
 use rustboyadvance_utils::index2d;
 
 impl Gpu {
-    pub(in super::super) fn render_mode3(&mut self, bg: usize) {
+    pub(in super::super) fn render_mode3(&mut self, bg: usize, start_x: usize, end_x: usize) {
         let _y = self.vcount;
 
         let pa = self.bg_aff[bg - 2].pa as i32;
@@ -19,7 +19,7 @@ impl Gpu {
 
         let wraparound = self.bgcnt[bg].affine_wraparound;
 
-        for x in 0..DISPLAY_WIDTH {
+        for x in start_x..end_x {
             let mut t = utils::transform_bg_point(ref_point, x as i32, pa, pc);
             if !SCREEN_VIEWPORT.contains_point(t) {
                 if wraparound {
@@ -37,7 +37,7 @@ impl Gpu {
         }
     }
 
-    pub(in super::super) fn render_mode4(&mut self, bg: usize) {
+    pub(in super::super) fn render_mode4(&mut self, bg: usize, start_x: usize, end_x: usize) {
         let page_ofs: u32 = match self.dispcnt.display_frame_select {
             0 => 0x0600_0000 - VRAM_ADDR,
             1 => 0x0600_a000 - VRAM_ADDR,
@@ -52,7 +52,7 @@ impl Gpu {
 
         let wraparound = self.bgcnt[bg].affine_wraparound;
 
-        for x in 0..DISPLAY_WIDTH {
+        for x in start_x..end_x {
             let mut t = utils::transform_bg_point(ref_point, x as i32, pa, pc);
             if !SCREEN_VIEWPORT.contains_point(t) {
                 if wraparound {
@@ -71,7 +71,7 @@ impl Gpu {
         }
     }
 
-    pub(in super::super) fn render_mode5(&mut self, bg: usize) {
+    pub(in super::super) fn render_mode5(&mut self, bg: usize, start_x: usize, end_x: usize) {
         let page_ofs: u32 = match self.dispcnt.display_frame_select {
             0 => 0x0600_0000 - VRAM_ADDR,
             1 => 0x0600_a000 - VRAM_ADDR,
@@ -86,7 +86,7 @@ impl Gpu {
 
         let wraparound = self.bgcnt[bg].affine_wraparound;
 
-        for x in 0..DISPLAY_WIDTH {
+        for x in start_x..end_x {
             let mut t = utils::transform_bg_point(ref_point, x as i32, pa, pc);
             if !MODE5_VIEWPORT.contains_point(t) {
                 if wraparound {
@@ -1,3 +1,6 @@
+use std::cmp;
+
+use super::super::mosaic::mosaic_start;
 use super::super::regs::*;
 use super::super::*;
 
@@ -9,6 +12,18 @@ const ATTRS_SIZE: u32 = 2 * 3 + 2;
 
 struct ObjAttrs(Attribute0, Attribute1, Attribute2);
 
+/// OBJ rendering statistics of a single scanline
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ObjStats {
+    /// Number of OBJs that drew at least one pixel, including OBJ window pixels
+    pub objs_rendered: usize,
+    /// OBJ rendering cycles the hardware spends on this line
+    pub obj_cycles: usize,
+    /// Whether `obj_cycles` exceeds the cycles available for OBJ rendering.
+    /// The hardware would drop the remaining sprites, while we render them anyway.
+    pub over_cycle_limit: bool,
+}
+
 const AFFINE_FILL: u32 = 2 * 3;
 
 impl ObjAttrs {
@@ -29,16 +44,24 @@ impl ObjAttrs {
             _ => (8, 8), // according to commit f01016a30b2e8482d06798895ebc674370e81816 in melonDS
         }
     }
-    fn coords(&self) -> (i32, i32) {
-        let mut y = self.0.y_coord() as i16 as i32;
-        let mut x = self.1.x_coord() as i16 as i32;
-        if y >= (DISPLAY_HEIGHT as i32) {
-            y -= 1 << 8;
-        }
+    fn x_coord(&self) -> i32 {
+        let x = self.1.x_coord() as i32;
         if x >= (DISPLAY_WIDTH as i32) {
-            x -= 1 << 9;
+            x - (1 << 9)
+        } else {
+            x
+        }
+    }
+    /// Returns the line inside the obj bounding box that is drawn at `screen_y`, if any.
+    /// The y coordinate wraps around at 256, so objs placed near the bottom of the screen
+    /// continue at the top.
+    fn sprite_line(&self, screen_y: i32, bbox_h: i32) -> Option<i32> {
+        let line = (screen_y - self.0.y_coord() as i32) & 0xff;
+        if line < bbox_h {
+            Some(line)
+        } else {
+            None
         }
-        (x, y)
     }
     fn tile_format(&self) -> (usize, PixelFormat) {
         if self.0.is_8bpp() {
@@ -47,6 +70,22 @@ impl ObjAttrs {
             (0x20, PixelFormat::BPP4)
         }
     }
+    /// Returns the VRAM offset of the first tile of the obj, and the number of tiles between
+    /// two consecutive tile rows of the obj.
+    ///
+    /// In 1D mapping the tiles of the obj are laid out consecutively, while in 2D mapping
+    /// the character data is a 32x32 matrix of 0x20 byte slots, where 8bpp tiles take 2 slots.
+    fn tile_layout(&self, mapping: ObjMapping, obj_w: i32) -> (u32, i32) {
+        let tile = self.2.tile() as u32;
+        match mapping {
+            ObjMapping::OneDimension => (OVRAM - VRAM_ADDR + 0x20 * tile, obj_w / 8),
+            // in 2D mapping, the lower bit of the tile number is ignored for 8bpp objs
+            ObjMapping::TwoDimension if self.0.is_8bpp() => {
+                (OVRAM - VRAM_ADDR + 0x20 * (tile & !1), 16)
+            }
+            ObjMapping::TwoDimension => (OVRAM - VRAM_ADDR + 0x20 * tile, 32),
+        }
+    }
     fn affine_index(&self) -> u32 {
         let attr1 = (self.1).0;
         ((attr1 >> 9) & 0x1f) as u32
@@ -81,25 +120,28 @@ impl Gpu {
     fn render_affine_obj(&mut self, attrs: ObjAttrs, _obj_num: usize) {
         let screen_y = self.vcount as i32;
 
-        let (ref_x, ref_y) = attrs.coords();
+        let ref_x = attrs.x_coord();
 
         let (obj_w, obj_h) = attrs.size();
 
+        // double-size objs occupy a bounding box twice as large, centered on the original obj
         let (bbox_w, bbox_h) = match attrs.0.objtype() {
             ObjType::AffineDoubleSize => (2 * obj_w, 2 * obj_h),
             _ => (obj_w, obj_h),
         };
 
         // skip this obj if not within its vertical bounds.
-        if !(screen_y >= ref_y && screen_y < ref_y + bbox_h) {
-            return;
-        }
+        let sprite_y = match attrs.sprite_line(screen_y, bbox_h) {
+            Some(line) => line,
+            None => return,
+        };
 
         if attrs.0.objmode() == ObjMode::Forbidden {
             return;
         }
+        self.obj_stats.obj_cycles += 10 + 2 * bbox_w as usize;
 
-        let tile_base = OVRAM - VRAM_ADDR + 0x20 * (attrs.2.tile() as u32);
+        let (tile_base, tile_array_width) = attrs.tile_layout(self.dispcnt.obj_mapping(), obj_w);
         if tile_base < self.vram_obj_tiles_start {
             return;
         }
@@ -110,23 +152,17 @@ impl Gpu {
             _ => 0u32,
         };
 
-        let tile_array_width = match self.dispcnt.obj_mapping() {
-            ObjMapping::OneDimension => obj_w / 8,
-            ObjMapping::TwoDimension => {
-                if attrs.0.is_8bpp() {
-                    16
-                } else {
-                    32
-                }
-            }
-        };
-
         let affine_matrix = self.get_affine_matrix(attrs.affine_index());
 
+        // mosaic blocks are aligned to the screen, but never sample outside of the obj
+        let (mosaic_w, mosaic_h) = self.obj_mosaic_size(attrs.0.mosaic());
+        let sprite_y = cmp::max(sprite_y - (screen_y - mosaic_start(screen_y, mosaic_h)), 0);
+
         let half_width = bbox_w / 2;
         let half_height = bbox_h / 2;
         let screen_width = DISPLAY_WIDTH as i32;
-        let iy = screen_y - (ref_y + half_height);
+        let iy = sprite_y - half_height;
+        let mut visible = false;
 
         macro_rules! render_loop {
             ($read_pixel_index_fn:ident) => {
@@ -147,6 +183,7 @@ impl Gpu {
                         continue;
                     }
 
+                    let ix = cmp::max(mosaic_start(screen_x, mosaic_w), ref_x) - ref_x - half_width;
                     let transformed_x = (affine_matrix.pa * ix + affine_matrix.pb * iy) >> 8;
                     let transformed_y = (affine_matrix.pc * ix + affine_matrix.pd * iy) >> 8;
                     let texture_x = transformed_x + obj_w / 2;
@@ -168,6 +205,7 @@ impl Gpu {
                                 pixel_color,
                                 &attrs,
                             );
+                            visible = true;
                         }
                     }
                 }
@@ -178,24 +216,37 @@ impl Gpu {
             PixelFormat::BPP4 => render_loop!(read_pixel_index_bpp4),
             PixelFormat::BPP8 => render_loop!(read_pixel_index_bpp8),
         }
+        if visible {
+            self.obj_stats.objs_rendered += 1;
+        }
     }
 
     fn render_normal_obj(&mut self, attrs: ObjAttrs, _obj_num: usize) {
         let screen_y = self.vcount as i32;
 
-        let (ref_x, ref_y) = attrs.coords();
+        let ref_x = attrs.x_coord();
         let (obj_w, obj_h) = attrs.size();
 
         // skip this obj if not within its vertical bounds.
-        if !(screen_y >= ref_y && screen_y < ref_y + obj_h) {
-            return;
-        }
+        let sprite_y = match attrs.sprite_line(screen_y, obj_h) {
+            Some(line) => line,
+            None => return,
+        };
+        // mosaic blocks are aligned to the screen, but never sample outside of the obj
+        let (mosaic_w, mosaic_h) = self.obj_mosaic_size(attrs.0.mosaic());
+        let sprite_y = cmp::max(sprite_y - (screen_y - mosaic_start(screen_y, mosaic_h)), 0);
+        let sprite_y = if attrs.1.v_flip() {
+            obj_h - sprite_y - 1
+        } else {
+            sprite_y
+        };
 
         if attrs.0.objmode() == ObjMode::Forbidden {
             return;
         }
+        self.obj_stats.obj_cycles += obj_w as usize;
 
-        let tile_base = OVRAM - VRAM_ADDR + 0x20 * (attrs.2.tile() as u32);
+        let (tile_base, tile_array_width) = attrs.tile_layout(self.dispcnt.obj_mapping(), obj_w);
         if tile_base < self.vram_obj_tiles_start {
             return;
         }
@@ -206,20 +257,10 @@ impl Gpu {
             _ => 0u32,
         };
 
-        let tile_array_width = match self.dispcnt.obj_mapping() {
-            ObjMapping::OneDimension => obj_w / 8,
-            ObjMapping::TwoDimension => {
-                if attrs.0.is_8bpp() {
-                    16
-                } else {
-                    32
-                }
-            }
-        };
-
         // render the pixels
         let screen_width = DISPLAY_WIDTH as i32;
         let end_x = ref_x + obj_w;
+        let mut visible = false;
 
         macro_rules! render_loop {
             ($read_pixel_index_fn:ident) => {
@@ -238,13 +279,7 @@ impl Gpu {
                     {
                         continue;
                     }
-                    let mut sprite_y = screen_y - ref_y;
-                    let mut sprite_x = screen_x - ref_x;
-                    sprite_y = if attrs.1.v_flip() {
-                        obj_h - sprite_y - 1
-                    } else {
-                        sprite_y
-                    };
+                    let mut sprite_x = cmp::max(mosaic_start(screen_x, mosaic_w), ref_x) - ref_x;
                     sprite_x = if attrs.1.h_flip() {
                         obj_w - sprite_x - 1
                     } else {
@@ -266,6 +301,7 @@ impl Gpu {
                             pixel_color,
                             &attrs,
                         );
+                        visible = true;
                     }
                 }
             };
@@ -275,6 +311,9 @@ impl Gpu {
             PixelFormat::BPP4 => render_loop!(read_pixel_index_bpp4),
             PixelFormat::BPP8 => render_loop!(read_pixel_index_bpp8),
         }
+        if visible {
+            self.obj_stats.objs_rendered += 1;
+        }
     }
 
     fn write_obj_pixel(&mut self, x: usize, y: usize, pixel_color: Rgb15, attrs: &ObjAttrs) {
@@ -294,6 +333,7 @@ impl Gpu {
     }
 
     pub(in super::super) fn render_objs(&mut self) {
+        self.obj_stats = ObjStats::default();
         for obj_num in 0..128 {
             let obj = self.read_obj_attrs(obj_num);
             match obj.0.objtype() {
@@ -302,6 +342,13 @@ impl Gpu {
                 ObjType::Affine | ObjType::AffineDoubleSize => self.render_affine_obj(obj, obj_num),
             }
         }
+        // 1210 cycles are available for OBJ rendering, or 954 if OAM access during HBlank is allowed
+        let cycle_limit = if self.dispcnt.hblank_interval_free {
+            954
+        } else {
+            1210
+        };
+        self.obj_stats.over_cycle_limit = self.obj_stats.obj_cycles > cycle_limit;
     }
 }
 
@@ -1,6 +1,7 @@
 //! Rendering for modes 0-3
 
 use super::super::consts::*;
+use super::super::mosaic::mosaic_start;
 use super::super::Rgb15;
 use super::super::{Gpu, PixelFormat, SCREEN_BLOCK_SIZE};
 use super::{utils, ViewPort};
@@ -9,7 +10,8 @@ use crate::prelude::BusIO;
 use rustboyadvance_utils::index2d;
 
 impl Gpu {
-    pub(in super::super) fn render_reg_bg(&mut self, bg: usize) {
+    /// Renders the background from `start_x` up to (not including) `end_x`
+    pub(in super::super) fn render_reg_bg(&mut self, bg: usize, start_x: usize, end_x: usize) {
         let (h_ofs, v_ofs) = (self.bg_hofs[bg] as u32, self.bg_vofs[bg] as u32);
         let tileset_base = self.bgcnt[bg].char_block();
         let tilemap_base = self.bgcnt[bg].screen_block();
@@ -18,9 +20,9 @@ impl Gpu {
         let (bg_width, bg_height) = self.bgcnt[bg].size_regular();
 
         let screen_y = self.vcount as u32;
-        let mut screen_x = 0;
+        let mut screen_x = start_x as u32;
 
-        // calculate the bg coords at the top-left corner, including wraparound
+        // calculate the bg coords at the starting point, including wraparound
         let bg_x = (screen_x + h_ofs) % bg_width;
         let bg_y = (screen_y + v_ofs) % bg_height;
 
@@ -70,7 +72,7 @@ impl Gpu {
                             let color = self.get_palette_color(index as u32, palette_bank, 0);
                             self.bg_line[bg][screen_x as usize] = color;
                             screen_x += 1;
-                            if (DISPLAY_WIDTH as u32) == screen_x {
+                            if (end_x as u32) == screen_x {
                                 return;
                             }
                         }
@@ -91,23 +93,31 @@ impl Gpu {
         }
     }
 
-    pub(in super::super) fn render_aff_bg(&mut self, bg: usize) {
+    /// Renders the background from `start_x` up to (not including) `end_x`
+    pub(in super::super) fn render_aff_bg(&mut self, bg: usize, start_x: usize, end_x: usize) {
         assert!(bg == 2 || bg == 3);
 
         let texture_size = 128 << self.bgcnt[bg].size;
         let viewport = ViewPort::new(texture_size, texture_size);
 
-        let ref_point = self.get_ref_point(bg);
+        let mut ref_point = self.get_ref_point(bg);
         let pa = self.bg_aff[bg - 2].pa as i16 as i32;
         let pc = self.bg_aff[bg - 2].pc as i16 as i32;
 
+        // vertical mosaic repeats the first line of each block, step the reference point back to it
+        let (mosaic_w, mosaic_h) = self.bg_mosaic_size(bg);
+        let lines_into_block = self.vcount as i32 % mosaic_h;
+        ref_point.0 -= lines_into_block * self.bg_aff[bg - 2].pb as i16 as i32;
+        ref_point.1 -= lines_into_block * self.bg_aff[bg - 2].pd as i16 as i32;
+
         let screen_block = self.bgcnt[bg].screen_block();
         let char_block = self.bgcnt[bg].char_block();
 
         let wraparound = self.bgcnt[bg].affine_wraparound;
 
-        for screen_x in 0..(DISPLAY_WIDTH as i32) {
-            let mut t = utils::transform_bg_point(ref_point, screen_x, pa, pc);
+        for screen_x in (start_x as i32)..(end_x as i32) {
+            let sample_x = mosaic_start(screen_x, mosaic_w);
+            let mut t = utils::transform_bg_point(ref_point, sample_x, pa, pc);
 
             if !viewport.contains_point(t) {
                 if wraparound {
@@ -2,6 +2,23 @@
 
 use serde::{Deserialize, Serialize};
 
+/// Color correction applied when converting the 15bit colors to the 24bit output
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorCorrection {
+    /// The raw colors
+    None,
+    /// The dark, low contrast reflective GBA LCD, see `Rgb15::to_gba_lcd_rgb24`
+    GbaLcd,
+    /// The backlit DS lite screen, which shows the colors unaltered over the full output range
+    Nds,
+}
+
+impl Default for ColorCorrection {
+    fn default() -> ColorCorrection {
+        ColorCorrection::None
+    }
+}
+
 bitfield! {
     #[repr(transparent)]
     #[derive(Serialize, Deserialize, Copy, Clone, Default, PartialEq, Eq)]
@@ -21,6 +38,44 @@ impl Rgb15 {
         ((self.r() as u32) << 19) | ((self.g() as u32) << 11) | ((self.b() as u32) << 3)
     }
 
+    pub fn to_rgb24_corrected(&self, correction: ColorCorrection) -> u32 {
+        match correction {
+            ColorCorrection::None => self.to_rgb24(),
+            ColorCorrection::GbaLcd => self.to_gba_lcd_rgb24(),
+            ColorCorrection::Nds => {
+                let expand = |c: u16| ((c << 3) | (c >> 2)) as u32;
+                (expand(self.r()) << 16) | (expand(self.g()) << 8) | expand(self.b())
+            }
+        }
+    }
+
+    /// Talarubi's & byuu's GBA color emulation, the LCD gamma of 4 darkens the mid-tones
+    /// and the subpixels bleed into each other.
+    fn to_gba_lcd_rgb24(&self) -> u32 {
+        const LCD_GAMMA: f64 = 4.0;
+        const OUT_GAMMA: f64 = 2.2;
+
+        let lcd = |c: u16| (c as f64 / 31.0).powf(LCD_GAMMA);
+        let (lr, lg, lb) = (lcd(self.r()), lcd(self.g()), lcd(self.b()));
+        let out = |mix: f64| {
+            let c = (mix / 255.0).powf(1.0 / OUT_GAMMA) * (255.0 * 255.0 / 280.0);
+            c.round().min(255.0) as u32
+        };
+        let r = out(50.0 * lg + 255.0 * lr);
+        let g = out(30.0 * lb + 230.0 * lg + 10.0 * lr);
+        let b = out(220.0 * lb + 10.0 * lg + 50.0 * lr);
+        (r << 16) | (g << 8) | b
+    }
+
+    /// Inverse of `to_rgb24`
+    pub fn from_rgb24(rgb: u32) -> Rgb15 {
+        Rgb15::from_rgb(
+            ((rgb >> 19) & 0x1f) as u16,
+            ((rgb >> 11) & 0x1f) as u16,
+            ((rgb >> 3) & 0x1f) as u16,
+        )
+    }
+
     pub fn from_rgb(r: u16, g: u16, b: u16) -> Rgb15 {
         let mut c = Rgb15(0);
         c.set_r(r);
@@ -14,6 +14,18 @@ impl Rgb15 {
         let b = cmp::min(31, (self.b() * my_weight + other.b() * other_weight) >> 4);
         Rgb15::from_rgb(r, g, b)
     }
+
+    /// Move each channel `evy`/16 of the way up to white, rounding down
+    fn brighten(self, evy: u16) -> Rgb15 {
+        let f = |c: u16| c + (((31 - c) * evy) >> 4);
+        Rgb15::from_rgb(f(self.r()), f(self.g()), f(self.b()))
+    }
+
+    /// Move each channel `evy`/16 of the way down to black, the decrease is rounded down
+    fn darken(self, evy: u16) -> Rgb15 {
+        let f = |c: u16| c - ((c * evy) >> 4);
+        Rgb15::from_rgb(f(self.r()), f(self.g()), f(self.b()))
+    }
 }
 
 /// Filters a background indexes array by whether they're active
@@ -37,13 +49,46 @@ impl Gpu {
             RenderLayerKind::Background2 => self.bg_line[2][x],
             RenderLayerKind::Background3 => self.bg_line[3][x],
             RenderLayerKind::Objects => self.obj_buffer_get(x, y).color,
-            RenderLayerKind::Backdrop => Rgb15(self.palette_ram.read_16(0)),
+            RenderLayerKind::Backdrop => self.backdrop_color(),
         }
     }
 
     /// Composes the render layers into a final scanline while applying needed special effects, and render it to the frame buffer
-    pub fn finalize_scanline(&mut self, bg_start: usize, bg_end: usize) {
-        let backdrop_color = Rgb15(self.palette_ram.read_16(0));
+    /// Only the pixels in the range [x_start, x_end) are composed.
+    pub fn finalize_scanline(
+        &mut self,
+        bg_start: usize,
+        bg_end: usize,
+        x_start: usize,
+        x_end: usize,
+    ) {
+        self.compose_scanline(bg_start, bg_end, x_start, x_end);
+        if self.green_swap {
+            self.swap_green(x_start, x_end);
+        }
+    }
+
+    /// Swap the green component of each pixel pair (2N, 2N+1) that is fully rendered by [x_start, x_end).
+    /// A pair that was split between calls is swapped by the call that renders its second pixel.
+    fn swap_green(&mut self, x_start: usize, x_end: usize) {
+        const GREEN_MASK: u32 = 0x00ff00;
+        let line = &mut self.frame_buffer[self.vcount * DISPLAY_WIDTH..][..DISPLAY_WIDTH];
+        for pair in line[x_start & !1..x_end & !1].chunks_exact_mut(2) {
+            let (left, right) = (pair[0], pair[1]);
+            pair[0] = (left & !GREEN_MASK) | (right & GREEN_MASK);
+            pair[1] = (right & !GREEN_MASK) | (left & GREEN_MASK);
+        }
+        const GREEN_MASK_RGB15: u16 = 0x03e0;
+        let line = &mut self.frame_buffer_rgb15[self.vcount * DISPLAY_WIDTH..][..DISPLAY_WIDTH];
+        for pair in line[x_start & !1..x_end & !1].chunks_exact_mut(2) {
+            let (left, right) = (pair[0].0, pair[1].0);
+            pair[0].0 = (left & !GREEN_MASK_RGB15) | (right & GREEN_MASK_RGB15);
+            pair[1].0 = (right & !GREEN_MASK_RGB15) | (left & GREEN_MASK_RGB15);
+        }
+    }
+
+    fn compose_scanline(&mut self, bg_start: usize, bg_end: usize, x_start: usize, x_end: usize) {
+        let backdrop_color = self.backdrop_color();
 
         // filter out disabled backgrounds and sort by priority
         // the backgrounds are sorted once for the entire scanline
@@ -54,8 +99,10 @@ impl Gpu {
 
         let y = self.vcount;
 
+        let num_pixels = x_end - x_start;
+
         if !self.dispcnt.is_using_windows() {
-            for x in 0..DISPLAY_WIDTH {
+            for x in x_start..x_end {
                 let win = WindowInfo::new(WindowType::WinNone, WindowFlags::all());
                 self.finalize_pixel(x, y, &win, &sorted_backgrounds, backdrop_color);
             }
@@ -68,15 +115,15 @@ impl Gpu {
                 for (x, is_occupid) in occupied
                     .iter_mut()
                     .enumerate()
-                    .take(self.win0.right())
-                    .skip(self.win0.left())
+                    .take(cmp::min(self.win0.right(), x_end))
+                    .skip(cmp::max(self.win0.left(), x_start))
                 {
                     self.finalize_pixel(x, y, &win, &backgrounds, backdrop_color);
                     *is_occupid = true;
                     occupied_count += 1;
                 }
             }
-            if occupied_count == DISPLAY_WIDTH {
+            if occupied_count == num_pixels {
                 return;
             }
             if self.dispcnt.enable_window1 && self.win1.contains_y(y) {
@@ -85,8 +132,8 @@ impl Gpu {
                 for (x, is_occupid) in occupied
                     .iter_mut()
                     .enumerate()
-                    .take(self.win0.right())
-                    .skip(self.win0.left())
+                    .take(cmp::min(self.win1.right(), x_end))
+                    .skip(cmp::max(self.win1.left(), x_start))
                 {
                     if *is_occupid {
                         continue;
@@ -96,16 +143,17 @@ impl Gpu {
                     occupied_count += 1;
                 }
             }
-            if occupied_count == DISPLAY_WIDTH {
+            if occupied_count == num_pixels {
                 return;
             }
+            // pixels outside of WIN0 and WIN1 are either inside the OBJ window, or outside of all windows
             let win_out = WindowInfo::new(WindowType::WinOut, self.winout_flags);
             let win_out_backgrounds = filter_window_backgrounds(&sorted_backgrounds, win_out.flags);
             if self.dispcnt.enable_obj_window {
                 let win_obj = WindowInfo::new(WindowType::WinObj, self.winobj_flags);
                 let win_obj_backgrounds =
                     filter_window_backgrounds(&sorted_backgrounds, win_obj.flags);
-                for (x, is_occupid) in occupied.iter().enumerate().take(DISPLAY_WIDTH) {
+                for (x, is_occupid) in occupied.iter().enumerate().take(x_end).skip(x_start) {
                     if *is_occupid {
                         continue;
                     }
@@ -119,7 +167,7 @@ impl Gpu {
                     }
                 }
             } else {
-                for x in 0..DISPLAY_WIDTH {
+                for x in x_start..x_end {
                     if occupied[x] {
                         continue;
                     }
@@ -180,56 +228,50 @@ impl Gpu {
         let top_flags = self.bldcnt.target1;
         let bot_flags = self.bldcnt.target2;
 
-        let sfx_enabled = (self.bldcnt.mode != BlendMode::BldNone || obj_alpha_blend)
-            && top_flags.contains_render_layer(&top_layer); // sfx must at least have a first target configured
-
-        if win.flags.sfx_enabled() && sfx_enabled {
-            if top_layer.is_object()
-                && obj_alpha_blend
-                && bot_flags.contains_render_layer(&bot_layer)
-            {
-                output[x] = self.do_alpha(top_layer.pixel, bot_layer.pixel).to_rgb24();
-            } else {
-                let (top_layer, bot_layer) = (top_layer, bot_layer);
-
-                match self.bldcnt.mode {
-                    BlendMode::BldAlpha => {
-                        output[x] = if bot_flags.contains_render_layer(&bot_layer) {
-                            self.do_alpha(top_layer.pixel, bot_layer.pixel).to_rgb24()
-                        } else {
-                            // alpha blending must have a 2nd target
-                            top_layer.pixel.to_rgb24()
-                        }
+        let color = if !win.flags.sfx_enabled() {
+            top_layer.pixel
+        } else if obj_alpha_blend && bot_flags.contains_render_layer(&bot_layer) {
+            // semi-transparent objects are always alpha blended with a 2nd target below them,
+            // regardless of the blend mode and the OBJ 1st target bit
+            self.do_alpha(top_layer.pixel, bot_layer.pixel)
+        } else if top_flags.contains_render_layer(&top_layer) {
+            // otherwise sfx must at least have a first target configured
+            match self.bldcnt.mode {
+                BlendMode::BldAlpha => {
+                    if bot_flags.contains_render_layer(&bot_layer) {
+                        self.do_alpha(top_layer.pixel, bot_layer.pixel)
+                    } else {
+                        // alpha blending must have a 2nd target
+                        top_layer.pixel
                     }
-                    BlendMode::BldWhite => output[x] = self.do_brighten(top_layer.pixel).to_rgb24(),
-
-                    BlendMode::BldBlack => output[x] = self.do_darken(top_layer.pixel).to_rgb24(),
-
-                    BlendMode::BldNone => output[x] = top_layer.pixel.to_rgb24(),
                 }
+                BlendMode::BldWhite => self.do_brighten(top_layer.pixel),
+                BlendMode::BldBlack => self.do_darken(top_layer.pixel),
+                BlendMode::BldNone => top_layer.pixel,
             }
         } else {
             // no blending, just use the top pixel
-            output[x] = top_layer.pixel.to_rgb24();
-        }
+            top_layer.pixel
+        };
+        output[x] = self.output_color(color);
+        self.frame_buffer_rgb15[y * DISPLAY_WIDTH + x] = color;
     }
 
     #[inline]
     fn do_alpha(&self, upper: Rgb15, lower: Rgb15) -> Rgb15 {
-        let eva = self.bldalpha.eva;
-        let evb = self.bldalpha.evb;
+        // coefficients above 16 act as 16
+        let eva = cmp::min(self.bldalpha.eva, 16);
+        let evb = cmp::min(self.bldalpha.evb, 16);
         upper.blend_with(lower, eva, evb)
     }
 
     #[inline]
     fn do_brighten(&self, c: Rgb15) -> Rgb15 {
-        let evy = self.bldy;
-        c.blend_with(Rgb15::WHITE, 16 - evy, evy)
+        c.brighten(self.bldy)
     }
 
     #[inline]
     fn do_darken(&self, c: Rgb15) -> Rgb15 {
-        let evy = self.bldy;
-        c.blend_with(Rgb15::BLACK, 16 - evy, evy)
+        c.darken(self.bldy)
     }
 }
@@ -49,6 +49,21 @@ impl InterruptController {
             & ((self.interrupt_flags.get().value() & self.interrupt_enable.0) != 0)
     }
 
+    /// Halt mode ends once an enabled interrupt is requested, regardless of IME
+    #[inline]
+    pub fn halt_wakeup_pending(&self) -> bool {
+        (self.interrupt_flags.get().value() & self.interrupt_enable.0) != 0
+    }
+
+    /// Like `halt_wakeup_pending`, but only the interrupts that are still active in Stop mode count
+    #[inline]
+    pub fn stop_wakeup_pending(&self) -> bool {
+        const STOP_WAKEUP_SOURCES: u16 = 1 << Interrupt::SerialCommunication as u16
+            | 1 << Interrupt::Keypad as u16
+            | 1 << Interrupt::GamePak as u16;
+        (self.interrupt_flags.get().value() & self.interrupt_enable.0 & STOP_WAKEUP_SOURCES) != 0
+    }
+
     #[inline]
     pub fn clear(&mut self, value: u16) {
         let _if = self.interrupt_flags.get();
@@ -57,6 +72,31 @@ impl InterruptController {
     }
 }
 
+/// A snapshot of the interrupt registers, for debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptState {
+    /// IE
+    pub interrupt_enable: IrqBitmask,
+    /// IF
+    pub interrupt_flags: IrqBitmask,
+    /// IME
+    pub interrupt_master_enable: bool,
+    /// The CPSR I bit is clear, so the cpu accepts IRQs
+    pub cpu_irq_enabled: bool,
+}
+
+impl InterruptState {
+    /// The requested interrupts that are also enabled in IE
+    pub fn pending(&self) -> IrqBitmask {
+        IrqBitmask(self.interrupt_flags.0 & self.interrupt_enable.0)
+    }
+
+    /// True if the cpu is going to take an IRQ before its next instruction
+    pub fn irq_pending(&self) -> bool {
+        self.interrupt_master_enable && self.cpu_irq_enabled && self.pending().0 != 0
+    }
+}
+
 impl InterruptConnect for InterruptController {
     fn connect_irq(&mut self, interrupt_flags: SharedInterruptFlags) {
         self.interrupt_flags = interrupt_flags;
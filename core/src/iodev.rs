@@ -6,7 +6,9 @@ use super::dma::DmaController;
 use super::gpu::regs::GpuMemoryMappedIO;
 use super::gpu::regs::WindowFlags;
 use super::gpu::*;
-use super::interrupt::{InterruptConnect, InterruptController, SharedInterruptFlags};
+use super::interrupt::{
+    signal_irq, Interrupt, InterruptConnect, InterruptController, SharedInterruptFlags,
+};
 use super::keypad;
 use super::mgba_debug::DebugPort;
 use super::sched::{Scheduler, SchedulerConnect, SharedScheduler};
@@ -21,8 +23,11 @@ use self::consts::*;
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HaltState {
     Running,
-    Halt, // In Halt mode, the CPU is paused as long as (IE AND IF)=0,
-          // Stop, // In Stop mode, most of the hardware including sound and video are paused TODO: handle
+    /// The CPU is paused as long as (IE AND IF)=0
+    Halt,
+    /// Most of the hardware including sound, video and timers is paused too,
+    /// only a Keypad, GamePak or Serial interrupt can wake up the system.
+    Stop,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -33,9 +38,13 @@ pub struct IoDevices {
     pub timers: Timers,
     pub dmac: DmaController,
     pub keyinput: u16,
+    pub keycnt: keypad::KeyControl,
     pub post_boot_flag: bool,
-    pub waitcnt: WaitControl, // TODO also implement 4000800
+    pub waitcnt: WaitControl,
+    pub memcnt: InternalMemoryControl,
     pub haltcnt: HaltState,
+    /// The HLE bios is in the middle of IntrWait, the old interrupt flags were already discarded
+    pub hle_intr_wait: bool,
     pub debug: DebugPort,
 
     // HACK
@@ -65,8 +74,11 @@ impl IoDevices {
             sound: sound_controller,
             post_boot_flag: false,
             haltcnt: HaltState::Running,
+            hle_intr_wait: false,
             keyinput: keypad::KEYINPUT_ALL_RELEASED,
+            keycnt: keypad::KeyControl(0),
             waitcnt: WaitControl(0),
+            memcnt: InternalMemoryControl::default(),
             debug: DebugPort::new(),
             scheduler,
             sysbus_ptr: Default::default(),
@@ -76,6 +88,46 @@ impl IoDevices {
     pub fn set_sysbus_ptr(&mut self, ptr: SysBusPtr) {
         self.sysbus_ptr = ptr;
     }
+
+    /// Resets all io devices to their power-on state.
+    /// The scheduler is expected to be cleared beforehand.
+    pub fn reset(&mut self, interrupt_flags: SharedInterruptFlags, audio_sample_rate: f32) {
+        self.intc = InterruptController::new(interrupt_flags.clone());
+        self.gpu.reset(&mut self.scheduler);
+        let resampler_kind = self.sound.resampler_kind();
+        let dc_filter = self.sound.dc_filter_enabled();
+        *self.sound = SoundController::new(&mut self.scheduler, audio_sample_rate);
+        self.sound.set_resampler(resampler_kind);
+        self.sound.set_dc_filter(dc_filter);
+        self.timers = Timers::new(interrupt_flags.clone());
+        self.dmac = DmaController::new(interrupt_flags);
+        self.keyinput = keypad::KEYINPUT_ALL_RELEASED;
+        self.keycnt = keypad::KeyControl(0);
+        self.post_boot_flag = false;
+        self.waitcnt = WaitControl(0);
+        self.memcnt = InternalMemoryControl::default();
+        self.haltcnt = HaltState::Running;
+        self.hle_intr_wait = false;
+        self.debug = DebugPort::new();
+    }
+
+    /// Request the keypad interrupt if the held keys satisfy the condition set in KEYCNT.
+    /// The keys are changed by the frontend between runs, so this is checked whenever the emulation resumes.
+    pub fn check_keypad_irq(&mut self) {
+        if self.keycnt.irq_condition_met(self.keyinput) {
+            signal_irq(&self.intc.interrupt_flags, Interrupt::Keypad);
+        }
+    }
+
+    /// Read every known register in `0x0400_0000..0x0400_0400` without side effects.
+    /// The upper halves of the 32bit registers are included as well.
+    pub fn dump_registers(&mut self) -> Vec<(Addr, u16)> {
+        (IO_BASE..IO_BASE + 0x400)
+            .step_by(2)
+            .filter(|addr| io_register_name(*addr).is_some() || is_upper_half_of_register(*addr))
+            .map(|addr| (addr, self.debug_read_16(addr - IO_BASE)))
+            .collect()
+    }
 }
 
 impl InterruptConnect for IoDevices {
@@ -93,22 +145,54 @@ impl SchedulerConnect for IoDevices {
     }
 }
 
-impl BusIO for IoDevices {
-    fn read_16(&mut self, addr: Addr) -> u16 {
-        let io = self;
-        let io_addr = addr + IO_BASE;
-        // if addr > 0x0800 {
-        //     return 0;
-        // }
+/// Registers that can't be read back, reading them sees the open bus
+fn is_write_only(io_addr: Addr) -> bool {
+    match io_addr {
+        REG_BG0HOFS..=REG_BG3Y_H | REG_WIN0H..=REG_WIN1V | REG_MOSAIC | REG_BLDY => true,
+        REG_FIFO_A..=0x0400_00A6 => true,
+        // the word counts read as zero
+        REG_DMA0SAD..=REG_DMA3CNT_H => (io_addr - REG_DMA0SAD) % 12 < 8,
+        _ => false,
+    }
+}
 
+impl IoDevices {
+    /// The value of a register as seen by a read, without the open bus of the write-only registers
+    fn read_register(&mut self, io_addr: Addr) -> u16 {
+        let io = self;
         match io_addr {
             REG_DISPCNT => io.gpu.dispcnt.read(),
+            REG_GREENSWAP => io.gpu.green_swap as u16,
             REG_DISPSTAT => io.gpu.dispstat.read(),
             REG_VCOUNT => io.gpu.vcount as u16,
             REG_BG0CNT => io.gpu.bgcnt[0].read(),
             REG_BG1CNT => io.gpu.bgcnt[1].read(),
             REG_BG2CNT => io.gpu.bgcnt[2].read(),
             REG_BG3CNT => io.gpu.bgcnt[3].read(),
+            REG_BG0HOFS => io.gpu.bg_hofs[0],
+            REG_BG0VOFS => io.gpu.bg_vofs[0],
+            REG_BG1HOFS => io.gpu.bg_hofs[1],
+            REG_BG1VOFS => io.gpu.bg_vofs[1],
+            REG_BG2HOFS => io.gpu.bg_hofs[2],
+            REG_BG2VOFS => io.gpu.bg_vofs[2],
+            REG_BG3HOFS => io.gpu.bg_hofs[3],
+            REG_BG3VOFS => io.gpu.bg_vofs[3],
+            REG_BG2PA => io.gpu.bg_aff[0].pa as u16,
+            REG_BG2PB => io.gpu.bg_aff[0].pb as u16,
+            REG_BG2PC => io.gpu.bg_aff[0].pc as u16,
+            REG_BG2PD => io.gpu.bg_aff[0].pd as u16,
+            REG_BG3PA => io.gpu.bg_aff[1].pa as u16,
+            REG_BG3PB => io.gpu.bg_aff[1].pb as u16,
+            REG_BG3PC => io.gpu.bg_aff[1].pc as u16,
+            REG_BG3PD => io.gpu.bg_aff[1].pd as u16,
+            REG_BG2X_L => io.gpu.bg_aff[0].x as u16,
+            REG_BG2X_H => (io.gpu.bg_aff[0].x >> 16) as u16,
+            REG_BG2Y_L => io.gpu.bg_aff[0].y as u16,
+            REG_BG2Y_H => (io.gpu.bg_aff[0].y >> 16) as u16,
+            REG_BG3X_L => io.gpu.bg_aff[1].x as u16,
+            REG_BG3X_H => (io.gpu.bg_aff[1].x >> 16) as u16,
+            REG_BG3Y_L => io.gpu.bg_aff[1].y as u16,
+            REG_BG3Y_H => (io.gpu.bg_aff[1].y >> 16) as u16,
             REG_WIN0H => ((io.gpu.win0.left as u16) << 8 | (io.gpu.win0.right as u16)),
             REG_WIN1H => ((io.gpu.win1.left as u16) << 8 | (io.gpu.win1.right as u16)),
             REG_WIN0V => ((io.gpu.win0.top as u16) << 8 | (io.gpu.win0.bottom as u16)),
@@ -121,6 +205,8 @@ impl BusIO for IoDevices {
             }
             REG_BLDCNT => io.gpu.bldcnt.read(),
             REG_BLDALPHA => io.gpu.bldalpha.read(),
+            REG_MOSAIC => io.gpu.mosaic.0,
+            REG_BLDY => io.gpu.bldy,
 
             REG_IME => io.intc.interrupt_master_enable as u16,
             REG_IE => io.intc.interrupt_enable.0 as u16,
@@ -129,13 +215,17 @@ impl BusIO for IoDevices {
             REG_TM0CNT_L..=REG_TM3CNT_H => io.timers.handle_read(io_addr, &io.scheduler),
 
             SOUND_BASE..=SOUND_END => io.sound.handle_read(io_addr),
-            REG_DMA0CNT_H => io.dmac.channels[0].ctrl.0,
-            REG_DMA1CNT_H => io.dmac.channels[1].ctrl.0,
-            REG_DMA2CNT_H => io.dmac.channels[2].ctrl.0,
-            REG_DMA3CNT_H => io.dmac.channels[3].ctrl.0,
-            // Even though these registers are write only,
-            // some games may still try to read them.
-            // TODO: should this be treated as an open-bus read?
+            REG_DMA0SAD..=REG_DMA3CNT_H if (io_addr - REG_DMA0SAD) % 12 < 8 => {
+                let ofs = (io_addr - REG_DMA0SAD) % 12;
+                let channel = &io.dmac.channels[((io_addr - REG_DMA0SAD) / 12) as usize];
+                let addr = if ofs < 4 { channel.src } else { channel.dst };
+                (addr >> (8 * (ofs % 4))) as u16
+            }
+            REG_DMA0CNT_H => io.dmac.channels[0].read_dma_ctrl(),
+            REG_DMA1CNT_H => io.dmac.channels[1].read_dma_ctrl(),
+            REG_DMA2CNT_H => io.dmac.channels[2].read_dma_ctrl(),
+            REG_DMA3CNT_H => io.dmac.channels[3].read_dma_ctrl(),
+            // Even though these registers are write only, reading them gives zero rather than open bus
             REG_DMA0CNT_L => 0,
             REG_DMA1CNT_L => 0,
             REG_DMA2CNT_L => 0,
@@ -143,9 +233,13 @@ impl BusIO for IoDevices {
 
             REG_WAITCNT => io.waitcnt.0,
 
+            REG_IMC_L => io.memcnt.0 as u16,
+            REG_IMC_H => (io.memcnt.0 >> 16) as u16,
+
             REG_POSTFLG => io.post_boot_flag as u16,
             REG_HALTCNT => 0,
             REG_KEYINPUT => io.keyinput,
+            REG_KEYCNT => io.keycnt.0,
 
             x if DebugPort::is_debug_access(x) => io.debug.read(io_addr),
 
@@ -159,6 +253,16 @@ impl BusIO for IoDevices {
             }
         }
     }
+}
+
+impl BusIO for IoDevices {
+    fn read_16(&mut self, addr: Addr) -> u16 {
+        let io_addr = addr + IO_BASE;
+        if is_write_only(io_addr) {
+            return self.sysbus_ptr.open_bus(io_addr) as u16;
+        }
+        self.read_register(io_addr)
+    }
 
     fn read_8(&mut self, addr: Addr) -> u8 {
         let t = self.read_16(addr & !1);
@@ -176,11 +280,16 @@ impl BusIO for IoDevices {
         // }
         let io_addr = addr + IO_BASE;
 
+        // these registers can be changed in the middle of a scanline (e.g for raster effects),
+        // so make sure the pixels drawn so far use the old values
+        if let REG_BG0HOFS..=REG_BG3Y_H | REG_WIN0H | REG_WIN1H | REG_BLDCNT..=REG_BLDY = io_addr {
+            io.gpu.sync_scanline(io.scheduler.timestamp());
+        }
+
         macro_rules! write_reference_point {
             (low bg $coord:ident $internal:ident) => {{
                 let i = ((io_addr - REG_BG2X_L) / 0x10) as usize;
                 let t = io.gpu.bg_aff[i].$coord as u32;
-                io.gpu.bg_aff[i].$coord = ((t & 0xffff0000) + (value as u32)) as i32;
                 let new_value = ((t & 0xffff0000) + (value as u32)) as i32;
                 io.gpu.bg_aff[i].$coord = new_value;
                 io.gpu.bg_aff[i].$internal = new_value;
@@ -197,7 +306,8 @@ impl BusIO for IoDevices {
 
         match io_addr {
             REG_DISPCNT => io.gpu.write_dispcnt(value),
-            REG_DISPSTAT => io.gpu.dispstat.write(value),
+            REG_GREENSWAP => io.gpu.green_swap = value & 1 != 0,
+            REG_DISPSTAT => io.gpu.write_dispstat(value),
             REG_BG0CNT => io.gpu.bgcnt[0].write(value),
             REG_BG1CNT => io.gpu.bgcnt[1].write(value),
             REG_BG2CNT => io.gpu.bgcnt[2].write(value),
@@ -270,7 +380,7 @@ impl BusIO for IoDevices {
             }
 
             SOUND_BASE..=SOUND_END => {
-                io.sound.handle_write(io_addr, value);
+                io.sound.handle_write(io_addr, value, &mut io.scheduler);
             }
 
             DMA_BASE..=REG_DMA3CNT_H => {
@@ -280,21 +390,26 @@ impl BusIO for IoDevices {
                     .write_16(channel_id, ofs % 12, value, &mut io.scheduler)
             }
 
+            REG_KEYCNT => {
+                io.keycnt.0 = value & keypad::KeyControl::WRITE_MASK;
+                io.check_keypad_irq();
+            }
+
             REG_WAITCNT => {
                 io.waitcnt.0 = value;
                 (*io.sysbus_ptr).on_waitcnt_written(io.waitcnt);
             }
-
-            REG_POSTFLG => io.post_boot_flag = value != 0,
-            REG_HALTCNT => {
-                if value & 0x80 != 0 {
-                    // io.haltcnt = HaltState::Stop;
-                    panic!("Can't handle HaltCtrl == Stop yet");
-                } else {
-                    io.haltcnt = HaltState::Halt;
+            REG_IMC_L | REG_IMC_H => {
+                let shift = (io_addr - REG_IMC_L) * 8;
+                io.memcnt.0 = (io.memcnt.0 & !(0xffff << shift)) | (value as u32) << shift;
+                if io.memcnt.ewram_wait_control() == 15 {
+                    warn!("EWRAM wait control of 15 locks up the hardware");
                 }
+                (*io.sysbus_ptr).on_memcnt_written(io.memcnt);
             }
 
+            // HALTCNT is the high byte, but it is only written by 8bit stores
+            REG_POSTFLG => io.post_boot_flag = value & 1 != 0,
             x if DebugPort::is_debug_access(x) => io.debug.write(io_addr, value),
 
             _ => {
@@ -317,8 +432,20 @@ impl BusIO for IoDevices {
             0x0400_00A4 | 0x0400_00A5 | 0x0400_00A6 | 0x0400_00A7 => {
                 self.sound.write_fifo(1, value as i8)
             }
+            REG_HALTCNT => {
+                self.haltcnt = if value & 0x80 != 0 {
+                    HaltState::Stop
+                } else {
+                    HaltState::Halt
+                };
+            }
             _ => {
-                let t = self.read_16(addr & !1);
+                // merge with the stored value of the other byte, not with what the cpu would read.
+                // IF clears the flags written as 1, so the other byte is left alone by writing 0.
+                let t = match (addr & !1) + IO_BASE {
+                    REG_IF => 0,
+                    io_addr => self.read_register(io_addr),
+                };
                 let t = if addr & 1 != 0 {
                     (t & 0xff) | (value as u16) << 8
                 } else {
@@ -331,8 +458,23 @@ impl BusIO for IoDevices {
 }
 
 impl DebugRead for IoDevices {
+    fn debug_read_16(&mut self, addr: Addr) -> u16 {
+        let io_addr = addr + IO_BASE;
+        match io_addr {
+            // reading the counters syncs the running timers
+            REG_TM0CNT_L..=REG_TM3CNT_H => self.timers.debug_read(io_addr, &self.scheduler),
+            // show the stored values of the write-only registers instead of the open bus
+            _ => self.read_register(io_addr),
+        }
+    }
+
     fn debug_read_8(&mut self, addr: Addr) -> u8 {
-        self.read_8(addr)
+        let t = self.debug_read_16(addr & !1);
+        if addr & 1 != 0 {
+            (t >> 8) as u8
+        } else {
+            t as u8
+        }
     }
 }
 
@@ -353,6 +495,31 @@ bitfield! {
     prefetch, _:               14;
 }
 
+bitfield! {
+    /// The undocumented internal memory control register at 0x04000800
+    #[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+    pub struct InternalMemoryControl(u32);
+    impl Debug;
+    u32;
+    pub ewram_enabled, _:          5;
+    pub ewram_wait_control, _:     27, 24;
+}
+
+impl Default for InternalMemoryControl {
+    fn default() -> InternalMemoryControl {
+        // EWRAM enabled with 2 waitstates
+        InternalMemoryControl(0x0D00_0020)
+    }
+}
+
+impl InternalMemoryControl {
+    /// The wait control counts down from 15 waitstates, 0x0E is the 1 waitstate "overclock"
+    /// some games use. 0x0F locks up the hardware, here it just means no waitstates.
+    pub fn ewram_waitstates(&self) -> usize {
+        15 - self.ewram_wait_control() as usize
+    }
+}
+
 #[rustfmt::skip]
 pub mod consts {
     use super::*;
@@ -467,6 +634,8 @@ pub mod consts {
     pub const REG_IME: Addr = 0x0400_0208;          //  2    R/W    Interrupt Master Enable Register
     pub const REG_POSTFLG: Addr = 0x0400_0300;      //  1    R/W    Undocumented - Post Boot Flag
     pub const REG_HALTCNT: Addr = 0x0400_0301;      //  1    W      Undocumented - Power Down Control
+    pub const REG_IMC_L: Addr = 0x0400_0800;        //  2    R/W    Undocumented - Internal Memory Control, lower 16 bit
+    pub const REG_IMC_H: Addr = 0x0400_0802;        //  2    R/W    Undocumented - Internal Memory Control, upper 16 bit
 
     pub const REG_DEBUG_STRING: Addr = 0x04FF_F600;
     pub const REG_DEBUG_FLAGS: Addr = 0x04FF_F700;
@@ -474,118 +643,146 @@ pub mod consts {
 }
 
 pub fn io_reg_string(addr: u32) -> &'static str {
+    io_register_name(addr).unwrap_or("UNKNOWN")
+}
+
+/// The name of the io register at `addr`, if it's a known register
+pub fn io_register_name(addr: u32) -> Option<&'static str> {
     match addr {
-        REG_DISPCNT => "REG_DISPCNT",
-        REG_DISPSTAT => "REG_DISPSTAT",
-        REG_VCOUNT => "REG_VCOUNT",
-        REG_BG0CNT => "REG_BG0CNT",
-        REG_BG1CNT => "REG_BG1CNT",
-        REG_BG2CNT => "REG_BG2CNT",
-        REG_BG3CNT => "REG_BG3CNT",
-        REG_BG0HOFS => "REG_BG0HOFS",
-        REG_BG0VOFS => "REG_BG0VOFS",
-        REG_BG1HOFS => "REG_BG1HOFS",
-        REG_BG1VOFS => "REG_BG1VOFS",
-        REG_BG2HOFS => "REG_BG2HOFS",
-        REG_BG2VOFS => "REG_BG2VOFS",
-        REG_BG3HOFS => "REG_BG3HOFS",
-        REG_BG3VOFS => "REG_BG3VOFS",
-        REG_BG2PA => "REG_BG2PA",
-        REG_BG2PB => "REG_BG2PB",
-        REG_BG2PC => "REG_BG2PC",
-        REG_BG2PD => "REG_BG2PD",
-        REG_BG2X_L => "REG_BG2X_L",
-        REG_BG2X_H => "REG_BG2X_H",
-        REG_BG2Y_L => "REG_BG2Y_L",
-        REG_BG2Y_H => "REG_BG2Y_H",
-        REG_BG3PA => "REG_BG3PA",
-        REG_BG3PB => "REG_BG3PB",
-        REG_BG3PC => "REG_BG3PC",
-        REG_BG3PD => "REG_BG3PD",
-        REG_BG3X_L => "REG_BG3X_L",
-        REG_BG3X_H => "REG_BG3X_H",
-        REG_BG3Y_L => "REG_BG3Y_L",
-        REG_BG3Y_H => "REG_BG3Y_H",
-        REG_WIN0H => "REG_WIN0H",
-        REG_WIN1H => "REG_WIN1H",
-        REG_WIN0V => "REG_WIN0V",
-        REG_WIN1V => "REG_WIN1V",
-        REG_WININ => "REG_WININ",
-        REG_WINOUT => "REG_WINOUT",
-        REG_MOSAIC => "REG_MOSAIC",
-        REG_BLDCNT => "REG_BLDCNT",
-        REG_BLDALPHA => "REG_BLDALPHA",
-        REG_BLDY => "REG_BLDY",
-        REG_SOUND1CNT_L => "REG_SOUND1CNT_L",
-        REG_SOUND1CNT_H => "REG_SOUND1CNT_H",
-        REG_SOUND1CNT_X => "REG_SOUND1CNT_X",
-        REG_SOUND2CNT_L => "REG_SOUND2CNT_L",
-        REG_SOUND2CNT_H => "REG_SOUND2CNT_H",
-        REG_SOUND3CNT_L => "REG_SOUND3CNT_L",
-        REG_SOUND3CNT_H => "REG_SOUND3CNT_H",
-        REG_SOUND3CNT_X => "REG_SOUND3CNT_X",
-        REG_SOUND4CNT_L => "REG_SOUND4CNT_L",
-        REG_SOUND4CNT_H => "REG_SOUND4CNT_H",
-        REG_SOUNDCNT_L => "REG_SOUNDCNT_L",
-        REG_SOUNDCNT_H => "REG_SOUNDCNT_H",
-        REG_SOUNDCNT_X => "REG_SOUNDCNT_X",
-        REG_SOUNDBIAS => "REG_SOUNDBIAS",
-        REG_WAVE_RAM => "REG_WAVE_RAM",
-        REG_FIFO_A => "REG_FIFO_A",
-        REG_FIFO_B => "REG_FIFO_B",
-        REG_DMA0SAD => "REG_DMA0SAD",
-        REG_DMA0DAD => "REG_DMA0DAD",
-        REG_DMA0CNT_L => "REG_DMA0CNT_L",
-        REG_DMA0CNT_H => "REG_DMA0CNT_H",
-        REG_DMA1SAD => "REG_DMA1SAD",
-        REG_DMA1DAD => "REG_DMA1DAD",
-        REG_DMA1CNT_L => "REG_DMA1CNT_L",
-        REG_DMA1CNT_H => "REG_DMA1CNT_H",
-        REG_DMA2SAD => "REG_DMA2SAD",
-        REG_DMA2DAD => "REG_DMA2DAD",
-        REG_DMA2CNT_L => "REG_DMA2CNT_L",
-        REG_DMA2CNT_H => "REG_DMA2CNT_H",
-        REG_DMA3SAD => "REG_DMA3SAD",
-        REG_DMA3DAD => "REG_DMA3DAD",
-        REG_DMA3CNT_L => "REG_DMA3CNT_L",
-        REG_DMA3CNT_H => "REG_DMA3CNT_H",
-        REG_TM0CNT_L => "REG_TM0CNT_L",
-        REG_TM0CNT_H => "REG_TM0CNT_H",
-        REG_TM1CNT_L => "REG_TM1CNT_L",
-        REG_TM1CNT_H => "REG_TM1CNT_H",
-        REG_TM2CNT_L => "REG_TM2CNT_L",
-        REG_TM2CNT_H => "REG_TM2CNT_H",
-        REG_TM3CNT_L => "REG_TM3CNT_L",
-        REG_TM3CNT_H => "REG_TM3CNT_H",
-        // REG_SIODATA32 => "REG_SIODATA32",
-        // REG_SIOMULTI0 => "REG_SIOMULTI0",
-        // REG_SIOMULTI1 => "REG_SIOMULTI1",
-        // REG_SIOMULTI2 => "REG_SIOMULTI2",
-        // REG_SIOMULTI3 => "REG_SIOMULTI3",
-        // REG_SIOCNT => "REG_SIOCNT",
-        // REG_SIOMLT_SEND => "REG_SIOMLT_SEND",
-        // REG_SIODATA8 => "REG_SIODATA8",
-        REG_KEYINPUT => "REG_KEYINPUT",
-        REG_KEYCNT => "REG_KEYCNT",
-        REG_RCNT => "REG_RCNT",
-        REG_IR => "REG_IR",
-        REG_JOYCNT => "REG_JOYCNT",
-        REG_JOY_RECV => "REG_JOY_RECV",
-        REG_JOY_TRANS => "REG_JOY_TRANS",
-        REG_JOYSTAT => "REG_JOYSTAT",
-        REG_IE => "REG_IE",
-        REG_IF => "REG_IF",
-        REG_WAITCNT => "REG_WAITCNT",
-        REG_IME => "REG_IME",
-        REG_POSTFLG => "REG_POSTFLG",
-        REG_HALTCNT => "REG_HALTCNT",
-        REG_DEBUG_STRING => "REG_DEBUG_STRING",
-        REG_DEBUG_FLAGS => "REG_DEBUG_FLAGS",
-        REG_DEBUG_ENABLE => "REG_DEBUG_ENABLE",
-        _ => "UNKNOWN",
+        REG_DISPCNT => Some("REG_DISPCNT"),
+        REG_DISPSTAT => Some("REG_DISPSTAT"),
+        REG_VCOUNT => Some("REG_VCOUNT"),
+        REG_BG0CNT => Some("REG_BG0CNT"),
+        REG_BG1CNT => Some("REG_BG1CNT"),
+        REG_BG2CNT => Some("REG_BG2CNT"),
+        REG_BG3CNT => Some("REG_BG3CNT"),
+        REG_BG0HOFS => Some("REG_BG0HOFS"),
+        REG_BG0VOFS => Some("REG_BG0VOFS"),
+        REG_BG1HOFS => Some("REG_BG1HOFS"),
+        REG_BG1VOFS => Some("REG_BG1VOFS"),
+        REG_BG2HOFS => Some("REG_BG2HOFS"),
+        REG_BG2VOFS => Some("REG_BG2VOFS"),
+        REG_BG3HOFS => Some("REG_BG3HOFS"),
+        REG_BG3VOFS => Some("REG_BG3VOFS"),
+        REG_BG2PA => Some("REG_BG2PA"),
+        REG_BG2PB => Some("REG_BG2PB"),
+        REG_BG2PC => Some("REG_BG2PC"),
+        REG_BG2PD => Some("REG_BG2PD"),
+        REG_BG2X_L => Some("REG_BG2X_L"),
+        REG_BG2X_H => Some("REG_BG2X_H"),
+        REG_BG2Y_L => Some("REG_BG2Y_L"),
+        REG_BG2Y_H => Some("REG_BG2Y_H"),
+        REG_BG3PA => Some("REG_BG3PA"),
+        REG_BG3PB => Some("REG_BG3PB"),
+        REG_BG3PC => Some("REG_BG3PC"),
+        REG_BG3PD => Some("REG_BG3PD"),
+        REG_BG3X_L => Some("REG_BG3X_L"),
+        REG_BG3X_H => Some("REG_BG3X_H"),
+        REG_BG3Y_L => Some("REG_BG3Y_L"),
+        REG_BG3Y_H => Some("REG_BG3Y_H"),
+        REG_WIN0H => Some("REG_WIN0H"),
+        REG_WIN1H => Some("REG_WIN1H"),
+        REG_WIN0V => Some("REG_WIN0V"),
+        REG_WIN1V => Some("REG_WIN1V"),
+        REG_WININ => Some("REG_WININ"),
+        REG_WINOUT => Some("REG_WINOUT"),
+        REG_MOSAIC => Some("REG_MOSAIC"),
+        REG_BLDCNT => Some("REG_BLDCNT"),
+        REG_BLDALPHA => Some("REG_BLDALPHA"),
+        REG_BLDY => Some("REG_BLDY"),
+        REG_SOUND1CNT_L => Some("REG_SOUND1CNT_L"),
+        REG_SOUND1CNT_H => Some("REG_SOUND1CNT_H"),
+        REG_SOUND1CNT_X => Some("REG_SOUND1CNT_X"),
+        REG_SOUND2CNT_L => Some("REG_SOUND2CNT_L"),
+        REG_SOUND2CNT_H => Some("REG_SOUND2CNT_H"),
+        REG_SOUND3CNT_L => Some("REG_SOUND3CNT_L"),
+        REG_SOUND3CNT_H => Some("REG_SOUND3CNT_H"),
+        REG_SOUND3CNT_X => Some("REG_SOUND3CNT_X"),
+        REG_SOUND4CNT_L => Some("REG_SOUND4CNT_L"),
+        REG_SOUND4CNT_H => Some("REG_SOUND4CNT_H"),
+        REG_SOUNDCNT_L => Some("REG_SOUNDCNT_L"),
+        REG_SOUNDCNT_H => Some("REG_SOUNDCNT_H"),
+        REG_SOUNDCNT_X => Some("REG_SOUNDCNT_X"),
+        REG_SOUNDBIAS => Some("REG_SOUNDBIAS"),
+        REG_WAVE_RAM => Some("REG_WAVE_RAM"),
+        REG_FIFO_A => Some("REG_FIFO_A"),
+        REG_FIFO_B => Some("REG_FIFO_B"),
+        REG_DMA0SAD => Some("REG_DMA0SAD"),
+        REG_DMA0DAD => Some("REG_DMA0DAD"),
+        REG_DMA0CNT_L => Some("REG_DMA0CNT_L"),
+        REG_DMA0CNT_H => Some("REG_DMA0CNT_H"),
+        REG_DMA1SAD => Some("REG_DMA1SAD"),
+        REG_DMA1DAD => Some("REG_DMA1DAD"),
+        REG_DMA1CNT_L => Some("REG_DMA1CNT_L"),
+        REG_DMA1CNT_H => Some("REG_DMA1CNT_H"),
+        REG_DMA2SAD => Some("REG_DMA2SAD"),
+        REG_DMA2DAD => Some("REG_DMA2DAD"),
+        REG_DMA2CNT_L => Some("REG_DMA2CNT_L"),
+        REG_DMA2CNT_H => Some("REG_DMA2CNT_H"),
+        REG_DMA3SAD => Some("REG_DMA3SAD"),
+        REG_DMA3DAD => Some("REG_DMA3DAD"),
+        REG_DMA3CNT_L => Some("REG_DMA3CNT_L"),
+        REG_DMA3CNT_H => Some("REG_DMA3CNT_H"),
+        REG_TM0CNT_L => Some("REG_TM0CNT_L"),
+        REG_TM0CNT_H => Some("REG_TM0CNT_H"),
+        REG_TM1CNT_L => Some("REG_TM1CNT_L"),
+        REG_TM1CNT_H => Some("REG_TM1CNT_H"),
+        REG_TM2CNT_L => Some("REG_TM2CNT_L"),
+        REG_TM2CNT_H => Some("REG_TM2CNT_H"),
+        REG_TM3CNT_L => Some("REG_TM3CNT_L"),
+        REG_TM3CNT_H => Some("REG_TM3CNT_H"),
+        // REG_SIODATA32 => Some("REG_SIODATA32"),
+        // REG_SIOMULTI0 => Some("REG_SIOMULTI0"),
+        // REG_SIOMULTI1 => Some("REG_SIOMULTI1"),
+        // REG_SIOMULTI2 => Some("REG_SIOMULTI2"),
+        // REG_SIOMULTI3 => Some("REG_SIOMULTI3"),
+        // REG_SIOCNT => Some("REG_SIOCNT"),
+        // REG_SIOMLT_SEND => Some("REG_SIOMLT_SEND"),
+        // REG_SIODATA8 => Some("REG_SIODATA8"),
+        REG_KEYINPUT => Some("REG_KEYINPUT"),
+        REG_KEYCNT => Some("REG_KEYCNT"),
+        REG_RCNT => Some("REG_RCNT"),
+        REG_IR => Some("REG_IR"),
+        REG_JOYCNT => Some("REG_JOYCNT"),
+        REG_JOY_RECV => Some("REG_JOY_RECV"),
+        REG_JOY_TRANS => Some("REG_JOY_TRANS"),
+        REG_JOYSTAT => Some("REG_JOYSTAT"),
+        REG_IE => Some("REG_IE"),
+        REG_IF => Some("REG_IF"),
+        REG_WAITCNT => Some("REG_WAITCNT"),
+        REG_IME => Some("REG_IME"),
+        REG_POSTFLG => Some("REG_POSTFLG"),
+        REG_HALTCNT => Some("REG_HALTCNT"),
+        REG_IMC_L => Some("REG_IMC_L"),
+        REG_IMC_H => Some("REG_IMC_H"),
+        REG_DEBUG_STRING => Some("REG_DEBUG_STRING"),
+        REG_DEBUG_FLAGS => Some("REG_DEBUG_FLAGS"),
+        REG_DEBUG_ENABLE => Some("REG_DEBUG_ENABLE"),
+        _ => None,
     }
 }
 
+/// The upper halves of the 32bit registers, which `io_register_name` only names by their lower half
+fn is_upper_half_of_register(addr: Addr) -> bool {
+    // the wave RAM bank is 16 bytes long
+    (REG_WAVE_RAM + 2..REG_WAVE_RAM + 16).contains(&addr)
+        || matches!(
+            addr - 2,
+            REG_FIFO_A
+                | REG_FIFO_B
+                | REG_DMA0SAD
+                | REG_DMA0DAD
+                | REG_DMA1SAD
+                | REG_DMA1DAD
+                | REG_DMA2SAD
+                | REG_DMA2DAD
+                | REG_DMA3SAD
+                | REG_DMA3DAD
+                | REG_JOY_RECV
+                | REG_JOY_TRANS
+        )
+}
+
 fn sign_extend_i32(value: i32, size: u32) -> i32 {
     let shift = 32 - size;
     ((value << shift) as i32) >> shift
@@ -1,3 +1,7 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Primitive, PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
 pub enum Keys {
@@ -31,3 +35,70 @@ impl Into<bool> for KeyState {
         }
     }
 }
+
+bitfield! {
+    /// KEYCNT, selects the keys and the condition that request the keypad interrupt
+    #[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Eq)]
+    pub struct KeyControl(u16);
+    impl Debug;
+    u16;
+    pub keys, _: 9, 0;
+    pub irq_enable, _: 14;
+    /// All of the selected keys must be held, rather than any of them
+    pub irq_and_condition, _: 15;
+}
+
+impl KeyControl {
+    pub const WRITE_MASK: u16 = 0xc3ff;
+
+    /// True if the interrupt is enabled and the keys held in `keyinput` satisfy its condition
+    pub fn irq_condition_met(&self, keyinput: u16) -> bool {
+        let selected = self.keys();
+        let pressed = !keyinput & selected;
+        if !self.irq_enable() {
+            false
+        } else if self.irq_and_condition() {
+            selected != 0 && pressed == selected
+        } else {
+            pressed != 0
+        }
+    }
+}
+
+/// A queue of `(keys, frames)` pairs for scripted input, `keys` is a KEYINPUT value
+/// (a cleared bit means the key is pressed) that is held for `frames` frames.
+#[derive(Debug, Default, Clone)]
+pub struct InputScript {
+    queue: VecDeque<(u16, usize)>,
+}
+
+impl InputScript {
+    pub fn new() -> InputScript {
+        InputScript::default()
+    }
+
+    pub fn push(&mut self, keys: u16, frames: usize) {
+        if frames > 0 {
+            self.queue.push_back((keys, frames));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Consumes one frame of the script, returning the keys to hold during it
+    pub fn next_frame(&mut self) -> Option<u16> {
+        let (keys, frames) = self.queue.front_mut()?;
+        let keys = *keys;
+        *frames -= 1;
+        if *frames == 0 {
+            self.queue.pop_front();
+        }
+        Some(keys)
+    }
+}
@@ -32,23 +32,32 @@ use std::fmt;
 pub use arm7tdmi;
 pub use arm7tdmi::disass;
 mod bios;
+pub use bios::hle::hle_bios_rom;
+#[cfg(feature = "png")]
+pub mod capture;
 pub mod cartridge;
+pub mod cheats;
 pub mod gpu;
 mod sched;
 pub mod sound;
 pub mod sysbus;
-pub use sysbus::SysBus;
+#[cfg(feature = "profiler")]
+pub use sysbus::MemoryProfile;
+pub use sysbus::{SysBus, WaitstateProfile, WatchKind};
 pub mod interrupt;
 pub mod iodev;
 pub use interrupt::Interrupt;
+pub use interrupt::InterruptState;
 pub use interrupt::SharedInterruptFlags;
 pub mod gba;
-pub use gba::GameBoyAdvance;
+pub use gba::{EmulatorStats, FrameOutcome, GameBoyAdvance, RunResult, StepInfo, StopReason};
 pub mod dma;
 pub mod gdb_support;
 pub mod keypad;
 mod mgba_debug;
 pub(crate) mod overrides;
+pub mod replay;
+pub mod savestate_slots;
 pub mod timer;
 
 use arm7tdmi::gdb::gdbstub::stub::GdbStubError;
@@ -63,6 +72,15 @@ pub enum GBAError {
     #[cfg(feature = "debugger")]
     DebuggerError(debugger::DebuggerError),
     GdbError(String),
+    /// The savestate is corrupt or of an unsupported version
+    SavestateError(String),
+    /// The savestate was created for a different game
+    SavestateGameMismatch {
+        expected: String,
+        found: String,
+    },
+    /// The imported save data doesn't fit the cartridge backup memory
+    SaveRamError(String),
 }
 
 impl fmt::Display for GBAError {
@@ -79,6 +97,77 @@ impl Error for GBAError {
 
 pub type GBAResult<T> = Result<T, GBAError>;
 
+/// Errors of saving or restoring a savestate
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// The data doesn't start with a savestate header
+    BadMagic,
+    /// The savestate version is newer than `want`, or too old to be migrated to it
+    VersionMismatch { have: u32, want: u32 },
+    /// The savestate was created for a different game
+    GameCodeMismatch { have: String, want: String },
+    /// The header records a byte order mark other than the little-endian one
+    ByteOrderMismatch(u32),
+    /// There is no savestate slot `index`, only `num_slots` of them
+    SlotOutOfRange { index: usize, num_slots: usize },
+    /// The savestate slot doesn't hold a savestate
+    EmptySlot(usize),
+    /// The savestate payload is corrupt
+    Deserialize(String),
+    /// Reading or writing the savestate failed
+    Io(::std::io::Error),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a savestate"),
+            SaveStateError::VersionMismatch { have, want } if have > want => write!(
+                f,
+                "savestate version {} is newer than the supported version {}",
+                have, want
+            ),
+            SaveStateError::VersionMismatch { have, want } => write!(
+                f,
+                "can't migrate savestate version {} to version {}",
+                have, want
+            ),
+            SaveStateError::GameCodeMismatch { have, want } => write!(
+                f,
+                "savestate was created for game {}, expected {}",
+                have, want
+            ),
+            SaveStateError::ByteOrderMismatch(mark) => write!(
+                f,
+                "savestate has byte order mark {:#010x}, only little-endian savestates are supported",
+                mark
+            ),
+            SaveStateError::SlotOutOfRange { index, num_slots } => write!(
+                f,
+                "slot {} is out of range, there are {} slots",
+                index, num_slots
+            ),
+            SaveStateError::EmptySlot(index) => write!(f, "slot {} is empty", index),
+            SaveStateError::Deserialize(message) => write!(f, "corrupt savestate: {}", message),
+            SaveStateError::Io(err) => write!(f, "savestate io error: {}", err),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl From<bincode::Error> for SaveStateError {
+    fn from(err: bincode::Error) -> SaveStateError {
+        SaveStateError::Deserialize(err.to_string())
+    }
+}
+
+impl From<::std::io::Error> for SaveStateError {
+    fn from(err: ::std::io::Error) -> SaveStateError {
+        SaveStateError::Io(err)
+    }
+}
+
 impl From<::std::io::Error> for GBAError {
     fn from(err: ::std::io::Error) -> GBAError {
         GBAError::IO(err)
@@ -92,6 +181,25 @@ impl From<debugger::DebuggerError> for GBAError {
     }
 }
 
+impl From<bincode::Error> for GBAError {
+    fn from(err: bincode::Error) -> GBAError {
+        GBAError::SavestateError(err.to_string())
+    }
+}
+
+impl From<SaveStateError> for GBAError {
+    fn from(err: SaveStateError) -> GBAError {
+        match err {
+            SaveStateError::GameCodeMismatch { have, want } => GBAError::SavestateGameMismatch {
+                expected: want,
+                found: have,
+            },
+            SaveStateError::Io(err) => GBAError::IO(err),
+            err => GBAError::SavestateError(err.to_string()),
+        }
+    }
+}
+
 impl From<zip::result::ZipError> for GBAError {
     fn from(_err: zip::result::ZipError) -> GBAError {
         GBAError::IO(::std::io::Error::from(::std::io::ErrorKind::InvalidInput))
@@ -108,12 +216,16 @@ pub mod prelude {
     pub use super::cartridge::{Cartridge, GamepakBuilder};
     #[cfg(feature = "debugger")]
     pub use super::debugger::Debugger;
-    pub use super::gpu::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+    pub use super::gpu::interface::{DynVideoInterface, VideoInterface};
+    pub use super::gpu::{ColorCorrection, Rgb15, DISPLAY_HEIGHT, DISPLAY_WIDTH, REFRESH_RATE};
     pub use super::sound::interface::{
         AudioInterface, DynAudioInterface, NullAudio, SimpleAudioInterface,
     };
-    pub use super::{GBAError, GBAResult, GameBoyAdvance};
+    pub use super::sound::{ChannelMask, ResamplerKind};
+    pub use super::{GBAError, GBAResult, GameBoyAdvance, SaveStateError};
     pub use arm7tdmi;
     pub use arm7tdmi::memory::{Addr, BusIO, MemoryAccess, MemoryAccessWidth, MemoryInterface};
+    #[cfg(feature = "trace")]
+    pub use arm7tdmi::trace::{CpuTraceEvent, TraceSink};
     pub use rustboyadvance_utils::{read_bin_file, write_bin_file};
 }
@@ -9,6 +9,7 @@ use super::cartridge::BackupType;
 pub struct GameOverride {
     force_rtc: bool,
     save_type: Option<BackupType>,
+    mp2k_song_table: Option<u32>,
 }
 
 impl GameOverride {
@@ -18,6 +19,10 @@ impl GameOverride {
     pub fn save_type(&self) -> Option<BackupType> {
         self.save_type
     }
+    /// Address of the song table of the MP2K (Sappy) sound engine
+    pub fn mp2k_song_table(&self) -> Option<u32> {
+        self.mp2k_song_table
+    }
 }
 
 lazy_static! {
@@ -42,9 +47,12 @@ lazy_static! {
                 None
             };
 
+            let mp2k_song_table = game["mp2k_song_table"].as_i64().map(|addr| addr as u32);
+
             let game_overrride = GameOverride {
                 force_rtc,
                 save_type,
+                mp2k_song_table,
             };
             m.insert(game_code, game_overrride);
         }
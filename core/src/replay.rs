@@ -0,0 +1,64 @@
+//! Input recording for deterministic replays.
+//!
+//! A `Replay` holds the savestate the recording was started from, and the KEYINPUT value of every frame
+//! that followed. Since the emulation has no source of randomness besides the input, feeding the same
+//! keys to the same initial state reproduces the session exactly.
+use serde::{Deserialize, Serialize};
+
+use super::keypad::InputScript;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// Savestate of the first recorded frame
+    initial_state: Vec<u8>,
+    /// The KEYINPUT value of each recorded frame
+    inputs: Vec<u16>,
+}
+
+impl Replay {
+    pub(crate) fn new(initial_state: Vec<u8>) -> Replay {
+        Replay {
+            initial_state,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record_frame(&mut self, keyinput: u16) {
+        self.inputs.push(keyinput);
+    }
+
+    pub fn initial_state(&self) -> &[u8] {
+        &self.initial_state
+    }
+
+    pub fn inputs(&self) -> &[u16] {
+        &self.inputs
+    }
+
+    /// Number of recorded frames
+    pub fn frames(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The recorded inputs as a script, consecutive frames with the same keys are merged
+    pub fn input_script(&self) -> InputScript {
+        let mut script = InputScript::new();
+        let mut inputs = self.inputs.iter().peekable();
+        while let Some(&keys) = inputs.next() {
+            let mut frames = 1;
+            while inputs.next_if_eq(&&keys).is_some() {
+                frames += 1;
+            }
+            script.push(keys, frames);
+        }
+        script
+    }
+
+    pub fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Replay> {
+        bincode::deserialize(bytes)
+    }
+}
@@ -0,0 +1,198 @@
+//! Numbered savestate slots of a single game.
+//!
+//! `SaveStateSlots` only keeps the savestates in memory along with the time they were saved at,
+//! frontends are free to persist them however they like and put them back with `set_slot`.
+use chrono::prelude::*;
+
+use super::{GameBoyAdvance, SaveStateError};
+
+#[derive(Debug, Clone)]
+struct Slot {
+    state: Vec<u8>,
+    saved_at: DateTime<Local>,
+}
+
+/// Description of an occupied slot, as returned by `SaveStateSlots::list_slots`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub index: usize,
+    pub saved_at: DateTime<Local>,
+    /// Size of the savestate in bytes
+    pub size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SaveStateSlots {
+    game_code: String,
+    slots: Vec<Option<Slot>>,
+}
+
+impl SaveStateSlots {
+    /// Create `num_slots` empty slots for the game identified by `game_code`
+    pub fn new(game_code: &str, num_slots: usize) -> SaveStateSlots {
+        SaveStateSlots {
+            game_code: game_code.to_owned(),
+            slots: vec![None; num_slots],
+        }
+    }
+
+    pub fn game_code(&self) -> &str {
+        &self.game_code
+    }
+
+    pub fn num_slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn check_slot(&self, index: usize) -> Result<(), SaveStateError> {
+        if index < self.slots.len() {
+            Ok(())
+        } else {
+            Err(SaveStateError::SlotOutOfRange {
+                index,
+                num_slots: self.slots.len(),
+            })
+        }
+    }
+
+    fn check_game(&self, gba: &GameBoyAdvance) -> Result<(), SaveStateError> {
+        let have = gba.get_game_code();
+        if have == self.game_code {
+            Ok(())
+        } else {
+            Err(SaveStateError::GameCodeMismatch {
+                have,
+                want: self.game_code.clone(),
+            })
+        }
+    }
+
+    /// Save the current state of `gba` to slot `index`, replacing the savestate it held
+    pub fn save_to_slot(
+        &mut self,
+        gba: &GameBoyAdvance,
+        index: usize,
+    ) -> Result<(), SaveStateError> {
+        self.check_slot(index)?;
+        self.check_game(gba)?;
+        let state = gba.save_state()?;
+        self.slots[index] = Some(Slot {
+            state,
+            saved_at: Local::now(),
+        });
+        Ok(())
+    }
+
+    /// Restore `gba` to the savestate held by slot `index`
+    pub fn load_from_slot(
+        &self,
+        gba: &mut GameBoyAdvance,
+        index: usize,
+    ) -> Result<(), SaveStateError> {
+        self.check_slot(index)?;
+        self.check_game(gba)?;
+        match &self.slots[index] {
+            Some(slot) => gba.restore_state(&slot.state),
+            None => Err(SaveStateError::EmptySlot(index)),
+        }
+    }
+
+    /// The occupied slots, in order
+    pub fn list_slots(&self) -> Vec<SlotInfo> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                slot.as_ref().map(|slot| SlotInfo {
+                    index,
+                    saved_at: slot.saved_at,
+                    size: slot.state.len(),
+                })
+            })
+            .collect()
+    }
+
+    /// The savestate held by slot `index`, for persisting it
+    pub fn slot_state(&self, index: usize) -> Option<&[u8]> {
+        self.slots
+            .get(index)
+            .and_then(|slot| slot.as_ref())
+            .map(|slot| slot.state.as_slice())
+    }
+
+    /// Put a previously persisted savestate in slot `index`
+    pub fn set_slot(
+        &mut self,
+        index: usize,
+        state: Vec<u8>,
+        saved_at: DateTime<Local>,
+    ) -> Result<(), SaveStateError> {
+        self.check_slot(index)?;
+        self.slots[index] = Some(Slot { state, saved_at });
+        Ok(())
+    }
+
+    pub fn clear_slot(&mut self, index: usize) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gba::tests::make_mock_gba;
+
+    fn make_gba(game_code: &[u8; 4]) -> GameBoyAdvance {
+        let mut rom = vec![0; 0x200];
+        rom[0..4].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes()); // b .
+        rom[0xac..0xb0].copy_from_slice(game_code);
+        make_mock_gba(&rom)
+    }
+
+    #[test]
+    fn test_save_and_load_slots() {
+        let mut gba = make_gba(b"AAAA");
+        let mut slots = SaveStateSlots::new("AAAA", 4);
+        assert!(slots.list_slots().is_empty());
+
+        gba.write_memory(0x0200_0000, &[1, 2, 3, 4]);
+        slots.save_to_slot(&gba, 0).unwrap();
+        gba.write_memory(0x0200_0000, &[5, 6, 7, 8]);
+        gba.frame();
+        slots.save_to_slot(&gba, 1).unwrap();
+
+        let list = slots.list_slots();
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[0].index, list[1].index), (0, 1));
+        assert!(list[0].saved_at <= list[1].saved_at);
+        assert_eq!(list[0].size, slots.slot_state(0).unwrap().len());
+        assert!(slots.slot_state(2).is_none());
+
+        slots.load_from_slot(&mut gba, 0).unwrap();
+        assert_eq!(gba.read_memory(0x0200_0000, 4), vec![1, 2, 3, 4]);
+        slots.load_from_slot(&mut gba, 1).unwrap();
+        assert_eq!(gba.read_memory(0x0200_0000, 4), vec![5, 6, 7, 8]);
+
+        assert!(matches!(
+            slots.load_from_slot(&mut gba, 2),
+            Err(SaveStateError::EmptySlot(2))
+        ));
+        assert!(matches!(
+            slots.save_to_slot(&gba, 4),
+            Err(SaveStateError::SlotOutOfRange {
+                index: 4,
+                num_slots: 4
+            })
+        ));
+        assert!(matches!(
+            slots.save_to_slot(&make_gba(b"BBBB"), 2),
+            Err(SaveStateError::GameCodeMismatch { .. })
+        ));
+
+        slots.clear_slot(0);
+        assert_eq!(slots.list_slots().len(), 1);
+        assert_eq!(slots.list_slots()[0].index, 1);
+    }
+}
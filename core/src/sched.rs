@@ -124,6 +124,12 @@ impl Scheduler {
         SharedScheduler::new(self)
     }
 
+    /// Clears all pending events and rewinds the timestamp to zero
+    pub fn reset(&mut self) {
+        self.timestamp = 0;
+        self.events.clear();
+    }
+
     #[inline]
     #[allow(unused)]
     pub fn num_pending_events(&self) -> usize {
@@ -159,6 +165,35 @@ impl Scheduler {
         self.events = new_events;
     }
 
+    /// Jump to the next event of type `typ`, all other events are postponed by the skipped cycles
+    /// so they stay the same distance away from the current time.
+    /// @return the number of cycles skipped, 0 if no `typ` event is scheduled
+    pub fn skip_to_next(&mut self, typ: EventType) -> usize {
+        let target = match self
+            .events
+            .iter()
+            .filter(|e| e.typ == typ)
+            .map(|e| e.time)
+            .min()
+        {
+            Some(time) => time,
+            None => return 0,
+        };
+        let cycles = target.saturating_sub(self.timestamp);
+        let mut new_events = BinaryHeap::with_capacity(NUM_EVENTS);
+        self.events.iter().for_each(|e| {
+            let time = if e.typ == typ {
+                e.time
+            } else {
+                e.time + cycles
+            };
+            new_events.push(Event::new(e.typ, time))
+        });
+        self.events = new_events;
+        self.timestamp += cycles;
+        cycles
+    }
+
     /// Updates the scheduler timestamp
     #[inline]
     pub fn update(&mut self, cycles: usize) {
@@ -6,6 +6,21 @@ const PI: f32 = std::f32::consts::PI;
 
 pub trait Resampler {
     fn feed(&mut self, s: &StereoSample<f32>, output: &mut Vec<StereoSample<f32>>);
+    fn in_freq(&self) -> f32;
+    fn set_in_freq(&mut self, in_freq: f32);
+    fn out_freq(&self) -> f32;
+}
+
+/// The interpolation used to convert the GBA's sample rate to the audio device rate
+#[derive(SmartDefault, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResamplerKind {
+    #[default]
+    Cosine,
+    Linear,
+    /// Cheapest, repeats or drops input samples
+    Nearest,
+    /// Best quality, but the most expensive
+    WindowedSinc,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -32,6 +47,18 @@ impl Resampler for CosineResampler {
         self.phase -= 1.0;
         self.last_in_sample = *s;
     }
+
+    fn in_freq(&self) -> f32 {
+        self.in_freq
+    }
+
+    fn set_in_freq(&mut self, in_freq: f32) {
+        self.in_freq = in_freq;
+    }
+
+    fn out_freq(&self) -> f32 {
+        self.out_freq
+    }
 }
 
 impl CosineResampler {
@@ -44,3 +71,369 @@ impl CosineResampler {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LinearResampler {
+    last_in_sample: StereoSample<f32>,
+    phase: f32,
+    in_freq: f32,
+    out_freq: f32,
+}
+
+impl LinearResampler {
+    pub fn new(in_freq: f32, out_freq: f32) -> LinearResampler {
+        LinearResampler {
+            last_in_sample: Default::default(),
+            phase: 0.0,
+            in_freq,
+            out_freq,
+        }
+    }
+}
+
+impl Resampler for LinearResampler {
+    fn feed(&mut self, s: &StereoSample<f32>, output: &mut Vec<StereoSample<f32>>) {
+        let last = self.last_in_sample;
+        while self.phase < 1.0 {
+            let left = last[0] + (s[0] - last[0]) * self.phase;
+            let right = last[1] + (s[1] - last[1]) * self.phase;
+            output.push([left, right]);
+            self.phase += self.in_freq / self.out_freq;
+        }
+        self.phase -= 1.0;
+        self.last_in_sample = *s;
+    }
+
+    fn in_freq(&self) -> f32 {
+        self.in_freq
+    }
+
+    fn set_in_freq(&mut self, in_freq: f32) {
+        self.in_freq = in_freq;
+    }
+
+    fn out_freq(&self) -> f32 {
+        self.out_freq
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NearestResampler {
+    last_in_sample: StereoSample<f32>,
+    phase: f32,
+    in_freq: f32,
+    out_freq: f32,
+}
+
+impl NearestResampler {
+    pub fn new(in_freq: f32, out_freq: f32) -> NearestResampler {
+        NearestResampler {
+            last_in_sample: Default::default(),
+            phase: 0.0,
+            in_freq,
+            out_freq,
+        }
+    }
+}
+
+impl Resampler for NearestResampler {
+    fn feed(&mut self, s: &StereoSample<f32>, output: &mut Vec<StereoSample<f32>>) {
+        while self.phase < 1.0 {
+            output.push(if self.phase < 0.5 {
+                self.last_in_sample
+            } else {
+                *s
+            });
+            self.phase += self.in_freq / self.out_freq;
+        }
+        self.phase -= 1.0;
+        self.last_in_sample = *s;
+    }
+
+    fn in_freq(&self) -> f32 {
+        self.in_freq
+    }
+
+    fn set_in_freq(&mut self, in_freq: f32) {
+        self.in_freq = in_freq;
+    }
+
+    fn out_freq(&self) -> f32 {
+        self.out_freq
+    }
+}
+
+/// Number of input samples on each side of the interpolated point
+const SINC_HALF_TAPS: usize = 4;
+const SINC_TAPS: usize = 2 * SINC_HALF_TAPS;
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Lanczos window, zero outside of (-SINC_HALF_TAPS, SINC_HALF_TAPS)
+fn lanczos(x: f32) -> f32 {
+    let a = SINC_HALF_TAPS as f32;
+    if x.abs() >= a {
+        0.0
+    } else {
+        sinc(x) * sinc(x / a)
+    }
+}
+
+/// Lanczos windowed sinc interpolation, delays the output by `SINC_HALF_TAPS` input samples
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SincResampler {
+    /// The last `SINC_TAPS` input samples, oldest first
+    history: [StereoSample<f32>; SINC_TAPS],
+    phase: f32,
+    in_freq: f32,
+    out_freq: f32,
+}
+
+impl SincResampler {
+    pub fn new(in_freq: f32, out_freq: f32) -> SincResampler {
+        SincResampler {
+            history: Default::default(),
+            phase: 0.0,
+            in_freq,
+            out_freq,
+        }
+    }
+}
+
+impl Resampler for SincResampler {
+    fn feed(&mut self, s: &StereoSample<f32>, output: &mut Vec<StereoSample<f32>>) {
+        self.history.rotate_left(1);
+        self.history[SINC_TAPS - 1] = *s;
+        while self.phase < 1.0 {
+            // interpolate between the two middle samples of the history
+            let mut sample = [0.0; 2];
+            let mut weight_sum = 0.0;
+            for (i, h) in self.history.iter().enumerate() {
+                let weight = lanczos(self.phase - (i as f32 - (SINC_HALF_TAPS - 1) as f32));
+                sample[0] += h[0] * weight;
+                sample[1] += h[1] * weight;
+                weight_sum += weight;
+            }
+            output.push([sample[0] / weight_sum, sample[1] / weight_sum]);
+            self.phase += self.in_freq / self.out_freq;
+        }
+        self.phase -= 1.0;
+    }
+
+    fn in_freq(&self) -> f32 {
+        self.in_freq
+    }
+
+    fn set_in_freq(&mut self, in_freq: f32) {
+        self.in_freq = in_freq;
+    }
+
+    fn out_freq(&self) -> f32 {
+        self.out_freq
+    }
+}
+
+/// Any of the resamplers, selected by a `ResamplerKind`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AnyResampler {
+    Cosine(CosineResampler),
+    Linear(LinearResampler),
+    Nearest(NearestResampler),
+    WindowedSinc(SincResampler),
+}
+
+impl AnyResampler {
+    pub fn new(kind: ResamplerKind, in_freq: f32, out_freq: f32) -> AnyResampler {
+        match kind {
+            ResamplerKind::Cosine => AnyResampler::Cosine(CosineResampler::new(in_freq, out_freq)),
+            ResamplerKind::Linear => AnyResampler::Linear(LinearResampler::new(in_freq, out_freq)),
+            ResamplerKind::Nearest => {
+                AnyResampler::Nearest(NearestResampler::new(in_freq, out_freq))
+            }
+            ResamplerKind::WindowedSinc => {
+                AnyResampler::WindowedSinc(SincResampler::new(in_freq, out_freq))
+            }
+        }
+    }
+
+    pub fn kind(&self) -> ResamplerKind {
+        match self {
+            AnyResampler::Cosine(_) => ResamplerKind::Cosine,
+            AnyResampler::Linear(_) => ResamplerKind::Linear,
+            AnyResampler::Nearest(_) => ResamplerKind::Nearest,
+            AnyResampler::WindowedSinc(_) => ResamplerKind::WindowedSinc,
+        }
+    }
+
+    fn inner(&self) -> &dyn Resampler {
+        match self {
+            AnyResampler::Cosine(r) => r,
+            AnyResampler::Linear(r) => r,
+            AnyResampler::Nearest(r) => r,
+            AnyResampler::WindowedSinc(r) => r,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn Resampler {
+        match self {
+            AnyResampler::Cosine(r) => r,
+            AnyResampler::Linear(r) => r,
+            AnyResampler::Nearest(r) => r,
+            AnyResampler::WindowedSinc(r) => r,
+        }
+    }
+}
+
+impl Resampler for AnyResampler {
+    #[inline]
+    fn feed(&mut self, s: &StereoSample<f32>, output: &mut Vec<StereoSample<f32>>) {
+        self.inner_mut().feed(s, output)
+    }
+
+    fn in_freq(&self) -> f32 {
+        self.inner().in_freq()
+    }
+
+    fn set_in_freq(&mut self, in_freq: f32) {
+        self.inner_mut().set_in_freq(in_freq)
+    }
+
+    fn out_freq(&self) -> f32 {
+        self.inner().out_freq()
+    }
+}
+
+/// Cutoff of the DC filter. The RC values of the GBA's output stage aren't documented,
+/// this is an approximation that is low enough to leave the audible range untouched.
+pub const DC_FILTER_CUTOFF: f32 = 20.0;
+
+/// Single-pole high-pass filter removing the DC offset of the output, like the capacitor on the GBA's audio output
+#[derive(Clone, Debug)]
+pub struct DcFilter {
+    cutoff: f32,
+    alpha: f32,
+    last_in_sample: StereoSample<f32>,
+    last_out_sample: StereoSample<f32>,
+}
+
+impl DcFilter {
+    pub fn new(cutoff: f32, sample_rate: f32) -> DcFilter {
+        let mut filter = DcFilter {
+            cutoff,
+            alpha: 0.0,
+            last_in_sample: Default::default(),
+            last_out_sample: Default::default(),
+        };
+        filter.set_sample_rate(sample_rate);
+        filter
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let rc = 1.0 / (2.0 * PI * self.cutoff);
+        let dt = 1.0 / sample_rate;
+        self.alpha = rc / (rc + dt);
+    }
+
+    pub fn filter(&mut self, s: &StereoSample<f32>) -> StereoSample<f32> {
+        let out = [
+            self.alpha * (self.last_out_sample[0] + s[0] - self.last_in_sample[0]),
+            self.alpha * (self.last_out_sample[1] + s[1] - self.last_in_sample[1]),
+        ];
+        self.last_in_sample = *s;
+        self.last_out_sample = out;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_KINDS: [ResamplerKind; 4] = [
+        ResamplerKind::Cosine,
+        ResamplerKind::Linear,
+        ResamplerKind::Nearest,
+        ResamplerKind::WindowedSinc,
+    ];
+
+    fn resample_sine(kind: ResamplerKind, in_freq: f32, out_freq: f32) -> Vec<StereoSample<f32>> {
+        let mut resampler = AnyResampler::new(kind, in_freq, out_freq);
+        assert_eq!(resampler.kind(), kind);
+        let mut output = Vec::new();
+        for i in 0..in_freq as usize {
+            // 440hz
+            let value = (2.0 * PI * 440.0 * i as f32 / in_freq).sin() * 256.0;
+            resampler.feed(&[value, -value], &mut output);
+        }
+        output
+    }
+
+    #[test]
+    fn test_output_length_scales_with_rate() {
+        for kind in ALL_KINDS {
+            for out_freq in [22050.0, 44100.0, 48000.0, 96000.0] {
+                // one second of input
+                let output = resample_sine(kind, 32768.0, out_freq);
+                let error = (output.len() as f32 - out_freq).abs();
+                assert!(
+                    error <= 2.0,
+                    "{:?} {}hz produced {} samples",
+                    kind,
+                    out_freq,
+                    output.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_follows_input() {
+        for kind in ALL_KINDS {
+            let output = resample_sine(kind, 32768.0, 44100.0);
+            assert!(output.iter().all(|[left, right]| {
+                // allow for some ringing
+                left.abs() <= 300.0 && (left + right).abs() < 0.01
+            }));
+            let peak = output
+                .iter()
+                .map(|[left, _]| left.abs())
+                .fold(0.0, f32::max);
+            assert!(peak > 250.0, "{:?} peak {}", kind, peak);
+        }
+    }
+
+    #[test]
+    fn test_change_in_freq() {
+        for kind in ALL_KINDS {
+            let mut resampler = AnyResampler::new(kind, 32768.0, 44100.0);
+            resampler.set_in_freq(65536.0);
+            assert_eq!(resampler.in_freq(), 65536.0);
+            assert_eq!(resampler.out_freq(), 44100.0);
+            let mut output = Vec::new();
+            for _ in 0..65536 {
+                resampler.feed(&[0.0, 0.0], &mut output);
+            }
+            assert!((output.len() as f32 - 44100.0).abs() <= 2.0);
+        }
+    }
+
+    #[test]
+    fn test_dc_filter_converges() {
+        let mut filter = DcFilter::new(DC_FILTER_CUTOFF, 32768.0);
+        let first = filter.filter(&[200.0, -100.0]);
+        // a step passes through at first
+        assert!(first[0] > 199.0 && first[1] < -99.0);
+        let mut out = first;
+        // then decays to zero over a fraction of a second
+        for _ in 0..32768 / 4 {
+            out = filter.filter(&[200.0, -100.0]);
+        }
+        assert!(out[0].abs() < 0.1 && out[1].abs() < 0.1, "{:?}", out);
+    }
+}
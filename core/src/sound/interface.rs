@@ -12,6 +12,21 @@ pub trait AudioInterface {
     /// Note: It is not guarentied that the sample will be played
     #[allow(unused_variables)]
     fn push_sample(&mut self, sample: &StereoSample<i16>) {}
+
+    /// Pushes a batch of stereo samples, override to avoid the per-sample overhead of `push_sample`
+    fn push_samples(&mut self, batch: &[StereoSample<i16>]) {
+        for sample in batch {
+            self.push_sample(sample);
+        }
+    }
+
+    /// Pushes a batch of stereo samples along with the scheduler timestamp (in cycles) it was produced at,
+    /// override to align the audio with the video frames, e.g when recording.
+    /// Falls back to the untimed `push_samples`
+    #[allow(unused_variables)]
+    fn push_timed_samples(&mut self, timestamp_cycles: u64, batch: &[StereoSample<i16>]) {
+        self.push_samples(batch);
+    }
 }
 
 pub struct SimpleAudioInterface {
@@ -47,6 +62,12 @@ impl AudioInterface for SimpleAudioInterface {
         let _ = self.producer.push(sample[0]);
         let _ = self.producer.push(sample[1]);
     }
+
+    #[inline]
+    fn push_samples(&mut self, batch: &[StereoSample<i16>]) {
+        self.producer
+            .push_iter(&mut batch.iter().flatten().copied());
+    }
 }
 
 pub type DynAudioInterface = Box<dyn AudioInterface>;
@@ -11,11 +11,17 @@ pub mod interface;
 pub use interface::{AudioInterface, DynAudioInterface, StereoSample};
 
 mod dsp;
-use dsp::{CosineResampler, Resampler};
+pub use dsp::ResamplerKind;
+use dsp::{AnyResampler, DcFilter, Resampler, DC_FILTER_CUTOFF};
+
+mod wave;
+use wave::WaveChannel;
 
 const DMG_RATIOS: [f32; 4] = [0.25, 0.5, 1.0, 0.0];
 const DMA_TIMERS: [usize; 2] = [0, 1];
 const DUTY_RATIOS: [f32; 4] = [0.125, 0.25, 0.5, 0.75];
+/// Samples pushed to the audio device at once, the rest is pushed when the emulation stops running
+const SAMPLE_BATCH_SIZE: usize = 256;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct DmaSoundChannel {
@@ -50,6 +56,21 @@ impl Default for DmaSoundChannel {
     }
 }
 
+bitflags! {
+    /// The channels mixed into the audio output, muting a channel doesn't affect its emulation.
+    /// Square 1, square 2 and noise aren't mixed into the output yet, so they have no bits.
+    pub struct ChannelMask: u8 {
+        const WAVE = 0b0000_0100;
+        const FIFO_A = 0b0001_0000;
+        const FIFO_B = 0b0010_0000;
+        const FIFO = Self::FIFO_A.bits | Self::FIFO_B.bits;
+    }
+}
+
+const FIFO_CHANNEL_MASK: [ChannelMask; 2] = [ChannelMask::FIFO_A, ChannelMask::FIFO_B];
+
+const REG_WAVE_RAM_END: u32 = REG_WAVE_RAM + 0xe;
+
 const REG_FIFO_A_L: u32 = REG_FIFO_A;
 const REG_FIFO_A_H: u32 = REG_FIFO_A + 2;
 
@@ -85,6 +106,8 @@ pub struct SoundController {
     sqr1_initial_vol: usize,
     sqr1_cur_vol: usize,
 
+    wave: WaveChannel,
+
     sound_bias: u16,
 
     sample_rate: f32,
@@ -92,13 +115,33 @@ pub struct SoundController {
 
     dma_sound: [DmaSoundChannel; 2],
 
-    resampler: CosineResampler,
+    resampler: AnyResampler,
+    /// Only holds the last samples, it is rebuilt for the current sample rate after loading a savestate
+    #[serde(skip, default = "dc_filter_default")]
+    dc_filter: DcFilter,
+    #[serde(skip, default = "dc_filter_enabled_default")]
+    dc_filter_enabled: bool,
     output_buffer: Vec<StereoSample<f32>>,
+    #[serde(skip, default = "ChannelMask::all")]
+    channel_mask: ChannelMask,
+    /// output_buffer converted to the audio device format, until a batch is pushed to the audio device
+    #[serde(skip)]
+    device_buffer: Vec<StereoSample<i16>>,
+    /// Timestamp of the first sample in device_buffer
+    #[serde(skip)]
+    batch_timestamp: usize,
+    /// Thousandths of a sample `fill_silence_ms` still owes the audio device
+    #[serde(skip)]
+    silence_remainder: usize,
 }
 
 impl SoundController {
     pub fn new(sched: &mut Scheduler, audio_device_sample_rate: f32) -> SoundController {
-        let resampler = CosineResampler::new(32768_f32, audio_device_sample_rate);
+        let resampler = AnyResampler::new(
+            ResamplerKind::default(),
+            32768_f32,
+            audio_device_sample_rate,
+        );
         let cycles_per_sample = 512;
         sched.schedule((EventType::Apu(ApuEvent::Sample), cycles_per_sample));
         SoundController {
@@ -124,15 +167,57 @@ impl SoundController {
             sqr1_step_increase: false,
             sqr1_initial_vol: 0,
             sqr1_cur_vol: 0,
+            wave: WaveChannel::default(),
             sound_bias: 0x200,
             sample_rate: 32_768f32,
             dma_sound: [Default::default(), Default::default()],
 
             resampler,
+            dc_filter: DcFilter::new(DC_FILTER_CUTOFF, 32768_f32),
+            dc_filter_enabled: true,
             output_buffer: Vec::with_capacity(1024),
+            channel_mask: ChannelMask::all(),
+            device_buffer: Vec::with_capacity(SAMPLE_BATCH_SIZE),
+            batch_timestamp: 0,
+            silence_remainder: 0,
+        }
+    }
+
+    pub fn resampler_kind(&self) -> ResamplerKind {
+        self.resampler.kind()
+    }
+
+    /// Switch the resampler used to convert to the audio device rate, keeping the current rates
+    pub fn set_resampler(&mut self, kind: ResamplerKind) {
+        if kind != self.resampler.kind() {
+            self.resampler =
+                AnyResampler::new(kind, self.resampler.in_freq(), self.resampler.out_freq());
         }
     }
 
+    /// Remove the DC offset of the output with a high-pass filter, on by default like the real hardware
+    pub fn set_dc_filter(&mut self, enabled: bool) {
+        self.dc_filter_enabled = enabled;
+    }
+
+    pub fn dc_filter_enabled(&self) -> bool {
+        self.dc_filter_enabled
+    }
+
+    /// Rebuild the DC filter for the current sample rate, its state isn't part of savestates
+    pub(crate) fn reset_dc_filter(&mut self) {
+        self.dc_filter = DcFilter::new(DC_FILTER_CUTOFF, self.sample_rate);
+    }
+
+    pub fn channel_mask(&self) -> ChannelMask {
+        self.channel_mask
+    }
+
+    /// Mix only the channels in `mask` into the audio output, e.g to solo a channel when debugging
+    pub fn set_channel_mask(&mut self, mask: ChannelMask) {
+        self.channel_mask = mask;
+    }
+
     pub fn handle_read(&self, io_addr: u32) -> u16 {
         let value = match io_addr {
             REG_SOUNDCNT_X => cbit(7, self.mse),
@@ -166,6 +251,11 @@ impl SoundController {
 
             REG_SOUNDBIAS => self.sound_bias,
 
+            REG_SOUND3CNT_L => self.wave.read_cnt_l(),
+            REG_SOUND3CNT_H => self.wave.read_cnt_h(),
+            REG_SOUND3CNT_X => self.wave.read_cnt_x(),
+            REG_WAVE_RAM..=REG_WAVE_RAM_END => self.wave.read_ram(io_addr - REG_WAVE_RAM),
+
             _ => {
                 // println!(
                 //     "Unimplemented read from {:x} {}",
@@ -184,7 +274,7 @@ impl SoundController {
         value
     }
 
-    pub fn handle_write(&mut self, io_addr: u32, value: u16) {
+    pub fn handle_write(&mut self, io_addr: u32, value: u16, sched: &mut Scheduler) {
         if io_addr == REG_SOUNDCNT_X {
             if value & bit(7) != 0 {
                 if !self.mse {
@@ -208,8 +298,8 @@ impl SoundController {
 
         match io_addr {
             REG_SOUNDCNT_L => {
-                self.left_volume = value.bit_range(0..2) as usize;
-                self.right_volume = value.bit_range(4..6) as usize;
+                self.left_volume = value.bit_range(0..3) as usize;
+                self.right_volume = value.bit_range(4..7) as usize;
                 self.left_sqr1 = value.bit(8);
                 self.left_sqr2 = value.bit(9);
                 self.left_wave = value.bit(10);
@@ -221,7 +311,7 @@ impl SoundController {
             }
 
             REG_SOUNDCNT_H => {
-                self.dmg_volume_ratio = DMG_RATIOS[value.bit_range(0..1) as usize];
+                self.dmg_volume_ratio = DMG_RATIOS[value.bit_range(0..2) as usize];
                 self.dma_sound[0].volume_shift = value.bit(2) as i16;
                 self.dma_sound[1].volume_shift = value.bit(3) as i16;
                 self.dma_sound[0].enable_right = value.bit(8);
@@ -255,6 +345,11 @@ impl SoundController {
                 }
             }
 
+            REG_SOUND3CNT_L => self.wave.write_cnt_l(value),
+            REG_SOUND3CNT_H => self.wave.write_cnt_h(value),
+            REG_SOUND3CNT_X => self.wave.write_cnt_x(value),
+            REG_WAVE_RAM..=REG_WAVE_RAM_END => self.wave.write_ram(io_addr - REG_WAVE_RAM, value),
+
             REG_FIFO_A_L | REG_FIFO_A_H => {
                 self.dma_sound[0].fifo.write((value & 0xff) as i8);
                 self.dma_sound[0].fifo.write(((value >> 8) & 0xff) as i8);
@@ -269,12 +364,19 @@ impl SoundController {
                 self.sound_bias = value & 0xc3fe;
                 let resolution = self.sound_bias.bit_range(14..16) as usize;
                 self.sample_rate = (32768 << resolution) as f32;
-                if self.sample_rate != self.resampler.in_freq {
-                    self.resampler.in_freq = self.sample_rate;
+                if self.sample_rate != self.resampler.in_freq() {
+                    self.resampler.set_in_freq(self.sample_rate);
+                    self.dc_filter.set_sample_rate(self.sample_rate);
+                }
+                let cycles_per_sample = 512 >> resolution;
+                if cycles_per_sample != self.cycles_per_sample {
+                    self.cycles_per_sample = cycles_per_sample;
+                    // the pending sample event was scheduled with the old rate, replace it
+                    // so the new rate takes effect immediately
+                    sched.cancel_pending(EventType::Apu(ApuEvent::Sample));
+                    sched.schedule((EventType::Apu(ApuEvent::Sample), cycles_per_sample));
                 }
-                self.cycles_per_sample = 512 >> resolution;
                 info!("bias - setting sample frequency to {}hz", self.sample_rate);
-                // TODO this will not affect the currently scheduled sample event
             }
 
             _ => {
@@ -315,14 +417,33 @@ impl SoundController {
         }
     }
 
+    /// The DMG channels mixed for the `channel` stereo side, scaled by the SOUNDCNT_L master volume
+    /// and the SOUNDCNT_H ratio
+    fn dmg_sample(&self, channel: usize) -> i16 {
+        let (volume, wave_enabled) = match channel {
+            0 => (self.left_volume, self.left_wave),
+            1 => (self.right_volume, self.right_wave),
+            _ => unreachable!(),
+        };
+        let mut sample = 0;
+        if wave_enabled && self.channel_mask.contains(ChannelMask::WAVE) {
+            sample += self.wave.value as usize;
+        }
+        (sample as f32 * (volume + 1) as f32 * self.dmg_volume_ratio) as i16
+    }
+
     #[inline]
-    fn on_sample(&mut self, audio_device: &mut DynAudioInterface) -> FutureEvent {
+    fn on_sample(&mut self, timestamp: usize, audio_device: &mut DynAudioInterface) -> FutureEvent {
         let mut sample = [0f32, 0f32];
 
+        if self.mse {
+            self.wave.step(self.cycles_per_sample);
+        }
+
         for (channel, out_sample) in sample.iter_mut().enumerate() {
-            let mut dma_sample = 0;
-            for dma in &mut self.dma_sound {
-                if dma.is_stereo_channel_enabled(channel) {
+            let mut dma_sample = self.dmg_sample(channel);
+            for (dma, mask) in self.dma_sound.iter().zip(FIFO_CHANNEL_MASK.iter()) {
+                if dma.is_stereo_channel_enabled(channel) && self.channel_mask.contains(*mask) {
                     let value = dma.value as i16;
                     dma_sample += value * (2 << dma.volume_shift);
                 }
@@ -332,29 +453,73 @@ impl SoundController {
             *out_sample = dma_sample as i32 as f32;
         }
 
+        if self.dc_filter_enabled {
+            sample = self.dc_filter.filter(&sample);
+        }
         self.resampler.feed(&sample, &mut self.output_buffer);
 
-        self.output_buffer.drain(..).for_each(|[left, right]| {
-            audio_device.push_sample(&[
-                (left.round() as i16) * (std::i16::MAX / 512),
-                (right.round() as i16) * (std::i16::MAX / 512),
-            ]);
-        });
+        if self.device_buffer.is_empty() {
+            self.batch_timestamp = timestamp;
+        }
+        self.device_buffer
+            .extend(self.output_buffer.drain(..).map(|[left, right]| {
+                [
+                    (left.round() as i16) * (std::i16::MAX / 512),
+                    (right.round() as i16) * (std::i16::MAX / 512),
+                ]
+            }));
+        if self.device_buffer.len() >= SAMPLE_BATCH_SIZE {
+            self.flush_samples(audio_device);
+        }
         (EventType::Apu(ApuEvent::Sample), self.cycles_per_sample)
     }
 
+    /// Push the samples that didn't make up a whole batch yet to the audio device
+    pub fn flush_samples(&mut self, audio_device: &mut DynAudioInterface) {
+        if !self.device_buffer.is_empty() {
+            audio_device.push_timed_samples(self.batch_timestamp as u64, &self.device_buffer);
+            self.device_buffer.clear();
+        }
+    }
+
+    /// Push `samples` silent stereo samples to the audio device, to keep it fed while the emulation is paused
+    pub fn fill_silence(&mut self, samples: usize, audio_device: &mut DynAudioInterface) {
+        self.flush_samples(audio_device);
+        self.device_buffer.resize(samples, [0, 0]);
+        audio_device.push_samples(&self.device_buffer);
+        self.device_buffer.clear();
+    }
+
+    /// Push `duration_ms` of silence to the audio device, the fraction of a sample left over is pushed
+    /// by a later call
+    pub fn fill_silence_ms(&mut self, duration_ms: u32, audio_device: &mut DynAudioInterface) {
+        let millisamples =
+            audio_device.get_sample_rate() as usize * duration_ms as usize + self.silence_remainder;
+        self.silence_remainder = millisamples % 1000;
+        self.fill_silence(millisamples / 1000, audio_device);
+    }
+
     pub fn on_event(
         &mut self,
         event: ApuEvent,
+        event_time: usize,
         audio_device: &mut DynAudioInterface,
     ) -> FutureEvent {
         match event {
-            ApuEvent::Sample => self.on_sample(audio_device),
+            ApuEvent::Sample => self.on_sample(event_time, audio_device),
             _ => unimplemented!("got {:?} event", event),
         }
     }
 }
 
+fn dc_filter_default() -> DcFilter {
+    DcFilter::new(DC_FILTER_CUTOFF, 32768_f32)
+}
+
+fn dc_filter_enabled_default() -> bool {
+    true
+}
+
 #[inline(always)]
 fn apply_bias(sample: &mut i16, level: i16) {
     let mut s = *sample;
@@ -382,3 +547,272 @@ fn cbit(idx: u8, value: bool) -> u16 {
 fn bit(idx: u8) -> u16 {
     1 << idx
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records the samples pushed one by one, and through the batch method if `batched`
+    struct RecordingAudio {
+        batched: bool,
+        samples: Rc<RefCell<Vec<StereoSample<i16>>>>,
+        batches: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl AudioInterface for RecordingAudio {
+        fn push_sample(&mut self, sample: &StereoSample<i16>) {
+            self.samples.borrow_mut().push(*sample);
+        }
+
+        fn push_samples(&mut self, batch: &[StereoSample<i16>]) {
+            if self.batched {
+                self.batches.borrow_mut().push(batch.len());
+                self.samples.borrow_mut().extend_from_slice(batch);
+            } else {
+                for sample in batch {
+                    self.push_sample(sample);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_samples_batch() {
+        fn record(batched: bool) -> (Vec<StereoSample<i16>>, Vec<usize>) {
+            let samples = Rc::new(RefCell::new(Vec::new()));
+            let batches = Rc::new(RefCell::new(Vec::new()));
+            let mut audio_device: DynAudioInterface = Box::new(RecordingAudio {
+                batched,
+                samples: samples.clone(),
+                batches: batches.clone(),
+            });
+
+            let mut sched = Scheduler::new();
+            let mut sound = SoundController::new(&mut sched, 44100.0);
+            for i in 0..1000 {
+                sound.on_event(ApuEvent::Sample, i * 512, &mut audio_device);
+            }
+            sound.flush_samples(&mut audio_device);
+
+            let samples = samples.borrow().clone();
+            let batches = batches.borrow().clone();
+            (samples, batches)
+        }
+
+        let (single, _) = record(false);
+        let (batched, batches) = record(true);
+        assert!(single.len() > SAMPLE_BATCH_SIZE);
+        assert_eq!(single, batched);
+        // a batch is pushed once a sample event fills it, which adds at most 2 samples at 44100Hz,
+        // then the remainder is flushed
+        assert!(batches.len() > 1);
+        let (last, whole) = batches.split_last().unwrap();
+        assert!(whole
+            .iter()
+            .all(|len| (SAMPLE_BATCH_SIZE..SAMPLE_BATCH_SIZE + 2).contains(len)));
+        assert!(*last > 0 && *last < SAMPLE_BATCH_SIZE + 2);
+    }
+
+    #[test]
+    fn test_channel_mask() {
+        fn record(mask: ChannelMask) -> Vec<StereoSample<i16>> {
+            let samples = Rc::new(RefCell::new(Vec::new()));
+            let mut audio_device: DynAudioInterface = Box::new(RecordingAudio {
+                batched: true,
+                samples: samples.clone(),
+                batches: Rc::new(RefCell::new(Vec::new())),
+            });
+
+            let mut sched = Scheduler::new();
+            let mut sound = SoundController::new(&mut sched, 32768.0);
+            sound.set_channel_mask(mask);
+            // play a constant sample on the wave channel, on both sides at full volume
+            sound.handle_write(REG_SOUNDCNT_X, 0x80, &mut sched);
+            sound.handle_write(REG_SOUNDCNT_L, 0x4477, &mut sched);
+            sound.handle_write(REG_SOUNDCNT_H, 0x0002, &mut sched);
+            sound.handle_write(REG_SOUND3CNT_L, 0x00, &mut sched);
+            for i in 0..8 {
+                sound.handle_write(REG_WAVE_RAM + 2 * i, 0xffff, &mut sched);
+            }
+            sound.handle_write(REG_SOUND3CNT_L, 0xc0, &mut sched);
+            sound.handle_write(REG_SOUND3CNT_H, 0x2000, &mut sched);
+            sound.handle_write(REG_SOUND3CNT_X, 0x8000 | 2047, &mut sched);
+            for (dma, value) in sound.dma_sound.iter_mut().zip(&[64, -32]) {
+                dma.value = *value;
+                dma.enable_left = true;
+                dma.enable_right = true;
+            }
+            for i in 0..100 {
+                sound.on_event(ApuEvent::Sample, i * 512, &mut audio_device);
+            }
+            sound.flush_samples(&mut audio_device);
+
+            let samples = samples.borrow().clone();
+            samples
+        }
+
+        let all = record(ChannelMask::all());
+        assert!(all.iter().any(|sample| *sample != [0, 0]));
+
+        let none = record(ChannelMask::empty());
+        assert_eq!(none.len(), all.len());
+        assert!(none.iter().all(|sample| *sample == [0, 0]));
+
+        for mask in [ChannelMask::WAVE, ChannelMask::FIFO_A, ChannelMask::FIFO_B].iter() {
+            let solo = record(*mask);
+            assert!(solo.iter().any(|sample| *sample != [0, 0]));
+            assert_ne!(solo, all);
+        }
+    }
+
+    #[test]
+    fn test_soundbias_reschedules_sample_event() {
+        let mut sched = Scheduler::new();
+        let mut sound = SoundController::new(&mut sched, 44100.0);
+        assert_eq!(sched.num_pending_events(), 1);
+        assert_eq!(sched.get_cycles_to_next_event(), 512);
+
+        sched.update(100);
+
+        // resolution 1 - 65.536kHz
+        sound.handle_write(REG_SOUNDBIAS, 0x4200, &mut sched);
+        assert_eq!(sched.num_pending_events(), 1);
+        assert_eq!(sched.peek_next(), Some(EventType::Apu(ApuEvent::Sample)));
+        assert_eq!(sched.get_cycles_to_next_event(), 256);
+
+        // rewriting the same resolution should leave the pending event untouched
+        sched.update(6);
+        sound.handle_write(REG_SOUNDBIAS, 0x4200, &mut sched);
+        assert_eq!(sched.num_pending_events(), 1);
+        assert_eq!(sched.get_cycles_to_next_event(), 250);
+
+        // resolution 3 - 262.144kHz
+        sound.handle_write(REG_SOUNDBIAS, 0xc200, &mut sched);
+        assert_eq!(sched.num_pending_events(), 1);
+        assert_eq!(sched.get_cycles_to_next_event(), 64);
+    }
+
+    #[test]
+    fn test_wave_channel_64_samples() {
+        let mut sched = Scheduler::new();
+        let mut sound = SoundController::new(&mut sched, 44100.0);
+
+        // with bank 1 selected for playback the cpu writes bank 0, and vice versa
+        let bank0: Vec<u8> = (0..32).map(|i| i as u8 & 0xf).collect();
+        let bank1: Vec<u8> = (0..32).map(|i| 0xf - (i as u8 & 0xf)).collect();
+        for (bank_select, samples) in [(0x40, &bank0), (0x00, &bank1)].iter() {
+            sound.handle_write(REG_SOUND3CNT_L, *bank_select, &mut sched);
+            for (i, pair) in samples.chunks(4).enumerate() {
+                let value = (pair[0] as u16) << 4
+                    | pair[1] as u16
+                    | (pair[2] as u16) << 12
+                    | (pair[3] as u16) << 8;
+                let addr = REG_WAVE_RAM + 2 * i as u32;
+                sound.handle_write(addr, value, &mut sched);
+                assert_eq!(sound.handle_read(addr), value);
+            }
+        }
+
+        // play bank 0 followed by bank 1 at 100% volume
+        sound.handle_write(REG_SOUND3CNT_L, 0xa0, &mut sched);
+        sound.handle_write(REG_SOUND3CNT_H, 0x2000, &mut sched);
+        sound.handle_write(REG_SOUND3CNT_X, 0x8000 | 2047, &mut sched);
+        assert_eq!(sound.handle_read(REG_SOUND3CNT_L), 0xa0);
+
+        let mut played = vec![sound.wave.value];
+        for _ in 0..64 {
+            sound.wave.step(8);
+            played.push(sound.wave.value);
+        }
+        let expected: Vec<u8> = bank0.iter().chain(bank1.iter()).copied().collect();
+        assert_eq!(&played[..64], &expected[..]);
+        // wraps around to the start of the selected bank
+        assert_eq!(played[64], bank0[0]);
+
+        // the cpu now accesses bank 1, a single bank mode replays bank 0 only
+        assert_eq!(sound.handle_read(REG_WAVE_RAM), 0xdcfe);
+        sound.handle_write(REG_SOUND3CNT_L, 0x80, &mut sched);
+        sound.handle_write(REG_SOUND3CNT_X, 0x8000 | 2047, &mut sched);
+        sound.wave.step(8 * 32);
+        assert_eq!(sound.wave.value, bank0[0]);
+    }
+
+    #[test]
+    fn test_wave_channel_mixed() {
+        fn record(soundcnt_l: u16, soundcnt_h: u16) -> Vec<StereoSample<i16>> {
+            let samples = Rc::new(RefCell::new(Vec::new()));
+            let mut audio_device: DynAudioInterface = Box::new(RecordingAudio {
+                batched: true,
+                samples: samples.clone(),
+                batches: Rc::new(RefCell::new(Vec::new())),
+            });
+
+            let mut sched = Scheduler::new();
+            let mut sound = SoundController::new(&mut sched, 32768.0);
+            sound.set_dc_filter(false);
+            sound.handle_write(REG_SOUNDCNT_X, 0x80, &mut sched);
+            sound.handle_write(REG_SOUNDCNT_L, soundcnt_l, &mut sched);
+            sound.handle_write(REG_SOUNDCNT_H, soundcnt_h, &mut sched);
+            // bank 1 is played, fill it with a constant sample
+            sound.handle_write(REG_SOUND3CNT_L, 0x00, &mut sched);
+            for i in 0..8 {
+                sound.handle_write(REG_WAVE_RAM + 2 * i, 0xffff, &mut sched);
+            }
+            sound.handle_write(REG_SOUND3CNT_L, 0xc0, &mut sched);
+            sound.handle_write(REG_SOUND3CNT_H, 0x2000, &mut sched);
+            sound.handle_write(REG_SOUND3CNT_X, 0x8000 | 2047, &mut sched);
+            for i in 0..100 {
+                sound.on_event(ApuEvent::Sample, i * 512, &mut audio_device);
+            }
+            sound.flush_samples(&mut audio_device);
+
+            let samples = samples.borrow().clone();
+            samples
+        }
+
+        // full master volume on the left side only, 100% DMG ratio
+        let left = record(0x0407, 0x0002);
+        assert!(left.iter().any(|[l, _]| *l != 0));
+        assert!(left.iter().all(|[_, r]| *r == 0));
+
+        // a lower master volume and ratio scale the output down
+        let quiet = record(0x0400, 0x0000);
+        let max = |samples: &[StereoSample<i16>]| samples.iter().map(|[l, _]| *l).max().unwrap();
+        assert!(max(&quiet) > 0);
+        assert!(max(&quiet) < max(&left));
+
+        // disabled on both sides
+        let silent = record(0x0077, 0x0002);
+        assert!(silent.iter().all(|sample| *sample == [0, 0]));
+    }
+
+    #[test]
+    fn test_wave_channel_length() {
+        let mut sched = Scheduler::new();
+        let mut sound = SoundController::new(&mut sched, 44100.0);
+        // bank 1 is played, fill it with a constant sample
+        sound.handle_write(REG_SOUND3CNT_L, 0x00, &mut sched);
+        for i in 0..8 {
+            sound.handle_write(REG_WAVE_RAM + 2 * i, 0xffff, &mut sched);
+        }
+        sound.handle_write(REG_SOUND3CNT_L, 0xc0, &mut sched);
+
+        // a length of 192 lasts 64/256 seconds
+        sound.handle_write(REG_SOUND3CNT_H, 0x2000 | 192, &mut sched);
+        sound.handle_write(REG_SOUND3CNT_X, 0xc000 | 2047, &mut sched);
+        let length = 64 * 65536;
+        sound.wave.step(length - 8);
+        assert_eq!(sound.wave.value, 0xf);
+        sound.wave.step(8);
+        assert_eq!(sound.wave.value, 0);
+        sound.wave.step(1000);
+        assert_eq!(sound.wave.value, 0);
+
+        // without the length flag the channel plays on
+        sound.handle_write(REG_SOUND3CNT_X, 0x8000 | 2047, &mut sched);
+        sound.wave.step(2 * length);
+        assert_eq!(sound.wave.value, 0xf);
+    }
+}
@@ -0,0 +1,158 @@
+use bit::BitIndex;
+use serde::{Deserialize, Serialize};
+
+use super::cbit;
+
+/// Samples in a bank of the wave RAM, each byte holds two 4-bit samples
+const BANK_SAMPLES: usize = 32;
+
+/// Volume shift for each of the SOUND3CNT_H volume settings: mute, 100%, 50% and 25%
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+/// The sound length is counted in units of 1/256 seconds
+const LENGTH_UNIT_CYCLES: usize = 16_777_216 / 256;
+
+/// DMG channel 3, plays back the 4-bit samples of the wave RAM.
+/// The wave RAM has two banks, the cpu can only access the bank that isn't selected for playback.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct WaveChannel {
+    /// Play both banks as a single 64-sample waveform, starting with the selected bank
+    double_size: bool,
+    /// The bank selected for playback
+    bank: usize,
+    enabled: bool,
+    length: u8,
+    /// Cycles until the sound length expires, a timed channel then stops playing
+    length_countdown: usize,
+    volume: u8,
+    force_volume: bool,
+    rate: u16,
+    timed: bool,
+    playing: bool,
+    ram: [[u8; 16]; 2],
+    /// The sample being played, counted from the start of the selected bank
+    position: usize,
+    /// Cycles until the next sample is played
+    countdown: usize,
+    /// The current sample with the volume applied
+    pub value: u8,
+}
+
+impl WaveChannel {
+    pub fn read_cnt_l(&self) -> u16 {
+        cbit(5, self.double_size) | (self.bank as u16) << 6 | cbit(7, self.enabled)
+    }
+
+    pub fn write_cnt_l(&mut self, value: u16) {
+        self.double_size = value.bit(5);
+        self.bank = value.bit(6) as usize;
+        self.enabled = value.bit(7);
+        if !self.enabled {
+            self.playing = false;
+        }
+    }
+
+    /// The sound length is write-only
+    pub fn read_cnt_h(&self) -> u16 {
+        (self.volume as u16) << 13 | cbit(15, self.force_volume)
+    }
+
+    pub fn write_cnt_h(&mut self, value: u16) {
+        self.length = value.bit_range(0..8) as u8;
+        self.length_countdown = self.length_cycles();
+        self.volume = value.bit_range(13..15) as u8;
+        self.force_volume = value.bit(15);
+    }
+
+    /// The sample rate is write-only
+    pub fn read_cnt_x(&self) -> u16 {
+        cbit(14, self.timed)
+    }
+
+    pub fn write_cnt_x(&mut self, value: u16) {
+        self.rate = value.bit_range(0..11);
+        self.timed = value.bit(14);
+        if value.bit(15) {
+            self.length_countdown = self.length_cycles();
+            self.position = 0;
+            self.countdown = self.period();
+            self.playing = self.enabled;
+            self.value = self.output();
+        }
+    }
+
+    /// Access the bank that isn't selected for playback, `offset` is relative to REG_WAVE_RAM
+    pub fn read_ram(&self, offset: u32) -> u16 {
+        let bank = &self.ram[self.bank ^ 1];
+        let index = (offset & 0xe) as usize;
+        u16::from_le_bytes([bank[index], bank[index + 1]])
+    }
+
+    pub fn write_ram(&mut self, offset: u32, value: u16) {
+        let bank = &mut self.ram[self.bank ^ 1];
+        let index = (offset & 0xe) as usize;
+        bank[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Cycles each sample is played for, the channel runs at 2097152 / (2048 - rate) samples per second
+    fn period(&self) -> usize {
+        (2048 - self.rate as usize) * 8
+    }
+
+    /// The sound length lasts (256 - length) / 256 seconds
+    fn length_cycles(&self) -> usize {
+        (256 - self.length as usize) * LENGTH_UNIT_CYCLES
+    }
+
+    fn num_samples(&self) -> usize {
+        if self.double_size {
+            2 * BANK_SAMPLES
+        } else {
+            BANK_SAMPLES
+        }
+    }
+
+    /// The 4-bit sample at the current position, the high nibble of each byte is played first
+    fn sample(&self) -> u8 {
+        let bank = &self.ram[(self.bank + self.position / BANK_SAMPLES) % 2];
+        let index = self.position % BANK_SAMPLES;
+        let byte = bank[index / 2];
+        if index % 2 == 0 {
+            byte >> 4
+        } else {
+            byte & 0xf
+        }
+    }
+
+    fn output(&self) -> u8 {
+        let sample = self.sample();
+        if self.force_volume {
+            sample * 3 / 4
+        } else {
+            sample >> VOLUME_SHIFTS[self.volume as usize]
+        }
+    }
+
+    /// Advance the playback by `cycles`
+    pub fn step(&mut self, mut cycles: usize) {
+        if !self.playing {
+            return;
+        }
+        if self.timed {
+            if cycles >= self.length_countdown {
+                self.length_countdown = 0;
+                self.playing = false;
+                self.value = 0;
+                return;
+            }
+            self.length_countdown -= cycles;
+        }
+        while cycles >= self.countdown {
+            cycles -= self.countdown;
+            self.countdown = self.period();
+            self.position = (self.position + 1) % self.num_samples();
+        }
+        self.countdown -= cycles;
+        self.value = self.output();
+    }
+}
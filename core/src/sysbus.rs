@@ -1,12 +1,15 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use serde::{Deserialize, Serialize};
 
 use super::arm7tdmi::memory::{
-    Addr, BusIO, DebugRead, MemoryAccess, MemoryAccessWidth, MemoryInterface,
+    Addr, BusIO, DebugRead, DebugWrite, MemoryAccess, MemoryAccessWidth, MemoryInterface,
 };
 use super::bios::Bios;
 use super::cartridge::Cartridge;
 use super::dma::DmaNotifer;
-use super::iodev::{IoDevices, WaitControl};
+use super::iodev::{InternalMemoryControl, IoDevices, WaitControl};
 use super::sched::*;
 use arm7tdmi::{self, Arm7tdmiCore};
 use rustboyadvance_utils::{Shared, WeakPointer};
@@ -72,11 +75,6 @@ impl Default for CycleLookupTables {
 
 impl CycleLookupTables {
     pub fn init(&mut self) {
-        self.n_cycles32[PAGE_EWRAM] = 6;
-        self.s_cycles32[PAGE_EWRAM] = 6;
-        self.n_cycles16[PAGE_EWRAM] = 3;
-        self.s_cycles16[PAGE_EWRAM] = 3;
-
         self.n_cycles32[PAGE_OAM] = 2;
         self.s_cycles32[PAGE_OAM] = 2;
         self.n_cycles16[PAGE_OAM] = 1;
@@ -93,53 +91,148 @@ impl CycleLookupTables {
         self.s_cycles16[PAGE_PALRAM] = 1;
     }
 
-    pub fn update_gamepak_waitstates(&mut self, waitcnt: WaitControl) {
+    pub fn update_ewram_waitstates(&mut self, memcnt: InternalMemoryControl) {
+        // EWRAM has a 16bit bus, while a disabled EWRAM mirrors IWRAM
+        let (cycles16, cycles32) = if memcnt.ewram_enabled() {
+            let cycles = 1 + memcnt.ewram_waitstates();
+            (cycles, 2 * cycles)
+        } else {
+            (1, 1)
+        };
+        self.n_cycles32[PAGE_EWRAM] = cycles32;
+        self.s_cycles32[PAGE_EWRAM] = cycles32;
+        self.n_cycles16[PAGE_EWRAM] = cycles16;
+        self.s_cycles16[PAGE_EWRAM] = cycles16;
+    }
+
+    pub fn update_gamepak_waitstates(&mut self, profile: &WaitstateProfile) {
+        // update SRAM access
+        let sram_wait_cycles = 1 + profile.sram;
+        for page in [PAGE_SRAM_LO, PAGE_SRAM_HI] {
+            self.n_cycles32[page] = sram_wait_cycles;
+            self.n_cycles16[page] = sram_wait_cycles;
+            self.s_cycles32[page] = sram_wait_cycles;
+            self.s_cycles16[page] = sram_wait_cycles;
+        }
+
+        // update both pages of each waitstate
+        let ws_pages = [PAGE_GAMEPAK_WS0, PAGE_GAMEPAK_WS1, PAGE_GAMEPAK_WS2];
+        for (ws_page, (first_access, second_access)) in ws_pages.iter().zip(profile.gamepak.iter())
+        {
+            for i in 0..2 {
+                let page = ws_page + i;
+                self.n_cycles16[page] = 1 + first_access;
+                self.s_cycles16[page] = 1 + second_access;
+
+                // ROM 32bit accesses are split into two 16bit accesses 1N+1S
+                self.n_cycles32[page] = self.n_cycles16[page] + self.s_cycles16[page];
+                self.s_cycles32[page] = 2 * self.s_cycles16[page];
+            }
+        }
+    }
+}
+
+/// Wait cycles of the gamepak regions, normally derived from WAITCNT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitstateProfile {
+    /// Wait cycles of the first (non-sequential) and second (sequential) 16bit access to WS0, WS1 and WS2
+    pub gamepak: [(usize, usize); 3],
+    /// Wait cycles of SRAM accesses
+    pub sram: usize,
+}
+
+impl WaitstateProfile {
+    /// Ignore the gamepak waitstates entirely, for maximum throughput
+    pub const NO_WAIT: WaitstateProfile = WaitstateProfile {
+        gamepak: [(0, 0); 3],
+        sram: 0,
+    };
+}
+
+impl From<WaitControl> for WaitstateProfile {
+    fn from(waitcnt: WaitControl) -> WaitstateProfile {
         static S_GAMEPAK_NSEQ_CYCLES: [usize; 4] = [4, 3, 2, 8];
         static S_GAMEPAK_WS0_SEQ_CYCLES: [usize; 2] = [2, 1];
         static S_GAMEPAK_WS1_SEQ_CYCLES: [usize; 2] = [4, 1];
         static S_GAMEPAK_WS2_SEQ_CYCLES: [usize; 2] = [8, 1];
 
-        let ws0_first_access = waitcnt.ws0_first_access() as usize;
-        let ws1_first_access = waitcnt.ws1_first_access() as usize;
-        let ws2_first_access = waitcnt.ws2_first_access() as usize;
-        let ws0_second_access = waitcnt.ws0_second_access() as usize;
-        let ws1_second_access = waitcnt.ws1_second_access() as usize;
-        let ws2_second_access = waitcnt.ws2_second_access() as usize;
+        WaitstateProfile {
+            gamepak: [
+                (
+                    S_GAMEPAK_NSEQ_CYCLES[waitcnt.ws0_first_access() as usize],
+                    S_GAMEPAK_WS0_SEQ_CYCLES[waitcnt.ws0_second_access() as usize],
+                ),
+                (
+                    S_GAMEPAK_NSEQ_CYCLES[waitcnt.ws1_first_access() as usize],
+                    S_GAMEPAK_WS1_SEQ_CYCLES[waitcnt.ws1_second_access() as usize],
+                ),
+                (
+                    S_GAMEPAK_NSEQ_CYCLES[waitcnt.ws2_first_access() as usize],
+                    S_GAMEPAK_WS2_SEQ_CYCLES[waitcnt.ws2_second_access() as usize],
+                ),
+            ],
+            sram: S_GAMEPAK_NSEQ_CYCLES[waitcnt.sram_wait_control() as usize],
+        }
+    }
+}
 
-        // update SRAM access
-        let sram_wait_cycles = 1 + S_GAMEPAK_NSEQ_CYCLES[waitcnt.sram_wait_control() as usize];
-        self.n_cycles32[PAGE_SRAM_LO] = sram_wait_cycles;
-        self.n_cycles32[PAGE_SRAM_LO] = sram_wait_cycles;
-        self.n_cycles16[PAGE_SRAM_HI] = sram_wait_cycles;
-        self.n_cycles16[PAGE_SRAM_HI] = sram_wait_cycles;
-        self.s_cycles32[PAGE_SRAM_LO] = sram_wait_cycles;
-        self.s_cycles32[PAGE_SRAM_LO] = sram_wait_cycles;
-        self.s_cycles16[PAGE_SRAM_HI] = sram_wait_cycles;
-        self.s_cycles16[PAGE_SRAM_HI] = sram_wait_cycles;
+/// The kind of memory access a watchpoint triggers on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
 
-        // update both pages of each waitstate
-        for i in 0..2 {
-            self.n_cycles16[PAGE_GAMEPAK_WS0 + i] = 1 + S_GAMEPAK_NSEQ_CYCLES[ws0_first_access];
-            self.s_cycles16[PAGE_GAMEPAK_WS0 + i] = 1 + S_GAMEPAK_WS0_SEQ_CYCLES[ws0_second_access];
-
-            self.n_cycles16[PAGE_GAMEPAK_WS1 + i] = 1 + S_GAMEPAK_NSEQ_CYCLES[ws1_first_access];
-            self.s_cycles16[PAGE_GAMEPAK_WS1 + i] = 1 + S_GAMEPAK_WS1_SEQ_CYCLES[ws1_second_access];
-
-            self.n_cycles16[PAGE_GAMEPAK_WS2 + i] = 1 + S_GAMEPAK_NSEQ_CYCLES[ws2_first_access];
-            self.s_cycles16[PAGE_GAMEPAK_WS2 + i] = 1 + S_GAMEPAK_WS2_SEQ_CYCLES[ws2_second_access];
-
-            // ROM 32bit accesses are split into two 16bit accesses 1N+1S
-            self.n_cycles32[PAGE_GAMEPAK_WS0 + i] =
-                self.n_cycles16[PAGE_GAMEPAK_WS0 + i] + self.s_cycles16[PAGE_GAMEPAK_WS0 + i];
-            self.n_cycles32[PAGE_GAMEPAK_WS1 + i] =
-                self.n_cycles16[PAGE_GAMEPAK_WS1 + i] + self.s_cycles16[PAGE_GAMEPAK_WS1 + i];
-            self.n_cycles32[PAGE_GAMEPAK_WS2 + i] =
-                self.n_cycles16[PAGE_GAMEPAK_WS2 + i] + self.s_cycles16[PAGE_GAMEPAK_WS2 + i];
-
-            self.s_cycles32[PAGE_GAMEPAK_WS0 + i] = 2 * self.s_cycles16[PAGE_GAMEPAK_WS0 + i];
-            self.s_cycles32[PAGE_GAMEPAK_WS1 + i] = 2 * self.s_cycles16[PAGE_GAMEPAK_WS1 + i];
-            self.s_cycles32[PAGE_GAMEPAK_WS2 + i] = 2 * self.s_cycles16[PAGE_GAMEPAK_WS2 + i];
-        }
+impl WatchKind {
+    #[inline]
+    fn triggers_on(&self, access: WatchKind) -> bool {
+        *self == WatchKind::ReadWrite || *self == access
+    }
+}
+
+/// A watchpoint hit recorded by the bus, to be picked up by the run loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub addr: Addr,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// Size of an entry in the MP2K song table: the song header pointer, and the music player numbers
+pub const SONG_TABLE_ENTRY_SIZE: u32 = 8;
+
+/// Reports the song index whenever code reads a song header pointer from the MP2K song table
+#[derive(Clone)]
+pub(crate) struct SongTableHook {
+    pub table: Addr,
+    pub num_songs: u32,
+    pub callback: Rc<RefCell<dyn FnMut(usize)>>,
+}
+
+/// Read and write counters of the bus, indexed by memory page (`addr >> 24`)
+#[cfg(feature = "profiler")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryProfile {
+    pub reads: [u64; 16],
+    pub writes: [u64; 16],
+}
+
+#[cfg(feature = "profiler")]
+impl MemoryProfile {
+    #[inline]
+    fn page(addr: Addr) -> usize {
+        ((addr >> 24) & 0xF) as usize
+    }
+
+    /// Number of reads from the region containing `addr`
+    pub fn reads_at(&self, addr: Addr) -> u64 {
+        self.reads[MemoryProfile::page(addr)]
+    }
+
+    /// Number of writes to the region containing `addr`
+    pub fn writes_at(&self, addr: Addr) -> u64 {
+        self.writes[MemoryProfile::page(addr)]
     }
 }
 
@@ -155,8 +248,20 @@ pub struct SysBus {
     pub cartridge: Cartridge,
 
     cycle_luts: CycleLookupTables,
+    ewram_enabled: bool,
 
     pub trace_access: bool,
+
+    watchpoints: Vec<(Addr, WatchKind)>,
+    pub(crate) watchpoint_hit: Option<WatchpointHit>,
+
+    waitstate_override: Option<WaitstateProfile>,
+    abort_on_unmapped: bool,
+
+    pub(crate) song_table_hook: Option<SongTableHook>,
+
+    #[cfg(feature = "profiler")]
+    pub(crate) profile: MemoryProfile,
 }
 
 pub type SysBusPtr = WeakPointer<SysBus>;
@@ -179,7 +284,9 @@ impl SysBus {
     ) -> SysBus {
         let mut luts = CycleLookupTables::default();
         luts.init();
-        luts.update_gamepak_waitstates(io.waitcnt);
+        luts.update_gamepak_waitstates(&io.waitcnt.into());
+        luts.update_ewram_waitstates(io.memcnt);
+        let ewram_enabled = io.memcnt.ewram_enabled();
 
         SysBus {
             io,
@@ -191,7 +298,15 @@ impl SysBus {
             ewram,
             iwram,
             cycle_luts: luts,
+            ewram_enabled,
             trace_access: false,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            waitstate_override: None,
+            abort_on_unmapped: false,
+            song_table_hook: None,
+            #[cfg(feature = "profiler")]
+            profile: MemoryProfile::default(),
         }
     }
 
@@ -224,6 +339,7 @@ impl SysBus {
 
     pub fn set_io_devices(&mut self, io_devs: Shared<IoDevices>) {
         self.io = io_devs;
+        self.update_waitstates();
     }
 
     /// must be called whenever this object is instanciated
@@ -235,9 +351,90 @@ impl SysBus {
         self.io.set_sysbus_ptr(ptr);
     }
 
+    /// Clears the work rams and resets the waitstates, the cartridge is kept as-is
+    pub fn reset(&mut self) {
+        self.ewram.fill(0);
+        self.iwram.fill(0);
+        self.update_waitstates();
+    }
+
+    /// Override the WAITCNT derived gamepak waitstates, `None` restores the hardware-accurate behavior
+    pub fn set_waitstate_override(&mut self, profile: Option<WaitstateProfile>) {
+        self.waitstate_override = profile;
+        self.update_waitstates();
+    }
+
+    /// Make accesses to unused memory abort instead of reading open bus.
+    /// The real hardware never aborts, this is meant for catching runaway code when fuzzing.
+    pub fn set_abort_on_unmapped(&mut self, enabled: bool) {
+        self.abort_on_unmapped = enabled;
+    }
+
+    fn update_waitstates(&mut self) {
+        let profile = self
+            .waitstate_override
+            .unwrap_or_else(|| self.io.waitcnt.into());
+        self.cycle_luts.update_gamepak_waitstates(&profile);
+        self.on_memcnt_written(self.io.memcnt);
+    }
+
+    pub fn add_watchpoint(&mut self, addr: Addr, kind: WatchKind) {
+        self.remove_watchpoint(addr);
+        self.watchpoints.push((addr, kind));
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: Addr) {
+        self.watchpoints
+            .retain(|(watch_addr, _)| *watch_addr != addr);
+    }
+
+    /// Find a watchpoint covered by an access of `size` bytes to `addr`
+    fn find_watchpoint(&self, addr: Addr, size: u32, access: WatchKind) -> Option<Addr> {
+        let base = addr & !(size - 1);
+        self.watchpoints
+            .iter()
+            .find(|(watch_addr, kind)| {
+                *watch_addr & !(size - 1) == base && kind.triggers_on(access)
+            })
+            .map(|(watch_addr, _)| *watch_addr)
+    }
+
+    #[cold]
+    fn on_watched_access(&mut self, addr: Addr, size: u32, access: WatchKind, old: u32, new: u32) {
+        if self.watchpoint_hit.is_some() {
+            return;
+        }
+        if let Some(watch_addr) = self.find_watchpoint(addr, size, access) {
+            self.watchpoint_hit = Some(WatchpointHit {
+                addr: watch_addr,
+                old,
+                new,
+            });
+        }
+    }
+
+    #[cold]
+    fn on_song_table_read(&self, addr: Addr) {
+        if let Some(hook) = &self.song_table_hook {
+            let offset = addr.wrapping_sub(hook.table);
+            if offset < hook.num_songs * SONG_TABLE_ENTRY_SIZE
+                && offset % SONG_TABLE_ENTRY_SIZE == 0
+            {
+                (&mut *hook.callback.borrow_mut())((offset / SONG_TABLE_ENTRY_SIZE) as usize);
+            }
+        }
+    }
+
     pub fn on_waitcnt_written(&mut self, waitcnt: WaitControl) {
-        self.cycle_luts.update_gamepak_waitstates(waitcnt);
+        if self.waitstate_override.is_none() {
+            self.cycle_luts.update_gamepak_waitstates(&waitcnt.into());
+        }
+    }
+    pub fn on_memcnt_written(&mut self, memcnt: InternalMemoryControl) {
+        self.cycle_luts.update_ewram_waitstates(memcnt);
+        self.ewram_enabled = memcnt.ewram_enabled();
     }
+
     pub fn idle_cycle(&mut self) {
         self.scheduler.update(1);
     }
@@ -264,12 +461,50 @@ impl SysBus {
         self.scheduler.update(*cycles);
     }
 
+    #[inline(always)]
+    #[allow(unused_variables)]
+    fn profile_access(&mut self, addr: Addr, kind: WatchKind) {
+        #[cfg(feature = "profiler")]
+        {
+            let page = MemoryProfile::page(addr);
+            match kind {
+                WatchKind::Write => self.profile.writes[page] += 1,
+                _ => self.profile.reads[page] += 1,
+            }
+        }
+    }
+
+    /// The work ram mapped at `addr` and the offset of `addr` into it, for bulk copies
+    fn work_ram_slice(&mut self, addr: Addr) -> Option<(&mut [u8], usize)> {
+        match addr & 0xff000000 {
+            EWRAM_ADDR if self.ewram_enabled => {
+                Some((&mut self.ewram[..], (addr & 0x3_ffff) as usize))
+            }
+            EWRAM_ADDR | IWRAM_ADDR => Some((&mut self.iwram[..], (addr & 0x7fff) as usize)),
+            _ => None,
+        }
+    }
+
+    /// Unused Memory (00004000-01FFFFFF,10000000-FFFFFFFF)
+    fn is_unused_memory(addr: Addr) -> bool {
+        match addr & 0xff000000 {
+            BIOS_ADDR => addr > 0x3fff,
+            EWRAM_ADDR..=SRAM_HI => false,
+            _ => true,
+        }
+    }
+
     /// Helper for "open-bus" accesses
     /// http://problemkaputt.de/gbatek.htm#gbaunpredictablethings
     /// Reading from Unused Memory (00004000-01FFFFFF,10000000-FFFFFFFF)
     /// `addr` is considered to be an address of
     fn read_invalid(&mut self, addr: Addr) -> u32 {
         warn!("invalid read @{:08x}", addr);
+        self.open_bus(addr)
+    }
+
+    /// The value left on the data bus by the last opcode fetch, shifted for the alignment of `addr`
+    pub(crate) fn open_bus(&self, addr: Addr) -> u32 {
         let value = match self.arm_core.cpsr.state() {
             arm7tdmi::CpuState::ARM => self.arm_core.get_prefetched_opcode(),
             arm7tdmi::CpuState::THUMB => {
@@ -306,6 +541,16 @@ impl SysBus {
     }
 }
 
+/// Offset of `addr` in the IO region, the internal memory control register is mirrored every 64K
+#[inline]
+fn io_offset(addr: Addr) -> Addr {
+    if addr & 0xfffc == 0x0800 {
+        0x800 | (addr & 3)
+    } else {
+        addr & 0x00ff_ffff
+    }
+}
+
 /// Todo - implement bound checks for EWRAM/IWRAM
 impl BusIO for SysBus {
     #[inline]
@@ -318,16 +563,9 @@ impl BusIO for SysBus {
                     self.read_invalid(addr)
                 }
             }
-            EWRAM_ADDR => self.ewram.read_32(addr & 0x3_fffc),
-            IWRAM_ADDR => self.iwram.read_32(addr & 0x7ffc),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xfffc == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00fffffc
-                };
-                self.io.read_32(addr)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.read_32(addr & 0x3_fffc),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.read_32(addr & 0x7ffc),
+            IOMEM_ADDR => self.io.read_32(io_offset(addr & !3)),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.read_32(addr),
             GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO => {
                 self.cartridge.read_32(addr)
@@ -348,16 +586,9 @@ impl BusIO for SysBus {
                     self.read_invalid(addr) as u16
                 }
             }
-            EWRAM_ADDR => self.ewram.read_16(addr & 0x3_fffe),
-            IWRAM_ADDR => self.iwram.read_16(addr & 0x7ffe),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xfffe == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00fffffe
-                };
-                self.io.read_16(addr)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.read_16(addr & 0x3_fffe),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.read_16(addr & 0x7ffe),
+            IOMEM_ADDR => self.io.read_16(io_offset(addr & !1)),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.read_16(addr),
             GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO => {
                 self.cartridge.read_16(addr)
@@ -378,16 +609,9 @@ impl BusIO for SysBus {
                     self.read_invalid(addr) as u8
                 }
             }
-            EWRAM_ADDR => self.ewram.read_8(addr & 0x3_ffff),
-            IWRAM_ADDR => self.iwram.read_8(addr & 0x7fff),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xffff == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00ffffff
-                };
-                self.io.read_8(addr)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.read_8(addr & 0x3_ffff),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.read_8(addr & 0x7fff),
+            IOMEM_ADDR => self.io.read_8(io_offset(addr)),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.read_8(addr),
             GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO => {
                 self.cartridge.read_8(addr)
@@ -402,16 +626,9 @@ impl BusIO for SysBus {
     fn write_32(&mut self, addr: Addr, value: u32) {
         match addr & 0xff000000 {
             BIOS_ADDR => {}
-            EWRAM_ADDR => self.ewram.write_32(addr & 0x3_fffc, value),
-            IWRAM_ADDR => self.iwram.write_32(addr & 0x7ffc, value),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xfffc == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00fffffc
-                };
-                self.io.write_32(addr, value)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.write_32(addr & 0x3_fffc, value),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.write_32(addr & 0x7ffc, value),
+            IOMEM_ADDR => self.io.write_32(io_offset(addr & !3), value),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.write_32(addr, value),
             GAMEPAK_WS0_LO => self.cartridge.write_32(addr, value),
             GAMEPAK_WS2_HI => self.cartridge.write_32(addr, value),
@@ -427,16 +644,9 @@ impl BusIO for SysBus {
     fn write_16(&mut self, addr: Addr, value: u16) {
         match addr & 0xff000000 {
             BIOS_ADDR => {}
-            EWRAM_ADDR => self.ewram.write_16(addr & 0x3_fffe, value),
-            IWRAM_ADDR => self.iwram.write_16(addr & 0x7ffe, value),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xfffe == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00fffffe
-                };
-                self.io.write_16(addr, value)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.write_16(addr & 0x3_fffe, value),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.write_16(addr & 0x7ffe, value),
+            IOMEM_ADDR => self.io.write_16(io_offset(addr & !1), value),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.write_16(addr, value),
             GAMEPAK_WS0_LO => self.cartridge.write_16(addr, value),
             GAMEPAK_WS2_HI => self.cartridge.write_16(addr, value),
@@ -452,16 +662,9 @@ impl BusIO for SysBus {
     fn write_8(&mut self, addr: Addr, value: u8) {
         match addr & 0xff000000 {
             BIOS_ADDR => {}
-            EWRAM_ADDR => self.ewram.write_8(addr & 0x3_ffff, value),
-            IWRAM_ADDR => self.iwram.write_8(addr & 0x7fff, value),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xffff == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00ffffff
-                };
-                self.io.write_8(addr, value)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.write_8(addr & 0x3_ffff, value),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.write_8(addr & 0x7fff, value),
+            IOMEM_ADDR => self.io.write_8(io_offset(addr), value),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.write_8(addr, value),
             GAMEPAK_WS0_LO => self.cartridge.write_8(addr, value),
             GAMEPAK_WS2_HI => self.cartridge.write_8(addr, value),
@@ -478,16 +681,9 @@ impl DebugRead for SysBus {
     fn debug_read_8(&mut self, addr: Addr) -> u8 {
         match addr & 0xff000000 {
             BIOS_ADDR => self.bios.debug_read_8(addr),
-            EWRAM_ADDR => self.ewram.debug_read_8(addr & 0x3_ffff),
-            IWRAM_ADDR => self.iwram.debug_read_8(addr & 0x7fff),
-            IOMEM_ADDR => {
-                let addr = if addr & 0xffff == 0x8000 {
-                    0x800
-                } else {
-                    addr & 0x00ffffff
-                };
-                self.io.debug_read_8(addr)
-            }
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.debug_read_8(addr & 0x3_ffff),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.debug_read_8(addr & 0x7fff),
+            IOMEM_ADDR => self.io.debug_read_8(io_offset(addr)),
             PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.debug_read_8(addr),
             GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO => {
                 self.cartridge.debug_read_8(addr)
@@ -500,53 +696,160 @@ impl DebugRead for SysBus {
             }
         }
     }
+
+    fn debug_get_into_bytes(&mut self, start_addr: Addr, bytes: &mut [u8]) {
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let addr = start_addr.wrapping_add(offset as Addr);
+            match self.work_ram_slice(addr) {
+                Some((memory, start)) => {
+                    // copy up to the end of the mirror
+                    let count = (memory.len() - start).min(bytes.len() - offset);
+                    bytes[offset..offset + count].copy_from_slice(&memory[start..start + count]);
+                    offset += count;
+                }
+                None => {
+                    bytes[offset] = self.debug_read_8(addr);
+                    offset += 1;
+                }
+            }
+        }
+    }
+}
+
+impl DebugWrite for SysBus {
+    fn debug_write_8(&mut self, addr: Addr, value: u8) {
+        match addr & 0xff000000 {
+            BIOS_ADDR => {}
+            EWRAM_ADDR if self.ewram_enabled => self.ewram.debug_write_8(addr & 0x3_ffff, value),
+            EWRAM_ADDR | IWRAM_ADDR => self.iwram.debug_write_8(addr & 0x7fff, value),
+            // IO registers can't be written without side effects
+            IOMEM_ADDR => self.write_8(addr, value),
+            PALRAM_ADDR | VRAM_ADDR | OAM_ADDR => self.io.gpu.debug_write_8(addr, value),
+            GAMEPAK_WS0_LO | GAMEPAK_WS0_HI | GAMEPAK_WS1_LO | GAMEPAK_WS1_HI | GAMEPAK_WS2_LO
+            | GAMEPAK_WS2_HI | SRAM_LO | SRAM_HI => self.cartridge.debug_write_8(addr, value),
+            _ => {}
+        }
+    }
+
+    fn debug_write_bytes(&mut self, start_addr: Addr, bytes: &[u8]) {
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let addr = start_addr.wrapping_add(offset as Addr);
+            match self.work_ram_slice(addr) {
+                Some((memory, start)) => {
+                    let count = (memory.len() - start).min(bytes.len() - offset);
+                    memory[start..start + count].copy_from_slice(&bytes[offset..offset + count]);
+                    offset += count;
+                }
+                None => {
+                    self.debug_write_8(addr, bytes[offset]);
+                    offset += 1;
+                }
+            }
+        }
+    }
 }
 
 impl MemoryInterface for SysBus {
     #[inline]
     fn load_8(&mut self, addr: u32, access: MemoryAccess) -> u8 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
-        self.read_8(addr)
+        self.profile_access(addr, WatchKind::Read);
+        let value = self.read_8(addr);
+        if !self.watchpoints.is_empty() {
+            self.on_watched_access(addr, 1, WatchKind::Read, value as u32, value as u32);
+        }
+        if self.song_table_hook.is_some() {
+            self.on_song_table_read(addr);
+        }
+        value
     }
 
     #[inline]
     fn load_16(&mut self, addr: u32, access: MemoryAccess) -> u16 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess16);
-        self.read_16(addr)
+        self.profile_access(addr, WatchKind::Read);
+        let value = self.read_16(addr);
+        if !self.watchpoints.is_empty() {
+            self.on_watched_access(addr, 2, WatchKind::Read, value as u32, value as u32);
+        }
+        if self.song_table_hook.is_some() {
+            self.on_song_table_read(addr);
+        }
+        value
     }
 
     #[inline]
     fn load_32(&mut self, addr: u32, access: MemoryAccess) -> u32 {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess32);
-        self.read_32(addr)
+        self.profile_access(addr, WatchKind::Read);
+        let value = self.read_32(addr);
+        if !self.watchpoints.is_empty() {
+            self.on_watched_access(addr, 4, WatchKind::Read, value, value);
+        }
+        if self.song_table_hook.is_some() {
+            self.on_song_table_read(addr);
+        }
+        value
     }
 
     #[inline]
     fn store_8(&mut self, addr: u32, value: u8, access: MemoryAccess) {
         self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
-        self.write_8(addr, value);
+        self.profile_access(addr, WatchKind::Write);
+        if self.watchpoints.is_empty() {
+            self.write_8(addr, value);
+        } else {
+            let old = self.debug_read_8(addr);
+            self.write_8(addr, value);
+            self.on_watched_access(addr, 1, WatchKind::Write, old as u32, value as u32);
+        }
     }
 
     #[inline]
     fn store_16(&mut self, addr: u32, value: u16, access: MemoryAccess) {
-        self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
-        self.write_16(addr, value);
+        self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess16);
+        self.profile_access(addr, WatchKind::Write);
+        if self.watchpoints.is_empty() {
+            self.write_16(addr, value);
+        } else {
+            let old = self.debug_read_16(addr & !1);
+            self.write_16(addr, value);
+            self.on_watched_access(addr, 2, WatchKind::Write, old as u32, value as u32);
+        }
     }
 
     #[inline]
     fn store_32(&mut self, addr: u32, value: u32, access: MemoryAccess) {
-        self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess8);
-        self.write_32(addr, value);
+        self.add_cycles(addr, access, MemoryAccessWidth::MemoryAccess32);
+        self.profile_access(addr, WatchKind::Write);
+        if self.watchpoints.is_empty() {
+            self.write_32(addr, value);
+        } else {
+            let old = self.debug_read_32(addr & !3);
+            self.write_32(addr, value);
+            self.on_watched_access(addr, 4, WatchKind::Write, old, value);
+        }
     }
 
     #[inline]
     fn idle_cycle(&mut self) {
         self.scheduler.update(1)
     }
+
+    #[inline]
+    fn is_abort(&self, addr: Addr) -> bool {
+        self.abort_on_unmapped && SysBus::is_unused_memory(addr)
+    }
 }
 
 impl DmaNotifer for SysBus {
     fn notify(&mut self, timing: u16) {
         self.io.dmac.notify_from_gpu(timing);
     }
+
+    fn stop_video_capture(&mut self) {
+        self.io.dmac.stop_video_capture();
+    }
 }
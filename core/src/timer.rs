@@ -51,7 +51,19 @@ impl Timer {
     #[inline]
     fn sync_timer_data(&mut self, timestamp: usize) {
         let ticks_passed = (timestamp - self.start_time) >> self.prescalar_shift;
-        self.data += ticks_passed as u16;
+        self.data = self.data.wrapping_add(ticks_passed as u16);
+        // keep the remainder of the prescaler period so syncing again doesn't count the same ticks twice
+        self.start_time += ticks_passed << self.prescalar_shift;
+    }
+
+    /// The current value of the counter, without syncing it
+    fn peek_timer_data(&self, timestamp: usize) -> u16 {
+        if self.is_scheduled {
+            let ticks_passed = (timestamp - self.start_time) >> self.prescalar_shift;
+            self.data.wrapping_add(ticks_passed as u16)
+        } else {
+            self.data
+        }
     }
 
     #[inline]
@@ -63,31 +75,20 @@ impl Timer {
         }
     }
 
-    /// increments the timer with an amount of ticks
-    /// returns the number of times it overflowed
-    fn update(&mut self, ticks: usize) -> usize {
-        let mut ticks = ticks as u32;
-        let mut num_overflows = 0;
-
-        let ticks_remaining = self.ticks_to_overflow();
-
-        if ticks >= ticks_remaining {
-            num_overflows += 1;
-            ticks -= ticks_remaining;
-            self.data = self.initial_data;
-
-            let ticks_remaining = self.ticks_to_overflow();
-            num_overflows += ticks / ticks_remaining;
-            ticks %= ticks_remaining;
-
-            if self.ctl.irq_enabled() {
-                interrupt::signal_irq(&self.interrupt_flags, self.irq);
-            }
-        }
-
-        self.data += ticks as u16;
+    /// Count-up timers are ticked by the overflow of the previous timer instead of the prescaler.
+    /// The cascade bit has no effect on timer 0.
+    #[inline]
+    fn is_count_up(&self) -> bool {
+        self.timer_id != 0 && self.ctl.cascade()
+    }
 
-        num_overflows as usize
+    /// Ticks a count-up timer once, on the same cycle the previous timer overflowed
+    /// returns true if the timer overflowed
+    #[inline]
+    fn count_up(&mut self) -> bool {
+        let (data, overflowed) = self.data.overflowing_add(1);
+        self.data = data;
+        overflowed
     }
 }
 
@@ -137,6 +138,14 @@ impl Timers {
         }
     }
 
+    /// Shift the time the running timers started counting from, after the scheduler skipped `cycles`
+    /// during which the timers were paused
+    pub fn postpone(&mut self, cycles: usize) {
+        for timer in self.timers.iter_mut().filter(|timer| timer.is_scheduled) {
+            timer.start_time += cycles;
+        }
+    }
+
     fn prepare_next_overflow_event(&mut self, id: usize, start_time: usize) -> FutureEvent {
         let timer = &mut self.timers[id];
         timer.is_scheduled = true;
@@ -160,7 +169,7 @@ impl Timers {
         if id != 3 {
             let next_timer_id = id + 1;
             let next_timer = &mut self.timers[next_timer_id];
-            if next_timer.ctl.cascade() && next_timer.update(1) > 0 {
+            if next_timer.ctl.enabled() && next_timer.is_count_up() && next_timer.count_up() {
                 self.handle_timer_overflow(next_timer_id, apu, dmac);
             }
         }
@@ -183,16 +192,31 @@ impl Timers {
     pub fn write_timer_ctl(&mut self, id: usize, value: u16, sched: &mut Scheduler) {
         let timer = &mut self.timers[id];
         let new_ctl = TimerCtl(value);
-        #[cfg(feature = "debugger")]
         let old_enabled = timer.ctl.enabled();
         let new_enabled = new_ctl.enabled();
-        let cascade = new_ctl.cascade();
+        let was_scheduled = timer.is_scheduled;
+        let old_prescalar_shift = timer.prescalar_shift;
+        if was_scheduled {
+            // bring the counter up to date before the prescaler changes or the timer stops
+            timer.sync_timer_data(sched.timestamp());
+        }
+        if new_enabled && !old_enabled {
+            // the counter is reloaded only on the start edge, not on every write
+            timer.data = timer.initial_data;
+        }
         timer.prescalar_shift = SHIFT_LUT[new_ctl.prescalar() as usize];
         timer.ctl = new_ctl;
-        if new_enabled && !cascade {
+        if new_enabled && !timer.is_count_up() {
             self.running_timers |= 1 << id;
+            // a timer that keeps running with the same prescaler keeps its prescaler phase
+            let start_time = if was_scheduled && old_prescalar_shift == timer.prescalar_shift {
+                timer.start_time
+            } else {
+                sched.timestamp()
+            };
             self.cancel_timer_event(id, sched);
-            sched.schedule(self.prepare_next_overflow_event(id, sched.timestamp()));
+            let (event, cycles) = self.prepare_next_overflow_event(id, start_time);
+            sched.schedule_at(event, start_time + cycles);
         } else {
             self.running_timers &= !(1 << id);
             self.cancel_timer_event(id, sched);
@@ -236,30 +260,33 @@ impl Timers {
         }
     }
 
+    /// Same as `handle_read` but doesn't sync the running timers
+    pub fn debug_read(&self, io_addr: u32, sched: &Scheduler) -> u16 {
+        match io_addr {
+            REG_TM0CNT_H => self.timers[0].ctl.0,
+            REG_TM1CNT_H => self.timers[1].ctl.0,
+            REG_TM2CNT_H => self.timers[2].ctl.0,
+            REG_TM3CNT_H => self.timers[3].ctl.0,
+            REG_TM0CNT_L => self.timers[0].peek_timer_data(sched.timestamp()),
+            REG_TM1CNT_L => self.timers[1].peek_timer_data(sched.timestamp()),
+            REG_TM2CNT_L => self.timers[2].peek_timer_data(sched.timestamp()),
+            REG_TM3CNT_L => self.timers[3].peek_timer_data(sched.timestamp()),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn handle_write(&mut self, io_addr: u32, value: u16, sched: &mut Scheduler) {
         match io_addr {
-            REG_TM0CNT_L => {
-                self.timers[0].data = value;
-                self.timers[0].initial_data = value;
-            }
+            REG_TM0CNT_L => self.timers[0].initial_data = value,
             REG_TM0CNT_H => self.write_timer_ctl(0, value, sched),
 
-            REG_TM1CNT_L => {
-                self.timers[1].data = value;
-                self.timers[1].initial_data = value;
-            }
+            REG_TM1CNT_L => self.timers[1].initial_data = value,
             REG_TM1CNT_H => self.write_timer_ctl(1, value, sched),
 
-            REG_TM2CNT_L => {
-                self.timers[2].data = value;
-                self.timers[2].initial_data = value;
-            }
+            REG_TM2CNT_L => self.timers[2].initial_data = value,
             REG_TM2CNT_H => self.write_timer_ctl(2, value, sched),
 
-            REG_TM3CNT_L => {
-                self.timers[3].data = value;
-                self.timers[3].initial_data = value;
-            }
+            REG_TM3CNT_L => self.timers[3].initial_data = value,
             REG_TM3CNT_H => self.write_timer_ctl(3, value, sched),
             _ => unreachable!(),
         }
@@ -276,3 +303,94 @@ bitfield! {
     irq_enabled, _ : 6;
     enabled, set_enabled : 7;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_timer_start_edge_reload() {
+        let mut sched = Scheduler::new();
+        let mut timers = Timers::new(Rc::new(Cell::new(Default::default())));
+
+        timers.handle_write(REG_TM0CNT_L, 0x1000, &mut sched);
+        timers.handle_write(REG_TM0CNT_H, 0x80, &mut sched);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1000);
+        sched.update(0x100);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1100);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1100);
+
+        // writing the control register while enabled doesn't reload the counter
+        timers.handle_write(REG_TM0CNT_H, 0x80, &mut sched);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1100);
+        sched.update(0x10);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1110);
+
+        // neither does writing a new reload value
+        timers.handle_write(REG_TM0CNT_L, 0x2000, &mut sched);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1110);
+
+        // changing the prescaler keeps the counter and counts at the new rate
+        timers.handle_write(REG_TM0CNT_H, 0x81, &mut sched);
+        sched.update(64 * 3);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1113);
+
+        // a stopped timer holds its value
+        timers.handle_write(REG_TM0CNT_H, 0x01, &mut sched);
+        sched.update(0x1000);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x1113);
+
+        // toggling the enable bit reloads the counter
+        timers.handle_write(REG_TM0CNT_H, 0x80, &mut sched);
+        assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0x2000);
+    }
+
+    #[test]
+    fn test_timer_cascade() {
+        let mut sched = Scheduler::new();
+        let interrupt_flags = Rc::new(Cell::new(Default::default()));
+        let mut timers = Timers::new(interrupt_flags.clone());
+        let mut apu = SoundController::new(&mut sched, 44100.0);
+        let mut dmac = DmaController::new(interrupt_flags.clone());
+
+        // timer 1 counts up with an IRQ, overflowing on every 4th overflow of timer 0
+        timers.handle_write(REG_TM1CNT_L, 0xfffc, &mut sched);
+        timers.handle_write(REG_TM1CNT_H, 0xc4, &mut sched);
+        // timer 0 overflows every 16 cycles
+        timers.handle_write(REG_TM0CNT_L, 0xfff0, &mut sched);
+        timers.handle_write(REG_TM0CNT_H, 0x80, &mut sched);
+        assert_eq!(timers.handle_read(REG_TM1CNT_L, &sched), 0xfffc);
+
+        for overflow in 1..=4u16 {
+            sched.update(15);
+            assert!(sched.pop_pending_event().is_none());
+            assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0xffff);
+            assert_eq!(
+                timers.handle_read(REG_TM1CNT_L, &sched),
+                0xfffc + overflow - 1
+            );
+
+            sched.update(1);
+            let (event, event_time) = sched.pop_pending_event().unwrap();
+            assert_eq!(event, EventType::TimerOverflow(0));
+            assert_eq!(event_time, 16 * overflow as usize);
+            let (event, when) = timers.handle_overflow_event(0, event_time, &mut apu, &mut dmac);
+            sched.schedule_at(event, event_time + when);
+            assert_eq!(timers.handle_read(REG_TM0CNT_L, &sched), 0xfff0);
+        }
+        // timer 1 overflowed and reloaded on the 4th overflow of timer 0
+        assert_eq!(timers.handle_read(REG_TM1CNT_L, &sched), 0xfffc);
+        assert!(interrupt_flags.get().Timer1_Overflow());
+        assert!(!interrupt_flags.get().Timer0_Overflow());
+
+        // a disabled count-up timer doesn't count
+        timers.handle_write(REG_TM1CNT_H, 0x04, &mut sched);
+        sched.update(16);
+        let (event, event_time) = sched.pop_pending_event().unwrap();
+        assert_eq!(event, EventType::TimerOverflow(0));
+        timers.handle_overflow_event(0, event_time, &mut apu, &mut dmac);
+        assert_eq!(timers.handle_read(REG_TM1CNT_L, &sched), 0xfffc);
+    }
+}
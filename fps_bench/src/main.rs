@@ -1,20 +1,74 @@
 use std::env;
 use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
 
 use rustboyadvance_core::prelude::*;
+use rustboyadvance_core::WaitstateProfile;
 use rustboyadvance_utils::FpsCounter;
 
-fn main() {
-    if env::args().count() < 3 {
-        eprintln!("usage: {} <bios> <rom>", env::args().nth(0).unwrap());
-        return;
+fn usage() -> ! {
+    eprintln!(
+        "usage: {} <bios> <rom> [--no-waitstates] [--frames N] [--hash]",
+        env::args().nth(0).unwrap()
+    );
+    process::exit(1);
+}
+
+/// Run exactly `frames` frames and print the timing summary
+fn run_frames(gba: &mut GameBoyAdvance, frames: usize, print_hash: bool) {
+    let mut min_frame_time = Duration::from_secs(u64::MAX);
+    let mut max_frame_time = Duration::from_secs(0);
+    let start = Instant::now();
+    for _ in 0..frames {
+        let frame_start = Instant::now();
+        gba.frame();
+        let frame_time = frame_start.elapsed();
+        min_frame_time = min_frame_time.min(frame_time);
+        max_frame_time = max_frame_time.max(frame_time);
+    }
+    let total = start.elapsed();
+
+    println!("frames: {}", frames);
+    println!("total time: {:.3}s", total.as_secs_f64());
+    println!("average FPS: {:.2}", frames as f64 / total.as_secs_f64());
+    println!(
+        "frame time: min {:.3}ms, max {:.3}ms",
+        min_frame_time.as_secs_f64() * 1000.0,
+        max_frame_time.as_secs_f64() * 1000.0
+    );
+    if print_hash {
+        println!("frame hash: {:016x}", gba.frame_hash());
     }
+}
 
-    let bios_path = env::args().nth(1).expect("missing <bios>");
-    let rom_path = env::args().nth(2).expect("missing <rom>");
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut positional = Vec::new();
+    let mut no_waitstates = false;
+    let mut frames = None;
+    let mut print_hash = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--no-waitstates" => no_waitstates = true,
+            "--hash" => print_hash = true,
+            "--frames" => {
+                let n = iter.next().and_then(|n| n.parse::<usize>().ok());
+                match n {
+                    Some(n) if n > 0 => frames = Some(n),
+                    _ => usage(),
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        usage();
+    }
 
-    let bios = read_bin_file(Path::new(&bios_path)).expect("failed to read bios file");
-    let rom = read_bin_file(Path::new(&rom_path)).expect("failed to read rom file");
+    let bios = read_bin_file(Path::new(positional[0])).expect("failed to read bios file");
+    let rom = read_bin_file(Path::new(positional[1])).expect("failed to read rom file");
 
     let gamepak = GamepakBuilder::new()
         .take_buffer(rom.into_boxed_slice())
@@ -25,6 +79,14 @@ fn main() {
 
     let mut gba = GameBoyAdvance::new(bios.into_boxed_slice(), gamepak, NullAudio::new());
     gba.skip_bios();
+    if no_waitstates {
+        gba.set_speed_profile(Some(WaitstateProfile::NO_WAIT));
+    }
+
+    if let Some(frames) = frames {
+        run_frames(&mut gba, frames, print_hash);
+        return;
+    }
 
     let mut fps_counter = FpsCounter::default();
     loop {
@@ -0,0 +1,37 @@
+//! Runs the benchmark binary the way a CI job would
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_fixed_frame_count() {
+    let dir = env::temp_dir().join("rustboyadvance_fps_bench_test");
+    fs::create_dir_all(&dir).unwrap();
+    let bios_path = dir.join("bios.bin");
+    let rom_path = dir.join("rom.gba");
+    fs::write(&bios_path, vec![0u8; 0x4000]).unwrap();
+    // an infinite loop: b .
+    let mut rom = vec![0u8; 0x200];
+    rom[0..4].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes());
+    fs::write(&rom_path, rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fps_bench"))
+        .arg(&bios_path)
+        .arg(&rom_path)
+        .args(&["--frames", "10", "--hash"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("frames: 10"), "{}", stdout);
+    assert!(stdout.contains("frame hash: "), "{}", stdout);
+    let fps: f64 = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("average FPS: "))
+        .expect("missing average FPS")
+        .parse()
+        .unwrap();
+    assert!(fps.is_finite() && fps > 0.0, "{}", fps);
+}
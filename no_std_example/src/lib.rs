@@ -0,0 +1,88 @@
+//! Runs the arm7tdmi core without `std`, to make sure it keeps building for bare-metal targets.
+//!
+//! Build for a target without `std`:
+//! ```text
+//! cargo build --manifest-path no_std_example/Cargo.toml --target thumbv7em-none-eabi
+//! ```
+//!
+//! Or run the tests on the host:
+//! ```text
+//! cargo test --manifest-path no_std_example/Cargo.toml
+//! ```
+#![no_std]
+
+use arm7tdmi::arm::{ArmFormat, ArmInstruction};
+use arm7tdmi::thumb::{ThumbFormat, ThumbInstruction};
+use arm7tdmi::{Arm7tdmiCore, InstructionDecoder, SimpleMemory};
+use rustboyadvance_utils::Shared;
+
+/// mov r0, #5
+/// mov r1, #7
+/// add r2, r0, r1
+/// b .
+pub const PROGRAM: [u32; 4] = [0xe3a0_0005, 0xe3a0_1007, 0xe080_2001, 0xeaff_fffe];
+
+pub fn program_bytes() -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(PROGRAM.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+/// Decode every instruction of `PROGRAM`, returning the format of each
+pub fn decode_program() -> [ArmFormat; 4] {
+    let bytes = program_bytes();
+    let mut formats = [ArmFormat::Undefined; 4];
+    for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+        formats[i] = ArmInstruction::decode_from_bytes(chunk, 4 * i as u32).fmt;
+    }
+    formats
+}
+
+/// Decode "mov r0, #0x27" from its THUMB encoding
+pub fn decode_thumb() -> ThumbFormat {
+    ThumbInstruction::decode_from_bytes(&[0x27, 0x20], 0).fmt
+}
+
+/// Reset the cpu into `PROGRAM` and execute `steps` instructions, returning r0-r2 and the pc
+pub fn run_program(steps: usize) -> ([u32; 3], u32) {
+    let mut memory = SimpleMemory::new(0x4000);
+    memory.load_program(&program_bytes());
+
+    let mut cpu = Arm7tdmiCore::new(Shared::new(memory));
+    cpu.reset();
+    for _ in 0..steps {
+        cpu.step();
+    }
+    (
+        [cpu.get_reg(0), cpu.get_reg(1), cpu.get_reg(2)],
+        cpu.get_next_pc(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode_program(),
+            [
+                ArmFormat::DataProcessing,
+                ArmFormat::DataProcessing,
+                ArmFormat::DataProcessing,
+                ArmFormat::BranchLink,
+            ]
+        );
+        assert_eq!(decode_thumb(), ThumbFormat::DataProcessImm);
+    }
+
+    #[test]
+    fn test_execute() {
+        assert_eq!(run_program(3), ([5, 7, 12], 0xc));
+        // the branch loops on itself
+        assert_eq!(run_program(10), ([5, 7, 12], 0xc));
+    }
+}
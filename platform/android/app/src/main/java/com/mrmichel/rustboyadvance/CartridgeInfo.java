@@ -0,0 +1,26 @@
+package com.mrmichel.rustboyadvance;
+
+/**
+ * Metadata of a loaded ROM, constructed by the native bindings
+ */
+public class CartridgeInfo {
+    public final String gameTitle;
+    public final String gameCode;
+    public final String makerCode;
+    public final int mainUnitCode;
+    public final int softwareVersion;
+    public final int romSize;
+    public final String backupType;
+    public final String region;
+
+    public CartridgeInfo(String gameTitle, String gameCode, String makerCode, int mainUnitCode, int softwareVersion, int romSize, String backupType, String region) {
+        this.gameTitle = gameTitle;
+        this.gameCode = gameCode;
+        this.makerCode = makerCode;
+        this.mainUnitCode = mainUnitCode;
+        this.softwareVersion = softwareVersion;
+        this.romSize = romSize;
+        this.backupType = backupType;
+        this.region = region;
+    }
+}
@@ -83,6 +83,12 @@ public class EmulatorBindings {
      */
     public static native String getGameCode(long ctx);
 
+    /**
+     * @param ctx
+     * @return The metadata of the loaded ROM
+     */
+    public static native CartridgeInfo getCartridgeInfo(long ctx);
+
     /**
      * Sets the keystate
      *
@@ -108,6 +114,24 @@ public class EmulatorBindings {
      */
     public static native void loadState(long ctx, byte[] state) throws NativeBindingException;
 
+    /**
+     * Exports the raw contents of the cartridge save memory (SRAM/Flash/EEPROM)
+     *
+     * @param ctx
+     * @return save memory buffer, in the format of a .sav file
+     * @throws NativeBindingException
+     */
+    public static native byte[] exportSaveRam(long ctx) throws NativeBindingException;
+
+    /**
+     * Overwrites the cartridge save memory
+     *
+     * @param ctx
+     * @param data contents of a .sav file, must match the size of the save memory
+     * @throws NativeBindingException
+     */
+    public static native void importSaveRam(long ctx, byte[] data) throws NativeBindingException;
+
     /**
      * Logs the emulator state
      *
@@ -0,0 +1,26 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Set to also write the generated header over the committed `include/rustboyadvance.h`
+const UPDATE_HEADER_VAR: &str = "RBA_UPDATE_HEADER";
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("failed to read cbindgen.toml");
+
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate the C header");
+    bindings.write_to_file(out_dir.join("rustboyadvance.h"));
+    if env::var_os(UPDATE_HEADER_VAR).is_some() {
+        bindings.write_to_file(crate_dir.join("include/rustboyadvance.h"));
+    }
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed={}", UPDATE_HEADER_VAR);
+}
@@ -0,0 +1,110 @@
+#ifndef RUSTBOYADVANCE_H
+#define RUSTBOYADVANCE_H
+
+/* Generated by cbindgen from platform/rustboyadvance-capi, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The call succeeded
+#define RBA_OK 0
+
+// A required pointer argument was NULL
+#define RBA_ERR_NULL -1
+
+// The ROM could not be loaded
+#define RBA_ERR_LOAD -2
+
+// No ROM was loaded into the context yet
+#define RBA_ERR_NOT_LOADED -3
+
+// The savestate could not be created or restored
+#define RBA_ERR_STATE -4
+
+// Width of the frame buffer in pixels
+#define RBA_SCREEN_WIDTH 240
+
+// Height of the frame buffer in pixels
+#define RBA_SCREEN_HEIGHT 160
+
+// Opaque emulator handle
+typedef struct RbaContext RbaContext;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty context, a ROM must be loaded with `rba_load` before running it.
+// The returned handle is owned by the caller and must be released with `rba_free`.
+RbaContext *rba_new(void);
+
+// Releases a context created by `rba_new`, the handle must not be used afterwards.
+// Passing NULL is a no-op.
+//
+// # Safety
+// `ctx` must be NULL or a handle returned by `rba_new` that wasn't freed yet.
+void rba_free(RbaContext *ctx);
+
+// Loads a ROM into the context, replacing any previously loaded game.
+// The BIOS and ROM buffers are copied, the caller keeps ownership of them.
+// `bios_ptr` may be NULL to run with the builtin high level emulated BIOS, `rom_ptr` must not be NULL.
+// Returns `RBA_OK` on success.
+//
+// # Safety
+// `bios_ptr` (if not NULL) and `rom_ptr` must point to at least `bios_len` and `rom_len` readable bytes.
+int32_t rba_load(RbaContext *ctx,
+                 const uint8_t *bios_ptr,
+                 size_t bios_len,
+                 const uint8_t *rom_ptr,
+                 size_t rom_len);
+
+// Runs the emulation for a single frame
+//
+// # Safety
+// `ctx` must be NULL or a valid handle.
+int32_t rba_run_frame(RbaContext *ctx);
+
+// Returns the frame buffer of `RBA_SCREEN_WIDTH * RBA_SCREEN_HEIGHT` pixels in 0x00RRGGBB format.
+// The buffer is owned by the context and is only valid until the next call that takes the same context.
+// Returns NULL if `ctx` is NULL or no ROM is loaded.
+//
+// # Safety
+// `ctx` must be NULL or a valid handle.
+const uint32_t *rba_get_framebuffer(RbaContext *ctx);
+
+// Sets the state of the keypad, `keys` is a bitmask of the pressed keys in KEYINPUT bit order:
+// A, B, Select, Start, Right, Left, Up, Down, R, L from bit 0 to bit 9.
+//
+// # Safety
+// `ctx` must be NULL or a valid handle.
+int32_t rba_set_keys(RbaContext *ctx, uint16_t keys);
+
+// Serializes the emulation state into a newly allocated buffer, its size is stored to `out_len`.
+// The buffer is owned by the caller and must be released with `rba_free_buffer`.
+// Returns NULL on failure.
+//
+// # Safety
+// `ctx` must be NULL or a valid handle, `out_len` must be a valid pointer.
+uint8_t *rba_save_state(RbaContext *ctx, size_t *out_len);
+
+// Restores a state created by `rba_save_state` for the same game.
+// The buffer is only read, the caller keeps ownership of it.
+//
+// # Safety
+// `ctx` must be NULL or a valid handle, `state_ptr` must point to at least `state_len` readable bytes.
+int32_t rba_load_state(RbaContext *ctx, const uint8_t *state_ptr, size_t state_len);
+
+// Releases a buffer returned by `rba_save_state`. Passing NULL is a no-op.
+//
+// # Safety
+// `ptr` and `len` must be exactly as returned by `rba_save_state`, and the buffer must not be freed twice.
+void rba_free_buffer(uint8_t *ptr, size_t len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* RUSTBOYADVANCE_H */
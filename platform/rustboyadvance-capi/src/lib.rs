@@ -0,0 +1,204 @@
+//! C bindings for rustboyadvance, see `include/rustboyadvance.h`
+//!
+//! The header is generated by cbindgen, after changing the API regenerate it with
+//! `RBA_UPDATE_HEADER=1 cargo build -p rustboyadvance-capi`. The tests fail while it is out of date.
+//!
+//! All functions take an opaque `RbaContext` handle created by `rba_new` and released by `rba_free`.
+//! Unless stated otherwise, passing a NULL handle is allowed and fails with `RBA_ERR_NULL`.
+//! A context must not be used from multiple threads at the same time.
+use std::ptr;
+use std::slice;
+
+use rustboyadvance_core::keypad::KEYINPUT_ALL_RELEASED;
+use rustboyadvance_core::prelude::*;
+
+/// The call succeeded
+pub const RBA_OK: i32 = 0;
+/// A required pointer argument was NULL
+pub const RBA_ERR_NULL: i32 = -1;
+/// The ROM could not be loaded
+pub const RBA_ERR_LOAD: i32 = -2;
+/// No ROM was loaded into the context yet
+pub const RBA_ERR_NOT_LOADED: i32 = -3;
+/// The savestate could not be created or restored
+pub const RBA_ERR_STATE: i32 = -4;
+
+/// Width of the frame buffer in pixels
+pub const RBA_SCREEN_WIDTH: usize = 240;
+/// Height of the frame buffer in pixels
+pub const RBA_SCREEN_HEIGHT: usize = 160;
+
+/// Opaque emulator handle
+pub struct RbaContext {
+    gba: Option<GameBoyAdvance>,
+}
+
+unsafe fn gba_mut<'a>(ctx: *mut RbaContext) -> Result<&'a mut GameBoyAdvance, i32> {
+    match ctx.as_mut() {
+        Some(ctx) => ctx.gba.as_mut().ok_or(RBA_ERR_NOT_LOADED),
+        None => Err(RBA_ERR_NULL),
+    }
+}
+
+/// Creates an empty context, a ROM must be loaded with `rba_load` before running it.
+/// The returned handle is owned by the caller and must be released with `rba_free`.
+#[no_mangle]
+pub extern "C" fn rba_new() -> *mut RbaContext {
+    Box::into_raw(Box::new(RbaContext { gba: None }))
+}
+
+/// Releases a context created by `rba_new`, the handle must not be used afterwards.
+/// Passing NULL is a no-op.
+///
+/// # Safety
+/// `ctx` must be NULL or a handle returned by `rba_new` that wasn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rba_free(ctx: *mut RbaContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Loads a ROM into the context, replacing any previously loaded game.
+/// The BIOS and ROM buffers are copied, the caller keeps ownership of them.
+/// `bios_ptr` may be NULL to run with the builtin high level emulated BIOS, `rom_ptr` must not be NULL.
+/// Returns `RBA_OK` on success.
+///
+/// # Safety
+/// `bios_ptr` (if not NULL) and `rom_ptr` must point to at least `bios_len` and `rom_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rba_load(
+    ctx: *mut RbaContext,
+    bios_ptr: *const u8,
+    bios_len: usize,
+    rom_ptr: *const u8,
+    rom_len: usize,
+) -> i32 {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return RBA_ERR_NULL,
+    };
+    if rom_ptr.is_null() {
+        return RBA_ERR_NULL;
+    }
+    let rom = slice::from_raw_parts(rom_ptr, rom_len);
+    let gamepak = match GamepakBuilder::new()
+        .buffer(rom)
+        .without_backup_to_file()
+        .build()
+    {
+        Ok(gamepak) => gamepak,
+        Err(_) => return RBA_ERR_LOAD,
+    };
+
+    let gba = if bios_ptr.is_null() {
+        GameBoyAdvance::new_hle(gamepak, NullAudio::new())
+    } else {
+        let bios = slice::from_raw_parts(bios_ptr, bios_len);
+        let mut gba = GameBoyAdvance::new(bios.into(), gamepak, NullAudio::new());
+        gba.skip_bios();
+        gba
+    };
+    ctx.gba = Some(gba);
+    RBA_OK
+}
+
+/// Runs the emulation for a single frame
+///
+/// # Safety
+/// `ctx` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rba_run_frame(ctx: *mut RbaContext) -> i32 {
+    match gba_mut(ctx) {
+        Ok(gba) => {
+            gba.frame();
+            RBA_OK
+        }
+        Err(err) => err,
+    }
+}
+
+/// Returns the frame buffer of `RBA_SCREEN_WIDTH * RBA_SCREEN_HEIGHT` pixels in 0x00RRGGBB format.
+/// The buffer is owned by the context and is only valid until the next call that takes the same context.
+/// Returns NULL if `ctx` is NULL or no ROM is loaded.
+///
+/// # Safety
+/// `ctx` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rba_get_framebuffer(ctx: *mut RbaContext) -> *const u32 {
+    match gba_mut(ctx) {
+        Ok(gba) => gba.get_frame_buffer().as_ptr(),
+        Err(_) => ptr::null(),
+    }
+}
+
+/// Sets the state of the keypad, `keys` is a bitmask of the pressed keys in KEYINPUT bit order:
+/// A, B, Select, Start, Right, Left, Up, Down, R, L from bit 0 to bit 9.
+///
+/// # Safety
+/// `ctx` must be NULL or a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn rba_set_keys(ctx: *mut RbaContext, keys: u16) -> i32 {
+    match gba_mut(ctx) {
+        Ok(gba) => {
+            // KEYINPUT bits are cleared while the key is pressed
+            *gba.get_key_state_mut() = !keys & KEYINPUT_ALL_RELEASED;
+            RBA_OK
+        }
+        Err(err) => err,
+    }
+}
+
+/// Serializes the emulation state into a newly allocated buffer, its size is stored to `out_len`.
+/// The buffer is owned by the caller and must be released with `rba_free_buffer`.
+/// Returns NULL on failure.
+///
+/// # Safety
+/// `ctx` must be NULL or a valid handle, `out_len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn rba_save_state(ctx: *mut RbaContext, out_len: *mut usize) -> *mut u8 {
+    if out_len.is_null() {
+        return ptr::null_mut();
+    }
+    let state = match gba_mut(ctx).map(|gba| gba.save_state()) {
+        Ok(Ok(state)) => state.into_boxed_slice(),
+        _ => return ptr::null_mut(),
+    };
+    *out_len = state.len();
+    Box::into_raw(state) as *mut u8
+}
+
+/// Restores a state created by `rba_save_state` for the same game.
+/// The buffer is only read, the caller keeps ownership of it.
+///
+/// # Safety
+/// `ctx` must be NULL or a valid handle, `state_ptr` must point to at least `state_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rba_load_state(
+    ctx: *mut RbaContext,
+    state_ptr: *const u8,
+    state_len: usize,
+) -> i32 {
+    if state_ptr.is_null() {
+        return RBA_ERR_NULL;
+    }
+    let gba = match gba_mut(ctx) {
+        Ok(gba) => gba,
+        Err(err) => return err,
+    };
+    match gba.restore_state(slice::from_raw_parts(state_ptr, state_len)) {
+        Ok(_) => RBA_OK,
+        Err(_) => RBA_ERR_STATE,
+    }
+}
+
+/// Releases a buffer returned by `rba_save_state`. Passing NULL is a no-op.
+///
+/// # Safety
+/// `ptr` and `len` must be exactly as returned by `rba_save_state`, and the buffer must not be freed twice.
+#[no_mangle]
+pub unsafe extern "C" fn rba_free_buffer(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
@@ -0,0 +1,80 @@
+//! Drives the C API through the exported symbols, the same way a C program would
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+use rustboyadvance_capi::{
+    RBA_ERR_NOT_LOADED, RBA_ERR_NULL, RBA_ERR_STATE, RBA_OK, RBA_SCREEN_HEIGHT, RBA_SCREEN_WIDTH,
+};
+
+extern "C" {
+    fn rba_new() -> *mut c_void;
+    fn rba_free(ctx: *mut c_void);
+    fn rba_load(
+        ctx: *mut c_void,
+        bios_ptr: *const u8,
+        bios_len: usize,
+        rom_ptr: *const u8,
+        rom_len: usize,
+    ) -> i32;
+    fn rba_run_frame(ctx: *mut c_void) -> i32;
+    fn rba_get_framebuffer(ctx: *mut c_void) -> *const u32;
+    fn rba_set_keys(ctx: *mut c_void, keys: u16) -> i32;
+    fn rba_save_state(ctx: *mut c_void, out_len: *mut usize) -> *mut u8;
+    fn rba_load_state(ctx: *mut c_void, state_ptr: *const u8, state_len: usize) -> i32;
+    fn rba_free_buffer(ptr: *mut u8, len: usize);
+}
+
+#[test]
+fn test_capi() {
+    // an infinite loop: b .
+    let mut rom = vec![0u8; 0x200];
+    rom[0..4].copy_from_slice(&0xeaff_fffe_u32.to_le_bytes());
+
+    unsafe {
+        assert_eq!(rba_run_frame(ptr::null_mut()), RBA_ERR_NULL);
+
+        let ctx = rba_new();
+        assert!(!ctx.is_null());
+        assert_eq!(rba_run_frame(ctx), RBA_ERR_NOT_LOADED);
+        assert!(rba_get_framebuffer(ctx).is_null());
+        assert_eq!(rba_load(ctx, ptr::null(), 0, ptr::null(), 0), RBA_ERR_NULL);
+
+        assert_eq!(
+            rba_load(ctx, ptr::null(), 0, rom.as_ptr(), rom.len()),
+            RBA_OK
+        );
+        assert_eq!(rba_set_keys(ctx, 0b1001), RBA_OK);
+        for _ in 0..3 {
+            assert_eq!(rba_run_frame(ctx), RBA_OK);
+        }
+        let frame_buffer = rba_get_framebuffer(ctx);
+        assert!(!frame_buffer.is_null());
+        assert_eq!(
+            slice::from_raw_parts(frame_buffer, FRAME_BUFFER_SIZE).len(),
+            FRAME_BUFFER_SIZE
+        );
+
+        let mut state_len = 0;
+        let state = rba_save_state(ctx, &mut state_len);
+        assert!(!state.is_null());
+        assert!(state_len > 0);
+        assert_eq!(rba_run_frame(ctx), RBA_OK);
+        assert_eq!(rba_load_state(ctx, state, state_len), RBA_OK);
+        assert_eq!(rba_load_state(ctx, state, 4), RBA_ERR_STATE);
+        rba_free_buffer(state, state_len);
+
+        rba_free(ctx);
+        rba_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn test_header_up_to_date() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/rustboyadvance.h"));
+    let committed = include_str!("../include/rustboyadvance.h");
+    assert!(
+        generated == committed,
+        "include/rustboyadvance.h is out of date, regenerate it with RBA_UPDATE_HEADER=1"
+    );
+}
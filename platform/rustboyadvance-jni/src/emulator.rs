@@ -1,10 +1,10 @@
 use rustboyadvance_core::prelude::*;
 use rustboyadvance_utils::audio::SampleConsumer;
+use rustboyadvance_utils::FrameLimiter;
 // use rustboyadvance_core::util::FpsCounter;
 
 use std::path::Path;
 use std::sync::{Mutex, MutexGuard};
-use std::time::{Duration, Instant};
 
 use jni::objects::{GlobalRef, JMethodID, JObject, JString, JValue};
 use jni::signature;
@@ -254,7 +254,7 @@ impl EmulatorContext {
 
     /// Run the emulation main loop
     pub fn native_run(&mut self, env: &JNIEnv) -> Result<(), jni::errors::Error> {
-        const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000u64 / 60);
+        let mut frame_limiter = FrameLimiter::new(REFRESH_RATE);
 
         // Set the state to running
         *self.emustate.lock().unwrap() = EmulationState::Running(false);
@@ -293,7 +293,6 @@ impl EmulatorContext {
                 EmulationState::Running(turbo) => !turbo,
             };
 
-            let start_time = Instant::now();
             // check key state
             *self.gba.get_key_state_mut() = self.keypad.get_key_state(env);
 
@@ -313,14 +312,7 @@ impl EmulatorContext {
             // }
 
             if vsync {
-                let time_passed = start_time.elapsed();
-                let delay = FRAME_TIME.checked_sub(time_passed);
-                match delay {
-                    None => {}
-                    Some(delay) => {
-                        std::thread::sleep(delay);
-                    }
-                }
+                frame_limiter.wait();
             }
         }
 
@@ -26,6 +26,7 @@ static mut DID_LOAD: bool = false;
 
 const NATIVE_EXCEPTION_CLASS: &'static str =
     "com/mrmichel/rustboyadvance/EmulatorBindings/NativeBindingException";
+const CARTRIDGE_INFO_CLASS: &'static str = "com/mrmichel/rustboyadvance/CartridgeInfo";
 
 fn save_state(env: &JNIEnv, gba: &mut GameBoyAdvance) -> Result<jbyteArray, String> {
     let saved_state = gba
@@ -45,6 +46,19 @@ fn load_state(env: &JNIEnv, gba: &mut GameBoyAdvance, state: jbyteArray) -> Resu
         .map_err(|e| format!("failed to restore state, error: {:?}", e))
 }
 
+fn export_save_ram(env: &JNIEnv, gba: &GameBoyAdvance) -> Result<jbyteArray, String> {
+    env.byte_array_from_slice(&gba.export_save_ram())
+        .map_err(|e| format!("failed to create byte array, error: {:?}", e))
+}
+
+fn import_save_ram(env: &JNIEnv, gba: &mut GameBoyAdvance, data: jbyteArray) -> Result<(), String> {
+    let data = env
+        .convert_byte_array(data)
+        .map_err(|e| format!("failed to convert byte array, error: {:?}", e))?;
+    gba.import_save_ram(&data)
+        .map_err(|e| format!("failed to import save data, error: {:?}", e))
+}
+
 #[allow(non_snake_case)]
 pub mod bindings {
     use super::*;
@@ -261,6 +275,45 @@ pub mod bindings {
         }
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_exportSaveRam(
+        env: JNIEnv,
+        _obj: JClass,
+        ctx: jlong,
+    ) -> jbyteArray {
+        let ctx = cast_ctx(ctx);
+        ctx.pause();
+        let (_lock, gba) = ctx.lock_and_get_gba();
+        let result = export_save_ram(&env, gba);
+        drop(_lock);
+        ctx.resume();
+        match result {
+            Ok(result) => result,
+            Err(msg) => {
+                env.throw_new(NATIVE_EXCEPTION_CLASS, msg).unwrap();
+                JObject::null().into_inner()
+            }
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_importSaveRam(
+        env: JNIEnv,
+        _obj: JClass,
+        ctx: jlong,
+        data: jbyteArray,
+    ) {
+        let ctx = cast_ctx(ctx);
+        ctx.pause();
+        let (_lock, gba) = ctx.lock_and_get_gba();
+        let result = import_save_ram(&env, gba, data);
+        drop(_lock);
+        ctx.resume();
+        if let Err(msg) = result {
+            env.throw_new(NATIVE_EXCEPTION_CLASS, msg).unwrap();
+        }
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_getGameTitle(
         env: JNIEnv,
@@ -285,6 +338,33 @@ pub mod bindings {
             .into_inner()
     }
 
+    #[no_mangle]
+    pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_getCartridgeInfo(
+        env: JNIEnv,
+        _obj: JClass,
+        ctx: jlong,
+    ) -> jobject {
+        let ctx = cast_ctx(ctx);
+        let info = ctx.gba.get_cartridge_info();
+        let string = |s: String| JValue::from(JObject::from(env.new_string(s).unwrap()));
+        env.new_object(
+            CARTRIDGE_INFO_CLASS,
+            "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;IIILjava/lang/String;Ljava/lang/String;)V",
+            &[
+                string(info.game_title),
+                string(info.game_code),
+                string(info.maker_code),
+                JValue::Int(info.main_unit_code as jint),
+                JValue::Int(info.software_version as jint),
+                JValue::Int(info.rom_size as jint),
+                string(format!("{:?}", info.backup_type)),
+                string(format!("{:?}", info.region)),
+            ],
+        )
+        .unwrap()
+        .into_inner()
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn Java_com_mrmichel_rustboyadvance_EmulatorBindings_log(
         _env: JNIEnv,
@@ -100,7 +100,7 @@ impl libretro_backend::Core for RustBoyAdvanceCore {
         match (result, bios) {
             (Ok(gamepak), Ok(bios)) => {
                 let av_info = AudioVideoInfo::new()
-                    .video(240, 160, 60.0, PixelFormat::ARGB8888)
+                    .video(240, 160, REFRESH_RATE, PixelFormat::ARGB8888)
                     .audio(44100.0);
 
                 let (audio_device, audio_consumer) =
@@ -2,37 +2,161 @@ use sdl2::controller::Axis;
 use sdl2::controller::Button;
 use sdl2::keyboard::Scancode;
 
+use gba_keypad::Keys as GbaKeys;
 use rustboyadvance_core::keypad as gba_keypad;
 
 use bit;
 use bit::BitIndex;
 
-pub fn on_keyboard_key_down(key_state: &mut u16, scancode: Scancode) {
-    if let Some(key) = scancode_to_keypad(scancode) {
+/// Bindings of keyboard keys and game controller buttons to the GBA keypad.
+/// A GBA key may be bound to more than one key or button.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    pub keyboard: Vec<(Scancode, GbaKeys)>,
+    pub controller: Vec<(Button, GbaKeys)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap {
+            keyboard: vec![
+                (Scancode::Up, GbaKeys::Up),
+                (Scancode::Down, GbaKeys::Down),
+                (Scancode::Left, GbaKeys::Left),
+                (Scancode::Right, GbaKeys::Right),
+                (Scancode::Z, GbaKeys::ButtonB),
+                (Scancode::X, GbaKeys::ButtonA),
+                (Scancode::Return, GbaKeys::Start),
+                (Scancode::Backspace, GbaKeys::Select),
+                (Scancode::A, GbaKeys::ButtonL),
+                (Scancode::S, GbaKeys::ButtonR),
+            ],
+            controller: vec![
+                (Button::DPadUp, GbaKeys::Up),
+                (Button::DPadDown, GbaKeys::Down),
+                (Button::DPadLeft, GbaKeys::Left),
+                (Button::DPadRight, GbaKeys::Right),
+                (Button::A, GbaKeys::ButtonB), // A and B are swapped compared to the SDL layout
+                (Button::B, GbaKeys::ButtonA),
+                (Button::Start, GbaKeys::Start),
+                (Button::Back, GbaKeys::Select),
+                (Button::LeftShoulder, GbaKeys::ButtonL),
+                (Button::RightShoulder, GbaKeys::ButtonR),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// Bind `scancode` to `key`, replacing its previous binding
+    pub fn bind_scancode(&mut self, scancode: Scancode, key: GbaKeys) {
+        self.keyboard.retain(|(s, _)| *s != scancode);
+        self.keyboard.push((scancode, key));
+    }
+
+    /// Bind `button` to `key`, replacing its previous binding
+    pub fn bind_button(&mut self, button: Button, key: GbaKeys) {
+        self.controller.retain(|(b, _)| *b != button);
+        self.controller.push((button, key));
+    }
+
+    pub fn scancode_to_keypad(&self, scancode: Scancode) -> Option<GbaKeys> {
+        self.keyboard
+            .iter()
+            .find(|(s, _)| *s == scancode)
+            .map(|(_, key)| *key)
+    }
+
+    pub fn button_to_keypad(&self, button: Button) -> Option<GbaKeys> {
+        self.controller
+            .iter()
+            .find(|(b, _)| *b == button)
+            .map(|(_, key)| *key)
+    }
+}
+
+/// Frontend actions bound to fixed keyboard keys, they take priority over the key map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Pause or resume the emulation
+    TogglePause,
+    /// Pause the emulation and advance it by a single frame
+    FrameStep,
+}
+
+pub fn scancode_to_hotkey(scancode: Scancode) -> Option<Hotkey> {
+    match scancode {
+        Scancode::P => Some(Hotkey::TogglePause),
+        Scancode::Period => Some(Hotkey::FrameStep),
+        _ => None,
+    }
+}
+
+pub fn on_keyboard_key_down(key_map: &KeyMap, key_state: &mut u16, scancode: Scancode) {
+    if let Some(key) = key_map.scancode_to_keypad(scancode) {
         key_state.set_bit(key as usize, false);
     }
 }
 
-pub fn on_keyboard_key_up(key_state: &mut u16, scancode: Scancode) {
-    if let Some(key) = scancode_to_keypad(scancode) {
+pub fn on_keyboard_key_up(key_map: &KeyMap, key_state: &mut u16, scancode: Scancode) {
+    if let Some(key) = key_map.scancode_to_keypad(scancode) {
         key_state.set_bit(key as usize, true);
     }
 }
 
-pub fn on_controller_button_down(key_state: &mut u16, button: Button) {
-    if let Some(key) = controller_button_to_keypad(button) {
+pub fn on_controller_button_down(key_map: &KeyMap, key_state: &mut u16, button: Button) {
+    if let Some(key) = key_map.button_to_keypad(button) {
         key_state.set_bit(key as usize, false);
     }
 }
 
-pub fn on_controller_button_up(key_state: &mut u16, button: Button) {
-    if let Some(key) = controller_button_to_keypad(button) {
+pub fn on_controller_button_up(key_map: &KeyMap, key_state: &mut u16, button: Button) {
+    if let Some(key) = key_map.button_to_keypad(button) {
         key_state.set_bit(key as usize, true);
     }
 }
 
-pub fn on_axis_motion(key_state: &mut u16, axis: Axis, val: i16) {
-    use gba_keypad::Keys as GbaKeys;
+/// Release every key a controller may be holding, so unplugging it doesn't leave keys stuck
+pub fn on_controller_removed(key_map: &KeyMap, key_state: &mut u16) {
+    for (_, key) in &key_map.controller {
+        key_state.set_bit(*key as usize, true);
+    }
+    for key in &[
+        GbaKeys::Left,
+        GbaKeys::Right,
+        GbaKeys::Up,
+        GbaKeys::Down,
+        GbaKeys::ButtonL,
+        GbaKeys::ButtonR,
+    ] {
+        key_state.set_bit(*key as usize, true);
+    }
+}
+
+/// How analog axis values are mapped to the digital GBA keys.
+/// Axis values are in the range [-32768, 32767].
+#[derive(Debug, Clone, Copy)]
+pub struct AxisConfig {
+    /// Values whose magnitude is below the dead zone release the keys of the axis
+    pub dead_zone: i16,
+    /// Values whose magnitude exceeds the threshold press the key of their direction.
+    /// Between the dead zone and the threshold the keys keep their state, so a stick resting near
+    /// the threshold doesn't flicker.
+    pub threshold: i16,
+}
+
+impl Default for AxisConfig {
+    fn default() -> AxisConfig {
+        AxisConfig {
+            dead_zone: 8_000,
+            threshold: 16_000,
+        }
+    }
+}
+
+/// Maps an axis motion to the keys of that axis alone, so moving the stick diagonally presses
+/// both a horizontal and a vertical direction.
+pub fn on_axis_motion(config: &AxisConfig, key_state: &mut u16, axis: Axis, val: i16) {
     let keys = match axis {
         Axis::LeftX => (GbaKeys::Left, GbaKeys::Right),
         Axis::LeftY => (GbaKeys::Up, GbaKeys::Down),
@@ -43,47 +167,145 @@ pub fn on_axis_motion(key_state: &mut u16, axis: Axis, val: i16) {
         }
     };
 
-    // Axis motion is an absolute value in the range
-    // [-32768, 32767]. Let's simulate a very rough dead
-    // zone to ignore spurious events.
-    let dead_zone = 10_000;
-    if val > dead_zone || val < -dead_zone {
-        let key = if val < 0 { keys.0 } else { keys.1 };
-        key_state.set_bit(key as usize, false);
-    } else {
+    let magnitude = (val as i32).abs();
+    if magnitude > config.threshold as i32 {
+        let (pressed, released) = if val < 0 { keys } else { (keys.1, keys.0) };
+        key_state.set_bit(released as usize, true);
+        key_state.set_bit(pressed as usize, false);
+    } else if magnitude < config.dead_zone as i32 {
         key_state.set_bit(keys.0 as usize, true);
         key_state.set_bit(keys.1 as usize, true);
     }
 }
 
-fn scancode_to_keypad(scancode: Scancode) -> Option<gba_keypad::Keys> {
-    match scancode {
-        Scancode::Up => Some(gba_keypad::Keys::Up),
-        Scancode::Down => Some(gba_keypad::Keys::Down),
-        Scancode::Left => Some(gba_keypad::Keys::Left),
-        Scancode::Right => Some(gba_keypad::Keys::Right),
-        Scancode::Z => Some(gba_keypad::Keys::ButtonB),
-        Scancode::X => Some(gba_keypad::Keys::ButtonA),
-        Scancode::Return => Some(gba_keypad::Keys::Start),
-        Scancode::Backspace => Some(gba_keypad::Keys::Select),
-        Scancode::A => Some(gba_keypad::Keys::ButtonL),
-        Scancode::S => Some(gba_keypad::Keys::ButtonR),
-        _ => None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gba_keypad::{KEYINPUT_ALL_RELEASED, NUM_KEYS};
+
+    const ALL_KEYS: [GbaKeys; NUM_KEYS] = [
+        GbaKeys::ButtonA,
+        GbaKeys::ButtonB,
+        GbaKeys::Select,
+        GbaKeys::Start,
+        GbaKeys::Right,
+        GbaKeys::Left,
+        GbaKeys::Up,
+        GbaKeys::Down,
+        GbaKeys::ButtonR,
+        GbaKeys::ButtonL,
+    ];
+
+    #[test]
+    fn test_default_key_map() {
+        let key_map = KeyMap::default();
+        let keyboard = [
+            Scancode::X,
+            Scancode::Z,
+            Scancode::Backspace,
+            Scancode::Return,
+            Scancode::Right,
+            Scancode::Left,
+            Scancode::Up,
+            Scancode::Down,
+            Scancode::S,
+            Scancode::A,
+        ];
+        let controller = [
+            Button::B,
+            Button::A,
+            Button::Back,
+            Button::Start,
+            Button::DPadRight,
+            Button::DPadLeft,
+            Button::DPadUp,
+            Button::DPadDown,
+            Button::RightShoulder,
+            Button::LeftShoulder,
+        ];
+        for i in 0..NUM_KEYS {
+            assert_eq!(key_map.scancode_to_keypad(keyboard[i]), Some(ALL_KEYS[i]));
+            assert_eq!(key_map.button_to_keypad(controller[i]), Some(ALL_KEYS[i]));
+        }
+        assert_eq!(key_map.scancode_to_keypad(Scancode::Q), None);
+        assert_eq!(key_map.button_to_keypad(Button::Guide), None);
     }
-}
 
-fn controller_button_to_keypad(button: Button) -> Option<gba_keypad::Keys> {
-    match button {
-        Button::DPadUp => Some(gba_keypad::Keys::Up),
-        Button::DPadDown => Some(gba_keypad::Keys::Down),
-        Button::DPadLeft => Some(gba_keypad::Keys::Left),
-        Button::DPadRight => Some(gba_keypad::Keys::Right),
-        Button::A => Some(gba_keypad::Keys::ButtonB), // A and B are swapped compared to the SDL layout
-        Button::B => Some(gba_keypad::Keys::ButtonA),
-        Button::Start => Some(gba_keypad::Keys::Start),
-        Button::Back => Some(gba_keypad::Keys::Select),
-        Button::LeftShoulder => Some(gba_keypad::Keys::ButtonL),
-        Button::RightShoulder => Some(gba_keypad::Keys::ButtonR),
-        _ => None,
+    #[test]
+    fn test_rebind() {
+        let mut key_map = KeyMap::default();
+        key_map.bind_scancode(Scancode::X, GbaKeys::ButtonB);
+        key_map.bind_button(Button::Y, GbaKeys::ButtonA);
+        assert_eq!(
+            key_map.scancode_to_keypad(Scancode::X),
+            Some(GbaKeys::ButtonB)
+        );
+        assert_eq!(key_map.button_to_keypad(Button::Y), Some(GbaKeys::ButtonA));
+
+        let mut key_state = KEYINPUT_ALL_RELEASED;
+        on_keyboard_key_down(&key_map, &mut key_state, Scancode::X);
+        assert_eq!(
+            key_state,
+            KEYINPUT_ALL_RELEASED & !(1 << GbaKeys::ButtonB as u16)
+        );
+        on_keyboard_key_up(&key_map, &mut key_state, Scancode::X);
+        assert_eq!(key_state, KEYINPUT_ALL_RELEASED);
+    }
+
+    #[test]
+    fn test_hotkeys() {
+        assert_eq!(scancode_to_hotkey(Scancode::P), Some(Hotkey::TogglePause));
+        assert_eq!(
+            scancode_to_hotkey(Scancode::Period),
+            Some(Hotkey::FrameStep)
+        );
+        assert_eq!(scancode_to_hotkey(Scancode::X), None);
+
+        // the hotkeys don't collide with the default bindings
+        let key_map = KeyMap::default();
+        assert_eq!(key_map.scancode_to_keypad(Scancode::P), None);
+        assert_eq!(key_map.scancode_to_keypad(Scancode::Period), None);
+    }
+
+    #[test]
+    fn test_axis_motion() {
+        let config = AxisConfig {
+            dead_zone: 8_000,
+            threshold: 16_000,
+        };
+        let pressed = |keys: &[GbaKeys]| {
+            keys.iter().fold(KEYINPUT_ALL_RELEASED, |state, key| {
+                state & !(1 << *key as u16)
+            })
+        };
+        let mut key_state = KEYINPUT_ALL_RELEASED;
+
+        on_axis_motion(&config, &mut key_state, Axis::LeftX, 16_000);
+        assert_eq!(key_state, KEYINPUT_ALL_RELEASED);
+        on_axis_motion(&config, &mut key_state, Axis::LeftX, 16_001);
+        assert_eq!(key_state, pressed(&[GbaKeys::Right]));
+
+        // both axes are evaluated independently
+        on_axis_motion(&config, &mut key_state, Axis::LeftY, -20_000);
+        assert_eq!(key_state, pressed(&[GbaKeys::Right, GbaKeys::Up]));
+
+        // between the dead zone and the threshold the keys are held
+        on_axis_motion(&config, &mut key_state, Axis::LeftX, 8_000);
+        assert_eq!(key_state, pressed(&[GbaKeys::Right, GbaKeys::Up]));
+        on_axis_motion(&config, &mut key_state, Axis::LeftX, 7_999);
+        assert_eq!(key_state, pressed(&[GbaKeys::Up]));
+
+        // flicking the stick to the opposite direction releases the previous one
+        on_axis_motion(&config, &mut key_state, Axis::LeftY, 32_767);
+        assert_eq!(key_state, pressed(&[GbaKeys::Down]));
+        on_axis_motion(&config, &mut key_state, Axis::LeftY, -32_768);
+        assert_eq!(key_state, pressed(&[GbaKeys::Up]));
+        on_axis_motion(&config, &mut key_state, Axis::LeftY, 0);
+        assert_eq!(key_state, KEYINPUT_ALL_RELEASED);
+
+        on_axis_motion(&config, &mut key_state, Axis::TriggerLeft, 30_000);
+        assert_eq!(key_state, pressed(&[GbaKeys::ButtonL]));
+        on_axis_motion(&config, &mut key_state, Axis::RightX, 30_000);
+        assert_eq!(key_state, pressed(&[GbaKeys::ButtonL]));
     }
 }
@@ -1,16 +1,14 @@
-use sdl2::controller::Button;
+use sdl2::controller::{Button, GameController};
 use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
 use sdl2::{self};
 
 use bytesize;
-use spin_sleep;
 use structopt::StructOpt;
 
 use std::fs;
 use std::io::Cursor;
-use std::path::Path;
-use std::time;
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 extern crate log;
@@ -24,7 +22,7 @@ mod video;
 
 use rustboyadvance_core::prelude::*;
 
-use rustboyadvance_utils::FpsCounter;
+use rustboyadvance_utils::{FpsCounter, FrameLimiter};
 
 const LOG_DIR: &str = ".logs";
 
@@ -56,7 +54,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .start()
         .unwrap();
 
-    let opts = options::Options::from_args();
+    let mut opts = options::Options::from_args();
 
     info!("Initializing SDL2 context");
     let sdl_context = sdl2::init().expect("failed to initialize sdl2");
@@ -66,25 +64,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         include_str!("../../../external/SDL_GameControllerDB/gamecontrollerdb.txt");
     controller_subsystem.load_mappings_from_read(&mut Cursor::new(controller_mappings))?;
 
-    let available_controllers = (0..controller_subsystem.num_joysticks()?)
-        .filter(|&id| controller_subsystem.is_game_controller(id))
-        .collect::<Vec<u32>>();
-
-    let mut active_controller = match available_controllers.first() {
-        Some(&id) => {
-            let controller = controller_subsystem.open(id)?;
-            info!("Found game controller: {}", controller.name());
-            Some(controller)
-        }
-        _ => {
-            info!("No game controllers were found");
-            None
-        }
+    // every connected controller drives the keypad, SDL reports the controllers that are
+    // already connected with ControllerDeviceAdded events as well
+    let mut controllers: Vec<GameController> = Vec::new();
+    let key_map = input::KeyMap::default();
+    let axis_config = input::AxisConfig {
+        dead_zone: opts.dead_zone,
+        threshold: opts.axis_threshold,
     };
 
     let mut renderer = video::init(&sdl_context)?;
     let (audio_interface, mut _sdl_audio_device) = audio::create_audio_player(&sdl_context)?;
-    let mut rom_name = opts.rom_name();
+    let mut rom_name = opts.rom_name().to_string();
 
     let bios_bin = load_bios(&opts.bios);
 
@@ -111,24 +102,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         gba.skip_bios();
     }
 
+    if let Some(cheats_path) = &opts.cheats {
+        for error in gba.load_cheat_file(cheats_path)? {
+            eprintln!("{}: {}", cheats_path.display(), error);
+        }
+    }
+
     if opts.gdbserver {
         gba.start_gdbserver(opts.gdbserver_port);
     }
 
     let mut vsync = true;
+    let mut paused = false;
+    let mut frame_step = false;
+    // samples of silence owed to the audio device while paused
+    let mut silence = 0.0;
     let mut fps_counter = FpsCounter::default();
-    const FRAME_TIME: time::Duration = time::Duration::new(0, 1_000_000_000u32 / 60);
+    let mut frame_limiter = FrameLimiter::new(REFRESH_RATE);
     let mut event_pump = sdl_context.event_pump()?;
     'running: loop {
-        let start_time = time::Instant::now();
         for event in event_pump.poll_iter() {
             match event {
                 Event::KeyDown {
                     scancode: Some(scancode),
                     ..
-                } => match scancode {
-                    Scancode::Space => vsync = false,
-                    k => input::on_keyboard_key_down(gba.get_key_state_mut(), k),
+                } => match input::scancode_to_hotkey(scancode) {
+                    Some(input::Hotkey::TogglePause) => {
+                        paused = !paused;
+                        info!("{}", if paused { "Paused" } else { "Resumed" });
+                    }
+                    Some(input::Hotkey::FrameStep) => {
+                        paused = true;
+                        frame_step = true;
+                    }
+                    None => match scancode {
+                        Scancode::Space => vsync = false,
+                        k => input::on_keyboard_key_down(&key_map, gba.get_key_state_mut(), k),
+                    },
                 },
                 Event::KeyUp {
                     scancode: Some(scancode),
@@ -174,42 +184,54 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                     Scancode::Space => vsync = true,
-                    k => input::on_keyboard_key_up(gba.get_key_state_mut(), k),
+                    k => input::on_keyboard_key_up(&key_map, gba.get_key_state_mut(), k),
                 },
                 Event::ControllerButtonDown { button, .. } => match button {
                     Button::RightStick => vsync = !vsync,
-                    b => input::on_controller_button_down(gba.get_key_state_mut(), b),
+                    b => input::on_controller_button_down(&key_map, gba.get_key_state_mut(), b),
                 },
                 Event::ControllerButtonUp { button, .. } => {
-                    input::on_controller_button_up(gba.get_key_state_mut(), button);
+                    input::on_controller_button_up(&key_map, gba.get_key_state_mut(), button);
                 }
                 Event::ControllerAxisMotion { axis, value, .. } => {
-                    input::on_axis_motion(gba.get_key_state_mut(), axis, value);
+                    input::on_axis_motion(&axis_config, gba.get_key_state_mut(), axis, value);
                 }
                 Event::ControllerDeviceRemoved { which, .. } => {
-                    let removed = if let Some(active_controller) = &active_controller {
-                        active_controller.instance_id() == (which as i32)
-                    } else {
-                        false
-                    };
-                    if removed {
-                        let name = active_controller
-                            .and_then(|controller| Some(controller.name()))
-                            .unwrap();
-                        info!("Removing game controller: {}", name);
-                        active_controller = None;
+                    // `which` is the instance id of the controller
+                    if let Some(index) = controllers
+                        .iter()
+                        .position(|controller| controller.instance_id() == (which as i32))
+                    {
+                        let controller = controllers.remove(index);
+                        info!("Removing game controller: {}", controller.name());
+                        input::on_controller_removed(&key_map, gba.get_key_state_mut());
                     }
                 }
                 Event::ControllerDeviceAdded { which, .. } => {
-                    if active_controller.is_none() {
-                        let controller = controller_subsystem.open(which)?;
+                    // `which` is the device index of the controller
+                    let controller = controller_subsystem.open(which)?;
+                    if controllers
+                        .iter()
+                        .all(|c| c.instance_id() != controller.instance_id())
+                    {
                         info!("Adding game controller: {}", controller.name());
-                        active_controller = Some(controller);
+                        controllers.push(controller);
                     }
                 }
                 Event::Quit { .. } => break 'running,
                 Event::DropFile { filename, .. } => {
-                    todo!("impl DropFile again")
+                    let previous_rom = std::mem::replace(&mut opts.rom, PathBuf::from(filename));
+                    match opts.cartridge_from_opts() {
+                        Ok(gamepak) => {
+                            info!("Loading {:?}", opts.rom);
+                            gba.load_gamepak(gamepak, opts.skip_bios);
+                            rom_name = opts.rom_name().to_string();
+                        }
+                        Err(e) => {
+                            error!("failed to load {:?}: {}", opts.rom, e);
+                            opts.rom = previous_rom;
+                        }
+                    }
                 }
                 _ => {}
             }
@@ -217,25 +239,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         if gba.is_debugger_attached() {
             gba.debugger_run()
-        } else {
+        } else if !paused || std::mem::take(&mut frame_step) {
             gba.frame();
+        } else {
+            // Keep the audio device fed so it doesn't underrun while paused, a frame worth of samples at a time
+            silence += gba.audio_sample_rate() as f64 / REFRESH_RATE;
+            let samples = silence as usize;
+            silence -= samples as f64;
+            gba.pump_silence_samples(samples);
         }
         renderer.render(gba.get_frame_buffer());
 
         if let Some(fps) = fps_counter.tick() {
-            let title = format!("{} ({} fps)", rom_name, fps);
+            let title = if paused {
+                format!("{} (paused)", rom_name)
+            } else {
+                format!("{} ({} fps)", rom_name, fps)
+            };
             renderer.set_window_title(&title);
         }
 
-        if vsync {
-            let time_passed = start_time.elapsed();
-            let delay = FRAME_TIME.checked_sub(time_passed);
-            match delay {
-                None => {}
-                Some(delay) => {
-                    spin_sleep::sleep(delay);
-                }
-            };
+        // Always throttle while paused rather than spinning on the event pump
+        if vsync || paused {
+            frame_limiter.wait();
         }
     }
 
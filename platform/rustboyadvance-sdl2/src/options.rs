@@ -43,6 +43,18 @@ pub struct Options {
     /// Override save type, useful for troublemaking games that fool the auto detection
     #[structopt(long, default_value = "autodetect", possible_values = SAVE_TYPE_POSSIBLE_VALUES)]
     pub save_type: BackupType,
+
+    /// Cheat list file to load at startup
+    #[structopt(long, parse(from_os_str))]
+    pub cheats: Option<PathBuf>,
+
+    /// Analog stick values below the dead zone release the d-pad (0 to 32767)
+    #[structopt(long, default_value = "8000")]
+    pub dead_zone: i16,
+
+    /// Analog stick values above the threshold press the d-pad (0 to 32767)
+    #[structopt(long, default_value = "16000")]
+    pub axis_threshold: i16,
 }
 
 type DynError = Box<dyn std::error::Error>;
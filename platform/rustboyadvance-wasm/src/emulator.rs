@@ -2,7 +2,7 @@ use rustboyadvance_utils::audio::SampleConsumer;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::Clamped;
 
-use js_sys::Float32Array;
+use js_sys::{Float32Array, Uint8Array};
 
 use web_sys::CanvasRenderingContext2d;
 
@@ -18,6 +18,9 @@ pub struct Emulator {
     frame: Option<Box<[u8]>>,
 }
 
+/// About 0.2 seconds of audio at 44100hz
+const AUDIO_BUFFER_SIZE: usize = 16384;
+
 fn translate_frame_to_u8(input_fb: &[u32], out_fb: &mut [u8]) {
     // TODO optimize
     for i in 0..input_fb.len() {
@@ -123,3 +126,88 @@ impl Emulator {
         Ok(Float32Array::from(samples.as_slice()))
     }
 }
+
+/// A canvas and audio agnostic emulator, the frontend uploads the frame buffer and plays the audio by itself.
+#[wasm_bindgen]
+pub struct WasmEmulator {
+    gba: GameBoyAdvance,
+    audio_consumer: SampleConsumer,
+    frame: Box<[u8]>,
+}
+
+#[wasm_bindgen]
+impl WasmEmulator {
+    /// An empty `bios` runs the game with the builtin high level emulated BIOS
+    #[wasm_bindgen(constructor)]
+    pub fn new(bios: &[u8], rom: &[u8]) -> Result<WasmEmulator, JsValue> {
+        let (audio_device, audio_consumer) =
+            SimpleAudioInterface::create_channel(44100, Some(AUDIO_BUFFER_SIZE));
+
+        let gamepak = GamepakBuilder::new()
+            .buffer(rom)
+            .without_backup_to_file()
+            .build()
+            .map_err(|e| JsValue::from_str(&format!("failed to load rom: {:?}", e)))?;
+
+        let gba = if bios.is_empty() {
+            GameBoyAdvance::new_hle(gamepak, audio_device)
+        } else {
+            let mut gba = GameBoyAdvance::new(bios.into(), gamepak, audio_device);
+            gba.skip_bios();
+            gba
+        };
+
+        Ok(WasmEmulator {
+            gba,
+            audio_consumer,
+            frame: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4].into_boxed_slice(),
+        })
+    }
+
+    pub fn run_frame(&mut self) {
+        self.gba.frame();
+        translate_frame_to_u8(self.gba.get_frame_buffer(), &mut self.frame);
+    }
+
+    /// Pointer to the RGBA frame of the last `run_frame`, to be viewed directly from the wasm memory.
+    /// It stays valid for the lifetime of the emulator.
+    pub fn frame_buffer_ptr(&self) -> *const u8 {
+        self.frame.as_ptr()
+    }
+
+    pub fn cartridge_info(&self) -> crate::CartridgeInfo {
+        self.gba.get_cartridge_info().into()
+    }
+
+    /// `keys` is a bitmask of the pressed keys, in KEYINPUT bit order
+    pub fn set_keys(&mut self, keys: u16) {
+        *self.gba.get_key_state_mut() = !keys & gba_keypad::KEYINPUT_ALL_RELEASED;
+    }
+
+    pub fn save_state(&self) -> Result<Uint8Array, JsValue> {
+        let state = self
+            .gba
+            .save_state()
+            .map_err(|e| JsValue::from_str(&format!("failed to save state: {:?}", e)))?;
+        Ok(Uint8Array::from(state.as_slice()))
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.gba
+            .restore_state(state)
+            .map_err(|e| JsValue::from_str(&format!("failed to load state: {:?}", e)))
+    }
+
+    /// Move the pending interleaved stereo samples into `out`, returns the number of samples written
+    pub fn drain_audio(&mut self, out: &mut [i16]) -> usize {
+        let mut count = 0;
+        for sample in out.iter_mut() {
+            match self.audio_consumer.pop() {
+                Some(value) => *sample = value,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
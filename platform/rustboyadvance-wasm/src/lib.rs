@@ -31,6 +31,7 @@ pub fn init() {
 pub struct RomInfo {
     game_code: String,
     game_title: String,
+    validation: cartridge::header::HeaderValidation,
 }
 
 #[wasm_bindgen]
@@ -42,17 +43,80 @@ impl RomInfo {
     pub fn get_game_title(&self) -> String {
         self.game_title.to_string()
     }
+
+    pub fn is_logo_valid(&self) -> bool {
+        self.validation.logo_valid
+    }
+
+    pub fn is_checksum_valid(&self) -> bool {
+        self.validation.checksum_valid
+    }
+
+    pub fn get_stored_checksum(&self) -> u8 {
+        self.validation.stored_checksum
+    }
+
+    pub fn get_calculated_checksum(&self) -> u8 {
+        self.validation.calculated_checksum
+    }
 }
 
 impl From<cartridge::header::CartridgeHeader> for RomInfo {
     fn from(header: cartridge::header::CartridgeHeader) -> RomInfo {
+        let validation = header.validate();
         RomInfo {
+            validation,
             game_code: header.game_code,
             game_title: header.game_title,
         }
     }
 }
 
+/// Metadata of the cartridge loaded by a `WasmEmulator`, for a game library
+#[wasm_bindgen]
+pub struct CartridgeInfo(cartridge::CartridgeInfo);
+
+#[wasm_bindgen]
+impl CartridgeInfo {
+    pub fn get_game_title(&self) -> String {
+        self.0.game_title.to_string()
+    }
+
+    pub fn get_game_code(&self) -> String {
+        self.0.game_code.to_string()
+    }
+
+    pub fn get_maker_code(&self) -> String {
+        self.0.maker_code.to_string()
+    }
+
+    pub fn get_main_unit_code(&self) -> u8 {
+        self.0.main_unit_code
+    }
+
+    pub fn get_software_version(&self) -> u8 {
+        self.0.software_version
+    }
+
+    pub fn get_rom_size(&self) -> usize {
+        self.0.rom_size
+    }
+
+    pub fn get_backup_type(&self) -> String {
+        format!("{:?}", self.0.backup_type)
+    }
+
+    pub fn get_region(&self) -> String {
+        format!("{:?}", self.0.region)
+    }
+}
+
+impl From<cartridge::CartridgeInfo> for CartridgeInfo {
+    fn from(info: cartridge::CartridgeInfo) -> CartridgeInfo {
+        CartridgeInfo(info)
+    }
+}
+
 #[wasm_bindgen]
 pub fn parse_rom_header(rom_bin: &[u8]) -> RomInfo {
     cartridge::header::parse(rom_bin).unwrap().into()
@@ -11,3 +11,20 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+#[wasm_bindgen_test]
+fn run_frame_headless() {
+    use rustboyadvance_wasm::emulator::WasmEmulator;
+
+    // a rom that just spins with `b .`
+    let mut rom = vec![0; 0x200];
+    rom[0..4].copy_from_slice(&0xeafffffe_u32.to_le_bytes());
+
+    let mut emulator = WasmEmulator::new(&[], &rom).unwrap();
+    emulator.set_keys(0b1);
+    emulator.run_frame();
+    assert!(!emulator.frame_buffer_ptr().is_null());
+
+    let mut samples = vec![0; 4096];
+    assert!(emulator.drain_audio(&mut samples) <= samples.len());
+}
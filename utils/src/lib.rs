@@ -1,29 +1,41 @@
+//! Without the default `std` feature only the `alloc` based helpers are available
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
 use std::io::prelude::*;
-use std::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
 use std::path::Path;
-use std::ptr;
+#[cfg(feature = "std")]
 use std::time;
 
+#[cfg(feature = "std")]
 pub mod elf;
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 type Instant = time::Instant;
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 fn now() -> Instant {
     time::Instant::now()
 }
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 use instant;
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 type Instant = instant::Instant;
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
 fn now() -> Instant {
     instant::Instant::now()
 }
 
+#[cfg(feature = "std")]
 pub fn read_bin_file(filename: &Path) -> io::Result<Vec<u8>> {
     let mut buf = Vec::new();
     let mut file = File::open(filename)?;
@@ -31,6 +43,7 @@ pub fn read_bin_file(filename: &Path) -> io::Result<Vec<u8>> {
     Ok(buf)
 }
 
+#[cfg(feature = "std")]
 pub fn write_bin_file(filename: &Path, data: &[u8]) -> io::Result<()> {
     let mut f = File::create(filename)?;
     f.write_all(data)?;
@@ -38,13 +51,16 @@ pub fn write_bin_file(filename: &Path, data: &[u8]) -> io::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "std")]
 pub struct FpsCounter {
     count: u32,
     timer: Instant,
 }
 
+#[cfg(feature = "std")]
 const SECOND: time::Duration = time::Duration::from_secs(1);
 
+#[cfg(feature = "std")]
 impl Default for FpsCounter {
     fn default() -> FpsCounter {
         FpsCounter {
@@ -54,6 +70,7 @@ impl Default for FpsCounter {
     }
 }
 
+#[cfg(feature = "std")]
 impl FpsCounter {
     pub fn tick(&mut self) -> Option<u32> {
         self.count += 1;
@@ -68,6 +85,65 @@ impl FpsCounter {
     }
 }
 
+/// Paces a loop to a target frame rate.
+///
+/// Instead of sleeping for whatever is left of the frame time, the limiter keeps the deadline
+/// of the next frame, so the time lost by oversleeping (or by a slow frame) is made up on the
+/// frames that follow instead of accumulating into drift.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub struct FrameLimiter {
+    frame_time: time::Duration,
+    speed_multiplier: f32,
+    next_frame: Instant,
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl FrameLimiter {
+    /// When falling behind by more than this many frames (e.g after the emulation was paused)
+    /// the limiter starts over instead of running as fast as it can to catch up
+    const MAX_LAG_FRAMES: u32 = 4;
+
+    pub fn new(fps: f64) -> FrameLimiter {
+        let frame_time = time::Duration::from_secs_f64(1.0 / fps);
+        FrameLimiter {
+            frame_time,
+            speed_multiplier: 1.0,
+            next_frame: now() + frame_time,
+        }
+    }
+
+    /// Scale the target frame rate, 2.0 for double speed or 0.5 for half speed
+    pub fn set_speed_multiplier(&mut self, speed_multiplier: f32) {
+        assert!(speed_multiplier > 0.0, "invalid speed multiplier");
+        self.speed_multiplier = speed_multiplier;
+    }
+
+    pub fn speed_multiplier(&self) -> f32 {
+        self.speed_multiplier
+    }
+
+    /// Forget about the previous frames, e.g when resuming the emulation
+    pub fn reset(&mut self) {
+        self.next_frame = now() + self.scaled_frame_time();
+    }
+
+    /// Sleep until the next frame is due
+    pub fn wait(&mut self) {
+        let frame_time = self.scaled_frame_time();
+        let now = now();
+        if now < self.next_frame {
+            spin_sleep::sleep(self.next_frame - now);
+        } else if now - self.next_frame > frame_time * Self::MAX_LAG_FRAMES {
+            self.next_frame = now;
+        }
+        self.next_frame += frame_time;
+    }
+
+    fn scaled_frame_time(&self) -> time::Duration {
+        self.frame_time.div_f32(self.speed_multiplier)
+    }
+}
+
 #[macro_export]
 macro_rules! index2d {
     ($x:expr, $y:expr, $w:expr) => {
@@ -88,6 +164,7 @@ macro_rules! host_breakpoint {
     };
 }
 
+#[cfg(feature = "std")]
 pub mod audio {
     pub use ringbuf::{Consumer, Producer, RingBuffer};
     pub type SampleProducer = Producer<i16>;
@@ -162,8 +239,8 @@ impl<T> Default for WeakPointer<T> {
     }
 }
 
-use std::cell::UnsafeCell;
-use std::rc::Rc;
+use alloc::rc::Rc;
+use core::cell::UnsafeCell;
 
 /// Opt-out of runtime borrow checking of RefCell by using UnsafeCell
 /// SAFETY: Up to the user to make sure the usage of the shared object is safe
@@ -171,7 +248,7 @@ use std::rc::Rc;
 #[derive(Debug)]
 pub struct Shared<T>(Rc<UnsafeCell<T>>);
 
-impl<T> std::ops::Deref for Shared<T> {
+impl<T> Deref for Shared<T> {
     type Target = T;
 
     #[inline]
@@ -180,7 +257,7 @@ impl<T> std::ops::Deref for Shared<T> {
     }
 }
 
-impl<T> std::ops::DerefMut for Shared<T> {
+impl<T> DerefMut for Shared<T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut (*self.0.get()) }
@@ -217,3 +294,48 @@ where
         Shared::new(Default::default())
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_limiter_converges() {
+        const FRAMES: u32 = 40;
+        let mut limiter = FrameLimiter::new(200.0);
+        let start = now();
+        for i in 0..FRAMES {
+            if i == 10 {
+                // a slow frame, should be made up by the following frames
+                std::thread::sleep(time::Duration::from_millis(12));
+            }
+            limiter.wait();
+        }
+        let average = start.elapsed() / FRAMES;
+        let target = time::Duration::from_millis(5);
+        assert!(
+            average >= target.mul_f32(0.95) && average <= target.mul_f32(1.2),
+            "average frame interval {:?}",
+            average
+        );
+    }
+
+    #[test]
+    fn test_frame_limiter_speed_multiplier() {
+        const FRAMES: u32 = 20;
+        let mut limiter = FrameLimiter::new(100.0);
+        limiter.set_speed_multiplier(2.0);
+        limiter.reset();
+        let start = now();
+        for _ in 0..FRAMES {
+            limiter.wait();
+        }
+        let average = start.elapsed() / FRAMES;
+        let target = time::Duration::from_millis(5);
+        assert!(
+            average >= target.mul_f32(0.95) && average <= target.mul_f32(1.2),
+            "average frame interval {:?}",
+            average
+        );
+    }
+}